@@ -0,0 +1,479 @@
+//! MAC vector authenticators for normal case messages.
+//!
+//! Signing every `PREPARE` costs each replica an asymmetric signature per message per
+//! consensus instance. As in the original PBFT, prepares can instead carry a vector
+//! of MACs (an [`Authenticator`]), one for each recipient, computed with the session
+//! key shared between the sender and that recipient.
+//!
+//! The session keys are agreed with an X25519 Diffie-Hellman exchange: each replica announces
+//! its public key in a signed [`SessionKeyMessage`], and both ends of a pair derive the same
+//! key from it. The key itself never goes over the wire. The MACs are HMAC-SHA256, verified
+//! in constant time. Until we share a key with every recipient, we fall back to signatures.
+//!
+//! Only prepares are authenticated with MACs: commits are always signed. MACs can only be
+//! verified by their recipients, and a quorum of signed commits is what makes a decided proof
+//! verifiable by third parties (in view changes, log replay and state transfer), as a correct
+//! replica only commits once it has prepared.
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tracing::{debug, warn};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use atlas_common::crypto::hash::{Context, Digest};
+use atlas_common::node_id::NodeId;
+use atlas_common::ordering::{Orderable, SeqNo};
+use atlas_common::serialization_helper::SerMsg;
+use atlas_core::ordering_protocol::networking::OrderProtocolSendNode;
+
+use crate::bft::message::{
+    Authenticator, ConsensusMessage, ConsensusMessageKind, PBFTMessage, SessionKeyMessage,
+};
+use crate::bft::PBFT;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The size, in bytes, of X25519 secret and public keys
+const X25519_KEY_BYTES: usize = 32;
+
+/// A symmetric key shared between two replicas
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SessionKey([u8; Digest::LENGTH]);
+
+impl SessionKey {
+    fn random() -> Self {
+        let mut key = [0; Digest::LENGTH];
+
+        getrandom::getrandom(&mut key).expect("Failed to generate a random session key");
+
+        Self(key)
+    }
+}
+
+/// Our half of the X25519 key exchange, used with every peer
+struct KeyExchange {
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl KeyExchange {
+    fn generate() -> Self {
+        let mut secret = [0; X25519_KEY_BYTES];
+
+        getrandom::getrandom(&mut secret).expect("Failed to generate a key exchange secret");
+
+        let secret = StaticSecret::from(secret);
+        let public = PublicKey::from(&secret);
+
+        Self { secret, public }
+    }
+
+    /// The public key we announce to our peers
+    fn public_key(&self) -> Vec<u8> {
+        self.public.as_bytes().to_vec()
+    }
+
+    /// Derive the key we share with `peer`, given the public key it announced.
+    /// Returns `None` if the public key is malformed or degenerate
+    fn derive(&self, me: NodeId, peer: NodeId, peer_public: &[u8]) -> Option<SessionKey> {
+        let peer_public: [u8; X25519_KEY_BYTES] = peer_public.try_into().ok()?;
+
+        let shared = self.secret.diffie_hellman(&PublicKey::from(peer_public));
+
+        // Reject the low order points, which would force the shared secret to a known value
+        if !shared.was_contributory() {
+            return None;
+        }
+
+        // Both ends must hash the pair in the same order
+        let (first, second): (u64, u64) = if me < peer {
+            (me.into(), peer.into())
+        } else {
+            (peer.into(), me.into())
+        };
+
+        let mut context = Context::new();
+
+        context.update(b"febft-session-key");
+        context.update(&first.to_le_bytes());
+        context.update(&second.to_le_bytes());
+        context.update(shared.as_bytes());
+
+        let mut key = [0; Digest::LENGTH];
+        key.copy_from_slice(context.finish().as_ref());
+
+        Some(SessionKey(key))
+    }
+}
+
+/// The last public value a peer announced to us
+struct AnnouncedKey {
+    public_key: Vec<u8>,
+    // The epoch in which the peer last changed its public value, if it ever did
+    changed_in: Option<SeqNo>,
+}
+
+/// Performs the key exchanges with our peers, stores the
+/// resulting session keys and uses them
+pub struct MacAuthenticator {
+    node_id: NodeId,
+    exchange: KeyExchange,
+    // The public values our peers have announced to us
+    announced: RwLock<BTreeMap<NodeId, AnnouncedKey>>,
+    // The keys we share with each of our peers
+    keys: RwLock<BTreeMap<NodeId, SessionKey>>,
+}
+
+impl MacAuthenticator {
+    pub fn new(node_id: NodeId) -> Self {
+        // We don't need to exchange keys with ourselves
+        let own_key = SessionKey::random();
+
+        Self {
+            node_id,
+            exchange: KeyExchange::generate(),
+            announced: RwLock::new(BTreeMap::new()),
+            keys: RwLock::new(BTreeMap::from([(node_id, own_key)])),
+        }
+    }
+
+    /// Announce our public value to each of the given peers,
+    /// so they can derive the session key they share with us
+    pub fn announce_keys<RQ, NT>(&self, peers: impl IntoIterator<Item = NodeId>, node: &NT)
+    where
+        RQ: SerMsg,
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+    {
+        let public_key = self.exchange.public_key();
+
+        for peer in peers.into_iter().filter(|peer| *peer != self.node_id) {
+            let message = PBFTMessage::SessionKey(SessionKeyMessage::new(public_key.clone()));
+
+            let _ = node.send_signed(message, peer, true);
+        }
+    }
+
+    /// Derive the session key we share with `peer` from the public value it announced
+    /// during `epoch`. Returns whether the public value is new, in which case the peer has
+    /// likely restarted and must be told our public value as well.
+    ///
+    /// Each derivation costs a scalar multiplication and each change makes us re-announce
+    /// our own value, so repeated public values are ignored, and a peer may only change
+    /// its public value once per epoch
+    pub fn install_peer_key(
+        &self,
+        peer: NodeId,
+        message: &SessionKeyMessage,
+        epoch: SeqNo,
+    ) -> bool {
+        if peer == self.node_id {
+            return false;
+        }
+
+        let changed_in = match self.announced.read().unwrap().get(&peer) {
+            None => None,
+            Some(previous) if previous.public_key == *message.public_key() => return false,
+            Some(previous) if previous.changed_in == Some(epoch) => {
+                warn!(
+                    "{:?} // Ignoring the second public value announced by {:?} in epoch {:?}",
+                    self.node_id, peer, epoch
+                );
+
+                return false;
+            }
+            Some(_) => Some(epoch),
+        };
+
+        let Some(key) = self
+            .exchange
+            .derive(self.node_id, peer, message.public_key())
+        else {
+            warn!(
+                "{:?} // Ignoring the degenerate public value announced by {:?}",
+                self.node_id, peer
+            );
+
+            return false;
+        };
+
+        self.announced.write().unwrap().insert(
+            peer,
+            AnnouncedKey {
+                public_key: message.public_key().clone(),
+                changed_in,
+            },
+        );
+
+        self.keys.write().unwrap().insert(peer, key);
+
+        debug!(
+            "{:?} // Derived the session key shared with {:?}",
+            self.node_id, peer
+        );
+
+        true
+    }
+
+    /// Authenticate the given message for each of the given recipients.
+    /// Returns `None` if the message cannot be authenticated with MACs, either
+    /// because it's not a prepare or because we lack the key of one of the recipients
+    pub fn authenticate<O>(
+        &self,
+        message: &ConsensusMessage<O>,
+        recipients: &[NodeId],
+    ) -> Option<Authenticator> {
+        let keys = self.keys.read().unwrap();
+
+        let mut authenticator = Authenticator::default();
+
+        for recipient in recipients {
+            let key = keys.get(recipient)?;
+
+            let tag = mac(key, self.node_id, message)?.finalize().into_bytes();
+
+            authenticator.insert(*recipient, Digest::from_bytes(&tag).ok()?);
+        }
+
+        Some(authenticator)
+    }
+
+    /// Verify the MAC destined to us in the authenticator of a message sent by `from`
+    pub fn verify<O>(&self, from: NodeId, message: &ConsensusMessage<O>) -> bool {
+        let Some(received) = message
+            .authenticator()
+            .and_then(|authenticator| authenticator.mac_for(&self.node_id))
+        else {
+            return false;
+        };
+
+        let Some(key) = self.keys.read().unwrap().get(&from).copied() else {
+            warn!(
+                "{:?} // Received an authenticated message from {:?}, with whom we share no session key",
+                self.node_id, from
+            );
+
+            return false;
+        };
+
+        // The comparison is done in constant time
+        mac(&key, from, message).is_some_and(|mac| mac.verify_slice(received.as_ref()).is_ok())
+    }
+}
+
+/// The HMAC of a prepare message sent by `from`, ready to be finalized or verified.
+/// Pre prepares and commits must be signed, so they have no MAC
+fn mac<O>(key: &SessionKey, from: NodeId, message: &ConsensusMessage<O>) -> Option<HmacSha256> {
+    let ConsensusMessageKind::Prepare(digest) = message.kind() else {
+        return None;
+    };
+
+    let sender: u64 = from.into();
+
+    let mut mac = HmacSha256::new_from_slice(&key.0).expect("HMAC accepts keys of any size");
+
+    mac.update(&sender.to_le_bytes());
+    mac.update(&u32::from(message.sequence_number()).to_le_bytes());
+    mac.update(&u32::from(message.view()).to_le_bytes());
+    mac.update(&message.nonce().to_le_bytes());
+    mac.update(digest.as_ref());
+
+    Some(mac)
+}
+
+#[cfg(test)]
+mod auth_tests {
+    use atlas_common::ordering::SeqNo;
+
+    use crate::bft::fixtures::digest;
+
+    use super::*;
+
+    type Message = ConsensusMessage<u8>;
+
+    fn announcement(authenticator: &MacAuthenticator) -> SessionKeyMessage {
+        SessionKeyMessage::new(authenticator.exchange.public_key())
+    }
+
+    /// Build authenticators for the given nodes, which have all exchanged keys
+    fn exchanged(nodes: &[u32]) -> Vec<MacAuthenticator> {
+        let authenticators: Vec<_> = nodes
+            .iter()
+            .map(|node| MacAuthenticator::new(NodeId::from(*node)))
+            .collect();
+
+        for receiver in &authenticators {
+            for sender in &authenticators {
+                receiver.install_peer_key(sender.node_id, &announcement(sender), SeqNo::ZERO);
+            }
+        }
+
+        authenticators
+    }
+
+    fn prepare(seq: u32, digest: Digest) -> Message {
+        Message::new(
+            SeqNo::from(seq),
+            SeqNo::ZERO,
+            ConsensusMessageKind::Prepare(digest),
+        )
+    }
+
+    fn authenticated(
+        sender: &MacAuthenticator,
+        mut message: Message,
+        recipients: &[NodeId],
+    ) -> Message {
+        let authenticator = sender
+            .authenticate(&message, recipients)
+            .expect("Should authenticate a prepare");
+
+        message.set_authenticator(authenticator);
+
+        message
+    }
+
+    #[test]
+    fn both_ends_derive_the_same_key() {
+        let nodes = exchanged(&[0, 1]);
+
+        let (first, second) = (&nodes[0], &nodes[1]);
+
+        let first_key = first.keys.read().unwrap().get(&second.node_id).copied();
+        let second_key = second.keys.read().unwrap().get(&first.node_id).copied();
+
+        assert!(first_key.is_some());
+        assert!(first_key == second_key);
+    }
+
+    #[test]
+    fn authenticated_prepare_round_trips() {
+        let nodes = exchanged(&[0, 1, 2]);
+
+        let recipients = [NodeId::from(1u32), NodeId::from(2u32)];
+
+        let message = authenticated(&nodes[0], prepare(3, digest(1)), &recipients);
+
+        assert!(nodes[1].verify(nodes[0].node_id, &message));
+        assert!(nodes[2].verify(nodes[0].node_id, &message));
+    }
+
+    #[test]
+    fn tampered_prepare_is_rejected() {
+        let nodes = exchanged(&[0, 1, 2]);
+
+        let recipients = [NodeId::from(1u32)];
+
+        let message = authenticated(&nodes[0], prepare(3, digest(1)), &recipients);
+
+        let authenticator = message.authenticator().cloned().unwrap();
+
+        let mut other_digest = prepare(3, digest(2));
+        other_digest.set_authenticator(authenticator.clone());
+
+        let mut other_seq = prepare(4, digest(1));
+        other_seq.set_authenticator(authenticator);
+
+        assert!(!nodes[1].verify(nodes[0].node_id, &other_digest));
+        assert!(!nodes[1].verify(nodes[0].node_id, &other_seq));
+        // Claiming another sender must not pass either
+        assert!(!nodes[1].verify(nodes[2].node_id, &message));
+        // Nor can a node that was not a recipient verify it
+        assert!(!nodes[2].verify(nodes[0].node_id, &message));
+    }
+
+    #[test]
+    fn only_prepares_are_authenticated_with_macs() {
+        let nodes = exchanged(&[0, 1]);
+
+        let recipients = [NodeId::from(1u32)];
+
+        let commit = Message::new(
+            SeqNo::ZERO,
+            SeqNo::ZERO,
+            ConsensusMessageKind::Commit(digest(1)),
+        );
+        let pre_prepare = Message::new(
+            SeqNo::ZERO,
+            SeqNo::ZERO,
+            ConsensusMessageKind::PrePrepare(Vec::new()),
+        );
+
+        assert!(nodes[0].authenticate(&commit, &recipients).is_none());
+        assert!(nodes[0].authenticate(&pre_prepare, &recipients).is_none());
+    }
+
+    #[test]
+    fn unknown_peers_cannot_be_authenticated() {
+        let authenticator = MacAuthenticator::new(NodeId::from(0u32));
+
+        let recipients = [NodeId::from(1u32)];
+
+        assert!(authenticator
+            .authenticate(&prepare(0, digest(1)), &recipients)
+            .is_none());
+    }
+
+    #[test]
+    fn degenerate_public_values_are_rejected() {
+        let authenticator = MacAuthenticator::new(NodeId::from(0u32));
+        let peer = NodeId::from(1u32);
+
+        let install = |public_key| {
+            authenticator.install_peer_key(peer, &SessionKeyMessage::new(public_key), SeqNo::ZERO)
+        };
+
+        assert!(!install(vec![1]));
+        assert!(!install(Vec::new()));
+        // The identity is a low order point
+        assert!(!install(vec![0; 32]));
+
+        assert!(authenticator.keys.read().unwrap().get(&peer).is_none());
+    }
+
+    #[test]
+    fn reannouncing_reports_changes() {
+        let authenticator = MacAuthenticator::new(NodeId::from(0u32));
+        let peer = MacAuthenticator::new(NodeId::from(1u32));
+        let restarted = MacAuthenticator::new(NodeId::from(1u32));
+
+        let install = |announced: &MacAuthenticator, epoch: u32| {
+            authenticator.install_peer_key(
+                announced.node_id,
+                &announcement(announced),
+                SeqNo::from(epoch),
+            )
+        };
+
+        assert!(install(&peer, 0));
+        assert!(!install(&peer, 0));
+        assert!(install(&restarted, 0));
+    }
+
+    #[test]
+    fn public_values_change_once_per_epoch() {
+        let authenticator = MacAuthenticator::new(NodeId::from(0u32));
+        let announcers: Vec<_> = (0..4)
+            .map(|_| MacAuthenticator::new(NodeId::from(1u32)))
+            .collect();
+
+        let install = |announced: &MacAuthenticator, epoch: u32| {
+            authenticator.install_peer_key(
+                announced.node_id,
+                &announcement(announced),
+                SeqNo::from(epoch),
+            )
+        };
+
+        assert!(install(&announcers[0], 0));
+        assert!(install(&announcers[1], 0));
+        // A second change in the same epoch is ignored, and the first one is kept
+        assert!(!install(&announcers[2], 0));
+        assert!(!install(&announcers[1], 0));
+
+        assert!(install(&announcers[2], 1));
+        assert!(!install(&announcers[3], 1));
+    }
+}
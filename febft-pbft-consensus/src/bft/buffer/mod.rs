@@ -0,0 +1,414 @@
+//! Bounds on the messages we buffer for future consensus instances and views.
+//!
+//! Messages that arrive ahead of time are kept in the `TboQueue`s of the consensus and
+//! the synchronizer until we are ready to process them. Without any bounds, a Byzantine
+//! replica could exhaust our memory by sending messages for far-future sequence numbers or views.
+//! We therefore limit the amount of buffered messages (both in count and in bytes), per peer and globally.
+//! When a limit is reached, the messages that are furthest ahead are the first to be evicted.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use tracing::warn;
+
+use atlas_common::node_id::NodeId;
+use atlas_core::ordering_protocol::ShareableMessage;
+use atlas_metrics::metrics::{metric_increment, metric_store_count};
+
+use crate::bft::config::MessageBufferConfig;
+use crate::bft::message::PBFTMessage;
+use crate::bft::metric::{BUFFER_DROPPED_BYTES_ID, BUFFER_DROPPED_MESSAGES_ID};
+
+type BufferedQueue<O> = VecDeque<VecDeque<ShareableMessage<PBFTMessage<O>>>>;
+
+/// The amount of buffered messages and the bytes they occupy
+#[derive(Default, Debug, Clone, Copy)]
+struct Usage {
+    count: usize,
+    bytes: usize,
+}
+
+impl Usage {
+    fn exceeds(&self, count: usize, bytes: usize) -> bool {
+        self.count > count || self.bytes > bytes
+    }
+}
+
+/// Keeps track of how much we are buffering, per peer and globally
+pub struct BufferUsage {
+    limits: MessageBufferConfig,
+    // The metrics to which we report the amount of buffered messages and bytes
+    messages_metric: usize,
+    bytes_metric: usize,
+    per_peer: BTreeMap<NodeId, Usage>,
+    global: Usage,
+}
+
+impl BufferUsage {
+    pub fn new(limits: MessageBufferConfig, messages_metric: usize, bytes_metric: usize) -> Self {
+        Self {
+            limits,
+            messages_metric,
+            bytes_metric,
+            per_peer: Default::default(),
+            global: Default::default(),
+        }
+    }
+
+    /// Check which limit would be exceeded by buffering a message with `bytes` size from `from`.
+    /// Returns `Some(Some(from))` if the limit of that peer would be exceeded, `Some(None)` if the
+    /// global limit would be exceeded and `None` if the message can be buffered
+    fn exceeded_by(&self, from: NodeId, bytes: usize) -> Option<Option<NodeId>> {
+        let peer = self.per_peer.get(&from).copied().unwrap_or_default();
+
+        let peer_after = Usage {
+            count: peer.count + 1,
+            bytes: peer.bytes + bytes,
+        };
+
+        if peer_after.exceeds(self.limits.per_peer_count, self.limits.per_peer_bytes) {
+            return Some(Some(from));
+        }
+
+        let global_after = Usage {
+            count: self.global.count + 1,
+            bytes: self.global.bytes + bytes,
+        };
+
+        if global_after.exceeds(self.limits.global_count, self.limits.global_bytes) {
+            return Some(None);
+        }
+
+        None
+    }
+
+    fn add<O>(&mut self, message: &ShareableMessage<PBFTMessage<O>>) {
+        let bytes = message_size(message);
+
+        let peer = self.per_peer.entry(message.header().from()).or_default();
+
+        peer.count += 1;
+        peer.bytes += bytes;
+
+        self.global.count += 1;
+        self.global.bytes += bytes;
+
+        self.report();
+    }
+
+    fn remove<O>(&mut self, message: &ShareableMessage<PBFTMessage<O>>) {
+        let bytes = message_size(message);
+        let from = message.header().from();
+
+        if let Some(peer) = self.per_peer.get_mut(&from) {
+            peer.count = peer.count.saturating_sub(1);
+            peer.bytes = peer.bytes.saturating_sub(bytes);
+
+            if peer.count == 0 {
+                self.per_peer.remove(&from);
+            }
+        }
+
+        self.global.count = self.global.count.saturating_sub(1);
+        self.global.bytes = self.global.bytes.saturating_sub(bytes);
+    }
+
+    /// Stop accounting for the given messages, as they are no longer buffered
+    /// for the future (they are either going to be processed or have been discarded)
+    pub fn release<'a, O: 'a>(
+        &mut self,
+        messages: impl IntoIterator<Item = &'a ShareableMessage<PBFTMessage<O>>>,
+    ) {
+        messages
+            .into_iter()
+            .for_each(|message| self.remove(message));
+
+        self.report();
+    }
+
+    /// Reset the accounting, as all buffered messages have been discarded
+    pub fn clear(&mut self) {
+        self.per_peer.clear();
+        self.global = Default::default();
+
+        self.report();
+    }
+
+    fn report(&self) {
+        metric_store_count(self.messages_metric, self.global.count);
+        metric_store_count(self.bytes_metric, self.global.bytes);
+    }
+
+    /// Make room for buffering `message` at position `index` of the given queues,
+    /// evicting the messages that are furthest ahead if necessary.
+    /// Returns whether the message should be buffered. If it returns true,
+    /// the message has already been accounted for.
+    pub fn make_room<O>(
+        &mut self,
+        queues: &mut [&mut BufferedQueue<O>],
+        index: usize,
+        message: &ShareableMessage<PBFTMessage<O>>,
+    ) -> bool {
+        let from = message.header().from();
+        let bytes = message_size(message);
+
+        while let Some(limited_peer) = self.exceeded_by(from, bytes) {
+            match evict_furthest(queues, index, limited_peer) {
+                Some(evicted) => {
+                    self.remove(&evicted);
+
+                    dropped(&evicted);
+                }
+                None => {
+                    warn!(
+                        "Dropping message from {:?} as the buffer limits have been reached ({:?})",
+                        from, limited_peer
+                    );
+
+                    dropped(message);
+
+                    return false;
+                }
+            }
+        }
+
+        self.add(message);
+
+        true
+    }
+}
+
+/// Remove the message that is furthest ahead of the given queues, as long as it is further ahead than `index`.
+/// If `from` is provided, only messages sent by that peer are considered
+fn evict_furthest<O>(
+    queues: &mut [&mut BufferedQueue<O>],
+    index: usize,
+    from: Option<NodeId>,
+) -> Option<ShareableMessage<PBFTMessage<O>>> {
+    let furthest = queues.iter().map(|queue| queue.len()).max().unwrap_or(0);
+
+    for slot in (index + 1..furthest).rev() {
+        for queue in queues.iter_mut() {
+            let Some(messages) = queue.get_mut(slot) else {
+                continue;
+            };
+
+            let position = messages
+                .iter()
+                .rposition(|message| from.map_or(true, |from| message.header().from() == from));
+
+            if let Some(position) = position {
+                return messages.remove(position);
+            }
+        }
+    }
+
+    None
+}
+
+fn dropped<O>(message: &ShareableMessage<PBFTMessage<O>>) {
+    metric_increment(BUFFER_DROPPED_MESSAGES_ID, Some(1));
+    metric_increment(BUFFER_DROPPED_BYTES_ID, Some(message_size(message) as u64));
+}
+
+/// The size of the given message, as it was received from the network
+fn message_size<O>(message: &ShareableMessage<PBFTMessage<O>>) -> usize {
+    message.header().payload_length()
+}
+
+#[cfg(test)]
+mod buffer_tests {
+    use std::sync::Arc;
+
+    use atlas_common::ordering::SeqNo;
+    use atlas_communication::lookup_table::MessageModule;
+    use atlas_communication::message::StoredMessage;
+
+    use crate::bft::fixtures::{digest, header_with};
+    use crate::bft::message::{ConsensusMessage, ConsensusMessageKind};
+
+    use super::*;
+
+    fn message(from: u32, bytes: usize) -> ShareableMessage<PBFTMessage<u8>> {
+        let header = header_with(MessageModule::Protocol, NodeId::from(from), digest(0), bytes);
+
+        let commit = ConsensusMessage::new(
+            SeqNo::ZERO,
+            SeqNo::ZERO,
+            ConsensusMessageKind::Commit(digest(0)),
+        );
+
+        Arc::new(StoredMessage::new(header, PBFTMessage::Consensus(commit)))
+    }
+
+    fn queue(slots: usize) -> BufferedQueue<u8> {
+        (0..slots).map(|_| VecDeque::new()).collect()
+    }
+
+    fn usage(per_peer_count: usize, per_peer_bytes: usize, global_count: usize) -> BufferUsage {
+        BufferUsage::new(
+            MessageBufferConfig::new(per_peer_count, per_peer_bytes, global_count, usize::MAX),
+            0,
+            0,
+        )
+    }
+
+    /// Buffer the message in the given slot of the queue, if there is room for it
+    fn buffer(
+        usage: &mut BufferUsage,
+        queue: &mut BufferedQueue<u8>,
+        slot: usize,
+        message: ShareableMessage<PBFTMessage<u8>>,
+    ) -> bool {
+        let room = usage.make_room(&mut [&mut *queue], slot, &message);
+
+        if room {
+            queue[slot].push_back(message);
+        }
+
+        room
+    }
+
+    /// The senders of the messages buffered in each slot of the queue
+    fn senders(queue: &BufferedQueue<u8>) -> Vec<Vec<NodeId>> {
+        queue
+            .iter()
+            .map(|slot| slot.iter().map(|message| message.header().from()).collect())
+            .collect()
+    }
+
+    fn nodes(ids: &[u32]) -> Vec<NodeId> {
+        ids.iter().copied().map(NodeId::from).collect()
+    }
+
+    #[test]
+    fn test_buffers_within_limits() {
+        let mut usage = usage(2, 100, 10);
+        let mut queue = queue(4);
+
+        assert!(buffer(&mut usage, &mut queue, 1, message(0, 50)));
+        assert!(buffer(&mut usage, &mut queue, 3, message(0, 50)));
+
+        assert_eq!(usage.global.count, 2);
+        assert_eq!(usage.global.bytes, 100);
+        assert_eq!(
+            senders(&queue),
+            vec![vec![], nodes(&[0]), vec![], nodes(&[0])]
+        );
+    }
+
+    #[test]
+    fn test_peer_count_limit_evicts_the_furthest_message_of_the_peer() {
+        let mut usage = usage(2, usize::MAX, 10);
+        let mut queue = queue(5);
+
+        assert!(buffer(&mut usage, &mut queue, 2, message(0, 1)));
+        assert!(buffer(&mut usage, &mut queue, 3, message(0, 1)));
+        assert!(buffer(&mut usage, &mut queue, 4, message(1, 1)));
+
+        // Peer 0 is at its limit, so its furthest message gives way, not peer 1's
+        assert!(buffer(&mut usage, &mut queue, 1, message(0, 1)));
+
+        assert_eq!(
+            senders(&queue),
+            vec![vec![], nodes(&[0]), nodes(&[0]), vec![], nodes(&[1])]
+        );
+        assert_eq!(usage.per_peer[&NodeId::from(0u32)].count, 2);
+        assert_eq!(usage.global.count, 3);
+    }
+
+    #[test]
+    fn test_nothing_further_ahead_drops_the_message() {
+        let mut usage = usage(2, usize::MAX, 10);
+        let mut queue = queue(4);
+
+        assert!(buffer(&mut usage, &mut queue, 1, message(0, 1)));
+        assert!(buffer(&mut usage, &mut queue, 2, message(0, 1)));
+
+        // Only messages further ahead than the new one may be evicted
+        assert!(!buffer(&mut usage, &mut queue, 2, message(0, 1)));
+        assert!(!buffer(&mut usage, &mut queue, 3, message(0, 1)));
+
+        assert_eq!(
+            senders(&queue),
+            vec![vec![], nodes(&[0]), nodes(&[0]), vec![]]
+        );
+        assert_eq!(usage.global.count, 2);
+    }
+
+    #[test]
+    fn test_global_limit_evicts_the_furthest_message_of_any_peer() {
+        let mut usage = usage(10, usize::MAX, 3);
+        let mut queue = queue(5);
+
+        assert!(buffer(&mut usage, &mut queue, 3, message(0, 1)));
+        assert!(buffer(&mut usage, &mut queue, 4, message(1, 1)));
+        assert!(buffer(&mut usage, &mut queue, 4, message(2, 1)));
+
+        // The latest message of the furthest slot is the first to go
+        assert!(buffer(&mut usage, &mut queue, 1, message(3, 1)));
+
+        assert_eq!(
+            senders(&queue),
+            vec![vec![], nodes(&[3]), vec![], nodes(&[0]), nodes(&[1])]
+        );
+        assert!(!usage.per_peer.contains_key(&NodeId::from(2u32)));
+        assert_eq!(usage.global.count, 3);
+    }
+
+    #[test]
+    fn test_byte_limit() {
+        let mut usage = usage(10, 100, 10);
+        let mut queue = queue(4);
+
+        assert!(buffer(&mut usage, &mut queue, 2, message(0, 60)));
+        assert!(buffer(&mut usage, &mut queue, 3, message(0, 30)));
+
+        // Both buffered messages must go to fit this one
+        assert!(buffer(&mut usage, &mut queue, 1, message(0, 80)));
+
+        assert_eq!(senders(&queue), vec![vec![], nodes(&[0]), vec![], vec![]]);
+        assert_eq!(usage.global.bytes, 80);
+
+        // A message over the limit by itself is never buffered
+        assert!(!buffer(&mut usage, &mut queue, 0, message(1, 101)));
+        assert_eq!(usage.global.bytes, 80);
+    }
+
+    #[test]
+    fn test_evicts_across_queues() {
+        let mut usage = usage(2, usize::MAX, 10);
+        let (mut first, mut second) = (queue(3), queue(5));
+
+        for (queue, slot) in [(&mut first, 2), (&mut second, 4)] {
+            let message = message(0, 1);
+
+            assert!(usage.make_room(&mut [&mut *queue], slot, &message));
+
+            queue[slot].push_back(message);
+        }
+
+        let message = message(0, 1);
+
+        assert!(usage.make_room(&mut [&mut first, &mut second], 1, &message));
+
+        assert_eq!(senders(&first)[2], nodes(&[0]));
+        assert!(senders(&second)[4].is_empty());
+    }
+
+    #[test]
+    fn test_release_makes_room() {
+        let mut usage = usage(1, usize::MAX, 10);
+        let mut queue = queue(3);
+
+        assert!(buffer(&mut usage, &mut queue, 1, message(0, 1)));
+        assert!(!buffer(&mut usage, &mut queue, 2, message(0, 1)));
+
+        let released = queue.pop_front().into_iter().chain(queue.pop_front());
+        let released = released.flatten().collect::<Vec<_>>();
+
+        usage.release(&released);
+
+        assert!(usage.per_peer.is_empty());
+        assert!(buffer(&mut usage, &mut queue, 0, message(0, 1)));
+    }
+}
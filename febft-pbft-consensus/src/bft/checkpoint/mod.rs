@@ -0,0 +1,809 @@
+//! The PBFT checkpoint sub-protocol.
+//!
+//! Whenever a replica takes a local checkpoint of the application state, it
+//! broadcasts a `CHECKPOINT` message with the digest of that state to the quorum.
+//! Once `2f + 1` replicas have reported the same digest for the same sequence
+//! number, the checkpoint becomes stable. The collected messages form a
+//! certificate which allows us to discard the decided proofs that preceded it
+//! and which serves as the trust anchor for state transfers.
+//!
+//! The execution layer reports the local checkpoints it finishes through a
+//! [`CheckpointNotifier`], and they are broadcast the next time the protocol is polled.
+//! The state transfer protocol reports the states it installs through the same notifier,
+//! so that a state beyond our window is only installed once a certificate vouches for it.
+//!
+//! A replica that falls behind keeps the latest vote of each member above its window,
+//! and receives the stable certificates of its peers when they can no longer replay their
+//! log to it, so it always learns the certificate its state transfer is anchored on.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+#[cfg(feature = "serialize_serde")]
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, error, info, warn};
+
+use atlas_common::crypto::hash::Digest;
+use atlas_common::error::*;
+use atlas_common::node_id::NodeId;
+use atlas_common::ordering::{Orderable, SeqNo};
+use atlas_common::serialization_helper::SerMsg;
+use atlas_common::Err;
+use atlas_core::ordering_protocol::networking::serialize::NetworkView;
+use atlas_core::ordering_protocol::networking::OrderProtocolSendNode;
+use atlas_core::ordering_protocol::ShareableMessage;
+use atlas_metrics::metrics::metric_increment;
+
+use crate::bft::consensus::SeqNoWindow;
+use crate::bft::message::{CheckpointMessage, PBFTMessage};
+use crate::bft::metric::STABLE_CHECKPOINTS_ID;
+use crate::bft::sync::view::election::LeaderElection;
+use crate::bft::sync::view::ViewInfo;
+use crate::bft::PBFT;
+
+/// A certificate attesting that a checkpoint is stable.
+///
+/// Contains the `2f + 1` signed checkpoint messages that agree on the
+/// digest of the state at the given sequence number.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct CheckpointCertificate<RQ> {
+    seq: SeqNo,
+    digest: Digest,
+    messages: Vec<ShareableMessage<PBFTMessage<RQ>>>,
+}
+
+impl<RQ> Orderable for CheckpointCertificate<RQ> {
+    fn sequence_number(&self) -> SeqNo {
+        self.seq
+    }
+}
+
+impl<RQ> CheckpointCertificate<RQ> {
+    /// The digest of the application state that was agreed upon
+    pub fn digest(&self) -> &Digest {
+        &self.digest
+    }
+
+    /// The checkpoint messages that compose this certificate
+    pub fn messages(&self) -> &Vec<ShareableMessage<PBFTMessage<RQ>>> {
+        &self.messages
+    }
+
+    /// The view and agreed leader election history reported by more than `f` of the
+    /// votes, so that at least one correct replica vouches for them.
+    /// Should there be several, the one of the latest view is returned
+    pub fn agreed_election(&self, f: usize) -> Option<(SeqNo, &LeaderElection)> {
+        let reported = self
+            .messages
+            .iter()
+            .filter_map(|message| match message.message() {
+                PBFTMessage::Checkpoint(checkpoint) => {
+                    Some((checkpoint.view(), checkpoint.election()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        reported
+            .iter()
+            .filter(|candidate| {
+                reported
+                    .iter()
+                    .filter(|other| *other == **candidate)
+                    .count()
+                    > f
+            })
+            .max_by_key(|(view, _)| *view)
+            .copied()
+    }
+
+    /// Check whether this certificate vouches for the state with the given
+    /// sequence number and digest.
+    /// This is meant to be used to validate states received through state transfer
+    pub fn certifies(&self, seq: SeqNo, digest: &Digest) -> bool {
+        self.seq == seq && self.digest == *digest
+    }
+}
+
+/// The handle through which the execution layer reports the local checkpoints it has
+/// finished taking
+#[derive(Clone, Debug)]
+pub struct CheckpointNotifier {
+    tx: Sender<(SeqNo, Digest)>,
+}
+
+impl CheckpointNotifier {
+    /// Report that the state at `seq`, with the given digest, has been checkpointed
+    pub fn checkpoint_taken(&self, seq: SeqNo, digest: Digest) {
+        // The protocol has shut down if the receiving end is gone
+        let _ = self.tx.send((seq, digest));
+    }
+
+    /// Turn this notifier into a callback, such as the checkpoint listener
+    /// of the state transfer protocol
+    pub fn into_listener(self) -> Box<dyn Fn(SeqNo, Digest) + Send + Sync> {
+        Box::new(move |seq, digest| self.checkpoint_taken(seq, digest))
+    }
+}
+
+/// The channel through which local checkpoints reach the protocol.
+///
+/// It can be created ahead of the protocol and handed to it through the [`PBFTConfig`],
+/// so that its notifier may be given to the state transfer protocol when both are initialized
+///
+/// [`PBFTConfig`]: crate::bft::config::PBFTConfig
+#[derive(Debug)]
+pub struct CheckpointChannel {
+    notifier: CheckpointNotifier,
+    taken: Receiver<(SeqNo, Digest)>,
+}
+
+impl CheckpointChannel {
+    pub fn new() -> Self {
+        let (tx, taken) = mpsc::channel();
+
+        Self {
+            notifier: CheckpointNotifier { tx },
+            taken,
+        }
+    }
+
+    /// The handle through which the local checkpoints are reported
+    pub fn notifier(&self) -> CheckpointNotifier {
+        self.notifier.clone()
+    }
+}
+
+impl Default for CheckpointChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The result of processing a checkpoint message
+pub enum CheckpointStatus<RQ> {
+    /// The message was ignored, as it refers to a checkpoint
+    /// which is older than our current stable checkpoint
+    Ignored,
+    /// The message was accepted, but no stable checkpoint was formed
+    Pending,
+    /// The message has made a checkpoint stable
+    Stable(CheckpointCertificate<RQ>),
+}
+
+/// Keeps track of the checkpoint messages we have received,
+/// until they form stable checkpoints
+pub struct CheckpointTracker<RQ> {
+    node_id: NodeId,
+    // The checkpoint messages we have received, for each sequence number
+    // which is still ahead of our stable checkpoint
+    votes: BTreeMap<SeqNo, BTreeMap<NodeId, ShareableMessage<PBFTMessage<RQ>>>>,
+    // The digests of the local checkpoints we have taken or installed,
+    // from our stable checkpoint onwards
+    local: BTreeMap<SeqNo, Digest>,
+    // The latest stable checkpoint
+    stable: Option<CheckpointCertificate<RQ>>,
+    // The local checkpoints reported by the execution layer, yet to be broadcast
+    channel: CheckpointChannel,
+}
+
+impl<RQ> CheckpointTracker<RQ>
+where
+    RQ: SerMsg,
+{
+    pub fn new(node_id: NodeId, channel: CheckpointChannel) -> Self {
+        Self {
+            node_id,
+            votes: Default::default(),
+            local: Default::default(),
+            stable: None,
+            channel,
+        }
+    }
+
+    /// The handle through which the execution layer reports its local checkpoints
+    pub fn notifier(&self) -> CheckpointNotifier {
+        self.channel.notifier()
+    }
+
+    /// The local checkpoints reported since the last call, which we have not broadcast yet
+    pub fn take_local_checkpoints(&self) -> Vec<(SeqNo, Digest)> {
+        self.channel.taken.try_iter().collect()
+    }
+
+    /// The digest of the local checkpoint we have taken or installed at `seq`, if any
+    pub fn local_digest(&self, seq: SeqNo) -> Option<&Digest> {
+        self.local.get(&seq)
+    }
+
+    /// The latest stable checkpoint we are aware of
+    pub fn stable_checkpoint(&self) -> Option<&CheckpointCertificate<RQ>> {
+        self.stable.as_ref()
+    }
+
+    /// The sequence number of the latest stable checkpoint
+    pub fn stable_seq(&self) -> SeqNo {
+        self.stable
+            .as_ref()
+            .map(|cert| cert.sequence_number())
+            .unwrap_or(SeqNo::ZERO)
+    }
+
+    /// Register that we have taken a local checkpoint of the state at `seq`,
+    /// broadcasting the corresponding checkpoint message to the quorum.
+    ///
+    /// A state at our stable checkpoint, such as one installed by the state transfer
+    /// protocol, is recorded so it can be checked against the certificate, but not broadcast
+    pub fn local_checkpoint<NT>(&mut self, seq: SeqNo, digest: Digest, view: &ViewInfo, node: &NT)
+    where
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+    {
+        if self.stable.is_some() && seq <= self.stable_seq() {
+            if seq == self.stable_seq() {
+                self.local.insert(seq, digest);
+            }
+
+            debug!(
+                "{:?} // Local checkpoint {:?} is not ahead of our stable checkpoint {:?}, not broadcasting",
+                self.node_id,
+                seq,
+                self.stable_seq()
+            );
+
+            return;
+        }
+
+        self.local.insert(seq, digest);
+
+        let message = PBFTMessage::Checkpoint(CheckpointMessage::new(
+            seq,
+            digest,
+            view.sequence_number(),
+            view.agreed_election().clone(),
+        ));
+
+        let targets = view.quorum_members().clone();
+
+        debug!(
+            "{:?} // Broadcasting checkpoint message for {:?} to {:?}",
+            self.node_id, seq, targets
+        );
+
+        let _ = node.broadcast_signed(message, targets.into_iter());
+    }
+
+    /// Process a checkpoint message received from another replica (or ourselves).
+    /// Above the sequence number window we only keep the latest vote of each member,
+    /// so the votes we keep are bounded
+    pub fn process_message(
+        &mut self,
+        message: ShareableMessage<PBFTMessage<RQ>>,
+        view: &ViewInfo,
+        window: &SeqNoWindow,
+    ) -> Result<CheckpointStatus<RQ>> {
+        let sender = message.header().from();
+        let checkpoint = message.message().checkpoint();
+
+        let seq = checkpoint.sequence_number();
+
+        if self.stable.is_some() && seq <= self.stable_seq() {
+            return Ok(CheckpointStatus::Ignored);
+        }
+
+        if !view.quorum_members().contains(&sender) {
+            warn!(
+                "{:?} // Received checkpoint message from {:?}, which is not a member of the quorum {:?}",
+                self.node_id,
+                sender,
+                view.quorum_members()
+            );
+
+            return Ok(CheckpointStatus::Ignored);
+        }
+
+        if window.is_above(seq) {
+            // We have fallen behind, but must still learn when the quorum moves on, as
+            // our state transfer will be anchored on that checkpoint
+            let previous = self
+                .votes
+                .iter()
+                .find(|(vote_seq, votes)| {
+                    window.is_above(**vote_seq) && votes.contains_key(&sender)
+                })
+                .map(|(vote_seq, _)| *vote_seq);
+
+            match previous {
+                Some(previous) if previous > seq => {
+                    debug!(
+                        "{:?} // Ignoring checkpoint message for {:?} from {:?}, above our high watermark {:?} and older than its vote for {:?}",
+                        self.node_id,
+                        seq,
+                        sender,
+                        window.high_watermark(),
+                        previous
+                    );
+
+                    return Ok(CheckpointStatus::Ignored);
+                }
+                Some(previous) if previous < seq => {
+                    if let Some(votes) = self.votes.get_mut(&previous) {
+                        votes.remove(&sender);
+
+                        if votes.is_empty() {
+                            self.votes.remove(&previous);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let votes = self.votes.entry(seq).or_default();
+
+        if votes.contains_key(&sender) {
+            return Err!(CheckpointError::DuplicateVote(sender, seq));
+        }
+
+        let digest = *checkpoint.digest();
+
+        votes.insert(sender, message);
+
+        let matching = votes
+            .values()
+            .filter(|vote| *vote.message().checkpoint().digest() == digest)
+            .count();
+
+        if matching < view.params().quorum() {
+            return Ok(CheckpointStatus::Pending);
+        }
+
+        let messages = votes
+            .values()
+            .filter(|vote| *vote.message().checkpoint().digest() == digest)
+            .cloned()
+            .collect();
+
+        let certificate = CheckpointCertificate {
+            seq,
+            digest,
+            messages,
+        };
+
+        if let Some(local_digest) = self.local.get(&seq) {
+            if *local_digest != digest {
+                error!("{:?} // Our local checkpoint {:?} has digest {:?}, which differs from the stable digest {:?}",
+                    self.node_id, seq, local_digest, digest);
+            }
+        }
+
+        self.install_stable_checkpoint(certificate.clone());
+
+        Ok(CheckpointStatus::Stable(certificate))
+    }
+
+    /// Adopt a stable checkpoint certificate received from a peer, if it is ahead of ours.
+    /// The signatures of its messages have already been verified when it was received
+    pub fn adopt_certificate(
+        &mut self,
+        certificate: CheckpointCertificate<RQ>,
+        view: &ViewInfo,
+    ) -> Result<CheckpointStatus<RQ>> {
+        let seq = certificate.sequence_number();
+
+        if self.stable.is_some() && seq <= self.stable_seq() {
+            return Ok(CheckpointStatus::Ignored);
+        }
+
+        verify_certificate(&certificate, view)?;
+
+        self.install_stable_checkpoint(certificate.clone());
+
+        Ok(CheckpointStatus::Stable(certificate))
+    }
+
+    /// Install a stable checkpoint, discarding every message which
+    /// referred to a checkpoint that is not ahead of it
+    fn install_stable_checkpoint(&mut self, certificate: CheckpointCertificate<RQ>) {
+        let seq = certificate.sequence_number();
+
+        info!(
+            "{:?} // Checkpoint {:?} is now stable with digest {:?}",
+            self.node_id,
+            seq,
+            certificate.digest()
+        );
+
+        self.votes = self.votes.split_off(&seq.next());
+        // The digest of our state at the stable checkpoint is kept, so we can
+        // anchor a state transfer on it
+        self.local = self.local.split_off(&seq);
+
+        self.stable = Some(certificate);
+
+        metric_increment(STABLE_CHECKPOINTS_ID, Some(1));
+    }
+}
+
+/// Verify that a certificate is made up of a quorum of checkpoint messages, sent by distinct
+/// members of the quorum, which agree on its sequence number and digest
+fn verify_certificate<RQ>(certificate: &CheckpointCertificate<RQ>, view: &ViewInfo) -> Result<()> {
+    let seq = certificate.sequence_number();
+
+    let mut senders = BTreeSet::new();
+
+    for message in certificate.messages() {
+        let sender = message.header().from();
+
+        let PBFTMessage::Checkpoint(checkpoint) = message.message() else {
+            return Err!(CheckpointError::CertificateMismatch(seq, sender));
+        };
+
+        if checkpoint.sequence_number() != seq || checkpoint.digest() != certificate.digest() {
+            return Err!(CheckpointError::CertificateMismatch(seq, sender));
+        }
+
+        if !view.quorum_members().contains(&sender) || !senders.insert(sender) {
+            return Err!(CheckpointError::CertificateMismatch(seq, sender));
+        }
+    }
+
+    if senders.len() < view.params().quorum() {
+        return Err!(CheckpointError::CertificateTooSmall(seq, senders.len()));
+    }
+
+    Ok(())
+}
+
+#[derive(Error, Debug)]
+pub enum CheckpointError {
+    #[error("Node {0:?} has already sent a checkpoint message for {1:?}")]
+    DuplicateVote(NodeId, SeqNo),
+    #[error("Cannot install {0:?}, beyond our window, as our stable checkpoint is {1:?}")]
+    NotCertified(SeqNo, SeqNo),
+    #[error("Our state at the stable checkpoint {0:?} does not match its certificate")]
+    StateNotCertified(SeqNo),
+    #[error("The certificate for {0:?} contains an invalid or repeated vote from {1:?}")]
+    CertificateMismatch(SeqNo, NodeId),
+    #[error("The certificate for {0:?} only contains {1} votes")]
+    CertificateTooSmall(SeqNo, usize),
+}
+
+#[cfg(test)]
+mod checkpoint_tests {
+    use crate::bft::fixtures::{digest, shared};
+    use crate::bft::log::decided::DecisionLog;
+    use crate::bft::log::decisions::{Proof, ProofMetadata};
+
+    use super::*;
+
+    fn checkpoint(from: u32, seq: u32, state: u8) -> ShareableMessage<PBFTMessage<u8>> {
+        checkpoint_in_view(from, seq, state, 0, LeaderElection::default())
+    }
+
+    fn checkpoint_in_view(
+        from: u32,
+        seq: u32,
+        state: u8,
+        view: u32,
+        election: LeaderElection,
+    ) -> ShareableMessage<PBFTMessage<u8>> {
+        shared(
+            NodeId::from(from),
+            digest(seq as u8),
+            PBFTMessage::Checkpoint(CheckpointMessage::new(
+                SeqNo::from(seq),
+                digest(state),
+                SeqNo::from(view),
+                election,
+            )),
+        )
+    }
+
+    fn view() -> ViewInfo {
+        ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap()
+    }
+
+    fn tracker() -> CheckpointTracker<u8> {
+        CheckpointTracker::new(NodeId::from(0u32), CheckpointChannel::new())
+    }
+
+    fn certificate(
+        seq: u32,
+        votes: Vec<ShareableMessage<PBFTMessage<u8>>>,
+    ) -> CheckpointCertificate<u8> {
+        CheckpointCertificate {
+            seq: SeqNo::from(seq),
+            digest: digest(1),
+            messages: votes,
+        }
+    }
+
+    fn window() -> SeqNoWindow {
+        SeqNoWindow::new(SeqNo::ZERO, Some(100))
+    }
+
+    fn process(
+        tracker: &mut CheckpointTracker<u8>,
+        message: ShareableMessage<PBFTMessage<u8>>,
+    ) -> CheckpointStatus<u8> {
+        tracker
+            .process_message(message, &view(), &window())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_quorum_of_matching_digests_forms_a_certificate() {
+        let mut tracker = tracker();
+
+        assert!(matches!(
+            process(&mut tracker, checkpoint(0, 10, 1)),
+            CheckpointStatus::Pending
+        ));
+        assert!(matches!(
+            process(&mut tracker, checkpoint(1, 10, 1)),
+            CheckpointStatus::Pending
+        ));
+        // A diverging digest does not count towards the certificate
+        assert!(matches!(
+            process(&mut tracker, checkpoint(2, 10, 2)),
+            CheckpointStatus::Pending
+        ));
+
+        let CheckpointStatus::Stable(certificate) = process(&mut tracker, checkpoint(3, 10, 1))
+        else {
+            panic!("A quorum of matching digests must form a stable checkpoint");
+        };
+
+        assert!(certificate.certifies(SeqNo::from(10u32), &digest(1)));
+        assert!(!certificate.certifies(SeqNo::from(10u32), &digest(2)));
+        assert_eq!(certificate.messages().len(), 3);
+        assert_eq!(tracker.stable_seq(), SeqNo::from(10u32));
+
+        // Late votes for the stable checkpoint are no longer needed
+        assert!(matches!(
+            process(&mut tracker, checkpoint(2, 10, 1)),
+            CheckpointStatus::Ignored
+        ));
+    }
+
+    #[test]
+    fn test_stable_checkpoint_discards_older_votes() {
+        let mut tracker = tracker();
+
+        process(&mut tracker, checkpoint(0, 5, 1));
+        process(&mut tracker, checkpoint(0, 20, 1));
+
+        for from in 0..3 {
+            process(&mut tracker, checkpoint(from, 10, 1));
+        }
+
+        assert_eq!(tracker.stable_seq(), SeqNo::from(10u32));
+        assert_eq!(
+            tracker.votes.keys().copied().collect::<Vec<_>>(),
+            vec![SeqNo::from(20u32)]
+        );
+    }
+
+    #[test]
+    fn test_rejects_duplicates_and_outsiders() {
+        let mut tracker = tracker();
+
+        process(&mut tracker, checkpoint(1, 10, 1));
+
+        assert!(tracker
+            .process_message(checkpoint(1, 10, 2), &view(), &window())
+            .is_err());
+
+        assert!(matches!(
+            process(&mut tracker, checkpoint(7, 10, 1)),
+            CheckpointStatus::Ignored
+        ));
+    }
+
+    #[test]
+    fn test_keeps_only_the_latest_vote_of_each_member_above_the_window() {
+        let mut tracker = tracker();
+
+        process(&mut tracker, checkpoint(1, 110, 1));
+        process(&mut tracker, checkpoint(1, 120, 1));
+
+        // An older vote above the window no longer replaces the latest one
+        assert!(matches!(
+            process(&mut tracker, checkpoint(1, 110, 1)),
+            CheckpointStatus::Ignored
+        ));
+        assert_eq!(
+            tracker.votes.keys().copied().collect::<Vec<_>>(),
+            vec![SeqNo::from(120u32)]
+        );
+
+        process(&mut tracker, checkpoint(2, 120, 1));
+
+        // A replica that has fallen behind still learns of the checkpoints of the quorum
+        let CheckpointStatus::Stable(certificate) = process(&mut tracker, checkpoint(3, 120, 1))
+        else {
+            panic!("A quorum of votes above the window must form a stable checkpoint");
+        };
+
+        assert!(certificate.certifies(SeqNo::from(120u32), &digest(1)));
+    }
+
+    #[test]
+    fn test_adopts_valid_certificates_from_peers() {
+        let mut tracker = tracker();
+
+        let votes = (1..=3).map(|from| checkpoint(from, 50, 1)).collect();
+
+        assert!(matches!(
+            tracker.adopt_certificate(certificate(50, votes), &view()),
+            Ok(CheckpointStatus::Stable(_))
+        ));
+        assert_eq!(tracker.stable_seq(), SeqNo::from(50u32));
+
+        // An older certificate does not replace ours
+        let votes = (1..=3).map(|from| checkpoint(from, 40, 1)).collect();
+
+        assert!(matches!(
+            tracker.adopt_certificate(certificate(40, votes), &view()),
+            Ok(CheckpointStatus::Ignored)
+        ));
+    }
+
+    #[test]
+    fn test_rejects_invalid_certificates() {
+        let mut tracker = tracker();
+
+        let too_small = (1..=2).map(|from| checkpoint(from, 50, 1)).collect();
+        let repeated = vec![
+            checkpoint(1, 50, 1),
+            checkpoint(1, 50, 1),
+            checkpoint(2, 50, 1),
+        ];
+        let diverging = vec![
+            checkpoint(1, 50, 1),
+            checkpoint(2, 50, 2),
+            checkpoint(3, 50, 1),
+        ];
+        let outsider = vec![
+            checkpoint(1, 50, 1),
+            checkpoint(2, 50, 1),
+            checkpoint(7, 50, 1),
+        ];
+        let wrong_seq = vec![
+            checkpoint(1, 50, 1),
+            checkpoint(2, 50, 1),
+            checkpoint(3, 49, 1),
+        ];
+
+        for votes in [too_small, repeated, diverging, outsider, wrong_seq] {
+            assert!(tracker
+                .adopt_certificate(certificate(50, votes), &view())
+                .is_err());
+        }
+
+        assert!(tracker.stable_checkpoint().is_none());
+    }
+
+    #[test]
+    fn test_certificate_vouches_for_the_latest_election_reported_by_f_plus_one() {
+        use crate::bft::sync::view::election::{LeaderElectionPolicy, StableLeader};
+
+        let members = NodeId::targets_u32(0..4).collect::<Vec<_>>();
+
+        let mut agreed = LeaderElection::StableLeader(StableLeader::default());
+        agreed.decided(SeqNo::from(3u32), NodeId::from(2u32), &members);
+
+        // A single (possibly faulty) replica claims a later view
+        let votes = vec![
+            checkpoint_in_view(0, 50, 1, 3, agreed.clone()),
+            checkpoint_in_view(1, 50, 1, 3, agreed.clone()),
+            checkpoint_in_view(2, 50, 1, 2, LeaderElection::default()),
+            checkpoint_in_view(3, 50, 1, 4, LeaderElection::default()),
+        ];
+
+        let certificate = certificate(50, votes);
+
+        assert_eq!(
+            certificate.agreed_election(1),
+            Some((SeqNo::from(3u32), &agreed))
+        );
+        assert_eq!(certificate.agreed_election(2), None);
+    }
+
+    #[test]
+    fn test_keeps_the_local_digest_of_the_stable_checkpoint() {
+        let mut tracker = tracker();
+
+        tracker.local.insert(SeqNo::from(5u32), digest(1));
+        tracker.local.insert(SeqNo::from(10u32), digest(1));
+
+        for from in 0..3 {
+            process(&mut tracker, checkpoint(from, 10, 1));
+        }
+
+        let certificate = tracker.stable_checkpoint().unwrap();
+
+        assert!(tracker.local_digest(SeqNo::from(5u32)).is_none());
+        assert!(certificate.certifies(
+            SeqNo::from(10u32),
+            tracker.local_digest(SeqNo::from(10u32)).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_notifier_reports_local_checkpoints() {
+        let tracker = tracker();
+
+        let notifier = tracker.notifier();
+
+        notifier.checkpoint_taken(SeqNo::from(10u32), digest(1));
+        notifier.checkpoint_taken(SeqNo::from(20u32), digest(2));
+
+        assert_eq!(
+            tracker.take_local_checkpoints(),
+            vec![
+                (SeqNo::from(10u32), digest(1)),
+                (SeqNo::from(20u32), digest(2))
+            ]
+        );
+        assert!(tracker.take_local_checkpoints().is_empty());
+    }
+
+    #[test]
+    fn test_garbage_collection_keeps_the_latest_decision() {
+        let proof = |seq: u32| {
+            Proof::<u8>::new(
+                ProofMetadata::new(SeqNo::from(seq), digest(seq as u8), Vec::new(), 0),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+            )
+        };
+
+        let mut log = DecisionLog::init(None, window());
+
+        for seq in 1..=5 {
+            log.append_proof(proof(seq)).unwrap();
+        }
+
+        assert_eq!(log.garbage_collect(SeqNo::from(3u32)), 3);
+        assert_eq!(log.retained_decisions(), 2);
+
+        assert_eq!(log.garbage_collect(SeqNo::from(10u32)), 1);
+        assert_eq!(log.retained_decisions(), 1);
+        assert_eq!(log.last_execution(), Some(SeqNo::from(5u32)));
+    }
+
+    #[test]
+    fn test_decision_log_is_bounded_by_the_window() {
+        let proof = |seq: u32| {
+            Proof::<u8>::new(
+                ProofMetadata::new(SeqNo::from(seq), digest(seq as u8), Vec::new(), 0),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+            )
+        };
+
+        let mut log = DecisionLog::init(None, SeqNoWindow::new(SeqNo::ZERO, Some(3)));
+
+        for seq in 1..=3 {
+            log.append_proof(proof(seq)).unwrap();
+        }
+
+        // Without a stable checkpoint, nothing past the high watermark is accepted
+        assert!(log.append_proof(proof(4)).is_err());
+        assert_eq!(log.retained_decisions(), 3);
+
+        // A stable checkpoint moves the window along
+        log.garbage_collect(SeqNo::from(2u32));
+
+        log.append_proof(proof(4)).unwrap();
+        log.append_proof(proof(5)).unwrap();
+
+        assert!(log.append_proof(proof(6)).is_err());
+        assert_eq!(log.first_retained(), Some(SeqNo::from(3u32)));
+    }
+}
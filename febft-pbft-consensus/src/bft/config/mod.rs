@@ -1,19 +1,330 @@
 use serde::Deserialize;
 use std::time::Duration;
 
+use crate::bft::checkpoint::CheckpointChannel;
+
 #[derive(Debug, Deserialize)]
 pub struct PBFTConfig {
     pub timeout_dur: Duration,
     pub proposer_config: ProposerConfig,
     pub watermark: u32,
+    /// The size `k` of the sequence number window `[h, h + k]`, where `h` is
+    /// the sequence number of the last stable checkpoint.
+    /// The window only advances if the execution layer reports its checkpoints through
+    /// the protocol's checkpoint notifier; otherwise the proposer stops once it fills.
+    /// When `None`, the window is unbounded
+    pub log_window: Option<u32>,
+    /// The limits on the messages we buffer for future instances and views.
+    /// When `None`, the default limits are used
+    pub message_buffer: Option<MessageBufferConfig>,
+    /// The per-peer rate limits for each kind of message.
+    /// When `None`, the default limits are used
+    pub rate_limits: Option<RateLimitConfig>,
+    /// How prepares are authenticated.
+    /// When `None`, they are signed
+    pub authentication: Option<AuthenticationMode>,
+    /// Have the leader propose empty batches while idle, so that replicas can
+    /// detect a crashed leader without client traffic. When `None`, a crashed
+    /// leader is only detected once a client request times out
+    pub heartbeats: Option<HeartbeatConfig>,
+    /// Vote to replace leaders whose throughput or proposal latency falls
+    /// below the expected performance. When `None`, slow leaders are tolerated
+    pub leader_monitor: Option<LeaderMonitorConfig>,
+    /// How the leader of each view is elected.
+    /// When `None`, leadership goes round robin over the quorum members
+    pub leader_election: Option<LeaderElectionMode>,
+    /// Hand leadership over to the next view every so many decisions, even if the
+    /// leader did not fail. When `None`, the leader only changes through view changes
+    pub leader_rotation: Option<LeaderRotationConfig>,
+    /// Grow the view change timeouts on each consecutive failed view change.
+    /// When `None`, they stay at `timeout_dur`
+    pub view_change_backoff: Option<ViewChangeBackoffConfig>,
+    /// The channel through which the execution layer and the state transfer protocol report
+    /// their checkpoints, when its notifier must be handed out before the protocol is built.
+    /// When `None`, the protocol creates its own, available through `checkpoint_notifier`
+    #[serde(skip)]
+    pub checkpoint_channel: Option<CheckpointChannel>,
 }
 
 impl PBFTConfig {
+    /// A configuration with the given mandatory parameters, leaving every optional
+    /// feature at its default. Use the `with_` methods to enable them
     pub fn new(timeout_dur: Duration, watermark: u32, proposer_config: ProposerConfig) -> Self {
         Self {
             timeout_dur,
             proposer_config,
             watermark,
+            log_window: None,
+            message_buffer: None,
+            rate_limits: None,
+            authentication: None,
+            heartbeats: None,
+            leader_monitor: None,
+            leader_election: None,
+            leader_rotation: None,
+            view_change_backoff: None,
+            checkpoint_channel: None,
+        }
+    }
+
+    pub fn with_log_window(mut self, log_window: u32) -> Self {
+        self.log_window = Some(log_window);
+        self
+    }
+
+    pub fn with_message_buffer(mut self, message_buffer: MessageBufferConfig) -> Self {
+        self.message_buffer = Some(message_buffer);
+        self
+    }
+
+    pub fn with_rate_limits(mut self, rate_limits: RateLimitConfig) -> Self {
+        self.rate_limits = Some(rate_limits);
+        self
+    }
+
+    pub fn with_authentication(mut self, authentication: AuthenticationMode) -> Self {
+        self.authentication = Some(authentication);
+        self
+    }
+
+    pub fn with_heartbeats(mut self, heartbeats: HeartbeatConfig) -> Self {
+        self.heartbeats = Some(heartbeats);
+        self
+    }
+
+    pub fn with_leader_monitor(mut self, leader_monitor: LeaderMonitorConfig) -> Self {
+        self.leader_monitor = Some(leader_monitor);
+        self
+    }
+
+    pub fn with_leader_election(mut self, leader_election: LeaderElectionMode) -> Self {
+        self.leader_election = Some(leader_election);
+        self
+    }
+
+    pub fn with_leader_rotation(mut self, leader_rotation: LeaderRotationConfig) -> Self {
+        self.leader_rotation = Some(leader_rotation);
+        self
+    }
+
+    pub fn with_view_change_backoff(mut self, backoff: ViewChangeBackoffConfig) -> Self {
+        self.view_change_backoff = Some(backoff);
+        self
+    }
+
+    pub fn with_checkpoint_channel(mut self, checkpoint_channel: CheckpointChannel) -> Self {
+        self.checkpoint_channel = Some(checkpoint_channel);
+        self
+    }
+}
+
+/// The null request heartbeats of the leader and the liveness timer of the other replicas
+#[derive(Debug, Clone, Deserialize)]
+pub struct HeartbeatConfig {
+    /// How long the leader stays idle before proposing an empty batch
+    pub idle_interval: Duration,
+    /// How long replicas wait for a pre prepare from the leader before starting a view change.
+    /// Should be comfortably above `idle_interval`
+    pub leader_timeout: Duration,
+}
+
+impl HeartbeatConfig {
+    pub fn new(idle_interval: Duration, leader_timeout: Duration) -> Self {
+        Self {
+            idle_interval,
+            leader_timeout,
+        }
+    }
+}
+
+/// The monitoring of the performance of the leader, evaluated over windows of consecutive
+/// saturated decisions (whose batches reach the target batch size) and compared against
+/// the best leader of the recent past windows
+#[derive(Debug, Clone, Deserialize)]
+pub struct LeaderMonitorConfig {
+    /// The fraction (between 0 and 1) of the expected performance under which the leader
+    /// is considered slow: either its throughput falls below `threshold` times the expected
+    /// throughput or its proposal latency rises above the expected latency over `threshold`
+    pub threshold: f64,
+    /// The amount of decisions in each evaluation window
+    pub window: u32,
+    /// The amount of past windows the best leader is taken from
+    pub history: u32,
+}
+
+impl LeaderMonitorConfig {
+    pub fn new(threshold: f64, window: u32, history: u32) -> Self {
+        Self {
+            threshold,
+            window,
+            history,
+        }
+    }
+}
+
+/// The planned rotation of the leader. The view changes at every sequence number which is
+/// a multiple of `every`, without running the view change protocol
+#[derive(Debug, Clone, Deserialize)]
+pub struct LeaderRotationConfig {
+    /// How many decisions each leader gets before handing over to the next view.
+    /// The hand offs happen at the sequence numbers which are multiples of it,
+    /// so a leader installed by a view change may get fewer. Rotation is off when 0
+    pub every: u32,
+    /// How long replicas wait for a pre prepare from the leader taking over before
+    /// falling back to a regular view change
+    pub handoff_timeout: Duration,
+}
+
+impl LeaderRotationConfig {
+    pub fn new(every: u32, handoff_timeout: Duration) -> Self {
+        Self {
+            every,
+            handoff_timeout,
+        }
+    }
+}
+
+/// The exponential backoff of the view change timeouts (the client request timeouts which
+/// start view changes and the timeouts of the view changes themselves). Each consecutive
+/// view change which fails to produce a view that decides something multiplies the timeout
+/// by `multiplier`, up to `cap`. The timeout goes back to `base` once a view makes progress
+#[derive(Debug, Clone, Deserialize)]
+pub struct ViewChangeBackoffConfig {
+    /// The timeout while views are making progress. Replaces `timeout_dur`
+    pub base: Duration,
+    pub cap: Duration,
+    pub multiplier: f64,
+}
+
+impl ViewChangeBackoffConfig {
+    pub fn new(base: Duration, cap: Duration, multiplier: f64) -> Self {
+        Self {
+            base,
+            cap,
+            multiplier,
+        }
+    }
+
+    /// The timeout after the given amount of consecutive failed view changes
+    pub fn timeout(&self, failed_view_changes: u32) -> Duration {
+        let cap = self.cap.max(self.base);
+
+        let factor = self
+            .multiplier
+            .max(1.0)
+            .powi(failed_view_changes.min(i32::MAX as u32) as i32);
+
+        Duration::try_from_secs_f64(self.base.as_secs_f64() * factor)
+            .map_or(cap, |timeout| timeout.min(cap))
+    }
+}
+
+/// The policies which can elect the leader of each view. All replicas must use the same one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum LeaderElectionMode {
+    /// The leader of view `v` is the quorum member `v mod n`
+    #[default]
+    RoundRobin,
+    /// Round robin, passing over the leaders of the last `memory` views which decided nothing
+    Reputation { memory: u32 },
+    /// The leader keeps its post until a view change hands it over to the next quorum member
+    StableLeader,
+}
+
+/// The ways in which prepare and commit messages can be authenticated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum AuthenticationMode {
+    /// Every message is signed
+    #[default]
+    Signatures,
+    /// Prepares carry a vector of MACs, computed with pairwise session keys.
+    /// Pre prepares and commits are still signed, as the signed commits are what keeps
+    /// decided proofs transferable in view changes, log replay and state transfer
+    MacVectors,
+}
+
+/// The limits on the amount of messages (and their size in bytes) that
+/// we are willing to buffer for future consensus instances and views
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageBufferConfig {
+    pub per_peer_count: usize,
+    pub per_peer_bytes: usize,
+    pub global_count: usize,
+    pub global_bytes: usize,
+}
+
+impl MessageBufferConfig {
+    pub fn new(
+        per_peer_count: usize,
+        per_peer_bytes: usize,
+        global_count: usize,
+        global_bytes: usize,
+    ) -> Self {
+        Self {
+            per_peer_count,
+            per_peer_bytes,
+            global_count,
+            global_bytes,
+        }
+    }
+}
+
+impl Default for MessageBufferConfig {
+    fn default() -> Self {
+        Self {
+            per_peer_count: 10_000,
+            per_peer_bytes: 64 * 1024 * 1024,
+            global_count: 50_000,
+            global_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+/// The configuration of a token bucket: it holds at most `capacity`
+/// tokens and is refilled with `refill_per_sec` tokens every second
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenBucketConfig {
+    pub capacity: u32,
+    pub refill_per_sec: u32,
+}
+
+impl TokenBucketConfig {
+    pub fn new(capacity: u32, refill_per_sec: u32) -> Self {
+        Self {
+            capacity,
+            refill_per_sec,
+        }
+    }
+}
+
+/// The rate limits applied to each peer, by message kind
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimitConfig {
+    pub pre_prepare: TokenBucketConfig,
+    pub prepare: TokenBucketConfig,
+    pub commit: TokenBucketConfig,
+    pub stop: TokenBucketConfig,
+    pub stop_data: TokenBucketConfig,
+    pub sync: TokenBucketConfig,
+    pub session_key: TokenBucketConfig,
+    pub log_replay: TokenBucketConfig,
+    pub checkpoint: TokenBucketConfig,
+    pub fetch_requests: TokenBucketConfig,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            pre_prepare: TokenBucketConfig::new(2_000, 1_000),
+            prepare: TokenBucketConfig::new(2_000, 1_000),
+            commit: TokenBucketConfig::new(2_000, 1_000),
+            stop: TokenBucketConfig::new(100, 10),
+            stop_data: TokenBucketConfig::new(50, 5),
+            sync: TokenBucketConfig::new(50, 5),
+            session_key: TokenBucketConfig::new(5, 1),
+            log_replay: TokenBucketConfig::new(10, 1),
+            checkpoint: TokenBucketConfig::new(50, 5),
+            fetch_requests: TokenBucketConfig::new(50, 5),
         }
     }
 }
@@ -24,9 +335,24 @@ pub struct ProposerConfig {
     pub max_batch_size: u64,
     pub batch_timeout: u64,
     pub processing_threads: u32,
+    /// The maximum size, in bytes, of the requests contained in a single batch.
+    /// Requests over this limit are rejected, as are the pre prepares of other leaders
+    /// that exceed it. When `None`, batches are only limited by count
+    pub max_batch_bytes: Option<u64>,
+    /// Tune the target batch size and batch timeout online.
+    /// When `None`, the values above are used as static thresholds
+    pub adaptive_batching: Option<AdaptiveBatchingConfig>,
+    /// The share of each batch every client is entitled to.
+    /// When `None`, clients are served in a round robin, without quotas
+    pub fairness: Option<FairnessConfig>,
+    /// Split requests into priority lanes, drained from the highest.
+    /// When `None`, all requests share a single lane
+    pub priority_lanes: Option<PriorityLanesConfig>,
 }
 
 impl ProposerConfig {
+    /// A configuration with the given batching thresholds, leaving every optional
+    /// feature at its default. Use the `with_` methods to enable them
     pub fn new(
         target_batch_size: u64,
         max_batch_size: u64,
@@ -38,6 +364,167 @@ impl ProposerConfig {
             max_batch_size,
             batch_timeout,
             processing_threads,
+            max_batch_bytes: None,
+            adaptive_batching: None,
+            fairness: None,
+            priority_lanes: None,
+        }
+    }
+
+    pub fn with_max_batch_bytes(mut self, max_batch_bytes: u64) -> Self {
+        self.max_batch_bytes = Some(max_batch_bytes);
+        self
+    }
+
+    pub fn with_adaptive_batching(mut self, adaptive_batching: AdaptiveBatchingConfig) -> Self {
+        self.adaptive_batching = Some(adaptive_batching);
+        self
+    }
+
+    pub fn with_fairness(mut self, fairness: FairnessConfig) -> Self {
+        self.fairness = Some(fairness);
+        self
+    }
+
+    pub fn with_priority_lanes(mut self, priority_lanes: PriorityLanesConfig) -> Self {
+        self.priority_lanes = Some(priority_lanes);
+        self
+    }
+}
+
+/// The priority lanes of the proposer. Requests are assigned a lane by the
+/// request classifier installed in the protocol (lane 0, if none is installed)
+#[derive(Debug, Clone, Deserialize)]
+pub struct PriorityLanesConfig {
+    pub lanes: u32,
+    /// The share of each batch (between 0 and 1) which is reserved
+    /// for the lower lanes, while they have pending requests
+    pub lower_lanes_share: f64,
+    /// The batch timeout, in microseconds, while requests above lane 0 are waiting
+    pub urgent_batch_timeout: u64,
+}
+
+impl PriorityLanesConfig {
+    pub fn new(lanes: u32, lower_lanes_share: f64, urgent_batch_timeout: u64) -> Self {
+        Self {
+            lanes,
+            lower_lanes_share,
+            urgent_batch_timeout,
+        }
+    }
+}
+
+/// How batches are shared between clients. Each client gets `weight` requests per
+/// turn of the round robin, with at most `quota` requests in a single batch.
+/// Clients which are not listed get a weight of 1 and the `default_quota`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FairnessConfig {
+    /// When `None`, clients are only limited by the size of the batch
+    pub default_quota: Option<u32>,
+    #[serde(default)]
+    pub clients: Vec<ClientShareConfig>,
+}
+
+impl FairnessConfig {
+    pub fn new(default_quota: Option<u32>, clients: Vec<ClientShareConfig>) -> Self {
+        Self {
+            default_quota,
+            clients,
+        }
+    }
+}
+
+/// The share of the batches given to a single client, identified by its node id
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClientShareConfig {
+    pub client: u32,
+    pub weight: Option<u32>,
+    pub quota: Option<u32>,
+}
+
+impl ClientShareConfig {
+    pub fn new(client: u32, weight: Option<u32>, quota: Option<u32>) -> Self {
+        Self {
+            client,
+            weight,
+            quota,
+        }
+    }
+}
+
+/// The bounds and parameters of the adaptive batching controller.
+/// The target batch size is bounded by `min_batch_size` and the `max_batch_size`
+/// of the proposer, while the batch timeout (in microseconds) is bounded by
+/// `min_batch_timeout` and `max_batch_timeout`
+#[derive(Debug, Clone, Deserialize)]
+pub struct AdaptiveBatchingConfig {
+    /// The decision latency we want to stay under, in microseconds
+    pub latency_target: u64,
+    pub min_batch_size: u64,
+    pub min_batch_timeout: u64,
+    pub max_batch_timeout: u64,
+    /// How many requests are added to the target batch size on each increase
+    pub additive_increase: u64,
+    /// The factor by which the target batch size and timeout are multiplied on each decrease
+    pub multiplicative_decrease: f64,
+}
+
+impl AdaptiveBatchingConfig {
+    pub fn new(
+        latency_target: u64,
+        min_batch_size: u64,
+        min_batch_timeout: u64,
+        max_batch_timeout: u64,
+        additive_increase: u64,
+        multiplicative_decrease: f64,
+    ) -> Self {
+        Self {
+            latency_target,
+            min_batch_size,
+            min_batch_timeout,
+            max_batch_timeout,
+            additive_increase,
+            multiplicative_decrease,
         }
     }
 }
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_with_failed_view_changes() {
+        let backoff =
+            ViewChangeBackoffConfig::new(Duration::from_secs(1), Duration::from_secs(10), 2.0);
+
+        assert_eq!(backoff.timeout(0), Duration::from_secs(1));
+        assert_eq!(backoff.timeout(1), Duration::from_secs(2));
+        assert_eq!(backoff.timeout(3), Duration::from_secs(8));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let backoff =
+            ViewChangeBackoffConfig::new(Duration::from_secs(1), Duration::from_secs(10), 2.0);
+
+        assert_eq!(backoff.timeout(4), Duration::from_secs(10));
+        assert_eq!(backoff.timeout(u32::MAX), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn backoff_never_shrinks_the_timeout() {
+        // A multiplier below 1 would shrink the timeout, so it is taken as 1
+        let shrinking =
+            ViewChangeBackoffConfig::new(Duration::from_secs(2), Duration::from_secs(10), 0.5);
+
+        assert_eq!(shrinking.timeout(3), Duration::from_secs(2));
+
+        // A cap below the base is raised to it
+        let low_cap =
+            ViewChangeBackoffConfig::new(Duration::from_secs(2), Duration::from_secs(1), 2.0);
+
+        assert_eq!(low_cap.timeout(0), Duration::from_secs(2));
+        assert_eq!(low_cap.timeout(5), Duration::from_secs(2));
+    }
+}
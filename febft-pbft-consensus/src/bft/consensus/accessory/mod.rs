@@ -5,6 +5,7 @@ use std::sync::Arc;
 use atlas_core::ordering_protocol::networking::OrderProtocolSendNode;
 
 use crate::bft::consensus::accessory::replica::ReplicaAccessory;
+use crate::bft::consensus::validity::InvalidBatchProof;
 use crate::bft::log::deciding::WorkingDecisionLog;
 use crate::bft::message::serialize::PBFTConsensus;
 use crate::bft::message::ConsensusMessage;
@@ -35,7 +36,9 @@ where
     ) where
         NT: OrderProtocolSendNode<RQ, PBFTConsensus<RQ>> + 'static;
 
-    /// Handle the prepare phase having been completed
+    /// Handle the prepare phase having been completed.
+    /// Returns the proof against the leader whose proposal is invalid, in which case
+    /// we must not vote for the batch
     fn handle_pre_prepare_phase_completed<NT>(
         &mut self,
         deciding_log: &WorkingDecisionLog<RQ>,
@@ -43,7 +46,8 @@ where
         header: &Header,
         msg: &ConsensusMessage<RQ>,
         node: &Arc<NT>,
-    ) where
+    ) -> Option<InvalidBatchProof<RQ>>
+    where
         NT: OrderProtocolSendNode<RQ, PBFTConsensus<RQ>> + 'static;
 
     /// Handle a prepare message processed during the preparing phase without having
@@ -123,13 +127,14 @@ where
         header: &Header,
         msg: &ConsensusMessage<RQ>,
         node: &Arc<NT>,
-    ) where
+    ) -> Option<InvalidBatchProof<RQ>>
+    where
         NT: OrderProtocolSendNode<RQ, PBFTConsensus<RQ>> + 'static,
     {
         match self {
-            ConsensusDecisionAccessory::Follower => {}
+            ConsensusDecisionAccessory::Follower => None,
             ConsensusDecisionAccessory::Replica(rep) => {
-                rep.handle_pre_prepare_phase_completed(deciding_log, view, header, msg, node);
+                rep.handle_pre_prepare_phase_completed(deciding_log, view, header, msg, node)
             }
         }
     }
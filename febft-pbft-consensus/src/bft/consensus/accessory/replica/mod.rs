@@ -3,8 +3,9 @@ use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 
-use tracing::debug;
+use tracing::{debug, warn};
 
+use atlas_common::crypto::hash::Digest;
 use atlas_common::node_id::NodeId;
 use atlas_common::ordering::{Orderable, SeqNo};
 use atlas_common::serialization_helper::SerMsg;
@@ -16,7 +17,11 @@ use atlas_communication::message::{
 use atlas_communication::reconfiguration::NetworkInformationProvider;
 use atlas_core::ordering_protocol::networking::OrderProtocolSendNode;
 
+use crate::bft::auth::MacAuthenticator;
 use crate::bft::consensus::accessory::AccessoryConsensus;
+use crate::bft::consensus::validity::{
+    self, AcceptAll, InvalidBatchProof, SharedValidityPredicate,
+};
 use crate::bft::log::deciding::WorkingDecisionLog;
 use crate::bft::message::{ConsensusMessage, ConsensusMessageKind, PBFTMessage};
 use crate::bft::sync::view::ViewInfo;
@@ -27,6 +32,10 @@ where
     RQ: SerMsg,
 {
     speculative_commits: Arc<Mutex<BTreeMap<NodeId, StoredSerializedMessage<SysMsg<RQ>>>>>,
+    /// When present, prepares are authenticated with MACs instead of signatures
+    authenticator: Option<Arc<MacAuthenticator>>,
+    /// The application predicate every proposal must satisfy before we prepare it
+    validity: SharedValidityPredicate<RQ>,
 }
 
 impl<RQ> AccessoryConsensus<RQ> for ReplicaAccessory<RQ>
@@ -52,62 +61,34 @@ where
         _header: &Header,
         _msg: &ConsensusMessage<RQ>,
         node: &Arc<NT>,
-    ) where
+    ) -> Option<InvalidBatchProof<RQ>>
+    where
         NT: OrderProtocolSendNode<RQ, PBFT<RQ>> + 'static,
     {
         let my_id = node.id();
-        let view_seq = view.sequence_number();
         let current_digest = deciding_log.current_digest().unwrap();
 
-        let key_pair = node.network_info_provider().get_key_pair().clone();
-        let n = view.params().n();
-
         let seq = deciding_log.sequence_number();
 
-        let speculative_commits = Arc::clone(&self.speculative_commits);
-
-        let node_clone = node.clone();
-
-        threadpool::execute(move || {
-            let message = PBFTMessage::Consensus(ConsensusMessage::new(
+        // Check the proposals with the application before vouching for them
+        if let Some(proof) = validity::validate_proposals(
+            my_id,
+            seq,
+            self.validity.as_ref(),
+            deciding_log.pre_prepares(),
+        ) {
+            warn!(
+                "{:?} // Not preparing {:?} as the batch proposed by {:?} is invalid: {}",
+                my_id,
                 seq,
-                view_seq,
-                ConsensusMessageKind::Commit(current_digest),
-            ));
-
-            let (message, digest) = node_clone.serialize_digest_message(message).unwrap();
-
-            let (message, buf) = message.into_inner();
-
-            for peer_id in NodeId::targets(0..n) {
-                let buf_clone = buf.clone();
-
-                // create header
-                let (header, _, _) = WireMessage::new(
-                    my_id,
-                    peer_id,
-                    MessageModule::Protocol,
-                    buf_clone,
-                    // NOTE: nonce not too important here,
-                    // since we already contain enough random
-                    // data with the unique digest of the
-                    // PRE-PREPARE message
-                    0,
-                    Some(digest),
-                    Some(&*key_pair),
-                )
-                .into_inner();
-
-                // store serialized header + message
-                let serialized = SerializedMessage::new(message.clone(), buf.clone());
-
-                let stored = StoredMessage::new(header, serialized);
+                proof.culprit(),
+                proof.reason()
+            );
 
-                let mut map = speculative_commits.lock().unwrap();
+            return Some(proof);
+        }
 
-                map.insert(peer_id, stored);
-            }
-        });
+        self.generate_speculative_commits(node, view, seq, current_digest);
 
         let targets = view.quorum_members().clone();
 
@@ -122,13 +103,15 @@ where
         // Also, since we can have # Leaders > f, if the leaders didn't partake in this
         // Instance we would have situations where faults joined with leaders would cause
         // Unresponsiveness
-        let message = PBFTMessage::Consensus(ConsensusMessage::new(
+        let message = ConsensusMessage::new(
             seq,
             view.sequence_number(),
             ConsensusMessageKind::Prepare(current_digest),
-        ));
+        );
+
+        self.broadcast_vote(message, targets, &**node);
 
-        let _ = node.broadcast_signed(message, targets.into_iter());
+        None
     }
 
     fn handle_preparing_no_quorum<NT>(
@@ -170,11 +153,11 @@ where
 
             let _ = node.broadcast_serialized(speculative_commits);
         } else {
-            let message = PBFTMessage::Consensus(ConsensusMessage::new(
+            let message = ConsensusMessage::new(
                 seq,
                 view.sequence_number(),
                 ConsensusMessageKind::Commit(current_digest),
-            ));
+            );
 
             debug!(
                 "{:?} // Broadcasting commit consensus message {:?}",
@@ -183,7 +166,9 @@ where
 
             let targets = view.quorum_members().clone();
 
-            let _ = node.broadcast_signed(message, targets.into_iter());
+            // Commits are always signed, so that the proofs they form can be
+            // verified by third parties
+            let _ = node.broadcast_signed(PBFTMessage::Consensus(message), targets.into_iter());
         }
 
         debug!(
@@ -225,7 +210,7 @@ where
     RQ: SerMsg,
 {
     fn default() -> Self {
-        Self::new()
+        Self::new(None, Arc::new(AcceptAll))
     }
 }
 
@@ -233,18 +218,118 @@ impl<RQ> ReplicaAccessory<RQ>
 where
     RQ: SerMsg,
 {
-    pub fn new() -> Self {
+    pub fn new(
+        authenticator: Option<Arc<MacAuthenticator>>,
+        validity: SharedValidityPredicate<RQ>,
+    ) -> Self {
         Self {
             speculative_commits: Arc::new(Mutex::new(BTreeMap::new())),
+            authenticator,
+            validity,
         }
     }
 
+    pub fn set_validity_predicate(&mut self, validity: SharedValidityPredicate<RQ>) {
+        self.validity = validity;
+    }
+
     fn take_speculative_commits(&self) -> BTreeMap<NodeId, StoredSerializedMessage<SysMsg<RQ>>> {
         let mut map = self.speculative_commits.lock().unwrap();
         std::mem::take(&mut *map)
     }
 }
 
+impl<RQ> ReplicaAccessory<RQ>
+where
+    RQ: SerMsg + 'static,
+{
+    /// Sign our commit for this instance in the background, such that it is
+    /// ready to be sent as soon as we have a prepare quorum
+    fn generate_speculative_commits<NT>(
+        &self,
+        node: &Arc<NT>,
+        view: &ViewInfo,
+        seq: SeqNo,
+        current_digest: Digest,
+    ) where
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>> + 'static,
+    {
+        let my_id = node.id();
+        let view_seq = view.sequence_number();
+
+        let key_pair = node.network_info_provider().get_key_pair().clone();
+        let n = view.params().n();
+
+        let speculative_commits = Arc::clone(&self.speculative_commits);
+
+        let node_clone = node.clone();
+
+        threadpool::execute(move || {
+            let message = PBFTMessage::Consensus(ConsensusMessage::new(
+                seq,
+                view_seq,
+                ConsensusMessageKind::Commit(current_digest),
+            ));
+
+            let (message, digest) = node_clone.serialize_digest_message(message).unwrap();
+
+            let (message, buf) = message.into_inner();
+
+            for peer_id in NodeId::targets(0..n) {
+                let buf_clone = buf.clone();
+
+                // create header
+                let (header, _, _) = WireMessage::new(
+                    my_id,
+                    peer_id,
+                    MessageModule::Protocol,
+                    buf_clone,
+                    // NOTE: nonce not too important here,
+                    // since we already contain enough random
+                    // data with the unique digest of the
+                    // PRE-PREPARE message
+                    0,
+                    Some(digest),
+                    Some(&*key_pair),
+                )
+                .into_inner();
+
+                // store serialized header + message
+                let serialized = SerializedMessage::new(message.clone(), buf.clone());
+
+                let stored = StoredMessage::new(header, serialized);
+
+                let mut map = speculative_commits.lock().unwrap();
+
+                map.insert(peer_id, stored);
+            }
+        });
+    }
+
+    /// Broadcast a prepare vote to the given targets, authenticating
+    /// it with MACs when possible and signing it otherwise
+    fn broadcast_vote<NT>(&self, mut message: ConsensusMessage<RQ>, targets: Vec<NodeId>, node: &NT)
+    where
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+    {
+        let authenticator = self
+            .authenticator
+            .as_ref()
+            .and_then(|authenticator| authenticator.authenticate(&message, &targets));
+
+        match authenticator {
+            Some(authenticator) => {
+                message.set_authenticator(authenticator);
+
+                let _ = node.broadcast(PBFTMessage::Consensus(message), targets.into_iter());
+            }
+            None => {
+                let _ = node.broadcast_signed(PBFTMessage::Consensus(message), targets.into_iter());
+            }
+        }
+    }
+}
+
 #[inline]
 fn valid_spec_commits<RQ>(
     speculative_commits: &BTreeMap<NodeId, StoredSerializedMessage<SysMsg<RQ>>>,
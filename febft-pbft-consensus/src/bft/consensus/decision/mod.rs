@@ -1,7 +1,7 @@
 use std::collections::VecDeque;
 use std::fmt::{Debug, Formatter};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use atlas_common::Err;
 use chrono::Utc;
@@ -20,8 +20,10 @@ use atlas_core::ordering_protocol::ShareableMessage;
 use atlas_core::timeouts::timeout::TimeoutModHandle;
 use atlas_metrics::metrics::{metric_correlation_id_passed, metric_duration};
 
+use crate::bft::auth::MacAuthenticator;
 use crate::bft::consensus::accessory::replica::ReplicaAccessory;
 use crate::bft::consensus::accessory::{AccessoryConsensus, ConsensusDecisionAccessory};
+use crate::bft::consensus::validity::{InvalidBatchProof, SharedValidityPredicate};
 use crate::bft::log::deciding::{CompletedBatch, WorkingDecisionLog};
 use crate::bft::log::decisions::{IncompleteProof, ProofMetadata};
 use crate::bft::message::{ConsensusMessage, ConsensusMessageKind, PBFTMessage};
@@ -124,6 +126,9 @@ where
     accessory: ConsensusDecisionAccessory<RQ>,
     // Metrics about the consensus instance
     consensus_metrics: ConsensusMetrics,
+    /// The proof against the leader whose proposal the validity predicate rejected,
+    /// until it is taken
+    invalid_batch: Option<InvalidBatchProof<RQ>>,
     //TODO: Store things directly into the persistent log as well as delete them when
     // Things go wrong
 }
@@ -184,15 +189,25 @@ impl<RQ> ConsensusDecision<RQ>
 where
     RQ: SerMsg + SessionBased + 'static,
 {
-    pub fn init_decision(node_id: NodeId, seq_no: SeqNo, view: &ViewInfo) -> Self {
+    pub fn init_decision(
+        node_id: NodeId,
+        seq_no: SeqNo,
+        view: &ViewInfo,
+        authenticator: Option<Arc<MacAuthenticator>>,
+        validity: SharedValidityPredicate<RQ>,
+    ) -> Self {
         Self {
             node_id,
             seq: seq_no,
             phase: DecisionPhase::Initialize,
             message_queue: MessageQueue::new(),
             working_log: WorkingDecisionLog::new(node_id, seq_no, view),
-            accessory: ConsensusDecisionAccessory::Replica(ReplicaAccessory::new()),
+            accessory: ConsensusDecisionAccessory::Replica(ReplicaAccessory::new(
+                authenticator,
+                validity,
+            )),
             consensus_metrics: ConsensusMetrics::new(),
+            invalid_batch: None,
         }
     }
 
@@ -201,6 +216,8 @@ where
         seq_no: SeqNo,
         view: &ViewInfo,
         message_queue: MessageQueue<RQ>,
+        authenticator: Option<Arc<MacAuthenticator>>,
+        validity: SharedValidityPredicate<RQ>,
     ) -> Self {
         Self {
             node_id,
@@ -208,8 +225,12 @@ where
             phase: DecisionPhase::Initialize,
             message_queue,
             working_log: WorkingDecisionLog::new(node_id, seq_no, view),
-            accessory: ConsensusDecisionAccessory::Replica(ReplicaAccessory::new()),
+            accessory: ConsensusDecisionAccessory::Replica(ReplicaAccessory::new(
+                authenticator,
+                validity,
+            )),
             consensus_metrics: ConsensusMetrics::new(),
+            invalid_batch: None,
         }
     }
 
@@ -263,6 +284,18 @@ where
         }
     }
 
+    /// Install the predicate the proposals of this instance must satisfy before we prepare them
+    pub fn set_validity_predicate(&mut self, validity: SharedValidityPredicate<RQ>) {
+        if let ConsensusDecisionAccessory::Replica(accessory) = &mut self.accessory {
+            accessory.set_validity_predicate(validity);
+        }
+    }
+
+    /// Take the proof against the leader whose proposal for this instance was invalid
+    pub fn take_invalid_batch(&mut self) -> Option<InvalidBatchProof<RQ>> {
+        self.invalid_batch.take()
+    }
+
     /// Allows us to skip the initialization phase of this consensus instance
     /// This is useful when we don't want the proposer to receive authorization
     /// To propose into this consensus instance.
@@ -375,7 +408,7 @@ where
 
                 let pre_prepare_received_time = Utc::now();
 
-                //TODO: Try out cloning each request on this method,
+                // The requests are shared with the pre prepare, so they are never copied here
                 let digests = request_batch_received(
                     header,
                     message,
@@ -413,7 +446,9 @@ where
 
                     let _current_digest = batch_metadata.batch_digest();
 
-                    self.accessory.handle_pre_prepare_phase_completed(
+                    // When the batch is invalid we withhold our prepare, and the leader
+                    // is voted out before the instance can be decided
+                    self.invalid_batch = self.accessory.handle_pre_prepare_phase_completed(
                         &self.working_log,
                         &view,
                         header,
@@ -683,6 +718,27 @@ where
         };
     }
 
+    /// The time it took to decide this instance
+    pub fn decision_latency(&self) -> Duration {
+        self.consensus_metrics.decision_latency()
+    }
+
+    /// The time the leader took to propose this instance, given when we
+    /// received the proposal of the instance before it
+    pub fn propose_latency(&self, previous_proposal: Option<Instant>) -> Duration {
+        self.consensus_metrics.propose_latency(previous_proposal)
+    }
+
+    /// When we received the first pre prepare of this instance
+    pub fn proposed_at(&self) -> Instant {
+        self.consensus_metrics.first_pre_prepare_time
+    }
+
+    /// The amount of requests in the batch of this instance
+    pub fn batch_size(&self) -> usize {
+        self.working_log.current_batch_size()
+    }
+
     /// Check if this consensus decision can be finalized
     pub fn is_finalizeable(&self) -> bool {
         matches!(self.phase, DecisionPhase::Decided)
@@ -710,6 +766,11 @@ where
         self.working_log.deciding(f)
     }
 
+    /// The pre prepares received so far for this decision
+    pub fn pre_prepares(&self) -> impl Iterator<Item = &ShareableMessage<PBFTMessage<RQ>>> {
+        self.working_log.pre_prepares()
+    }
+
     pub fn phase(&self) -> &DecisionPhase {
         &self.phase
     }
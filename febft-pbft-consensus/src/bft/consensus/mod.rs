@@ -3,9 +3,9 @@ use std::collections::{BTreeMap, BTreeSet, BinaryHeap, VecDeque};
 use std::fmt::{Debug, Formatter};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use either::Either;
-use event_listener::{Event, Listener};
 use tracing::{debug, error, info, instrument, trace, warn};
 
 use atlas_common::error::*;
@@ -24,20 +24,32 @@ use atlas_core::ordering_protocol::{Decision, ShareableMessage};
 use atlas_core::timeouts::timeout::TimeoutModHandle;
 use atlas_metrics::metrics::metric_increment;
 
+use crate::bft::auth::MacAuthenticator;
+use crate::bft::buffer::BufferUsage;
+use crate::bft::config::MessageBufferConfig;
 use crate::bft::consensus::decision::{
     ConsensusDecision, DecisionPollStatus, DecisionStatus, MessageQueue,
 };
+use crate::bft::consensus::validity::{AcceptAll, InvalidBatchProof, SharedValidityPredicate};
 use crate::bft::log::deciding::CompletedBatch;
 use crate::bft::log::decisions::{IncompleteProof, Proof, ProofMetadata};
 use crate::bft::log::Log;
-use crate::bft::message::{ConsensusMessage, ConsensusMessageKind, PBFTMessage};
-use crate::bft::metric::OPERATIONS_ORDERED_ID;
+use crate::bft::message::{ConsensusMessage, ConsensusMessageKind, PBFTMessage, SharedRequest};
+use crate::bft::metric::{
+    CONSENSUS_BUFFERED_BYTES_ID, CONSENSUS_BUFFERED_MESSAGES_ID, OPERATIONS_ORDERED_ID,
+};
+use crate::bft::monitor::LeaderSample;
+use crate::bft::proposer::events::{ProposerEvent, ProposerWaker};
 use crate::bft::sync::view::ViewInfo;
 use crate::bft::sync::Synchronizer;
 use crate::bft::{FeDecision, SysMsg, PBFT};
 
 pub mod accessory;
 pub mod decision;
+pub mod validity;
+
+/// How many decision latencies we keep around for the proposer, until it takes them
+const MAX_DECISION_LATENCY_SAMPLES: usize = 64;
 
 #[derive(Debug)]
 /// Status returned from processing a consensus message.
@@ -82,6 +94,9 @@ pub struct TboQueue<O> {
     curr_seq: SeqNo,
     watermark: u32,
     get_queue: bool,
+    /// How much we are currently buffering, so that a peer
+    /// cannot flood us with messages for far-future instances
+    usage: BufferUsage,
     pre_prepares: VecDeque<VecDeque<ShareableMessage<PBFTMessage<O>>>>,
     prepares: VecDeque<VecDeque<ShareableMessage<PBFTMessage<O>>>>,
     commits: VecDeque<VecDeque<ShareableMessage<PBFTMessage<O>>>>,
@@ -95,11 +110,16 @@ impl<O> Orderable for TboQueue<O> {
 }
 
 impl<O> TboQueue<O> {
-    fn new(curr_seq: SeqNo, watermark: u32) -> Self {
+    fn new(curr_seq: SeqNo, watermark: u32, buffer_limits: MessageBufferConfig) -> Self {
         Self {
             curr_seq,
             watermark,
             get_queue: false,
+            usage: BufferUsage::new(
+                buffer_limits,
+                CONSENSUS_BUFFERED_MESSAGES_ID,
+                CONSENSUS_BUFFERED_BYTES_ID,
+            ),
             pre_prepares: VecDeque::new(),
             prepares: VecDeque::new(),
             commits: VecDeque::new(),
@@ -124,12 +144,23 @@ impl<O> TboQueue<O> {
         let prepares = tbo_advance_message_queue_return(&mut self.prepares).unwrap_or_default();
         let commits = tbo_advance_message_queue_return(&mut self.commits).unwrap_or_default();
 
+        self.usage
+            .release(pre_prepares.iter().chain(prepares.iter()).chain(commits.iter()));
+
         MessageQueue::from_messages(pre_prepares, prepares, commits)
     }
 
     /// Advances the message queue, and updates the consensus instance id.
     fn next_instance_queue(&mut self) {
         self.curr_seq = self.curr_seq.next();
+
+        let discarded = [&self.pre_prepares, &self.prepares, &self.commits]
+            .into_iter()
+            .filter_map(|queue| queue.front())
+            .flatten();
+
+        self.usage.release(discarded);
+
         tbo_advance_message_queue(&mut self.pre_prepares);
         tbo_advance_message_queue(&mut self.prepares);
         tbo_advance_message_queue(&mut self.commits);
@@ -138,6 +169,18 @@ impl<O> TboQueue<O> {
     /// Queues a consensus message for later processing, or drops it
     /// immediately if it pertains to an older consensus instance.
     pub fn queue(&mut self, message: ShareableMessage<PBFTMessage<O>>) {
+        let index = match message.sequence_number().index(self.base_seq()) {
+            Either::Right(index) => index,
+            // The message pertains to an older consensus instance, so it would be dropped anyway
+            Either::Left(_) => return,
+        };
+
+        let mut queues = [&mut self.pre_prepares, &mut self.prepares, &mut self.commits];
+
+        if !self.usage.make_room(&mut queues, index, &message) {
+            return;
+        }
+
         match message.message().consensus().kind() {
             ConsensusMessageKind::PrePrepare(_) => self.queue_pre_prepare(message),
             ConsensusMessageKind::Prepare(_) => self.queue_prepare(message),
@@ -178,12 +221,63 @@ impl<O> TboQueue<O> {
     /// Clear this queue
     fn clear(&mut self) {
         self.get_queue = false;
+        self.usage.clear();
         self.pre_prepares.clear();
         self.prepares.clear();
         self.commits.clear();
     }
 }
 
+/// The PBFT sequence number window `[h, h + k]`.
+///
+/// `h` is the low watermark, which corresponds to the sequence number of the last
+/// stable checkpoint, and `k` is the size of the window. Messages with sequence numbers
+/// that fall outside of this window are rejected. When no size is provided, the window
+/// is unbounded.
+#[derive(Debug, Clone, Copy)]
+pub struct SeqNoWindow {
+    low: SeqNo,
+    size: Option<u32>,
+}
+
+impl SeqNoWindow {
+    pub fn new(low: SeqNo, size: Option<u32>) -> Self {
+        Self { low, size }
+    }
+
+    /// The low watermark `h` of the window
+    pub fn low_watermark(&self) -> SeqNo {
+        self.low
+    }
+
+    /// The high watermark `h + k` of the window, if the window is bounded
+    pub fn high_watermark(&self) -> Option<SeqNo> {
+        self.size.map(|size| self.low + SeqNo::from(size))
+    }
+
+    /// Is the given sequence number contained in this window
+    pub fn contains(&self, seq: SeqNo) -> bool {
+        seq >= self.low && !self.is_above(seq)
+    }
+
+    /// Is the given sequence number above the high watermark of this window
+    pub fn is_above(&self, seq: SeqNo) -> bool {
+        self.high_watermark().is_some_and(|high| seq > high)
+    }
+
+    /// Move the low watermark of this window forward.
+    /// Returns whether the window has been advanced
+    pub(crate) fn advance(&mut self, low: SeqNo) -> bool {
+        if low > self.low {
+            self.low = low;
+
+            true
+        } else {
+            false
+        }
+    }
+}
+
 /// A data structure to keep track of any consensus instances that have been signalled
 ///
 /// A consensus instance being signalled means it should be polled.
@@ -203,6 +297,8 @@ where
     node_id: NodeId,
     /// How many consensus instances can we overlap at the same time.
     watermark: u32,
+    /// The sequence number window we are currently accepting messages for
+    window: SeqNoWindow,
     /// The current seq no that we are currently in
     seq_no: SeqNo,
     /// The current sequence numbers that are awaiting polling
@@ -227,6 +323,24 @@ where
     timeouts: TimeoutModHandle,
     /// Check if we are currently recovering from a fault, meaning we should ignore timeouts
     is_recovering: bool,
+    /// The MAC authenticator used for prepares, when
+    /// we are not signing them
+    authenticator: Option<Arc<MacAuthenticator>>,
+    /// The application predicate the proposals must satisfy before we prepare them
+    validity: SharedValidityPredicate<RQ>,
+    /// The proofs against the leaders whose proposals the validity predicate rejected,
+    /// since they were last taken
+    invalid_batches: Vec<InvalidBatchProof<RQ>>,
+    /// The performance of the leader on the instances decided since
+    /// they were last taken by the leader monitor
+    leader_samples: Vec<LeaderSample>,
+    /// When we received the proposal of the last instance we decided in the current view
+    last_proposal: Option<Instant>,
+    /// Hand leadership over to the next view every this many decisions
+    leader_rotation: Option<u32>,
+    /// The sequence number at which the next view takes over from the current one,
+    /// when leadership rotates. Messages of the current view are not accepted from it on
+    handoff_at: Option<SeqNo>,
 }
 
 impl<RQ> Consensus<RQ>
@@ -238,28 +352,45 @@ where
         view: &ViewInfo,
         seq_no: SeqNo,
         watermark: u32,
+        window: SeqNoWindow,
+        buffer_limits: MessageBufferConfig,
         consensus_guard: Arc<ProposerConsensusGuard>,
         timeouts: TimeoutModHandle,
+        authenticator: Option<Arc<MacAuthenticator>>,
     ) -> Self {
         let mut curr_seq = seq_no;
 
         let mut consensus = Self {
             node_id,
             watermark,
+            window,
             seq_no,
             signalled: Signals::new(watermark),
             curr_view: view.clone(),
             decisions: VecDeque::with_capacity(watermark as usize),
-            tbo_queue: TboQueue::new(seq_no, watermark),
+            tbo_queue: TboQueue::new(seq_no, watermark, buffer_limits),
             view_queue: VecDeque::with_capacity(watermark as usize),
             consensus_guard,
             timeouts,
             is_recovering: false,
+            authenticator,
+            validity: Arc::new(AcceptAll),
+            invalid_batches: Vec::new(),
+            leader_samples: Vec::new(),
+            last_proposal: None,
+            leader_rotation: None,
+            handoff_at: None,
         };
 
         // Initialize the consensus instances
         for _ in 0..watermark {
-            let decision = ConsensusDecision::init_decision(node_id, curr_seq, view);
+            let decision = ConsensusDecision::init_decision(
+                node_id,
+                curr_seq,
+                view,
+                consensus.authenticator.clone(),
+                consensus.validity.clone(),
+            );
 
             consensus.enqueue_decision(decision);
 
@@ -306,6 +437,13 @@ where
             }
         };
 
+        if self.window.is_above(message_seq) {
+            debug!("{:?} // Rejecting consensus message {:?} received from {:?} as it is above the high watermark {:?}",
+                self.node_id, message, header.from(), self.window.high_watermark());
+
+            return;
+        }
+
         if i >= self.decisions.len() {
             debug!("{:?} // Queueing message out of context msg {:?} received from {:?} into tbo queue",
                 self.node_id, message, header.from());
@@ -352,7 +490,9 @@ where
 
                         //TODO: Prevent non leaders from forming an always increasing
                         // List of available sequence numbers
-                        if self.curr_view.leader_set().contains(&self.node_id) {
+                        if self.curr_view.leader_set().contains(&self.node_id)
+                            && !self.is_handed_off(seq_no)
+                        {
                             self.consensus_guard.make_seq_available(seq_no);
                         }
                     }
@@ -401,6 +541,13 @@ where
 
                 return Ok(ConsensusStatus::MessageQueued);
             }
+            Either::Right(_) if self.is_handed_off(message_seq) => {
+                // This instance belongs to the next view, as leadership rotates before it
+                debug!("{:?} // Ignoring consensus message {:?} received from {:?} as the current view hands off at {:?}",
+                    self.node_id, message, header.from(), self.handoff_at);
+
+                return Ok(ConsensusStatus::MessageIgnored);
+            }
             Either::Right(_) => {}
             Either::Left(_) => {
                 // The message pertains to older views
@@ -425,6 +572,17 @@ where
             }
         };
 
+        if self.window.is_above(message_seq) {
+            debug!(
+                "Message {:?} from {:?} is above our high watermark {:?}. Rejecting",
+                message,
+                header.from(),
+                self.window.high_watermark(),
+            );
+
+            return Ok(ConsensusStatus::MessageIgnored);
+        }
+
         if i >= self.decisions.len() {
             // We are not currently processing this consensus instance
             // so we need to queue the message
@@ -445,6 +603,10 @@ where
 
         let status = decision.process_message(s_message, synchronizer, timeouts, node)?;
 
+        if let Some(proof) = decision.take_invalid_batch() {
+            self.invalid_batches.push(proof);
+        }
+
         Ok(match status {
             DecisionStatus::VotedTwice(node) => ConsensusStatus::VotedTwice(node),
             DecisionStatus::Deciding(message) => ConsensusStatus::Deciding(MaybeVec::from_one(
@@ -477,14 +639,86 @@ where
                     ))
                 }
             }
-            DecisionStatus::Decided(message) => ConsensusStatus::Decided(MaybeVec::from_one(
-                Decision::decision_info_from_message(decision_seq, message),
-            )),
+            DecisionStatus::Decided(message) => {
+                self.consensus_guard
+                    .record_decision_latency(decision.decision_latency());
+
+                self.leader_samples.push(LeaderSample {
+                    view: self.curr_view.sequence_number(),
+                    batch_size: decision.batch_size(),
+                    propose_latency: decision.propose_latency(self.last_proposal),
+                    decided_at: Instant::now(),
+                });
+
+                self.last_proposal = Some(decision.proposed_at());
+
+                ConsensusStatus::Decided(MaybeVec::from_one(Decision::decision_info_from_message(
+                    decision_seq,
+                    message,
+                )))
+            }
             DecisionStatus::DecidedIgnored => ConsensusStatus::Decided(MaybeVec::None),
             DecisionStatus::MessageIgnored => ConsensusStatus::MessageIgnored,
         })
     }
 
+    /// Install the predicate the proposals must satisfy before we prepare them,
+    /// including those of the instances which are already open
+    pub fn set_validity_predicate(&mut self, validity: SharedValidityPredicate<RQ>) {
+        for decision in self.decisions.iter_mut() {
+            decision.set_validity_predicate(validity.clone());
+        }
+
+        self.validity = validity;
+    }
+
+    /// Take the proofs against the leaders whose proposals were rejected by
+    /// the validity predicate since the last call
+    pub fn take_invalid_batches(&mut self) -> Vec<InvalidBatchProof<RQ>> {
+        std::mem::take(&mut self.invalid_batches)
+    }
+
+    /// Hand leadership over to the next view every `every` decisions, at the sequence
+    /// numbers which are multiples of `every`. When `None`, leadership does not rotate
+    pub fn set_leader_rotation(&mut self, every: Option<u32>) {
+        self.leader_rotation = every.filter(|every| *every > 0);
+
+        self.schedule_handoff();
+    }
+
+    /// Schedule the hand off of the current view, at the first rotation
+    /// point after the sequence number we are currently at
+    fn schedule_handoff(&mut self) {
+        self.handoff_at = self
+            .leader_rotation
+            .map(|every| next_handoff(self.seq_no, every));
+    }
+
+    /// Whether the given instance belongs to the views after the current one
+    fn is_handed_off(&self, seq: SeqNo) -> bool {
+        self.handoff_at.is_some_and(|handoff_at| seq >= handoff_at)
+    }
+
+    /// The amount of rotation points we have decided our way past in the current view.
+    /// Leadership must be handed over that many views ahead
+    pub fn pending_handoffs(&self) -> u32 {
+        self.pending_handoff_points().len() as u32
+    }
+
+    /// The rotation points we have decided our way past in the current view, in order.
+    /// Leadership is handed over once at each of them
+    pub fn pending_handoff_points(&self) -> Vec<SeqNo> {
+        match (self.leader_rotation, self.handoff_at) {
+            (Some(every), Some(handoff_at)) => handoff_points(self.seq_no, handoff_at, every),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Take the performance of the leader on the instances decided since the last call
+    pub fn take_leader_samples(&mut self) -> Vec<LeaderSample> {
+        std::mem::take(&mut self.leader_samples)
+    }
+
     /// Are we able to finalize the next consensus instance on the queue?
     pub fn can_finalize(&self) -> bool {
         self.decisions
@@ -565,14 +799,22 @@ where
             .unwrap_or(self.seq_no);
 
         // Create the decision to keep the queue populated
-        let novel_decision =
-            ConsensusDecision::init_with_msg_log(self.node_id, new_seq_no, view, queue);
+        let novel_decision = ConsensusDecision::init_with_msg_log(
+            self.node_id,
+            new_seq_no,
+            view,
+            queue,
+            self.authenticator.clone(),
+            self.validity.clone(),
+        );
 
         self.enqueue_decision(novel_decision);
 
         decision
     }
 
+    /// Jump to the given sequence number. A sequence number beyond our window must only be
+    /// installed once a stable checkpoint has advanced the window to cover it
     #[instrument(skip(self), level = "debug")]
     pub fn install_sequence_number(&mut self, novel_seq_no: SeqNo, view: &ViewInfo) {
         info!(
@@ -580,6 +822,8 @@ where
             self.node_id, novel_seq_no, self.seq_no
         );
 
+        let moved = novel_seq_no != self.seq_no;
+
         match novel_seq_no.index(self.seq_no) {
             Either::Left(_) => {
                 debug!("{:?} // Installed sequence number is left of the current on. Clearing all queues", self.node_id);
@@ -589,8 +833,13 @@ where
                 let mut sequence_no = novel_seq_no;
 
                 while self.decisions.len() < self.watermark as usize {
-                    let novel_decision =
-                        ConsensusDecision::init_decision(self.node_id, sequence_no, view);
+                    let novel_decision = ConsensusDecision::init_decision(
+                        self.node_id,
+                        sequence_no,
+                        view,
+                        self.authenticator.clone(),
+                        self.validity.clone(),
+                    );
 
                     self.enqueue_decision(novel_decision);
 
@@ -648,6 +897,8 @@ where
                         sequence_no,
                         view,
                         messages,
+                        self.authenticator.clone(),
+                        self.validity.clone(),
                     );
 
                     debug!(
@@ -662,8 +913,13 @@ where
                 }
 
                 while self.decisions.len() < self.watermark as usize {
-                    let decision =
-                        ConsensusDecision::init_decision(self.node_id, sequence_no, view);
+                    let decision = ConsensusDecision::init_decision(
+                        self.node_id,
+                        sequence_no,
+                        view,
+                        self.authenticator.clone(),
+                        self.validity.clone(),
+                    );
 
                     self.enqueue_decision(decision);
 
@@ -701,6 +957,8 @@ where
                         sequence_no,
                         view,
                         messages,
+                        self.authenticator.clone(),
+                        self.validity.clone(),
                     );
 
                     self.enqueue_decision(decision);
@@ -715,6 +973,11 @@ where
         self.consensus_guard.install_seq_no(novel_seq_no);
         self.tbo_queue.signal();
 
+        if moved {
+            // We did not decide our way here, so we did not go through the hand offs in between
+            self.schedule_handoff();
+        }
+
         // A couple of assertions to make sure we are good
         assert_eq!(self.tbo_queue.sequence_number(), self.seq_no);
         assert_eq!(
@@ -723,6 +986,34 @@ where
         );
     }
 
+    /// Move the low watermark of the sequence number window forward,
+    /// as a result of a checkpoint becoming stable
+    #[instrument(skip(self), level = "debug")]
+    pub fn advance_low_watermark(&mut self, low: SeqNo) {
+        if !self.window.advance(low) {
+            return;
+        }
+
+        debug!(
+            "{:?} // Advanced sequence number window to {:?} - {:?}",
+            self.node_id,
+            self.window.low_watermark(),
+            self.window.high_watermark()
+        );
+
+        self.consensus_guard.install_window(self.window);
+    }
+
+    /// The sequence number window we are currently accepting messages for
+    pub fn window(&self) -> &SeqNoWindow {
+        &self.window
+    }
+
+    /// The sequence number of the view we are currently in
+    pub fn view_sequence_number(&self) -> SeqNo {
+        self.curr_view.sequence_number()
+    }
+
     /// Catch up to the quorums latest decided consensus
     #[instrument(skip(self, proof, log), level = "debug", fields(proof_seq = proof.sequence_number().into_u32()))]
     pub fn catch_up_to_quorum(
@@ -741,12 +1032,17 @@ where
         Ok(to_execute)
     }
 
-    /// Create a fake `PRE-PREPARE`. This is useful during the view
+    /// Create a fake `PRE-PREPARE` for the instance `seq`. This is useful during the view
     /// change protocol.
     #[instrument(skip(self, requests), level = "debug", fields(request_count = requests.len()))]
-    pub fn forge_propose(&self, requests: Vec<StoredMessage<RQ>>, view: &ViewInfo) -> SysMsg<RQ> {
+    pub fn forge_propose(
+        &self,
+        seq: SeqNo,
+        requests: Vec<SharedRequest<RQ>>,
+        view: &ViewInfo,
+    ) -> SysMsg<RQ> {
         PBFTMessage::Consensus(ConsensusMessage::new(
-            self.sequence_number(),
+            seq,
             view.sequence_number(),
             ConsensusMessageKind::PrePrepare(requests),
         ))
@@ -780,15 +1076,26 @@ where
         }
 
         self.curr_view = view.clone();
+
+        // The leader of the new view could not propose before it was installed
+        self.last_proposal = None;
         self.consensus_guard.install_view(view.clone());
 
+        self.schedule_handoff();
+
         // Since we are changing view, all messages from the previous view are now invalid
         self.clear_all_queues();
 
         let mut sequence_no = self.sequence_number();
 
         while self.decisions.len() < self.watermark as usize {
-            let novel_decision = ConsensusDecision::init_decision(self.node_id, sequence_no, view);
+            let novel_decision = ConsensusDecision::init_decision(
+                self.node_id,
+                sequence_no,
+                view,
+                self.authenticator.clone(),
+                self.validity.clone(),
+            );
 
             self.enqueue_decision(novel_decision);
 
@@ -861,7 +1168,7 @@ where
             let mut final_rqs = Vec::with_capacity(reqs.len());
 
             for x in reqs {
-                final_rqs.push(ClientRqInfo::from(x));
+                final_rqs.push(ClientRqInfo::from(&**x));
             }
 
             // Register the messages that we have received in this pre prepare from the view change
@@ -895,6 +1202,14 @@ where
         }
     }
 
+    /// Collect the pre prepares received for the decision that is currently being decided
+    pub fn collect_in_exec_pre_prepares(&self) -> Vec<ShareableMessage<PBFTMessage<RQ>>> {
+        self.decisions
+            .front()
+            .map(|decision| decision.pre_prepares().cloned().collect())
+            .unwrap_or_default()
+    }
+
     /// Enqueue a decision onto our overlapping decision log
     fn enqueue_decision(&mut self, decision: ConsensusDecision<RQ>) {
         self.signalled.push_signalled(decision.sequence_number());
@@ -931,8 +1246,12 @@ where
 pub struct ProposerConsensusGuard {
     /// Can I propose batches at this time
     can_propose: AtomicBool,
-    /// The proposer should sleep until we are ready to start proposing again
-    event_waker: Event,
+    /// Is the sequence number window full, meaning we can't propose until it advances
+    window_full: AtomicBool,
+    /// The sequence number window we are allowed to propose in
+    window: Mutex<SeqNoWindow>,
+    /// Wakes up the proposer when it may be able to propose again
+    waker: ProposerWaker,
     /// The revolving door of available sequence numbers to propose to
     /// We want to have a Min Heap so we reverse the SeqNo's ordering
     seq_no_queue: Mutex<(BinaryHeap<Reverse<SeqNo>>, ViewInfo)>,
@@ -943,29 +1262,35 @@ pub struct ProposerConsensusGuard {
     /// We must store them due to the way the request pre processor
     /// sends requests to the proposer
     last_view_change: Mutex<Option<BTreeMap<NodeId, BTreeMap<SeqNo, SeqNo>>>>,
+    /// The latencies of the most recent decisions, which the proposer
+    /// uses to tune its batching
+    decision_latencies: Mutex<VecDeque<Duration>>,
 }
 
 impl ProposerConsensusGuard {
     /// Initialize a new consensus guard object
-    pub(super) fn new(view: ViewInfo, watermark: u32) -> Arc<Self> {
+    pub(super) fn new(view: ViewInfo, watermark: u32, window: SeqNoWindow) -> Arc<Self> {
         Arc::new(Self {
             // We start at false since we have to wait for the state transfer protocol
             can_propose: AtomicBool::new(false),
-            event_waker: Event::new(),
+            window_full: AtomicBool::new(false),
+            window: Mutex::new(window),
+            waker: ProposerWaker::default(),
             seq_no_queue: Mutex::new((BinaryHeap::with_capacity(watermark as usize), view)),
             has_pending_view_change_reqs: AtomicBool::new(false),
             last_view_change: Mutex::new(None),
+            decision_latencies: Mutex::new(VecDeque::with_capacity(MAX_DECISION_LATENCY_SAMPLES)),
         })
     }
 
     /// Are we able to propose to the current consensus instance
     pub fn can_propose(&self) -> bool {
-        self.can_propose.load(Ordering::Relaxed)
+        self.can_propose.load(Ordering::Relaxed) && !self.window_full.load(Ordering::Relaxed)
     }
 
-    /// Block until we are ready to start proposing again
-    pub fn block_until_ready(&self) {
-        self.event_waker.listen().wait();
+    /// The waker through which the proposer is notified of changes to this guard
+    pub fn waker(&self) -> &ProposerWaker {
+        &self.waker
     }
 
     /// Lock the consensus, making it impossible for the proposer to propose any requests
@@ -979,18 +1304,55 @@ impl ProposerConsensusGuard {
     pub fn unlock_consensus(&self) {
         self.can_propose.store(true, Ordering::Relaxed);
 
-        self.event_waker.notify(usize::MAX);
+        self.waker.wake(ProposerEvent::Resumed);
 
         debug!("Unlocking consensus")
     }
 
     /// Get the next sequence number to propose to
+    ///
+    /// If the next sequence number is above the high watermark of the window,
+    /// the proposer is stopped until the window advances
     pub fn next_seq_no(&self) -> Option<(SeqNo, ViewInfo)> {
         let mut guard = self.seq_no_queue.lock().unwrap();
 
+        if let Some(Reverse(seq)) = guard.0.peek() {
+            let window = self.window.lock().unwrap();
+
+            if window.is_above(*seq) {
+                debug!(
+                    "Sequence number window is full ({:?} is above {:?}), stopping the proposer",
+                    seq,
+                    window.high_watermark()
+                );
+
+                self.window_full.store(true, Ordering::Relaxed);
+
+                return None;
+            }
+        }
+
         guard.0.pop().map(|first| (first.0, guard.1.clone()))
     }
 
+    /// Install a new sequence number window onto this consensus guard,
+    /// resuming the proposer if it was stopped due to the window being full.
+    ///
+    /// The proposer is always woken up, since it may be waiting on a sequence number
+    /// which was made available above the old window without ever calling [`Self::next_seq_no`]
+    pub fn install_window(&self, window: SeqNoWindow) {
+        *self.window.lock().unwrap() = window;
+
+        if self.window_full.swap(false, Ordering::Relaxed) {
+            debug!(
+                "Sequence number window advanced to {:?}, resuming the proposer",
+                window.low_watermark()
+            );
+        }
+
+        self.waker.wake(ProposerEvent::Resumed);
+    }
+
     /// Mark a given consensus sequence number as available to be proposed to
     pub fn make_seq_available(&self, seq: SeqNo) {
         debug!(
@@ -998,9 +1360,19 @@ impl ProposerConsensusGuard {
             seq
         );
 
-        let mut guard = self.seq_no_queue.lock().unwrap();
+        self.seq_no_queue.lock().unwrap().0.push(Reverse(seq));
+
+        self.waker.wake(ProposerEvent::SeqAvailable);
+    }
+
+    /// Is there a sequence number (within the window) we can propose to
+    pub fn has_available_seq_no(&self) -> bool {
+        let guard = self.seq_no_queue.lock().unwrap();
 
-        guard.0.push(Reverse(seq));
+        guard
+            .0
+            .peek()
+            .is_some_and(|Reverse(seq)| !self.window.lock().unwrap().is_above(*seq))
     }
 
     /// Install a given sequence number onto this consensus guard
@@ -1023,6 +1395,24 @@ impl ProposerConsensusGuard {
 
         guard.1 = view;
         guard.0.clear();
+
+        self.waker.wake(ProposerEvent::ViewChanged);
+    }
+
+    /// Record the latency of a decision we have just reached
+    pub fn record_decision_latency(&self, latency: Duration) {
+        let mut latencies = self.decision_latencies.lock().unwrap();
+
+        if latencies.len() >= MAX_DECISION_LATENCY_SAMPLES {
+            latencies.pop_front();
+        }
+
+        latencies.push_back(latency);
+    }
+
+    /// Take the latencies of the decisions reached since the last call
+    pub fn take_decision_latencies(&self) -> Vec<Duration> {
+        self.decision_latencies.lock().unwrap().drain(..).collect()
     }
 
     /// Check if we have pending view change requests
@@ -1113,6 +1503,23 @@ impl Signals {
     }
 }
 
+/// The first rotation point after `seq`, when leadership rotates every `every` decisions
+fn next_handoff(seq: SeqNo, every: u32) -> SeqNo {
+    SeqNo::from((seq.into_u32() / every + 1) * every)
+}
+
+/// The rotation points from `handoff_at` up to `seq`, which we have decided our way past
+fn handoff_points(seq: SeqNo, handoff_at: SeqNo, every: u32) -> Vec<SeqNo> {
+    if seq < handoff_at {
+        return Vec::new();
+    }
+
+    (handoff_at.into_u32()..=seq.into_u32())
+        .step_by(every as usize)
+        .map(SeqNo::from)
+        .collect()
+}
+
 impl<O> Debug for ConsensusPollStatus<O> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -1128,3 +1535,129 @@ impl<O> Debug for ConsensusPollStatus<O> {
         }
     }
 }
+
+#[cfg(test)]
+mod window_tests {
+    use super::*;
+
+    fn seq(seq: u32) -> SeqNo {
+        SeqNo::from(seq)
+    }
+
+    #[test]
+    fn test_window_bounds() {
+        let mut window = SeqNoWindow::new(seq(10), Some(5));
+
+        assert_eq!(window.high_watermark(), Some(seq(15)));
+        assert!(!window.contains(seq(9)));
+        assert!(window.contains(seq(10)));
+        assert!(window.contains(seq(15)));
+        assert!(window.is_above(seq(16)));
+
+        assert!(!window.advance(seq(10)));
+        assert!(!window.advance(seq(5)));
+        assert!(window.advance(seq(20)));
+
+        assert_eq!(window.low_watermark(), seq(20));
+        assert!(window.contains(seq(25)));
+        assert!(!window.contains(seq(15)));
+
+        let unbounded = SeqNoWindow::new(seq(10), None);
+
+        assert_eq!(unbounded.high_watermark(), None);
+        assert!(unbounded.contains(seq(u32::MAX)));
+    }
+
+    #[test]
+    fn test_proposer_stops_at_the_high_watermark() {
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+
+        let guard = ProposerConsensusGuard::new(view, 10, SeqNoWindow::new(SeqNo::ZERO, Some(2)));
+
+        guard.unlock_consensus();
+
+        for available in 0..5 {
+            guard.make_seq_available(seq(available));
+        }
+
+        for expected in 0..=2 {
+            assert_eq!(guard.next_seq_no().map(|(seq, _)| seq), Some(seq(expected)));
+        }
+
+        assert!(guard.next_seq_no().is_none());
+        assert!(!guard.has_available_seq_no());
+        assert!(!guard.can_propose());
+
+        guard.install_window(SeqNoWindow::new(seq(3), Some(2)));
+
+        assert!(guard.can_propose());
+        assert!(guard.has_available_seq_no());
+
+        for expected in 3..5 {
+            assert_eq!(guard.next_seq_no().map(|(seq, _)| seq), Some(seq(expected)));
+        }
+    }
+
+    #[test]
+    fn test_window_advance_wakes_the_proposer() {
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+
+        let guard = ProposerConsensusGuard::new(view, 10, SeqNoWindow::new(SeqNo::ZERO, Some(2)));
+
+        guard.unlock_consensus();
+
+        // The only available sequence number is above the window, so the proposer
+        // goes to sleep without ever asking for it and the window is never marked full
+        guard.make_seq_available(seq(5));
+
+        assert!(!guard.has_available_seq_no());
+
+        guard.waker().wait(Some(Instant::now()));
+
+        guard.install_window(SeqNoWindow::new(seq(3), Some(2)));
+
+        let events = guard.waker().wait(Some(Instant::now()));
+
+        assert!(events.contains(ProposerEvent::Resumed));
+        assert!(guard.has_available_seq_no());
+    }
+}
+
+#[cfg(test)]
+mod rotation_tests {
+    use super::*;
+
+    fn seq(seq: u32) -> SeqNo {
+        SeqNo::from(seq)
+    }
+
+    fn seqs(seqs: &[u32]) -> Vec<SeqNo> {
+        seqs.iter().copied().map(SeqNo::from).collect()
+    }
+
+    #[test]
+    fn test_handoffs_at_multiples_of_every() {
+        assert_eq!(next_handoff(SeqNo::ZERO, 10), seq(10));
+        assert_eq!(next_handoff(seq(9), 10), seq(10));
+
+        // A view installed right at a rotation point keeps it for the whole period
+        assert_eq!(next_handoff(seq(10), 10), seq(20));
+
+        // A view installed midway only lasts until the next rotation point
+        assert_eq!(next_handoff(seq(13), 10), seq(20));
+        assert_eq!(next_handoff(seq(13), 1), seq(14));
+    }
+
+    #[test]
+    fn test_handoff_points_passed() {
+        let handoff_at = seq(10);
+
+        assert!(handoff_points(seq(9), handoff_at, 10).is_empty());
+        assert_eq!(handoff_points(seq(10), handoff_at, 10), seqs(&[10]));
+        assert_eq!(handoff_points(seq(19), handoff_at, 10), seqs(&[10]));
+
+        // Deciding our way past several rotation points hands over once at each of them
+        assert_eq!(handoff_points(seq(30), handoff_at, 10), seqs(&[10, 20, 30]));
+        assert_eq!(handoff_points(seq(12), seq(11), 1), seqs(&[11, 12]));
+    }
+}
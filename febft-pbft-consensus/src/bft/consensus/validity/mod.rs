@@ -0,0 +1,367 @@
+//! Application level validity of the proposed batches.
+//!
+//! Signature checks only tell us that the requests in a pre prepare were sent by clients,
+//! not that they make sense to the application (well formed transactions, nonces used in
+//! order, ...). Before voting to prepare a batch, replicas run it through the
+//! [`BatchValidityPredicate`] installed by the application. A replica does not prepare a batch
+//! with an invalid proposal, and votes to replace the leader that proposed it instead of
+//! ordering requests which would only be rejected at execution.
+//!
+//! The predicate must be deterministic, so that every correct replica reaches the same verdict
+//! on the same batch. The signed pre prepare of the invalid proposal is kept as an
+//! [`InvalidBatchProof`], which anyone can check against the predicate.
+//!
+//! A correct leader runs the requests through the same predicate before proposing them (see
+//! [`filter_batch`]), so a batch which still fails the predicate can only have been proposed
+//! by a faulty leader.
+
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use atlas_common::crypto::hash::Digest;
+use atlas_common::error::*;
+use atlas_common::node_id::NodeId;
+use atlas_common::ordering::{Orderable, SeqNo};
+use atlas_common::Err;
+use atlas_communication::message::StoredMessage;
+use atlas_core::messages::ClientRqInfo;
+use atlas_core::ordering_protocol::ShareableMessage;
+
+use crate::bft::message::{ConsensusMessageKind, PBFTMessage, SharedRequest};
+
+/// Decides whether the requests proposed by a leader can be ordered
+pub trait BatchValidityPredicate<RQ>: Send + Sync {
+    /// Check the requests a leader proposed for the given consensus instance.
+    /// Returns the reason why they are invalid, if they are
+    fn validate(
+        &self,
+        seq: SeqNo,
+        requests: &[SharedRequest<RQ>],
+    ) -> std::result::Result<(), String>;
+
+    /// Check a single request, independently of the instance it will be proposed in.
+    /// Requests which fail this check are discarded as soon as they reach the proposer,
+    /// so they are neither proposed nor watched for timeouts
+    fn validate_request(&self, _request: &StoredMessage<RQ>) -> std::result::Result<(), String> {
+        Ok(())
+    }
+}
+
+/// The default predicate, which accepts every batch
+pub struct AcceptAll;
+
+impl<RQ> BatchValidityPredicate<RQ> for AcceptAll {
+    fn validate(
+        &self,
+        _seq: SeqNo,
+        _requests: &[SharedRequest<RQ>],
+    ) -> std::result::Result<(), String> {
+        Ok(())
+    }
+}
+
+pub type SharedValidityPredicate<RQ> = Arc<dyn BatchValidityPredicate<RQ>>;
+
+/// A signed pre prepare whose proposal was deemed invalid by the validity predicate
+#[derive(Clone)]
+pub struct InvalidBatchProof<RQ> {
+    culprit: NodeId,
+    view: SeqNo,
+    seq: SeqNo,
+    reason: String,
+    pre_prepare: ShareableMessage<PBFTMessage<RQ>>,
+}
+
+impl<RQ> Orderable for InvalidBatchProof<RQ> {
+    fn sequence_number(&self) -> SeqNo {
+        self.seq
+    }
+}
+
+impl<RQ> InvalidBatchProof<RQ> {
+    /// The leader which proposed the invalid batch
+    pub fn culprit(&self) -> NodeId {
+        self.culprit
+    }
+
+    pub fn view(&self) -> SeqNo {
+        self.view
+    }
+
+    /// Why the predicate rejected the batch
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// The signed pre prepare carrying the invalid batch
+    pub fn pre_prepare(&self) -> &ShareableMessage<PBFTMessage<RQ>> {
+        &self.pre_prepare
+    }
+
+    /// The requests of the invalid batch, which are still waiting to be ordered
+    pub fn requests(&self) -> Vec<ClientRqInfo> {
+        proposed_requests(&self.pre_prepare)
+            .map(|requests| {
+                requests
+                    .iter()
+                    .map(|request| ClientRqInfo::from(&**request))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The compact record of this proof, which we keep after the proof itself is dropped
+    pub fn record(&self) -> InvalidBatchRecord {
+        InvalidBatchRecord {
+            culprit: self.culprit,
+            view: self.view,
+            seq: self.seq,
+            digest: *self.pre_prepare.header().digest(),
+        }
+    }
+
+    /// Verify that the pre prepare was sent by the culprit and that the
+    /// given predicate rejects the batch it carries
+    pub fn verify(&self, predicate: &dyn BatchValidityPredicate<RQ>) -> Result<()> {
+        if self.pre_prepare.header().from() != self.culprit {
+            return Err!(ValidityError::WrongSender(
+                self.pre_prepare.header().from(),
+                self.culprit
+            ));
+        }
+
+        let Some(requests) = proposed_requests(&self.pre_prepare) else {
+            return Err!(ValidityError::NotPrePrepare);
+        };
+
+        if predicate.validate(self.seq, requests).is_ok() {
+            return Err!(ValidityError::BatchIsValid);
+        }
+
+        Ok(())
+    }
+}
+
+/// The digest of the signed pre prepare of an invalid batch, along with who proposed it
+/// and where. The pre prepare itself is not kept, as the batch can be arbitrarily large
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidBatchRecord {
+    culprit: NodeId,
+    view: SeqNo,
+    seq: SeqNo,
+    digest: Digest,
+}
+
+impl Orderable for InvalidBatchRecord {
+    fn sequence_number(&self) -> SeqNo {
+        self.seq
+    }
+}
+
+impl InvalidBatchRecord {
+    /// The leader which proposed the invalid batch
+    pub fn culprit(&self) -> NodeId {
+        self.culprit
+    }
+
+    pub fn view(&self) -> SeqNo {
+        self.view
+    }
+
+    /// The digest of the signed pre prepare carrying the invalid batch
+    pub fn digest(&self) -> &Digest {
+        &self.digest
+    }
+}
+
+/// Remove from a batch we are about to propose in the given instance the requests which
+/// would make the predicate reject it.
+///
+/// Requests rejected on their own are dropped and returned in the first vector. Requests which
+/// are only rejected in the company of the others (such as a nonce used ahead of its turn) are
+/// returned in the second vector, to be proposed in a later batch
+pub fn filter_batch<RQ>(
+    predicate: &dyn BatchValidityPredicate<RQ>,
+    seq: SeqNo,
+    batch: &mut Vec<SharedRequest<RQ>>,
+) -> (Vec<SharedRequest<RQ>>, Vec<SharedRequest<RQ>>) {
+    if predicate.validate(seq, batch).is_ok() {
+        return (Vec::new(), Vec::new());
+    }
+
+    let (accepted, invalid): (Vec<_>, Vec<_>) =
+        std::mem::take(batch).into_iter().partition(|request| {
+            predicate
+                .validate(seq, std::slice::from_ref(request))
+                .is_ok()
+        });
+
+    let mut deferred = Vec::new();
+
+    for request in accepted {
+        batch.push(request);
+
+        if predicate.validate(seq, batch).is_err() {
+            deferred.extend(batch.pop());
+        }
+    }
+
+    (invalid, deferred)
+}
+
+/// The requests proposed in the given message, if it is a pre prepare
+fn proposed_requests<RQ>(
+    message: &ShareableMessage<PBFTMessage<RQ>>,
+) -> Option<&[SharedRequest<RQ>]> {
+    match message.message() {
+        PBFTMessage::Consensus(consensus) => match consensus.kind() {
+            ConsensusMessageKind::PrePrepare(requests) => Some(requests.as_slice()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Run the proposals of a consensus instance through the predicate, skipping our own.
+/// Returns the proof against the first leader whose proposal is invalid
+pub fn validate_proposals<'a, RQ: 'a>(
+    my_id: NodeId,
+    seq: SeqNo,
+    predicate: &dyn BatchValidityPredicate<RQ>,
+    pre_prepares: impl Iterator<Item = &'a ShareableMessage<PBFTMessage<RQ>>>,
+) -> Option<InvalidBatchProof<RQ>> {
+    pre_prepares
+        .filter(|pre_prepare| pre_prepare.header().from() != my_id)
+        .find_map(|pre_prepare| {
+            let requests = proposed_requests(pre_prepare)?;
+
+            let reason = predicate.validate(seq, requests).err()?;
+
+            Some(InvalidBatchProof {
+                culprit: pre_prepare.header().from(),
+                view: pre_prepare.message().consensus().view(),
+                seq,
+                reason,
+                pre_prepare: pre_prepare.clone(),
+            })
+        })
+}
+
+#[derive(Error, Debug)]
+pub enum ValidityError {
+    #[error("The pre prepare was sent by {0:?} instead of {1:?}")]
+    WrongSender(NodeId, NodeId),
+    #[error("The message is not a pre prepare")]
+    NotPrePrepare,
+    #[error("The predicate accepts the batch")]
+    BatchIsValid,
+}
+
+#[cfg(test)]
+mod validity_tests {
+    use crate::bft::fixtures::{digest, shared};
+    use crate::bft::message::ConsensusMessage;
+
+    use super::*;
+
+    /// Rejects the zero request on its own, and batches whose requests are not in
+    /// increasing order, like nonces which must be used in turn
+    struct Increasing;
+
+    impl BatchValidityPredicate<u8> for Increasing {
+        fn validate(
+            &self,
+            _seq: SeqNo,
+            requests: &[SharedRequest<u8>],
+        ) -> std::result::Result<(), String> {
+            if requests.iter().any(|request| *request.message() == 0) {
+                return Err("zero request".to_string());
+            }
+
+            if requests
+                .windows(2)
+                .any(|pair| pair[0].message() >= pair[1].message())
+            {
+                return Err("out of order".to_string());
+            }
+
+            Ok(())
+        }
+    }
+
+    fn batch(bytes: &[u8]) -> Vec<SharedRequest<u8>> {
+        bytes
+            .iter()
+            .map(|byte| shared(NodeId::from(1000u32), digest(*byte), *byte))
+            .collect()
+    }
+
+    fn bytes(requests: &[SharedRequest<u8>]) -> Vec<u8> {
+        requests.iter().map(|request| *request.message()).collect()
+    }
+
+    fn pre_prepare(from: NodeId, requests: &[u8]) -> ShareableMessage<PBFTMessage<u8>> {
+        shared(
+            from,
+            digest(200),
+            PBFTMessage::Consensus(ConsensusMessage::new(
+                SeqNo::ZERO,
+                SeqNo::ZERO,
+                ConsensusMessageKind::PrePrepare(batch(requests)),
+            )),
+        )
+    }
+
+    #[test]
+    fn test_filter_keeps_valid_batches() {
+        let mut requests = batch(&[1, 2, 3]);
+
+        let (invalid, deferred) = filter_batch(&Increasing, SeqNo::ZERO, &mut requests);
+
+        assert!(invalid.is_empty());
+        assert!(deferred.is_empty());
+        assert_eq!(bytes(&requests), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_filter_drops_invalid_requests_and_defers_conflicts() {
+        let mut requests = batch(&[1, 0, 4, 2, 5]);
+
+        let (invalid, deferred) = filter_batch(&Increasing, SeqNo::ZERO, &mut requests);
+
+        assert_eq!(bytes(&invalid), vec![0]);
+        assert_eq!(bytes(&deferred), vec![2]);
+        assert_eq!(bytes(&requests), vec![1, 4, 5]);
+        assert!(Increasing.validate(SeqNo::ZERO, &requests).is_ok());
+    }
+
+    #[test]
+    fn test_only_other_leaders_are_blamed() {
+        let leader = NodeId::from(0u32);
+        let me = NodeId::from(1u32);
+
+        let ours = pre_prepare(me, &[2, 1]);
+        let valid = pre_prepare(leader, &[1, 2]);
+
+        assert!(
+            validate_proposals(me, SeqNo::ZERO, &Increasing, [&ours, &valid].into_iter()).is_none()
+        );
+
+        let invalid = pre_prepare(leader, &[3, 1]);
+
+        let proof = validate_proposals(me, SeqNo::ZERO, &Increasing, [&ours, &invalid].into_iter())
+            .unwrap();
+
+        assert_eq!(proof.culprit(), leader);
+        assert_eq!(proof.reason(), "out of order");
+        assert_eq!(proof.requests().len(), 2);
+        assert!(proof.verify(&Increasing).is_ok());
+        assert!(proof.verify(&AcceptAll).is_err());
+
+        let record = proof.record();
+
+        assert_eq!(record.culprit(), leader);
+        assert_eq!(record.digest(), invalid.header().digest());
+    }
+}
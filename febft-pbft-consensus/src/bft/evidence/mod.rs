@@ -0,0 +1,469 @@
+//! Equivocation evidence.
+//!
+//! A correct replica never signs two different consensus messages of the same kind
+//! for the same view and sequence number. When we receive such a pair, we keep both
+//! signed messages as a [`MisbehaviorProof`], which any third party can verify and
+//! which gives us grounds to remove the culprit from the quorum.
+//!
+//! Messages authenticated with MACs are not tracked, as they prove nothing to third parties.
+//! We only track the messages of the view and sequence numbers we are processing, and keep
+//! the first proof against each replica for each kind of message, so the memory we use is
+//! bounded by the size of the quorum and of the sequence number window.
+
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+use tracing::error;
+
+use atlas_common::crypto::hash::Digest;
+use atlas_common::error::*;
+use atlas_common::node_id::NodeId;
+use atlas_common::ordering::{Orderable, SeqNo};
+use atlas_common::serialization_helper::SerMsg;
+use atlas_common::Err;
+use atlas_core::ordering_protocol::networking::OrderProtocolSendNode;
+use atlas_core::ordering_protocol::ShareableMessage;
+
+use crate::bft::message::{ConsensusMessage, ConsensusMessageKind, PBFTMessage};
+use crate::bft::sync::validate_signature;
+use crate::bft::PBFT;
+
+/// The kind of consensus message that was equivocated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EquivocationKind {
+    PrePrepare,
+    Prepare,
+    Commit,
+}
+
+impl EquivocationKind {
+    fn of<O>(message: &ConsensusMessage<O>) -> Self {
+        match message.kind() {
+            ConsensusMessageKind::PrePrepare(_) => EquivocationKind::PrePrepare,
+            ConsensusMessageKind::Prepare(_) => EquivocationKind::Prepare,
+            ConsensusMessageKind::Commit(_) => EquivocationKind::Commit,
+        }
+    }
+}
+
+/// A verifiable proof that a replica has signed two conflicting
+/// consensus messages for the same slot
+#[derive(Clone)]
+pub struct MisbehaviorProof<RQ> {
+    culprit: NodeId,
+    kind: EquivocationKind,
+    view: SeqNo,
+    seq: SeqNo,
+    first: ShareableMessage<PBFTMessage<RQ>>,
+    second: ShareableMessage<PBFTMessage<RQ>>,
+}
+
+impl<RQ> Orderable for MisbehaviorProof<RQ> {
+    fn sequence_number(&self) -> SeqNo {
+        self.seq
+    }
+}
+
+impl<RQ> MisbehaviorProof<RQ> {
+    /// The replica that signed both messages
+    pub fn culprit(&self) -> NodeId {
+        self.culprit
+    }
+
+    pub fn kind(&self) -> EquivocationKind {
+        self.kind
+    }
+
+    pub fn view(&self) -> SeqNo {
+        self.view
+    }
+
+    /// The two conflicting signed messages
+    pub fn messages(
+        &self,
+    ) -> (
+        &ShareableMessage<PBFTMessage<RQ>>,
+        &ShareableMessage<PBFTMessage<RQ>>,
+    ) {
+        (&self.first, &self.second)
+    }
+
+    /// Verify that this proof actually shows an equivocation: both messages
+    /// are consensus messages of the same kind, signed by the culprit for the same
+    /// view and sequence number, and they vouch for different values.
+    pub fn verify<NT>(&self, node: &NT) -> Result<()>
+    where
+        RQ: SerMsg,
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+    {
+        self.verify_conflict()?;
+
+        // The senders are the culprit, so the signatures are checked against its public key
+        for message in [&self.first, &self.second] {
+            if !validate_signature(node, message) {
+                return Err!(EvidenceError::InvalidSignature(self.culprit));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verify that the messages of this proof conflict, regardless of who signed them
+    fn verify_conflict(&self) -> Result<()> {
+        for message in [&self.first, &self.second] {
+            if message.header().from() != self.culprit {
+                return Err!(EvidenceError::WrongSender(
+                    message.header().from(),
+                    self.culprit
+                ));
+            }
+
+            let PBFTMessage::Consensus(consensus) = message.message() else {
+                return Err!(EvidenceError::NotConsensusMessage);
+            };
+
+            if consensus.sequence_number() != self.seq
+                || consensus.view() != self.view
+                || EquivocationKind::of(consensus) != self.kind
+            {
+                return Err!(EvidenceError::DifferentSlot);
+            }
+        }
+
+        let first = proposed_value(self.first.message().consensus());
+        let second = proposed_value(self.second.message().consensus());
+
+        if first == second {
+            return Err!(EvidenceError::NoConflict);
+        }
+
+        Ok(())
+    }
+}
+
+/// The value a consensus message vouches for: the digests of the proposed
+/// requests for pre prepares and the batch digest for prepares and commits
+fn proposed_value<O>(message: &ConsensusMessage<O>) -> Vec<Digest> {
+    match message.kind() {
+        ConsensusMessageKind::PrePrepare(requests) => requests
+            .iter()
+            .map(|request| *request.header().digest())
+            .collect(),
+        ConsensusMessageKind::Prepare(digest) | ConsensusMessageKind::Commit(digest) => {
+            vec![*digest]
+        }
+    }
+}
+
+/// The slot a consensus message refers to
+type Slot = (NodeId, EquivocationKind, SeqNo, SeqNo);
+
+/// Detects and stores equivocations in the consensus messages we receive
+pub struct EvidenceCollector<RQ> {
+    node_id: NodeId,
+    // The first message we have seen for each slot which we have not yet discarded
+    seen: BTreeMap<Slot, ShareableMessage<PBFTMessage<RQ>>>,
+    // The messages for instances before this one are no longer tracked
+    discarded_before: SeqNo,
+    // The view of the messages we are tracking
+    view: SeqNo,
+    // The first proof we have collected against each replica, for each kind of message
+    proofs: BTreeMap<(NodeId, EquivocationKind), MisbehaviorProof<RQ>>,
+}
+
+impl<RQ> EvidenceCollector<RQ> {
+    pub fn new(node_id: NodeId) -> Self {
+        Self {
+            node_id,
+            seen: Default::default(),
+            discarded_before: SeqNo::ZERO,
+            view: SeqNo::ZERO,
+            proofs: Default::default(),
+        }
+    }
+
+    /// All the proofs of misbehavior we have collected
+    pub fn proofs(&self) -> impl Iterator<Item = &MisbehaviorProof<RQ>> {
+        self.proofs.values()
+    }
+
+    /// Check whether the given consensus message, which belongs to the view we are in,
+    /// conflicts with one we have previously received from the same sender for the same slot.
+    /// Returns the resulting proof, if it is the first one against the sender for this kind
+    /// of message
+    pub fn observe(
+        &mut self,
+        message: &ShareableMessage<PBFTMessage<RQ>>,
+        view: SeqNo,
+    ) -> Option<MisbehaviorProof<RQ>> {
+        let PBFTMessage::Consensus(consensus) = message.message() else {
+            return None;
+        };
+
+        // A MAC only convinces its recipient, so there would be nothing to show for it.
+        // We don't process the messages of other views or discarded instances either,
+        // so tracking them would only let faulty replicas fill our memory
+        if consensus.authenticator().is_some()
+            || consensus.view() != view
+            || consensus.sequence_number() < self.discarded_before
+        {
+            return None;
+        }
+
+        if view > self.view {
+            // We will no longer process the messages of the previous views
+            self.view = view;
+
+            self.seen
+                .retain(|(_, _, slot_view, _), _| *slot_view >= view);
+        }
+
+        let sender = message.header().from();
+        let kind = EquivocationKind::of(consensus);
+
+        if self.proofs.contains_key(&(sender, kind)) {
+            return None;
+        }
+
+        let slot = (
+            sender,
+            kind,
+            consensus.view(),
+            consensus.sequence_number(),
+        );
+
+        let first = match self.seen.get(&slot) {
+            Some(first) => first,
+            None => {
+                self.seen.insert(slot, message.clone());
+
+                return None;
+            }
+        };
+
+        if proposed_value(first.message().consensus()) == proposed_value(consensus) {
+            return None;
+        }
+
+        error!(
+            "{:?} // Node {:?} equivocated: sent conflicting {:?} messages for view {:?} and seq {:?}",
+            self.node_id, sender, kind, slot.2, slot.3
+        );
+
+        let proof = MisbehaviorProof {
+            culprit: sender,
+            kind,
+            view: slot.2,
+            seq: slot.3,
+            first: first.clone(),
+            second: message.clone(),
+        };
+
+        self.proofs.insert((sender, kind), proof.clone());
+
+        // We have all we need against the sender
+        self.seen
+            .retain(|(node, seen_kind, _, _), _| *node != sender || *seen_kind != kind);
+
+        Some(proof)
+    }
+
+    /// Stop tracking the slots of consensus instances that precede `seq`,
+    /// as we will no longer process messages for them
+    pub fn discard_before(&mut self, seq: SeqNo) {
+        self.discarded_before = self.discarded_before.max(seq);
+
+        self.seen.retain(|(_, _, _, slot_seq), _| *slot_seq >= seq);
+    }
+
+    /// The amount of messages we are currently tracking
+    pub fn tracked(&self) -> usize {
+        self.seen.len()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum EvidenceError {
+    #[error("The message was sent by {0:?}, not by the culprit {1:?}")]
+    WrongSender(NodeId, NodeId),
+    #[error("The proof contains a message that is not a consensus message")]
+    NotConsensusMessage,
+    #[error("The messages of the proof do not refer to the same slot")]
+    DifferentSlot,
+    #[error("The messages of the proof do not conflict")]
+    NoConflict,
+    #[error("A message of the proof is not correctly signed by the culprit {0:?}")]
+    InvalidSignature(NodeId),
+}
+
+#[cfg(test)]
+mod evidence_tests {
+    use crate::bft::fixtures::{digest, shared};
+    use crate::bft::message::Authenticator;
+
+    use super::*;
+
+    fn consensus(from: u32, message: ConsensusMessage<u8>) -> ShareableMessage<PBFTMessage<u8>> {
+        shared(NodeId::from(from), digest(0), PBFTMessage::Consensus(message))
+    }
+
+    fn prepare(from: u32, view: u32, seq: u32, value: u8) -> ShareableMessage<PBFTMessage<u8>> {
+        consensus(
+            from,
+            ConsensusMessage::new(
+                SeqNo::from(seq),
+                SeqNo::from(view),
+                ConsensusMessageKind::Prepare(digest(value)),
+            ),
+        )
+    }
+
+    fn collector() -> EvidenceCollector<u8> {
+        EvidenceCollector::new(NodeId::from(0u32))
+    }
+
+    #[test]
+    fn conflicting_prepares_are_proven() {
+        let mut collector = collector();
+
+        assert!(collector
+            .observe(&prepare(1, 0, 3, 1), SeqNo::ZERO)
+            .is_none());
+        // Receiving the same vote again is not an equivocation
+        assert!(collector
+            .observe(&prepare(1, 0, 3, 1), SeqNo::ZERO)
+            .is_none());
+
+        let proof = collector
+            .observe(&prepare(1, 0, 3, 2), SeqNo::ZERO)
+            .expect("Should prove the equivocation");
+
+        assert_eq!(proof.culprit(), NodeId::from(1u32));
+        assert_eq!(proof.kind(), EquivocationKind::Prepare);
+        assert_eq!(proof.sequence_number(), SeqNo::from(3u32));
+        assert!(proof.verify_conflict().is_ok());
+
+        // One proof against each replica is enough
+        assert!(collector
+            .observe(&prepare(1, 0, 4, 1), SeqNo::ZERO)
+            .is_none());
+        assert!(collector
+            .observe(&prepare(1, 0, 4, 2), SeqNo::ZERO)
+            .is_none());
+        assert_eq!(collector.proofs().count(), 1);
+        assert_eq!(collector.tracked(), 0);
+    }
+
+    #[test]
+    fn different_slots_do_not_conflict() {
+        let mut collector = collector();
+
+        assert!(collector
+            .observe(&prepare(1, 0, 3, 1), SeqNo::ZERO)
+            .is_none());
+        assert!(collector
+            .observe(&prepare(1, 0, 4, 2), SeqNo::ZERO)
+            .is_none());
+        assert!(collector
+            .observe(&prepare(2, 0, 3, 2), SeqNo::ZERO)
+            .is_none());
+
+        let commit = consensus(
+            1,
+            ConsensusMessage::new(
+                SeqNo::from(3u32),
+                SeqNo::ZERO,
+                ConsensusMessageKind::Commit(digest(2)),
+            ),
+        );
+
+        assert!(collector.observe(&commit, SeqNo::ZERO).is_none());
+        assert_eq!(collector.proofs().count(), 0);
+    }
+
+    #[test]
+    fn mac_authenticated_messages_are_not_tracked() {
+        let mut collector = collector();
+
+        let mut message = ConsensusMessage::new(
+            SeqNo::from(3u32),
+            SeqNo::ZERO,
+            ConsensusMessageKind::Prepare(digest(1)),
+        );
+        message.set_authenticator(Authenticator::default());
+
+        assert!(collector
+            .observe(&consensus(1, message), SeqNo::ZERO)
+            .is_none());
+        assert_eq!(collector.tracked(), 0);
+    }
+
+    #[test]
+    fn only_the_current_view_is_tracked() {
+        let mut collector = collector();
+
+        assert!(collector
+            .observe(&prepare(1, 1, 3, 1), SeqNo::ZERO)
+            .is_none());
+        assert_eq!(collector.tracked(), 0);
+
+        assert!(collector
+            .observe(&prepare(1, 0, 3, 1), SeqNo::ZERO)
+            .is_none());
+        assert_eq!(collector.tracked(), 1);
+
+        // Moving to the next view discards the messages of the previous one
+        assert!(collector
+            .observe(&prepare(1, 1, 3, 2), SeqNo::from(1u32))
+            .is_none());
+        assert_eq!(collector.tracked(), 1);
+        assert_eq!(collector.proofs().count(), 0);
+    }
+
+    #[test]
+    fn discarded_instances_are_no_longer_tracked() {
+        let mut collector = collector();
+
+        assert!(collector
+            .observe(&prepare(1, 0, 3, 1), SeqNo::ZERO)
+            .is_none());
+        assert!(collector
+            .observe(&prepare(1, 0, 5, 1), SeqNo::ZERO)
+            .is_none());
+
+        collector.discard_before(SeqNo::from(4u32));
+
+        assert_eq!(collector.tracked(), 1);
+
+        // Late messages for discarded instances are ignored
+        assert!(collector
+            .observe(&prepare(1, 0, 3, 2), SeqNo::ZERO)
+            .is_none());
+        assert_eq!(collector.tracked(), 1);
+        assert_eq!(collector.proofs().count(), 0);
+    }
+
+    #[test]
+    fn tampered_proofs_do_not_verify() {
+        let mut collector = collector();
+
+        collector.observe(&prepare(1, 0, 3, 1), SeqNo::ZERO);
+
+        let proof = collector
+            .observe(&prepare(1, 0, 3, 2), SeqNo::ZERO)
+            .unwrap();
+
+        let mut other_sender = proof.clone();
+        other_sender.second = prepare(2, 0, 3, 2);
+
+        let mut other_slot = proof.clone();
+        other_slot.second = prepare(1, 0, 4, 2);
+
+        let mut no_conflict = proof;
+        no_conflict.second = prepare(1, 0, 3, 1);
+
+        assert!(other_sender.verify_conflict().is_err());
+        assert!(other_slot.verify_conflict().is_err());
+        assert!(no_conflict.verify_conflict().is_err());
+    }
+}
@@ -0,0 +1,53 @@
+//! Message fixtures shared by the unit tests of the protocol.
+//!
+//! The messages built here carry unsigned headers, so they are only fit for
+//! the code paths that trust the signatures to have been verified on reception.
+
+use std::sync::Arc;
+
+use atlas_common::crypto::hash::Digest;
+use atlas_common::node_id::NodeId;
+use atlas_communication::lookup_table::MessageModule;
+use atlas_communication::message::{Header, StoredMessage, WireMessage};
+
+/// A digest made of `byte` repeated
+pub fn digest(byte: u8) -> Digest {
+    Digest::from_bytes(&[byte; Digest::LENGTH]).unwrap()
+}
+
+/// The header of a message sent by `from` to itself through `module`,
+/// whose payload of `payload_bytes` bytes hashes to `digest`
+pub fn header_with(
+    module: MessageModule,
+    from: NodeId,
+    digest: Digest,
+    payload_bytes: usize,
+) -> Header {
+    let (header, _, _) = WireMessage::new(
+        from,
+        from,
+        module,
+        vec![0u8; payload_bytes].into(),
+        0,
+        Some(digest),
+        None,
+    )
+    .into_inner();
+
+    header
+}
+
+/// The header of a protocol message with an empty payload, sent by `from` to itself
+pub fn header(from: NodeId, digest: Digest) -> Header {
+    header_with(MessageModule::Protocol, from, digest, 0)
+}
+
+/// The protocol message `message`, sent by `from` with a header carrying `digest`
+pub fn stored<M>(from: NodeId, digest: Digest, message: M) -> StoredMessage<M> {
+    StoredMessage::new(header(from, digest), message)
+}
+
+/// The shareable version of [`stored`]
+pub fn shared<M>(from: NodeId, digest: Digest, message: M) -> Arc<StoredMessage<M>> {
+    Arc::new(stored(from, digest, message))
+}
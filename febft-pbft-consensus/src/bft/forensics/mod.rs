@@ -0,0 +1,588 @@
+//! Forensic accountability after a safety violation.
+//!
+//! Should more than `f` replicas misbehave, two correct replicas may end up deciding
+//! different batches for the same sequence number. Given the conflicting [`Proof`]s of
+//! those decisions, along with the view change data (the signed `STOP-DATA` messages,
+//! usually taken from the [`LeaderCollects`] of the view changes that happened in
+//! between), we can pinpoint the replicas whose signed messages contradict each other.
+//!
+//! - When both decisions were made in the same view, any replica that voted for both
+//!   batches (or any leader that proposed both) is a culprit. Since both proofs contain a
+//!   quorum of votes, at least `f + 1` replicas must have done so.
+//! - When the decisions were made in different views, any replica that committed the
+//!   first batch but later reported, in a view change leading up to the second decision,
+//!   that it had not prepared it is a culprit.
+//!
+//! A replica is only ever blamed on the strength of messages it can't deny having sent:
+//! signed votes, from a member of the quorum, for the instance and view in question.
+//! Prepares may have been authenticated with MACs, which only their recipients can verify
+//! (and which any of them could have forged), so those are not held against their senders.
+//! Commits are always signed, so a conflict between commits is always established.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use thiserror::Error;
+
+use atlas_common::crypto::hash::Digest;
+use atlas_common::error::*;
+use atlas_common::node_id::NodeId;
+use atlas_common::ordering::{Orderable, SeqNo};
+use atlas_common::Err;
+use atlas_communication::message::StoredMessage;
+use atlas_core::ordering_protocol::networking::serialize::NetworkView;
+
+use crate::bft::log::decisions::{CollectData, Proof, StoredConsensusMessage, ViewDecisionPair};
+use crate::bft::message::{
+    ConsensusMessage, ConsensusMessageKind, PBFTMessage, ViewChangeMessageKind,
+};
+use crate::bft::sync::view::ViewInfo;
+use crate::bft::sync::LeaderCollects;
+
+/// Why a given replica was found to be a culprit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Contradiction {
+    /// The replica proposed both batches, in the same view
+    ConflictingProposals { view: SeqNo },
+    /// The replica sent prepares for both batches, in the same view
+    ConflictingPrepares { view: SeqNo },
+    /// The replica sent commits for both batches, in the same view
+    ConflictingCommits { view: SeqNo },
+    /// The replica committed a batch in `committed_view`, but in the view change
+    /// to `reported_view` it claimed to not have prepared it
+    HidPreparedValue {
+        committed_view: SeqNo,
+        reported_view: SeqNo,
+        reported: Option<ViewDecisionPair>,
+    },
+    /// The replica committed a batch, but in a later view change it
+    /// presented a proof that a different batch was decided
+    ConflictingDecision {
+        reported_view: SeqNo,
+        reported_digest: Digest,
+    },
+}
+
+/// The result of the forensic analysis of a safety violation
+#[derive(Debug)]
+pub struct ForensicReport {
+    seq: SeqNo,
+    f: usize,
+    culprits: BTreeMap<NodeId, Vec<Contradiction>>,
+}
+
+impl Orderable for ForensicReport {
+    fn sequence_number(&self) -> SeqNo {
+        self.seq
+    }
+}
+
+impl ForensicReport {
+    /// The replicas whose signed messages contradict each other,
+    /// along with the contradictions we have found
+    pub fn culprits(&self) -> &BTreeMap<NodeId, Vec<Contradiction>> {
+        &self.culprits
+    }
+
+    /// Whether we have found at least `f + 1` culprits, which is the
+    /// minimum amount of replicas that must have misbehaved to violate safety
+    pub fn is_conclusive(&self) -> bool {
+        self.culprits.len() > self.f
+    }
+
+    fn accuse(&mut self, node: NodeId, contradiction: Contradiction) {
+        let contradictions = self.culprits.entry(node).or_default();
+
+        if !contradictions.contains(&contradiction) {
+            contradictions.push(contradiction);
+        }
+    }
+}
+
+/// Analyze two conflicting decisions for the same sequence number, deriving
+/// the replicas responsible for the safety violation.
+///
+/// `view_changes` and `collects` hold the view change data of the view changes
+/// that happened between both decisions. The messages contained within are
+/// expected to have had their signatures verified.
+pub fn analyze_conflicting_proofs<O>(
+    first: &Proof<O>,
+    second: &Proof<O>,
+    view_changes: &[LeaderCollects<O>],
+    collects: &[StoredMessage<PBFTMessage<O>>],
+    view: &ViewInfo,
+) -> Result<ForensicReport> {
+    let seq = first.sequence_number();
+
+    if seq != second.sequence_number() {
+        return Err!(ForensicsError::DifferentSequenceNumbers(
+            seq,
+            second.sequence_number()
+        ));
+    }
+
+    if first.batch_digest() == second.batch_digest() {
+        return Err!(ForensicsError::NoConflict(seq));
+    }
+
+    let (first_view, second_view) = (proof_view(first)?, proof_view(second)?);
+
+    let members = view.quorum_members();
+
+    // Order the proofs by view, such that the earlier decision comes first
+    let (earlier, later, earlier_view, later_view) = if first_view <= second_view {
+        (first, second, first_view, second_view)
+    } else {
+        (second, first, second_view, first_view)
+    };
+
+    let mut report = ForensicReport {
+        seq,
+        f: view.params().f(),
+        culprits: Default::default(),
+    };
+
+    if earlier_view == later_view {
+        same_view_contradictions(&mut report, earlier, later, earlier_view, members);
+    } else {
+        let stop_data = view_changes
+            .iter()
+            .flat_map(|leader_collects| leader_collects.collects().iter())
+            .chain(collects.iter());
+
+        view_change_contradictions(
+            &mut report,
+            earlier,
+            earlier_view,
+            later_view,
+            members,
+            stop_data,
+        );
+    }
+
+    Ok(report)
+}
+
+/// The view in which the given proof was decided
+fn proof_view<O>(proof: &Proof<O>) -> Result<SeqNo> {
+    match proof.commits().first() {
+        Some(commit) => Ok(commit.message().consensus().view()),
+        None => Err!(ForensicsError::EmptyProof(proof.sequence_number())),
+    }
+}
+
+/// The consensus message in the given message, if it can be held against its sender:
+/// it must be signed by a member of the quorum, for the instance `seq` of the view `view`
+fn evidence<'a, O>(
+    message: &'a StoredConsensusMessage<O>,
+    seq: SeqNo,
+    view: SeqNo,
+    members: &[NodeId],
+) -> Option<&'a ConsensusMessage<O>> {
+    if !members.contains(&message.header().from()) {
+        return None;
+    }
+
+    match message.message() {
+        PBFTMessage::Consensus(consensus)
+            if consensus.sequence_number() == seq
+                && consensus.view() == view
+                && consensus.authenticator().is_none() =>
+        {
+            Some(consensus)
+        }
+        _ => None,
+    }
+}
+
+/// The senders of the votes in the given messages that vouch for the given digest,
+/// in the instance `seq` of the view `view`
+fn voters<O>(
+    messages: &[StoredConsensusMessage<O>],
+    seq: SeqNo,
+    view: SeqNo,
+    digest: &Digest,
+    members: &[NodeId],
+) -> BTreeSet<NodeId> {
+    messages
+        .iter()
+        .filter(|message| {
+            evidence(message, seq, view, members)
+                .and_then(|consensus| consensus.has_proposed_digest(digest))
+                .unwrap_or(false)
+        })
+        .map(|message| message.header().from())
+        .collect()
+}
+
+/// The digests of the pre prepares each leader proposed, in the instance `seq` of the view `view`
+fn proposals<O>(
+    proof: &Proof<O>,
+    seq: SeqNo,
+    view: SeqNo,
+    members: &[NodeId],
+) -> BTreeMap<NodeId, BTreeSet<Digest>> {
+    let mut proposals = BTreeMap::<_, BTreeSet<_>>::new();
+
+    for pre_prepare in proof.pre_prepares() {
+        let proposed = evidence(pre_prepare, seq, view, members).is_some_and(|consensus| {
+            matches!(consensus.kind(), ConsensusMessageKind::PrePrepare(_))
+        });
+
+        if proposed {
+            proposals
+                .entry(pre_prepare.header().from())
+                .or_default()
+                .insert(*pre_prepare.header().digest());
+        }
+    }
+
+    proposals
+}
+
+fn same_view_contradictions<O>(
+    report: &mut ForensicReport,
+    earlier: &Proof<O>,
+    later: &Proof<O>,
+    view: SeqNo,
+    members: &[NodeId],
+) {
+    let seq = earlier.sequence_number();
+    let (earlier_digest, later_digest) = (earlier.batch_digest(), later.batch_digest());
+
+    let voted_both = |earlier_votes: &[StoredConsensusMessage<O>],
+                      later_votes: &[StoredConsensusMessage<O>]| {
+        voters(earlier_votes, seq, view, &earlier_digest, members)
+            .intersection(&voters(later_votes, seq, view, &later_digest, members))
+            .copied()
+            .collect::<Vec<_>>()
+    };
+
+    let prepared_both = voted_both(earlier.prepares(), later.prepares());
+
+    for node in prepared_both {
+        report.accuse(node, Contradiction::ConflictingPrepares { view });
+    }
+
+    let committed_both = voted_both(earlier.commits(), later.commits());
+
+    for node in committed_both {
+        report.accuse(node, Contradiction::ConflictingCommits { view });
+    }
+
+    // A leader that is present in both proofs with different pre prepares has proposed both batches
+    let later_proposals = proposals(later, seq, view, members);
+
+    for (leader, proposed) in proposals(earlier, seq, view, members) {
+        if later_proposals
+            .get(&leader)
+            .is_some_and(|other| *other != proposed)
+        {
+            report.accuse(leader, Contradiction::ConflictingProposals { view });
+        }
+    }
+}
+
+fn view_change_contradictions<'a, O: 'a>(
+    report: &mut ForensicReport,
+    earlier: &Proof<O>,
+    earlier_view: SeqNo,
+    later_view: SeqNo,
+    members: &[NodeId],
+    stop_data: impl Iterator<Item = &'a StoredMessage<PBFTMessage<O>>>,
+) {
+    let seq = earlier.sequence_number();
+    let digest = earlier.batch_digest();
+
+    // Only members of the quorum are in this set
+    let committed = voters(earlier.commits(), seq, earlier_view, &digest, members);
+
+    for message in stop_data {
+        let sender = message.header().from();
+
+        if !committed.contains(&sender) {
+            continue;
+        }
+
+        let PBFTMessage::ViewChange(view_change) = message.message() else {
+            continue;
+        };
+
+        let reported_view = view_change.sequence_number();
+
+        // Only the view changes that happened after the earlier decision and
+        // up to the later decision are relevant
+        if reported_view <= earlier_view || reported_view > later_view {
+            continue;
+        }
+
+        let ViewChangeMessageKind::StopData(collect) = view_change.kind() else {
+            continue;
+        };
+
+        if let Some(contradiction) =
+            collect_contradiction(collect, seq, &digest, earlier_view, reported_view)
+        {
+            report.accuse(sender, contradiction);
+        }
+    }
+}
+
+/// Check whether the view change data reported by a replica which committed `digest`
+/// in `committed_view` for the instance `seq` contradicts that commit
+fn collect_contradiction<O>(
+    collect: &CollectData<O>,
+    seq: SeqNo,
+    digest: &Digest,
+    committed_view: SeqNo,
+    reported_view: SeqNo,
+) -> Option<Contradiction> {
+    if let Some(last_proof) = collect.last_proof() {
+        if last_proof.sequence_number() == seq {
+            return (last_proof.batch_digest() != *digest).then(|| {
+                Contradiction::ConflictingDecision {
+                    reported_view,
+                    reported_digest: last_proof.batch_digest(),
+                }
+            });
+        }
+
+        if last_proof.sequence_number() > seq {
+            // The replica has already moved past this instance, so we can't judge it here
+            return None;
+        }
+    }
+
+    let incomplete = collect.incomplete_proof();
+
+    if incomplete.executing() != seq {
+        return None;
+    }
+
+    let hid = match incomplete.quorum_prepares() {
+        None => true,
+        Some(ViewDecisionPair(view, prepared)) => {
+            *view < committed_view || (*view == committed_view && prepared != digest)
+        }
+    };
+
+    hid.then(|| Contradiction::HidPreparedValue {
+        committed_view,
+        reported_view,
+        reported: incomplete.quorum_prepares().cloned(),
+    })
+}
+
+#[derive(Error, Debug)]
+pub enum ForensicsError {
+    #[error("The proofs refer to different sequence numbers {0:?} and {1:?}")]
+    DifferentSequenceNumbers(SeqNo, SeqNo),
+    #[error("The proofs for {0:?} decide the same batch, there is no conflict")]
+    NoConflict(SeqNo),
+    #[error("The proof for {0:?} does not contain any commits")]
+    EmptyProof(SeqNo),
+}
+
+#[cfg(test)]
+mod forensics_tests {
+    use crate::bft::fixtures::{digest, shared, stored};
+    use crate::bft::log::decisions::{IncompleteProof, PrepareSet, ProofMetadata};
+    use crate::bft::message::{Authenticator, ViewChangeMessage};
+
+    use super::*;
+
+    fn consensus(from: u32, message: ConsensusMessage<u8>) -> StoredConsensusMessage<u8> {
+        shared(
+            NodeId::from(from),
+            digest(from as u8),
+            PBFTMessage::Consensus(message),
+        )
+    }
+
+    fn vote(from: u32, view: u32, kind: ConsensusMessageKind<u8>) -> StoredConsensusMessage<u8> {
+        consensus(
+            from,
+            ConsensusMessage::new(SeqNo::ONE, SeqNo::from(view), kind),
+        )
+    }
+
+    fn mac_vote(
+        from: u32,
+        view: u32,
+        kind: ConsensusMessageKind<u8>,
+    ) -> StoredConsensusMessage<u8> {
+        let mut message = ConsensusMessage::new(SeqNo::ONE, SeqNo::from(view), kind);
+
+        let mut authenticator = Authenticator::default();
+        authenticator.insert(NodeId::from(0u32), digest(0));
+
+        message.set_authenticator(authenticator);
+
+        consensus(from, message)
+    }
+
+    /// A certificate for the batch `batch` in the instance 1 of the view `view`,
+    /// proposed by `leader` and voted for by `voters`
+    fn certificate(
+        batch: u8,
+        view: u32,
+        leader: u32,
+        voters: &[u32],
+        mac_prepares: bool,
+    ) -> Proof<u8> {
+        let batch_digest = digest(batch);
+
+        let pre_prepare = shared(
+            NodeId::from(leader),
+            digest(100 + batch),
+            PBFTMessage::Consensus(ConsensusMessage::new(
+                SeqNo::ONE,
+                SeqNo::from(view),
+                ConsensusMessageKind::PrePrepare(Vec::new()),
+            )),
+        );
+
+        let prepares = voters
+            .iter()
+            .map(|voter| {
+                let kind = ConsensusMessageKind::Prepare(batch_digest);
+
+                if mac_prepares {
+                    mac_vote(*voter, view, kind)
+                } else {
+                    vote(*voter, view, kind)
+                }
+            })
+            .collect();
+
+        let commits = voters
+            .iter()
+            .map(|voter| vote(*voter, view, ConsensusMessageKind::Commit(batch_digest)))
+            .collect();
+
+        let metadata = ProofMetadata::new(
+            SeqNo::ONE,
+            batch_digest,
+            vec![*pre_prepare.header().digest()],
+            0,
+        );
+
+        Proof::new(metadata, vec![pre_prepare], prepares, commits)
+    }
+
+    fn stop_data(
+        from: u32,
+        view: u32,
+        prepared: Option<ViewDecisionPair>,
+    ) -> StoredMessage<PBFTMessage<u8>> {
+        let collect = CollectData::new(
+            IncompleteProof::new(SeqNo::ONE, PrepareSet(Vec::new()), prepared),
+            Vec::new(),
+            None,
+        );
+
+        stored(
+            NodeId::from(from),
+            digest(200 + from as u8),
+            PBFTMessage::ViewChange(ViewChangeMessage::new(
+                SeqNo::from(view),
+                ViewChangeMessageKind::StopData(collect),
+            )),
+        )
+    }
+
+    fn view() -> ViewInfo {
+        ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap()
+    }
+
+    fn culprits(report: &ForensicReport) -> Vec<NodeId> {
+        report.culprits().keys().copied().collect()
+    }
+
+    fn nodes(ids: &[u32]) -> Vec<NodeId> {
+        ids.iter().copied().map(NodeId::from).collect()
+    }
+
+    #[test]
+    fn test_same_view_double_voters_are_blamed() {
+        let first = certificate(1, 0, 0, &[0, 1, 2], false);
+        let second = certificate(2, 0, 0, &[1, 2, 3], false);
+
+        let report = analyze_conflicting_proofs(&first, &second, &[], &[], &view()).unwrap();
+
+        assert_eq!(culprits(&report), nodes(&[0, 1, 2]));
+        assert!(report.is_conclusive());
+
+        let blamed = &report.culprits()[&NodeId::from(1u32)];
+
+        assert!(blamed.contains(&Contradiction::ConflictingPrepares { view: SeqNo::ZERO }));
+        assert!(blamed.contains(&Contradiction::ConflictingCommits { view: SeqNo::ZERO }));
+
+        assert_eq!(
+            report.culprits()[&NodeId::from(0u32)],
+            vec![Contradiction::ConflictingProposals { view: SeqNo::ZERO }]
+        );
+    }
+
+    #[test]
+    fn test_mac_prepares_are_not_held_against_their_senders() {
+        let first = certificate(1, 0, 0, &[0, 1, 2], true);
+        let second = certificate(2, 0, 0, &[1, 2, 3], true);
+
+        let report = analyze_conflicting_proofs(&first, &second, &[], &[], &view()).unwrap();
+
+        // The commits are signed, so the double voters are still found out
+        let blamed = &report.culprits()[&NodeId::from(2u32)];
+
+        assert!(!blamed.contains(&Contradiction::ConflictingPrepares { view: SeqNo::ZERO }));
+        assert!(blamed.contains(&Contradiction::ConflictingCommits { view: SeqNo::ZERO }));
+    }
+
+    #[test]
+    fn test_votes_outside_the_quorum_or_instance_are_not_blamed() {
+        let mut first = certificate(1, 0, 1, &[0, 1, 2, 7], false);
+        let second = certificate(2, 0, 1, &[1, 2, 3, 7], false);
+
+        // Votes of replica 3 for the first batch, but in another view
+        let (metadata, mut messages) = first.into_parts();
+        messages.push(vote(3, 1, ConsensusMessageKind::Prepare(digest(1))));
+        messages.push(vote(3, 1, ConsensusMessageKind::Commit(digest(1))));
+        first = Proof::init_from_messages(metadata, messages).unwrap();
+
+        let report = analyze_conflicting_proofs(&first, &second, &[], &[], &view()).unwrap();
+
+        assert_eq!(culprits(&report), nodes(&[1, 2]));
+    }
+
+    #[test]
+    fn test_hiding_a_committed_value_in_a_view_change_is_blamed() {
+        let first = certificate(1, 0, 0, &[0, 1, 2], false);
+        let second = certificate(2, 1, 1, &[1, 2, 3], false);
+
+        let collects = vec![
+            // Replica 1 committed batch 1, but claims to not have prepared anything
+            stop_data(1, 1, None),
+            // Replica 2 honestly reports it
+            stop_data(2, 1, Some(ViewDecisionPair(SeqNo::ZERO, digest(1)))),
+            // Replica 7 is not in the quorum
+            stop_data(7, 1, None),
+        ];
+
+        let report = analyze_conflicting_proofs(&first, &second, &[], &collects, &view()).unwrap();
+
+        assert_eq!(culprits(&report), nodes(&[1]));
+        assert!(!report.is_conclusive());
+
+        assert!(matches!(
+            report.culprits()[&NodeId::from(1u32)][..],
+            [Contradiction::HidPreparedValue { reported: None, .. }]
+        ));
+    }
+
+    #[test]
+    fn test_certificates_must_conflict() {
+        let first = certificate(1, 0, 0, &[0, 1, 2], false);
+        let same = certificate(1, 0, 0, &[1, 2, 3], false);
+
+        assert!(analyze_conflicting_proofs(&first, &same, &[], &[], &view()).is_err());
+    }
+}
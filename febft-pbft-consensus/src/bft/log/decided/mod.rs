@@ -1,46 +1,132 @@
+use std::collections::VecDeque;
+
+use atlas_common::error::*;
+use atlas_common::node_id::NodeId;
 use atlas_common::ordering::{Orderable, SeqNo};
+use atlas_common::Err;
 
+use crate::bft::consensus::SeqNoWindow;
 use crate::bft::log::decisions::Proof;
+use crate::bft::log::LogError;
 
 /// A necessary decision log for the ability to perform view changes.
-/// Only stores the latest performed decision
+/// Stores the decisions performed since the last stable checkpoint,
+/// (always keeping at least the latest performed decision).
+///
+/// Decisions are only accepted up to the high watermark of the sequence number window,
+/// so the log never holds more than a window's worth of them, even if no checkpoint
+/// becomes stable
 pub struct DecisionLog<O> {
-    /// The last decision that was performed by the ordering protocol
-    last_decision: Option<Proof<O>>,
+    /// The decisions performed by the ordering protocol since the last
+    /// stable checkpoint, ordered by sequence number
+    decisions: VecDeque<Proof<O>>,
+    /// The window `[h, h + k]`, whose low watermark is the last stable checkpoint
+    window: SeqNoWindow,
 }
 
 impl<O> DecisionLog<O> {
-    pub(crate) fn init(last_proof: Option<Proof<O>>) -> Self {
+    pub(crate) fn init(last_proof: Option<Proof<O>>, window: SeqNoWindow) -> Self {
         DecisionLog {
-            last_decision: last_proof,
+            decisions: last_proof.into_iter().collect(),
+            window,
         }
     }
 
     /// Install a given proof
-    pub fn install_proof(&mut self, proof: Proof<O>) {
-        self.last_decision = Some(proof)
+    pub fn install_proof(&mut self, proof: Proof<O>) -> Result<()> {
+        self.append_proof(proof)
     }
 
     /// Get the last decision
     pub fn last_decision(&self) -> Option<Proof<O>> {
-        self.last_decision.clone()
+        self.decisions.back().cloned()
+    }
+
+    /// The view and the leader of the last decision
+    pub fn last_proposal(&self) -> Option<(SeqNo, NodeId)> {
+        self.decisions.back()?.proposal()
     }
 
     pub fn last_execution(&self) -> Option<SeqNo> {
-        self.last_decision
-            .as_ref()
+        self.decisions
+            .back()
             .map(|decision| decision.sequence_number())
     }
 
-    pub fn append_proof(&mut self, proof: Proof<O>) {
-        self.last_decision = Some(proof)
+    /// Append the proof of the next decision, unless it lies beyond the high watermark
+    pub fn append_proof(&mut self, proof: Proof<O>) -> Result<()> {
+        let seq = proof.sequence_number();
+
+        if self.window.is_above(seq) {
+            return Err!(LogError::BeyondHighWatermark {
+                install_attempt: seq,
+                high_watermark: self.window.high_watermark().unwrap_or(seq),
+            });
+        }
+
+        self.decisions.push_back(proof);
+
+        Ok(())
+    }
+
+    /// The amount of decisions that are currently retained
+    pub fn retained_decisions(&self) -> usize {
+        self.decisions.len()
+    }
+
+    /// The sequence number of the oldest decision that is still retained
+    pub fn first_retained(&self) -> Option<SeqNo> {
+        self.decisions
+            .front()
+            .map(|decision| decision.sequence_number())
+    }
+
+    /// Get the proofs of the decisions in the range `[first, last]`.
+    /// Returns `None` if we no longer retain the first decision of the range
+    pub fn proofs_in_range(&self, first: SeqNo, last: SeqNo) -> Option<Vec<Proof<O>>> {
+        if self.first_retained()? > first {
+            return None;
+        }
+
+        Some(
+            self.decisions
+                .iter()
+                .skip_while(|proof| proof.sequence_number() < first)
+                .take_while(|proof| proof.sequence_number() <= last)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Discard all the decisions up to (and including) the given sequence number,
+    /// as they are covered by a stable checkpoint, moving the window past them.
+    /// The latest decision is always kept, as it is required for view changes.
+    ///
+    /// Returns the amount of discarded decisions
+    pub fn garbage_collect(&mut self, seq: SeqNo) -> usize {
+        self.window.advance(seq);
+
+        let mut discarded = 0;
+
+        while self.decisions.len() > 1 {
+            match self.decisions.front() {
+                Some(proof) if proof.sequence_number() <= seq => {
+                    self.decisions.pop_front();
+
+                    discarded += 1;
+                }
+                _ => break,
+            }
+        }
+
+        discarded
     }
 }
 
 impl<O> Orderable for DecisionLog<O> {
     fn sequence_number(&self) -> SeqNo {
-        self.last_decision
-            .as_ref()
+        self.decisions
+            .back()
             .map(|f| f.sequence_number())
             .unwrap_or(SeqNo::ZERO)
     }
@@ -9,7 +9,6 @@ use atlas_common::error::*;
 use atlas_common::node_id::NodeId;
 use atlas_common::ordering::{Orderable, SeqNo};
 use atlas_common::Err;
-use atlas_communication::message::StoredMessage;
 use atlas_core::messages::ClientRqInfo;
 use atlas_core::ordering_protocol::networking::serialize::NetworkView;
 use atlas_core::ordering_protocol::ShareableMessage;
@@ -17,7 +16,7 @@ use atlas_metrics::benchmarks::BatchMeta;
 use atlas_metrics::metrics::metric_duration;
 
 use crate::bft::log::decisions::{IncompleteProof, PrepareSet, ProofMetadata, ViewDecisionPair};
-use crate::bft::message::{ConsensusMessageKind, PBFTMessage};
+use crate::bft::message::{ConsensusMessageKind, PBFTMessage, SharedRequest};
 use crate::bft::metric::PRE_PREPARE_LOG_ANALYSIS_ID;
 use crate::bft::sync::view::ViewInfo;
 
@@ -47,7 +46,7 @@ pub struct CompletedBatch<O> {
     // The information of the client requests that are contained in this batch
     pub(super) client_request_info: Vec<ClientRqInfo>,
     // The client requests contained in this batch
-    pub(super) client_requests: Vec<StoredMessage<O>>,
+    pub(super) client_requests: Vec<SharedRequest<O>>,
 
     // The metadata for the batch
     pub(super) batch_meta: BatchMeta,
@@ -81,7 +80,7 @@ pub struct WorkingDecisionLog<O> {
     // Some logging information about metadata
     batch_meta: Arc<Mutex<BatchMeta>>,
     // The contained requests per each of the received pre prepares
-    contained_requests: Vec<Option<Vec<StoredMessage<O>>>>,
+    contained_requests: Vec<Option<Vec<SharedRequest<O>>>>,
 }
 
 /// Checks to make sure replicas aren't providing more than one vote for the
@@ -203,6 +202,7 @@ where
 
         self.pre_prepare_digests[leader_index] = Some(*s_message.header().digest());
         self.contained_requests[leader_index] = Some(match message.kind() {
+            // Only the references to the requests are copied here
             ConsensusMessageKind::PrePrepare(requests) => requests.clone(),
             _ => unreachable!(),
         });
@@ -279,6 +279,11 @@ where
         self.current_batch_size
     }
 
+    /// The pre prepares received so far, in the order of the leader set
+    pub fn pre_prepares(&self) -> impl Iterator<Item = &ShareableMessage<PBFTMessage<O>>> {
+        self.message_log.pre_prepare.iter().flatten()
+    }
+
     /// Calculate the instance of a completed consensus pre prepare phase with
     /// all the batches received
     fn calculate_instance_digest(&self) -> Option<(Digest, Vec<Digest>)> {
@@ -394,7 +399,7 @@ impl<O> CompletedBatch<O> {
         pre_prepare_ordering: Vec<Digest>,
         contained_messages: FinishedMessageLog<O>,
         client_request_info: Vec<ClientRqInfo>,
-        client_requests: Vec<StoredMessage<O>>,
+        client_requests: Vec<SharedRequest<O>>,
         batch_meta: BatchMeta,
     ) -> Self {
         Self {
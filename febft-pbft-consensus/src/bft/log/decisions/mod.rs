@@ -4,6 +4,7 @@ use std::ops::Deref;
 
 use atlas_common::crypto::hash::Digest;
 use atlas_common::error::*;
+use atlas_common::node_id::NodeId;
 use atlas_common::ordering::{Orderable, SeqNo};
 use atlas_common::Err;
 use atlas_core::ordering_protocol::networking::serialize::OrderProtocolProof;
@@ -64,7 +65,7 @@ pub struct PrepareSet(pub Vec<ViewDecisionPair>);
 ///
 /// Corresponds to the `TimestampValuePair` class in `BFT-SMaRt`.
 #[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ViewDecisionPair(pub SeqNo, pub Digest);
 
 /// Represents an incomplete decision from the `DecisionLog`.
@@ -187,6 +188,13 @@ impl<O> Proof<O> {
         &self.pre_prepares[..]
     }
 
+    /// The view this `Proof` was decided in, along with the leader which proposed it
+    pub fn proposal(&self) -> Option<(SeqNo, NodeId)> {
+        let pre_prepare = self.pre_prepares.first()?;
+
+        Some((pre_prepare.message().consensus().view(), pre_prepare.header().from()))
+    }
+
     /// Returns the `PREPARE` message of this `Proof`.
     pub fn prepares(&self) -> &[StoredConsensusMessage<O>] {
         &self.prepares[..]
@@ -197,6 +205,15 @@ impl<O> Proof<O> {
         &self.commits[..]
     }
 
+    /// Whether this proof can be verified by a third party, which requires its commits
+    /// to be signed. Its prepares may have been authenticated with MACs, which only
+    /// their recipients can verify
+    pub fn is_transferable(&self) -> bool {
+        self.commits
+            .iter()
+            .all(|message| message.message().consensus().authenticator().is_none())
+    }
+
     /// Check if the amount of pre prepares line up with the expected amount
     fn check_pre_prepare_sizes(&self) -> Result<()> {
         if self.metadata.pre_prepare_ordering().len() != self.pre_prepares.len() {
@@ -330,13 +347,21 @@ impl IncompleteProof {
 #[derive(Clone)]
 pub struct CollectData<O> {
     pub(crate) incomplete_proof: IncompleteProof,
+    // The pre prepares of the instance in execution, so that a value
+    // prepared with them can be proposed again in the next view
+    pub(crate) pre_prepares: Vec<StoredConsensusMessage<O>>,
     pub(crate) last_proof: Option<Proof<O>>,
 }
 
 impl<O> CollectData<O> {
-    pub fn new(incomplete_proof: IncompleteProof, last_proof: Option<Proof<O>>) -> Self {
+    pub fn new(
+        incomplete_proof: IncompleteProof,
+        pre_prepares: Vec<StoredConsensusMessage<O>>,
+        last_proof: Option<Proof<O>>,
+    ) -> Self {
         Self {
             incomplete_proof,
+            pre_prepares,
             last_proof,
         }
     }
@@ -345,6 +370,11 @@ impl<O> CollectData<O> {
         &self.incomplete_proof
     }
 
+    /// The pre prepares received for the instance in execution, in the order of the leader set
+    pub fn pre_prepares(&self) -> &[StoredConsensusMessage<O>] {
+        &self.pre_prepares
+    }
+
     pub fn last_proof(&self) -> Option<&Proof<O>> {
         self.last_proof.as_ref()
     }
@@ -402,8 +432,10 @@ impl<O> Debug for CollectData<O> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "CollectData {{ incomplete_proof: {:?}, last_proof: {:?} }}",
-            self.incomplete_proof, self.last_proof
+            "CollectData {{ incomplete_proof: {:?}, pre_prepares: {}, last_proof: {:?} }}",
+            self.incomplete_proof,
+            self.pre_prepares.len(),
+            self.last_proof
         )
     }
 }
@@ -420,4 +452,6 @@ pub enum ProofError {
     WrongPrePrepareCount(usize, usize),
     #[error("Proof's batches do not match with the digests provided.")]
     BatchDigestsDoNotMatch,
+    #[error("The proof {0:?} has commits authenticated with MACs, which we cannot verify")]
+    NotTransferable(SeqNo),
 }
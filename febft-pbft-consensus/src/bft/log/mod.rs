@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use atlas_common::Err;
 use either::Either;
 use thiserror::Error;
@@ -7,19 +9,23 @@ use atlas_common::maybe_vec::MaybeVec;
 use atlas_common::node_id::NodeId;
 use atlas_common::ordering::{Orderable, SeqNo};
 use atlas_common::serialization_helper::SerMsg;
-use atlas_communication::message::Header;
+use atlas_communication::message::{Header, StoredMessage};
 use atlas_core::messages::{ClientRqInfo, SessionBased};
 use atlas_core::ordering_protocol::{BatchedDecision, Decision, ProtocolConsensusDecision};
+use atlas_metrics::metrics::metric_increment;
 
+use crate::bft::consensus::SeqNoWindow;
 use crate::bft::log::decided::DecisionLog;
 use crate::bft::log::deciding::{CompletedBatch, FinishedMessageLog};
 use crate::bft::log::decisions::{Proof, ProofMetadata};
-use crate::bft::message::ConsensusMessageKind;
+use crate::bft::message::{ConsensusMessageKind, SharedRequest};
+use crate::bft::metric::CHECKPOINT_DISCARDED_PROOFS_ID;
 use crate::bft::FeDecision;
 
 pub mod decided;
 pub mod deciding;
 pub mod decisions;
+pub mod replay;
 
 pub struct Log<RQ>
 where
@@ -50,7 +56,7 @@ where
                     });
                 }
                 Either::Right(1) => {
-                    self.decided.append_proof(proof.clone());
+                    self.decided.append_proof(proof.clone())?;
                 }
                 Either::Right(_) => {
                     return Err!(LogError::CannotInstallWouldSkip {
@@ -59,6 +65,8 @@ where
                     });
                 }
             }
+        } else {
+            self.decided.append_proof(proof.clone())?;
         }
 
         let batch_info = ProtocolConsensusDecision::from(&proof);
@@ -71,6 +79,14 @@ where
         ))
     }
 
+    /// Discard the decided proofs which are covered by the stable checkpoint
+    /// with sequence number `stable_seq`
+    pub fn garbage_collect(&mut self, stable_seq: SeqNo) {
+        let discarded = self.decided.garbage_collect(stable_seq);
+
+        metric_increment(CHECKPOINT_DISCARDED_PROOFS_ID, Some(discarded as u64));
+    }
+
     pub fn finalize_batch(
         &mut self,
         completed: CompletedBatch<RQ>,
@@ -95,12 +111,12 @@ where
 
         let proof = Proof::new(metadata, pre_prepares, prepares, commits);
 
-        self.decided.append_proof(proof);
+        self.decided.append_proof(proof)?;
 
         let mut batch = BatchedDecision::new_with_cap(seq, client_requests.len());
 
         for cli_rq in client_requests {
-            batch.add_message(cli_rq);
+            batch.add_message(into_executable(cli_rq));
         }
 
         Ok(ProtocolConsensusDecision::new(
@@ -112,15 +128,28 @@ where
     }
 }
 
-pub fn initialize_decided_log<RQ>(_node_id: NodeId) -> Log<RQ>
+pub fn initialize_decided_log<RQ>(_node_id: NodeId, window: SeqNoWindow) -> Log<RQ>
 where
     RQ: SerMsg,
 {
     Log {
-        decided: DecisionLog::init(None),
+        decided: DecisionLog::init(None, window),
     }
 }
 
+/// Hand a shared request over to the executor, which takes ownership of the requests it executes.
+/// The proof we keep in the decision log still references the request (it is served in log
+/// replays and STOP-DATA messages, which need the bodies), so the body is copied here.
+/// This is the one copy left in the pipeline, and it can only go once the executor's
+/// `BatchedDecision` accepts shared requests
+#[inline]
+fn into_executable<O>(request: SharedRequest<O>) -> StoredMessage<O>
+where
+    O: Clone,
+{
+    Arc::unwrap_or_clone(request)
+}
+
 #[inline]
 pub fn operation_key<O>(header: &Header, message: &O) -> u64
 where
@@ -154,9 +183,7 @@ where
         }
 
         for pre_prepare in value.pre_prepares() {
-            let consensus_msg = (*pre_prepare.message()).clone();
-
-            let reqs = match consensus_msg.into_consensus().into_kind() {
+            let reqs = match pre_prepare.message().consensus().kind() {
                 ConsensusMessageKind::PrePrepare(reqs) => reqs,
                 _ => {
                     unreachable!()
@@ -164,9 +191,9 @@ where
             };
 
             for request in reqs {
-                client_rqs.push(ClientRqInfo::from(&request));
+                client_rqs.push(ClientRqInfo::from(&**request));
 
-                decided_batch.add_message(request);
+                decided_batch.add_message(into_executable(request.clone()));
             }
         }
 
@@ -191,4 +218,9 @@ pub enum LogError {
         install_attempt: SeqNo,
         currently_installed: SeqNo,
     },
+    #[error("Failed to install decision {install_attempt:?} as it is beyond our high watermark {high_watermark:?}")]
+    BeyondHighWatermark {
+        install_attempt: SeqNo,
+        high_watermark: SeqNo,
+    },
 }
@@ -0,0 +1,583 @@
+//! Log replay based catch up.
+//!
+//! When a replica falls a few consensus instances behind the rest of the quorum,
+//! it does not need the entire application state in order to catch up. Instead, it
+//! requests the proofs of the decisions it is missing from its peers, verifies them and
+//! installs them in order. Only when the peers no longer retain the proofs we need
+//! (as they have been discarded by a stable checkpoint) do we have to fall back to
+//! the state transfer protocol. Those peers hand us their stable checkpoint certificate
+//! instead, which anchors the state we are about to transfer.
+//!
+//! A proof is accepted on the strength of its quorum of signed commits. Prepares may have
+//! been authenticated with MACs, which only their recipients can verify, so they are not
+//! counted: a correct replica only commits a batch once it has seen a quorum of prepares.
+//!
+//! A replay that does not complete within its timeout is abandoned in favour of the state
+//! transfer protocol, so peers which never reply cannot stall us.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use atlas_common::error::*;
+use atlas_common::node_id::NodeId;
+use atlas_common::ordering::{Orderable, SeqNo};
+use atlas_common::serialization_helper::SerMsg;
+use atlas_common::Err;
+use atlas_communication::message::Header;
+use atlas_core::messages::SessionBased;
+use atlas_core::ordering_protocol::networking::serialize::NetworkView;
+use atlas_core::ordering_protocol::networking::OrderProtocolSendNode;
+use atlas_core::ordering_protocol::ShareableMessage;
+
+use crate::bft::checkpoint::CheckpointCertificate;
+use crate::bft::log::decisions::{Proof, StoredConsensusMessage};
+use crate::bft::log::Log;
+use crate::bft::message::{ConsensusMessageKind, LogReplayMessage, PBFTMessage};
+use crate::bft::sync::verify::batch_digest;
+use crate::bft::sync::view::ViewInfo;
+use crate::bft::PBFT;
+
+/// The most decisions we replay, or serve the proofs of, for a single request.
+/// Replicas that are further behind run the state transfer protocol instead
+pub const MAX_REPLAYED_PROOFS: u32 = 128;
+
+/// The last decision of the range starting at `first` that we replay or serve,
+/// given the last one that was requested
+fn replayed_range_end(first: SeqNo, last: SeqNo) -> SeqNo {
+    last.min(SeqNo::from(
+        u32::from(first).saturating_add(MAX_REPLAYED_PROOFS - 1),
+    ))
+}
+
+/// The status of the log replay, after processing a message
+pub enum ReplayStatus<RQ> {
+    /// We are not replaying, or the message did not contribute to the replay
+    Nil,
+    /// We are still waiting for more proofs
+    Running,
+    /// We have gathered all of the proofs we were missing.
+    /// They are ordered by sequence number and should be installed in that order
+    Done(Vec<Proof<RQ>>),
+    /// Our peers no longer retain the proofs we require, so we must
+    /// run the state transfer protocol
+    RunCst,
+}
+
+/// The range of decisions we are currently attempting to replay
+struct PendingReplay {
+    first: SeqNo,
+    last: SeqNo,
+    // The peers that have already responded to our request
+    responded: BTreeSet<NodeId>,
+    // The peers that have responded that they no longer retain the requested proofs
+    unavailable: BTreeSet<NodeId>,
+    // The instant after which we give up and run the state transfer protocol
+    deadline: Instant,
+}
+
+/// Keeps track of an ongoing log replay
+pub struct LogReplay<RQ> {
+    node_id: NodeId,
+    // How long we wait for the proofs before giving up on the replay
+    timeout: Duration,
+    pending: Option<PendingReplay>,
+    // The verified proofs we have received for the pending range
+    received: BTreeMap<SeqNo, Proof<RQ>>,
+}
+
+impl<RQ> LogReplay<RQ> {
+    pub fn new(node_id: NodeId, timeout: Duration) -> Self {
+        Self {
+            node_id,
+            timeout,
+            pending: None,
+            received: Default::default(),
+        }
+    }
+
+    /// Are we currently replaying the log
+    pub fn is_replaying(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    fn begin(&mut self, first: SeqNo, last: SeqNo) {
+        self.received.clear();
+        self.pending = Some(PendingReplay {
+            first,
+            last,
+            responded: Default::default(),
+            unavailable: Default::default(),
+            deadline: Instant::now() + self.timeout,
+        });
+    }
+
+    /// Abort the current log replay if it did not complete before its deadline,
+    /// in which case we must run the state transfer protocol instead.
+    /// Returns whether it was aborted
+    pub fn timed_out(&mut self) -> bool {
+        let pending = match &self.pending {
+            Some(pending) if Instant::now() >= pending.deadline => pending,
+            _ => return false,
+        };
+
+        warn!(
+            "{:?} // Log replay of decisions {:?} - {:?} timed out with {} proofs received, falling back to state transfer",
+            self.node_id,
+            pending.first,
+            pending.last,
+            self.received.len()
+        );
+
+        self.clear();
+
+        true
+    }
+
+    /// Handle a reply to our request for proofs
+    pub fn handle_reply(
+        &mut self,
+        message: ShareableMessage<PBFTMessage<RQ>>,
+        view: &ViewInfo,
+    ) -> Result<ReplayStatus<RQ>> {
+        let pending = match &mut self.pending {
+            Some(pending) => pending,
+            None => return Ok(ReplayStatus::Nil),
+        };
+
+        let sender = message.header().from();
+
+        if !pending.responded.insert(sender) {
+            return Err!(LogReplayError::DuplicateReply(sender));
+        }
+
+        match message.message().log_replay() {
+            LogReplayMessage::Proofs(proofs) => {
+                for proof in proofs {
+                    let seq = proof.sequence_number();
+
+                    if seq < pending.first || seq > pending.last || self.received.contains_key(&seq)
+                    {
+                        continue;
+                    }
+
+                    if let Err(err) = verify_replayed_proof(proof, view) {
+                        warn!(
+                            "{:?} // Received invalid proof {:?} from {:?}: {:?}",
+                            self.node_id, seq, sender, err
+                        );
+
+                        continue;
+                    }
+
+                    self.received.insert(seq, proof.clone());
+                }
+            }
+            LogReplayMessage::Unavailable(first_retained, _) => {
+                debug!(
+                    "{:?} // Node {:?} no longer retains the proofs we require, first retained: {:?}",
+                    self.node_id, sender, first_retained
+                );
+
+                pending.unavailable.insert(sender);
+            }
+            LogReplayMessage::RequestProofs(_, _) => return Ok(ReplayStatus::Nil),
+        }
+
+        let missing = (u32::from(pending.first)..=u32::from(pending.last))
+            .map(SeqNo::from)
+            .any(|seq| !self.received.contains_key(&seq));
+
+        if !missing {
+            info!(
+                "{:?} // Log replay gathered all {} proofs",
+                self.node_id,
+                self.received.len()
+            );
+
+            self.pending = None;
+
+            let proofs = std::mem::take(&mut self.received).into_values().collect();
+
+            return Ok(ReplayStatus::Done(proofs));
+        }
+
+        // If more than f replicas no longer retain the proofs, then at least one correct
+        // replica does not retain them and neither will the others, since they are discarded by stable checkpoints.
+        // The same goes for when all of our peers have responded and we are still missing proofs
+        if pending.unavailable.len() > view.params().f()
+            || pending.responded.len() >= view.params().n() - 1
+        {
+            warn!(
+                "{:?} // Unable to replay decisions {:?} - {:?} from our peers, falling back to state transfer",
+                self.node_id, pending.first, pending.last
+            );
+
+            self.clear();
+
+            return Ok(ReplayStatus::RunCst);
+        }
+
+        Ok(ReplayStatus::Running)
+    }
+
+    /// Abort the current log replay
+    pub fn clear(&mut self) {
+        self.pending = None;
+        self.received.clear();
+    }
+}
+
+impl<RQ> LogReplay<RQ>
+where
+    RQ: SerMsg + SessionBased,
+{
+    /// Start replaying the decisions in the range `[first, last]`,
+    /// by requesting their proofs from the rest of the quorum.
+    /// Returns whether the replay was started, as ranges of more than
+    /// [`MAX_REPLAYED_PROOFS`] decisions are left to the state transfer protocol
+    pub fn start<NT>(&mut self, first: SeqNo, last: SeqNo, view: &ViewInfo, node: &NT) -> bool
+    where
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+    {
+        if replayed_range_end(first, last) < last {
+            info!(
+                "{:?} // Too far behind to replay decisions {:?} - {:?}",
+                self.node_id, first, last
+            );
+
+            return false;
+        }
+
+        info!(
+            "{:?} // Starting log replay of decisions {:?} - {:?}",
+            self.node_id, first, last
+        );
+
+        self.begin(first, last);
+
+        let message = PBFTMessage::LogReplay(LogReplayMessage::RequestProofs(first, last));
+
+        let my_id = self.node_id;
+
+        let targets = view
+            .quorum_members()
+            .clone()
+            .into_iter()
+            .filter(move |member| *member != my_id);
+
+        let _ = node.broadcast_signed(message, targets);
+
+        true
+    }
+
+    /// Reply to a request by a member of the quorum for the proofs of the decisions in the
+    /// range `[first, last]`, serving at most [`MAX_REPLAYED_PROOFS`] of them.
+    /// Should we no longer retain them, we reply with our stable checkpoint instead
+    #[allow(clippy::too_many_arguments)]
+    pub fn handle_request<NT>(
+        &self,
+        header: &Header,
+        first: SeqNo,
+        last: SeqNo,
+        log: &Log<RQ>,
+        stable: Option<&CheckpointCertificate<RQ>>,
+        view: &ViewInfo,
+        node: &NT,
+    ) -> Result<()>
+    where
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+    {
+        if !view.quorum_members().contains(&header.from()) {
+            return Err!(LogReplayError::NotQuorumMember(header.from()));
+        }
+
+        let last = replayed_range_end(first, last);
+
+        let reply = match log.decision_log().proofs_in_range(first, last) {
+            Some(proofs) => {
+                debug!(
+                    "{:?} // Replying to log replay request from {:?} with {} proofs",
+                    self.node_id,
+                    header.from(),
+                    proofs.len()
+                );
+
+                LogReplayMessage::Proofs(proofs)
+            }
+            None => {
+                debug!("{:?} // Log replay request from {:?} for {:?} - {:?} refers to proofs we no longer retain",
+                    self.node_id, header.from(), first, last);
+
+                LogReplayMessage::Unavailable(log.decision_log().first_retained(), stable.cloned())
+            }
+        };
+
+        let _ = node.send_signed(PBFTMessage::LogReplay(reply), header.from(), true);
+
+        Ok(())
+    }
+}
+
+/// Verify that a proof received through log replay is actually backed by a quorum.
+/// The signatures of the contained messages have already been verified when the message was received.
+///
+/// The pre prepares must hash to the digest of the proof and belong to its instance, so that
+/// the commits for that digest actually decide the requests we are going to install
+fn verify_replayed_proof<RQ>(proof: &Proof<RQ>, view: &ViewInfo) -> Result<()> {
+    let seq = proof.sequence_number();
+
+    if !proof.are_pre_prepares_ordered()? {
+        return Err!(LogReplayError::PrePreparesNotOrdered(seq));
+    }
+
+    let digest = proof.batch_digest();
+
+    if proof.pre_prepares().is_empty() || batch_digest(proof.pre_prepares()) != digest {
+        return Err!(LogReplayError::DigestMismatch(seq));
+    }
+
+    let consensus = |message: &StoredConsensusMessage<RQ>| match message.message() {
+        PBFTMessage::Consensus(consensus) if consensus.sequence_number() == seq => Some(consensus),
+        _ => None,
+    };
+
+    let is_member = |message: &StoredConsensusMessage<RQ>| {
+        view.quorum_members().contains(&message.header().from())
+    };
+
+    // Every pre prepare of the batch was proposed in the same view, the one its commits belong to
+    let mut proposal_view = None;
+
+    for pre_prepare in proof.pre_prepares() {
+        let bound = is_member(pre_prepare)
+            && consensus(pre_prepare).is_some_and(|consensus| {
+                matches!(consensus.kind(), ConsensusMessageKind::PrePrepare(_))
+                    && *proposal_view.get_or_insert(consensus.view()) == consensus.view()
+            });
+
+        if !bound {
+            return Err!(LogReplayError::UnboundPrePrepare(
+                seq,
+                pre_prepare.header().from()
+            ));
+        }
+    }
+
+    let commits = proof
+        .commits()
+        .iter()
+        .filter(|message| is_member(message))
+        .filter(|message| {
+            consensus(message).is_some_and(|consensus| {
+                // Only signed votes can be verified by us
+                consensus.authenticator().is_none()
+                    && Some(consensus.view()) == proposal_view
+                    && matches!(consensus.kind(), ConsensusMessageKind::Commit(voted) if *voted == digest)
+            })
+        })
+        .map(|message| message.header().from())
+        .collect::<BTreeSet<_>>()
+        .len();
+
+    let quorum = view.params().quorum();
+
+    if commits < quorum {
+        return Err!(LogReplayError::NotEnoughCommits(seq, commits, quorum));
+    }
+
+    Ok(())
+}
+
+#[derive(Error, Debug)]
+pub enum LogReplayError {
+    #[error("Node {0:?} has already replied to our log replay request")]
+    DuplicateReply(NodeId),
+    #[error("The pre prepares of the proof {0:?} are not ordered")]
+    PrePreparesNotOrdered(SeqNo),
+    #[error("The pre prepares of the proof {0:?} do not hash to its digest")]
+    DigestMismatch(SeqNo),
+    #[error("The pre prepare of {1:?} in the proof {0:?} does not belong to its instance")]
+    UnboundPrePrepare(SeqNo, NodeId),
+    #[error("The proof {0:?} only contains {1} matching commits, needed {2}")]
+    NotEnoughCommits(SeqNo, usize, usize),
+    #[error("Node {0:?} requested proofs but is not a member of the quorum")]
+    NotQuorumMember(NodeId),
+}
+
+#[cfg(test)]
+mod replay_tests {
+    use crate::bft::fixtures::{digest, shared};
+    use crate::bft::log::decisions::ProofMetadata;
+    use crate::bft::message::ConsensusMessage;
+
+    use super::*;
+
+    fn seq(seq: u32) -> SeqNo {
+        SeqNo::from(seq)
+    }
+
+    fn consensus(
+        from: u32,
+        digest_byte: u8,
+        message: ConsensusMessage<u8>,
+    ) -> StoredConsensusMessage<u8> {
+        shared(
+            NodeId::from(from),
+            digest(digest_byte),
+            PBFTMessage::Consensus(message),
+        )
+    }
+
+    /// A proof for the instance `instance` whose pre prepare was proposed for `proposed`,
+    /// committed by the given voters
+    fn proof(instance: u32, proposed: u32, voters: &[u32]) -> Proof<u8> {
+        let pre_prepare = consensus(
+            0,
+            instance as u8,
+            ConsensusMessage::new(
+                seq(proposed),
+                SeqNo::ZERO,
+                ConsensusMessageKind::PrePrepare(Vec::new()),
+            ),
+        );
+
+        let batch = batch_digest(&[pre_prepare.clone()]);
+
+        let commits = voters
+            .iter()
+            .map(|voter| {
+                consensus(
+                    *voter,
+                    100 + *voter as u8,
+                    ConsensusMessage::new(
+                        seq(instance),
+                        SeqNo::ZERO,
+                        ConsensusMessageKind::Commit(batch),
+                    ),
+                )
+            })
+            .collect();
+
+        let metadata = ProofMetadata::new(
+            seq(instance),
+            batch,
+            vec![*pre_prepare.header().digest()],
+            0,
+        );
+
+        Proof::new(metadata, vec![pre_prepare], Vec::new(), commits)
+    }
+
+    fn view() -> ViewInfo {
+        ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap()
+    }
+
+    fn reply(from: u32, proofs: Vec<Proof<u8>>) -> ShareableMessage<PBFTMessage<u8>> {
+        shared(
+            NodeId::from(from),
+            digest(200 + from as u8),
+            PBFTMessage::LogReplay(LogReplayMessage::Proofs(proofs)),
+        )
+    }
+
+    #[test]
+    fn test_proof_with_a_quorum_of_commits_is_accepted() {
+        assert!(verify_replayed_proof(&proof(1, 1, &[0, 1, 2]), &view()).is_ok());
+        assert!(verify_replayed_proof(&proof(1, 1, &[0, 1]), &view()).is_err());
+
+        // Repeated votes count once
+        assert!(verify_replayed_proof(&proof(1, 1, &[0, 1, 1]), &view()).is_err());
+    }
+
+    #[test]
+    fn test_commits_from_outside_the_quorum_are_not_counted() {
+        assert!(verify_replayed_proof(&proof(1, 1, &[0, 1, 7]), &view()).is_err());
+    }
+
+    #[test]
+    fn test_pre_prepare_must_belong_to_the_instance() {
+        // The commits vote for the digest of a pre prepare of another instance
+        assert!(verify_replayed_proof(&proof(1, 2, &[0, 1, 2]), &view()).is_err());
+    }
+
+    #[test]
+    fn test_pre_prepares_must_hash_to_the_digest() {
+        let valid = proof(1, 1, &[0, 1, 2]);
+
+        let metadata =
+            ProofMetadata::new(seq(1), digest(42), valid.pre_prepare_ordering().clone(), 0);
+
+        let (_, messages) = valid.into_parts();
+
+        let forged = Proof::init_from_messages(metadata, messages).unwrap();
+
+        assert!(verify_replayed_proof(&forged, &view()).is_err());
+    }
+
+    #[test]
+    fn test_invalid_proof_does_not_discard_the_rest_of_the_reply() {
+        let view = view();
+
+        let mut replay = LogReplay::<u8>::new(NodeId::from(3u32), Duration::from_secs(60));
+
+        replay.begin(seq(1), seq(2));
+
+        let status = replay
+            .handle_reply(
+                reply(1, vec![proof(1, 1, &[0, 1]), proof(2, 2, &[0, 1, 2])]),
+                &view,
+            )
+            .unwrap();
+
+        assert!(matches!(status, ReplayStatus::Running));
+
+        let status = replay
+            .handle_reply(reply(2, vec![proof(1, 1, &[0, 1, 2])]), &view)
+            .unwrap();
+
+        match status {
+            ReplayStatus::Done(proofs) => {
+                let seqs: Vec<_> = proofs.iter().map(Orderable::sequence_number).collect();
+
+                assert_eq!(seqs, vec![seq(1), seq(2)]);
+            }
+            _ => panic!("The replay should have gathered every proof"),
+        }
+
+        assert!(!replay.is_replaying());
+    }
+
+    #[test]
+    fn test_replayed_ranges_are_clamped() {
+        assert_eq!(replayed_range_end(seq(1), seq(10)), seq(10));
+        assert_eq!(
+            replayed_range_end(seq(1), seq(1_000)),
+            seq(MAX_REPLAYED_PROOFS)
+        );
+        assert_eq!(
+            replayed_range_end(seq(u32::MAX - 1), seq(u32::MAX)),
+            seq(u32::MAX)
+        );
+    }
+
+    #[test]
+    fn test_replay_gives_up_at_its_deadline() {
+        let mut replay = LogReplay::<u8>::new(NodeId::from(3u32), Duration::from_secs(60));
+
+        replay.begin(seq(1), seq(2));
+
+        assert!(!replay.timed_out());
+        assert!(replay.is_replaying());
+
+        let mut replay = LogReplay::<u8>::new(NodeId::from(3u32), Duration::ZERO);
+
+        replay.begin(seq(1), seq(2));
+
+        assert!(replay.timed_out());
+        assert!(!replay.is_replaying());
+        assert!(!replay.timed_out());
+    }
+}
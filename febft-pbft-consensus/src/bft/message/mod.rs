@@ -1,8 +1,10 @@
 //! This module contains types associated with messages traded
 //! between the system processes.
 
+use std::collections::BTreeMap;
 use std::fmt::{Debug, Formatter};
 use std::io::Write;
+use std::sync::Arc;
 
 use getset::Getters;
 #[cfg(feature = "serialize_serde")]
@@ -13,13 +15,25 @@ use atlas_common::crypto::hash::Digest;
 use atlas_common::node_id::NodeId;
 use atlas_common::ordering::{Orderable, SeqNo};
 use atlas_communication::message::{Header, StoredMessage};
+use atlas_core::messages::ClientRqInfo;
 
-use crate::bft::log::decisions::CollectData;
+use crate::bft::checkpoint::CheckpointCertificate;
+use crate::bft::log::decisions::{CollectData, Proof};
+use crate::bft::sync::view::election::LeaderElection;
 use crate::bft::sync::view::ViewInfo;
 use crate::bft::sync::LeaderCollects;
 
 pub mod serialize;
 
+/// A client request proposed in a pre prepare.
+///
+/// Requests are shared (instead of copied) between the pre prepare they were received in,
+/// the consensus instance and the decision log, so a request body is only ever deserialized once.
+/// The executor takes ownership of the requests it executes, while the decision log must keep
+/// them, so each request is copied once when its batch is handed over to the executor.
+/// The wire format is the same as the one of a plain [`StoredMessage`]
+pub type SharedRequest<O> = Arc<StoredMessage<O>>;
+
 /// PBFT protocol messages
 #[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
 #[derive(Clone)]
@@ -28,6 +42,14 @@ pub enum PBFTMessage<R> {
     Consensus(ConsensusMessage<R>),
     /// View change messages
     ViewChange(ViewChangeMessage<R>),
+    /// Checkpoint messages, used to agree on stable checkpoints
+    Checkpoint(CheckpointMessage),
+    /// Log replay messages, used to catch up with the rest of the quorum
+    LogReplay(LogReplayMessage<R>),
+    /// Fetch messages, used to obtain the client requests advertised in STOP messages
+    FetchRequests(FetchRequestsMessage<R>),
+    /// Session key messages, used to establish the keys for MAC authenticators
+    SessionKey(SessionKeyMessage),
     //Observer related messages
     ObserverMessage(ObserverMessage),
 }
@@ -41,6 +63,18 @@ impl<R> Debug for PBFTMessage<R> {
             PBFTMessage::ViewChange(_) => {
                 write!(f, "View change msg")
             }
+            PBFTMessage::Checkpoint(checkpoint) => {
+                write!(f, "Checkpoint msg {:?}", checkpoint)
+            }
+            PBFTMessage::LogReplay(replay) => {
+                write!(f, "Log replay msg {:?}", replay)
+            }
+            PBFTMessage::FetchRequests(fetch) => {
+                write!(f, "Fetch requests msg {:?}", fetch)
+            }
+            PBFTMessage::SessionKey(_) => {
+                write!(f, "Session key msg")
+            }
             PBFTMessage::ObserverMessage(_) => {
                 write!(f, "Observer msg")
             }
@@ -53,6 +87,10 @@ impl<R> Orderable for PBFTMessage<R> {
         match self {
             PBFTMessage::Consensus(consensus) => consensus.sequence_number(),
             PBFTMessage::ViewChange(view) => view.sequence_number(),
+            PBFTMessage::Checkpoint(checkpoint) => checkpoint.sequence_number(),
+            PBFTMessage::LogReplay(_replay) => SeqNo::ZERO,
+            PBFTMessage::FetchRequests(_fetch) => SeqNo::ZERO,
+            PBFTMessage::SessionKey(_key) => SeqNo::ZERO,
             PBFTMessage::ObserverMessage(_obs) => SeqNo::ZERO,
         }
     }
@@ -87,6 +125,34 @@ impl<R> PBFTMessage<R> {
         }
     }
 
+    pub fn checkpoint(&self) -> &CheckpointMessage {
+        match self {
+            PBFTMessage::Checkpoint(msg) => msg,
+            _ => panic!("Not a checkpoint message"),
+        }
+    }
+
+    pub fn log_replay(&self) -> &LogReplayMessage<R> {
+        match self {
+            PBFTMessage::LogReplay(msg) => msg,
+            _ => panic!("Not a log replay message"),
+        }
+    }
+
+    pub fn fetch_requests(&self) -> &FetchRequestsMessage<R> {
+        match self {
+            PBFTMessage::FetchRequests(msg) => msg,
+            _ => panic!("Not a fetch requests message"),
+        }
+    }
+
+    pub fn session_key(&self) -> &SessionKeyMessage {
+        match self {
+            PBFTMessage::SessionKey(msg) => msg,
+            _ => panic!("Not a session key message"),
+        }
+    }
+
     pub fn observer_message(&self) -> &ObserverMessage {
         match self {
             PBFTMessage::ObserverMessage(msg) => msg,
@@ -151,10 +217,14 @@ impl<O> ViewChangeMessage<O> {
 #[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
 #[derive(Clone)]
 pub enum ViewChangeMessageKind<O> {
-    /// A STOP message, broadcast when we want to call a view change due to requests getting timed out
-    Stop(Vec<StoredMessage<O>>),
+    /// A STOP message, broadcast when we want to call a view change due to requests getting timed out.
+    /// Only carries the digests of the requests, the missing bodies are fetched from the sender
+    Stop(Vec<ClientRqInfo>),
     /// A STOP message, broadcast when we want to call a view change due to us having received a Node Quorum Join message
     StopQuorumJoin(NodeId),
+    /// Sent to the candidates of a join round which ended without any of them receiving a
+    /// quorum of votes, so they stop waiting for the view which would have added them
+    AbortQuorumJoin,
     // Each of the latest decisions from the sender, so the new leader can sync
     StopData(CollectData<O>),
     Sync(LeaderCollects<O>),
@@ -171,6 +241,8 @@ pub struct ConsensusMessage<O> {
     view: SeqNo,
     nonce: u16,
     kind: ConsensusMessageKind<O>,
+    // The MAC vector authenticating this message, when it is not signed
+    authenticator: Option<Authenticator>,
 }
 
 impl<O> Debug for ConsensusMessage<O> {
@@ -197,9 +269,8 @@ pub enum ConsensusMessageKind<O> {
     /// Pre-prepare a request, according to the BFT consensus protocol.
     /// Sent by a single leader
     ///
-    /// The value `Vec<Digest>` contains a batch of hash digests of the
-    /// serialized client requests to be proposed.
-    PrePrepare(Vec<StoredMessage<O>>),
+    /// The value contains the batch of client requests to be proposed.
+    PrePrepare(Vec<SharedRequest<O>>),
     /// Prepare a batch of requests.
     ///
     /// The `Digest` represents the hash of the serialized `PRE-PREPARE`,
@@ -246,9 +317,24 @@ impl<O> ConsensusMessage<O> {
             view,
             kind,
             nonce,
+            authenticator: None,
         }
     }
 
+    pub fn nonce(&self) -> u16 {
+        self.nonce
+    }
+
+    /// The MAC vector authenticating this message, if it was
+    /// authenticated with MACs instead of a signature
+    pub fn authenticator(&self) -> Option<&Authenticator> {
+        self.authenticator.as_ref()
+    }
+
+    pub fn set_authenticator(&mut self, authenticator: Authenticator) {
+        self.authenticator = Some(authenticator);
+    }
+
     /// Returns a reference to the consensus message kind.
     pub fn kind(&self) -> &ConsensusMessageKind<O> {
         &self.kind
@@ -278,7 +364,7 @@ impl<O> ConsensusMessage<O> {
 
     /// Takes the proposed client requests embedded in this consensus message,
     /// if they are available.
-    pub fn take_proposed_requests(&mut self) -> Option<Vec<StoredMessage<O>>> {
+    pub fn take_proposed_requests(&mut self) -> Option<Vec<SharedRequest<O>>> {
         let kind = std::mem::replace(&mut self.kind, ConsensusMessageKind::PrePrepare(Vec::new()));
         match kind {
             ConsensusMessageKind::PrePrepare(v) => Some(v),
@@ -290,6 +376,158 @@ impl<O> ConsensusMessage<O> {
     }
 }
 
+/// A PBFT checkpoint message.
+///
+/// Broadcast by a replica after it has taken a local checkpoint of the
+/// application state at sequence number `seq`, whose digest is `digest`.
+///
+/// It also carries the leader election history agreed on by the view the replica is in, so
+/// that a replica which skips the decisions installed by a state transfer can still elect
+/// the same leaders as the rest of the quorum.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Getters)]
+pub struct CheckpointMessage {
+    seq: SeqNo,
+    #[get = "pub"]
+    digest: Digest,
+    view: SeqNo,
+    #[get = "pub"]
+    election: LeaderElection,
+}
+
+impl Orderable for CheckpointMessage {
+    /// Returns the sequence number of the checkpointed state.
+    fn sequence_number(&self) -> SeqNo {
+        self.seq
+    }
+}
+
+impl Debug for CheckpointMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Seq: {:?} Digest: {:?} View: {:?}",
+            self.seq, self.digest, self.view
+        )
+    }
+}
+
+impl CheckpointMessage {
+    /// Creates a new `CheckpointMessage` for the state at sequence number `seq`,
+    /// with the state digest `digest`, sent while in the view `view`, whose
+    /// agreed election history is `election`.
+    pub fn new(seq: SeqNo, digest: Digest, view: SeqNo, election: LeaderElection) -> Self {
+        Self {
+            seq,
+            digest,
+            view,
+            election,
+        }
+    }
+
+    /// The view the sender was in when it took the checkpoint
+    pub fn view(&self) -> SeqNo {
+        self.view
+    }
+}
+
+/// A vector of MACs, one for each of the recipients of a message,
+/// computed with the session key shared between the sender and that recipient.
+///
+/// Unlike signatures, these can only be verified by their recipients,
+/// so messages authenticated this way do not constitute transferable proof.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Default)]
+pub struct Authenticator(BTreeMap<NodeId, Digest>);
+
+impl Authenticator {
+    pub fn insert(&mut self, recipient: NodeId, mac: Digest) {
+        self.0.insert(recipient, mac);
+    }
+
+    /// The MAC destined to the given recipient
+    pub fn mac_for(&self, recipient: &NodeId) -> Option<&Digest> {
+        self.0.get(recipient)
+    }
+}
+
+/// Announces the Diffie-Hellman public value of the sender, from which the recipient
+/// derives the session key they share. The key itself is never sent.
+///
+/// Only ever sent signed, so the exchange cannot be tampered with
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Getters)]
+pub struct SessionKeyMessage {
+    #[get = "pub"]
+    public_key: Vec<u8>,
+}
+
+impl SessionKeyMessage {
+    pub fn new(public_key: Vec<u8>) -> Self {
+        Self { public_key }
+    }
+}
+
+/// Messages used by a lagging replica to catch up with the quorum by
+/// replaying the decided proofs it is missing, instead of transferring the entire state.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub enum LogReplayMessage<O> {
+    /// Request the proofs of the decisions in the range `[first, last]`
+    RequestProofs(SeqNo, SeqNo),
+    /// The requested proofs, ordered by sequence number
+    Proofs(Vec<Proof<O>>),
+    /// The requested proofs are no longer retained by this replica.
+    /// Carries the first sequence number that is still retained, if any, along with
+    /// the stable checkpoint which discarded them, on which the state transfer is anchored
+    Unavailable(Option<SeqNo>, Option<CheckpointCertificate<O>>),
+}
+
+/// Messages used to fetch the bodies of the client requests advertised in a STOP message,
+/// which only carries their digests, from the replica which sent it
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub enum FetchRequestsMessage<O> {
+    /// Request the bodies of the given client requests
+    Request(Vec<ClientRqInfo>),
+    /// The requested bodies which the replica has
+    Reply(Vec<StoredMessage<O>>),
+}
+
+impl<O> Debug for FetchRequestsMessage<O> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchRequestsMessage::Request(requests) => {
+                write!(f, "Request {} requests", requests.len())
+            }
+            FetchRequestsMessage::Reply(requests) => {
+                write!(f, "Reply with {} requests", requests.len())
+            }
+        }
+    }
+}
+
+impl<O> Debug for LogReplayMessage<O> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogReplayMessage::RequestProofs(first, last) => {
+                write!(f, "Request proofs {:?} - {:?}", first, last)
+            }
+            LogReplayMessage::Proofs(proofs) => {
+                write!(f, "Reply with {} proofs", proofs.len())
+            }
+            LogReplayMessage::Unavailable(first_retained, stable) => {
+                write!(
+                    f,
+                    "Proofs unavailable, first retained {:?}, stable checkpoint {:?}",
+                    first_retained,
+                    stable.as_ref().map(|stable| stable.sequence_number())
+                )
+            }
+        }
+    }
+}
+
 #[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
 #[derive(Clone, Getters)]
 pub struct FwdConsensusMessage<O> {
@@ -376,6 +614,9 @@ pub enum ObserveEventKind {
     /// Report that the replica is now in the collaborative state
     /// transfer state
     CollabStateTransfer,
+    ///Report that the given replica has been caught signing conflicting
+    /// consensus messages for the given sequence number
+    Equivocation(NodeId, SeqNo),
 }
 
 impl Debug for ObserveEventKind {
@@ -411,6 +652,9 @@ impl Debug for ObserveEventKind {
             ObserveEventKind::Executed(seq) => {
                 write!(f, "Executed the consensus instance {:?}", seq)
             }
+            ObserveEventKind::Equivocation(node, seq) => {
+                write!(f, "Node {:?} equivocated in consensus instance {:?}", node, seq)
+            }
         }
     }
 }
@@ -430,6 +674,9 @@ impl<O> Debug for ViewChangeMessageKind<O> {
             ViewChangeMessageKind::StopQuorumJoin(node) => {
                 write!(f, "Stop quorum join message {:?}", node)
             }
+            ViewChangeMessageKind::AbortQuorumJoin => {
+                write!(f, "Abort quorum join message")
+            }
         }
     }
 }
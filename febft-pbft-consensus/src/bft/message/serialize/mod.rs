@@ -13,6 +13,7 @@ use std::sync::Arc;
 use atlas_common::error::*;
 use atlas_common::ordering::Orderable;
 use atlas_common::serialization_helper::SerMsg;
+use atlas_common::Err;
 use atlas_communication::message::Header;
 use atlas_communication::reconfiguration::NetworkInformationProvider;
 use atlas_core::ordering_protocol::loggable::message::PersistentOrderProtocolTypes;
@@ -20,9 +21,10 @@ use atlas_core::ordering_protocol::networking::serialize::{
     OrderProtocolVerificationHelper, OrderingProtocolMessage, PermissionedOrderingProtocolMessage,
 };
 
-use crate::bft::log::decisions::{Proof, ProofMetadata};
+use crate::bft::log::decisions::{Proof, ProofError, ProofMetadata};
 use crate::bft::message::{
-    ConsensusMessage, ConsensusMessageKind, PBFTMessage, ViewChangeMessageKind,
+    ConsensusMessage, ConsensusMessageKind, FetchRequestsMessage, LogReplayMessage, PBFTMessage,
+    ViewChangeMessageKind,
 };
 use crate::bft::sync::view::ViewInfo;
 
@@ -32,6 +34,8 @@ pub mod capnp;
 #[cfg(feature = "serialize_serde")]
 pub mod serde;
 
+pub mod verify;
+
 pub fn serialize_consensus<W, RQ>(w: &mut W, message: &ConsensusMessage<RQ>) -> Result<()>
 where
     RQ: SerMsg,
@@ -87,21 +91,12 @@ where
                 let (_seq, _view) = (consensus.sequence_number(), consensus.view());
 
                 match consensus.kind() {
+                    // Their size is checked by the protocol, which knows the configured limit
                     ConsensusMessageKind::PrePrepare(requests) => {
-                        let request_iter = requests.iter();
-
-                        for request in request_iter {
-                            let (header, message) = (request.header(), request.message());
-
-                            let _ = OPVH::verify_request_message(
-                                network_info,
-                                header,
-                                message.clone(),
-                            )?;
-                        }
-
-                        Ok(())
+                        verify::verify_requests::<RQ, _, Self, NI, OPVH>(network_info, requests)
                     }
+                    // Prepares and commits authenticated with MACs can only be verified by
+                    // their recipient, so they are verified by the protocol itself
                     ConsensusMessageKind::Prepare(_digest) => Ok(()),
                     ConsensusMessageKind::Commit(_digest) => Ok(()),
                 }
@@ -110,20 +105,10 @@ where
                 let _view = view_change.sequence_number();
 
                 match view_change.kind() {
-                    ViewChangeMessageKind::Stop(timed_out_req) => {
-                        for client_rq in timed_out_req.iter() {
-                            let (header, message) = (client_rq.header(), client_rq.message());
-
-                            let _ = OPVH::verify_request_message(
-                                network_info,
-                                header,
-                                message.clone(),
-                            )?;
-                        }
-
-                        Ok(())
-                    }
+                    // The bodies of the requests are verified when they are fetched
+                    ViewChangeMessageKind::Stop(_timed_out_rqs) => Ok(()),
                     ViewChangeMessageKind::StopQuorumJoin(_node) => Ok(()),
+                    ViewChangeMessageKind::AbortQuorumJoin => Ok(()),
                     ViewChangeMessageKind::StopData(collect_data) => {
                         if let Some(_proof) = &collect_data.last_proof {}
 
@@ -157,7 +142,59 @@ where
                     }
                 }
             }
+            PBFTMessage::Checkpoint(_checkpoint) => Ok(()),
+            PBFTMessage::LogReplay(replay) => match replay {
+                LogReplayMessage::Proofs(proofs) => {
+                    for proof in proofs {
+                        let messages = proof
+                            .pre_prepares()
+                            .iter()
+                            .chain(proof.prepares().iter())
+                            .chain(proof.commits().iter());
+
+                        if !proof.is_transferable() {
+                            return Err!(ProofError::NotTransferable(proof.sequence_number()));
+                        }
+
+                        // Prepares authenticated with MACs cannot be verified by us,
+                        // the proof stands on its signed commits
+                        let messages = messages
+                            .filter(|message| message.message().consensus().authenticator().is_none());
+
+                        for message in messages {
+                            let _ = OPVH::verify_protocol_message(
+                                network_info,
+                                message.header(),
+                                message.message().clone(),
+                            )?;
+                        }
+                    }
+
+                    Ok(())
+                }
+                LogReplayMessage::Unavailable(_, Some(stable)) => {
+                    for message in stable.messages() {
+                        let _ = OPVH::verify_protocol_message(
+                            network_info,
+                            message.header(),
+                            message.message().clone(),
+                        )?;
+                    }
+
+                    Ok(())
+                }
+                LogReplayMessage::RequestProofs(_, _) | LogReplayMessage::Unavailable(_, None) => {
+                    Ok(())
+                }
+            },
+            PBFTMessage::FetchRequests(fetch) => match fetch {
+                FetchRequestsMessage::Reply(requests) => {
+                    verify::verify_requests::<RQ, _, Self, NI, OPVH>(network_info, requests)
+                }
+                FetchRequestsMessage::Request(_) => Ok(()),
+            },
             PBFTMessage::ObserverMessage(_m) => Ok(()),
+            PBFTMessage::SessionKey(_key) => Ok(()),
         }
     }
 
@@ -226,9 +263,20 @@ where
         OPVH: OrderProtocolVerificationHelper<RQ, Self, NI>,
         Self: Sized,
     {
+        if !proof.is_transferable() {
+            return Err!(ProofError::NotTransferable(proof.sequence_number()));
+        }
+
         let (metadata, messages) = proof.into_parts();
 
-        for msg in &messages {
+        // Prepares authenticated with MACs cannot be verified by us,
+        // the proof stands on its signed commits
+        let signed = messages.iter().filter(|msg| match msg.message() {
+            PBFTMessage::Consensus(consensus) => consensus.authenticator().is_none(),
+            _ => true,
+        });
+
+        for msg in signed {
             let _ =
                 OPVH::verify_protocol_message(network_info, msg.header(), msg.message().clone())?;
         }
@@ -0,0 +1,227 @@
+//! Verification of the client requests carried by `PRE-PREPARE` and `STOP` messages.
+//!
+//! A batch can hold thousands of requests, each with its own signature, and a replica
+//! can only vote for the batch once all of them have been verified. Verifying them one
+//! at a time dominates the time between receiving a `PRE-PREPARE` and sending our `PREPARE`,
+//! so large batches are split into chunks which are verified in parallel by the rayon worker pool.
+//!
+//! Every request goes through the full verification of the [`OrderProtocolVerificationHelper`],
+//! which binds its payload to the signed digest and runs the checks of the application, so a
+//! leader cannot swap the body of a request for another. The signature scheme is hidden behind
+//! that helper and exposes no batch verification, so the worker pool verifies each request of
+//! its chunk on its own, which means a failed batch always points to the offending request.
+//!
+//! The byte limit on the requests of a pre prepare is part of the protocol's configuration,
+//! so it is checked by the protocol itself with [`verify_pre_prepare_size`].
+
+use std::borrow::Borrow;
+use std::sync::Arc;
+use std::time::Instant;
+
+use rayon::prelude::*;
+use thiserror::Error;
+
+use atlas_common::error::*;
+use atlas_common::node_id::NodeId;
+use atlas_common::serialization_helper::SerMsg;
+use atlas_common::Err;
+use atlas_communication::message::StoredMessage;
+use atlas_communication::reconfiguration::NetworkInformationProvider;
+use atlas_core::ordering_protocol::networking::serialize::{
+    OrderProtocolVerificationHelper, OrderingProtocolMessage,
+};
+use atlas_metrics::metrics::{metric_duration, metric_increment};
+
+use crate::bft::metric::{INVALID_REQUESTS_ID, REQUEST_VERIFICATION_TIME_ID};
+
+/// Batches smaller than this are verified serially, as spreading
+/// them over the worker pool would cost more than it saves
+const PARALLEL_VERIFICATION_THRESHOLD: usize = 32;
+
+/// The amount of requests verified by each task of the worker pool
+const VERIFICATION_CHUNK_SIZE: usize = 16;
+
+/// The size of a request, as it was serialized
+#[inline]
+pub fn request_size<RQ>(request: &StoredMessage<RQ>) -> usize {
+    request.header().payload_length()
+}
+
+/// Check that the requests of a pre prepare fit within the byte limit, if there is one
+pub fn verify_pre_prepare_size<RQ, R>(requests: &[R], limit: Option<usize>) -> Result<()>
+where
+    R: Borrow<StoredMessage<RQ>>,
+{
+    let Some(limit) = limit else {
+        return Ok(());
+    };
+
+    let bytes = requests
+        .iter()
+        .map(|request| request_size(request.borrow()))
+        .sum::<usize>();
+
+    if bytes > limit {
+        return Err!(RequestVerificationError::PrePrepareTooLarge { bytes, limit });
+    }
+
+    Ok(())
+}
+
+/// Verify all the given requests, along with their signatures.
+///
+/// Should any of them fail, the returned error identifies the first offending request
+/// of the batch by its position and sender
+pub fn verify_requests<RQ, R, OP, NI, OPVH>(network_info: &Arc<NI>, requests: &[R]) -> Result<()>
+where
+    RQ: SerMsg,
+    R: Borrow<StoredMessage<RQ>> + Sync,
+    OP: OrderingProtocolMessage<RQ>,
+    NI: NetworkInformationProvider,
+    OPVH: OrderProtocolVerificationHelper<RQ, OP, NI>,
+{
+    let start = Instant::now();
+
+    let failure = first_failure(requests, |request| {
+        let request: &StoredMessage<RQ> = request.borrow();
+
+        OPVH::verify_request_message(network_info, request.header(), request.message().clone())
+            .err()
+    });
+
+    metric_duration(REQUEST_VERIFICATION_TIME_ID, start.elapsed());
+
+    match failure {
+        None => Ok(()),
+        Some((index, error)) => {
+            metric_increment(INVALID_REQUESTS_ID, Some(1));
+
+            Err!(RequestVerificationError::InvalidRequest {
+                index,
+                batch_size: requests.len(),
+                from: requests[index].borrow().header().from(),
+                reason: format!("{:?}", error),
+            })
+        }
+    }
+}
+
+/// Find the first of the requests which fails the given check, along with its position.
+///
+/// Large batches are checked in parallel, but the failure reported is always the
+/// one which comes first in the batch
+fn first_failure<R, E, F>(requests: &[R], verify: F) -> Option<(usize, E)>
+where
+    R: Sync,
+    E: Send,
+    F: Fn(&R) -> Option<E> + Sync,
+{
+    let verify = |index: usize, request: &R| verify(request).map(|error| (index, error));
+
+    if requests.len() < PARALLEL_VERIFICATION_THRESHOLD {
+        requests
+            .iter()
+            .enumerate()
+            .find_map(|(index, request)| verify(index, request))
+    } else {
+        requests
+            .par_chunks(VERIFICATION_CHUNK_SIZE)
+            .enumerate()
+            .find_map_first(|(chunk, requests)| {
+                requests.iter().enumerate().find_map(|(offset, request)| {
+                    verify(chunk * VERIFICATION_CHUNK_SIZE + offset, request)
+                })
+            })
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RequestVerificationError {
+    #[error("Request {index} (of {batch_size}) sent by {from:?} failed verification: {reason}")]
+    InvalidRequest {
+        index: usize,
+        batch_size: usize,
+        from: NodeId,
+        reason: String,
+    },
+    #[error("The requests of the pre prepare take up {bytes} bytes, over the limit of {limit}")]
+    PrePrepareTooLarge { bytes: usize, limit: usize },
+}
+
+#[cfg(test)]
+mod verify_tests {
+    use atlas_communication::lookup_table::MessageModule;
+
+    use crate::bft::fixtures::{digest, header_with};
+
+    use super::*;
+
+    fn request(bytes: usize) -> StoredMessage<u8> {
+        let header = header_with(MessageModule::Protocol, NodeId::from(0u32), digest(0), bytes);
+
+        StoredMessage::new(header, 0)
+    }
+
+    #[test]
+    fn test_pre_prepare_within_limit() {
+        let requests = vec![request(100), request(200)];
+
+        assert!(verify_pre_prepare_size::<u8, _>(&requests, Some(300)).is_ok());
+        assert!(verify_pre_prepare_size::<u8, _>(&requests, None).is_ok());
+    }
+
+    #[test]
+    fn test_pre_prepare_over_limit() {
+        let requests = vec![request(100), request(201)];
+
+        let err = verify_pre_prepare_size::<u8, _>(&requests, Some(300)).unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<RequestVerificationError>(),
+            Some(RequestVerificationError::PrePrepareTooLarge {
+                bytes: 301,
+                limit: 300
+            })
+        ));
+    }
+
+    /// Fails the requests whose value is in `invalid`, reporting that value
+    fn check(invalid: &[u8]) -> impl Fn(&u8) -> Option<u8> + Sync + '_ {
+        move |request| invalid.contains(request).then_some(*request)
+    }
+
+    #[test]
+    fn test_valid_batch_has_no_failure() {
+        let small = (0..10u8).collect::<Vec<_>>();
+        let large = (0..200u8).collect::<Vec<_>>();
+
+        assert!(first_failure(&small, check(&[])).is_none());
+        assert!(first_failure(&large, check(&[])).is_none());
+    }
+
+    #[test]
+    fn test_offending_request_reported_serially() {
+        let requests = (0..10u8).collect::<Vec<_>>();
+
+        assert_eq!(first_failure(&requests, check(&[7])), Some((7, 7)));
+        assert_eq!(first_failure(&requests, check(&[8, 3])), Some((3, 3)));
+    }
+
+    #[test]
+    fn test_offending_request_reported_in_parallel() {
+        let requests = (0..200u8).rev().collect::<Vec<_>>();
+
+        // Position 0 holds 199, position 199 holds 0
+        assert_eq!(first_failure(&requests, check(&[0])), Some((199, 0)));
+
+        // The first failure of the batch is reported, whichever chunk finishes first
+        let invalid = [199 - 150, 199 - 17, 199 - 16, 199 - 180];
+
+        for _ in 0..50 {
+            assert_eq!(
+                first_failure(&requests, check(&invalid)),
+                Some((16, 199 - 16))
+            );
+        }
+    }
+}
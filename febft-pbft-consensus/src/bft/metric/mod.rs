@@ -2,7 +2,7 @@ use atlas_metrics::metrics::{metric_duration, metric_store_count, MetricKind};
 use atlas_metrics::{MetricLevel, MetricRegistry};
 use lazy_static::lazy_static;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 /// Consensus will take the ID range 1XX, for now
 ///
@@ -34,7 +34,7 @@ pub const PROPOSER_PROPOSE_TIME_ID: usize = 107;
 
 pub const PROPOSER_REQUEST_TIME_ITERATIONS: &str = "PROPOSER_REQUEST_TIME_ITERATIONS";
 pub const PROPOSER_REQUEST_TIME_ITERATIONS_ID: usize = 108;
-/// 110-119: Consensus
+/// 110-129: Consensus
 
 pub const PROPOSE_LATENCY: &str = "PROPOSE_LATENCY";
 pub const PROPOSE_LATENCY_ID: usize = 110;
@@ -72,7 +72,36 @@ pub const CONSENSUS_INSTALL_STATE_TIME_ID: usize = 120;
 pub const MSG_LOG_INSTALL_TIME: &str = "MSG_LOG_INSTALL_TIME";
 pub const MSG_LOG_INSTALL_TIME_ID: usize = 121;
 
-/// 120-129: Synchronizer
+/// 130-139: Checkpoints and log replay
+pub const STABLE_CHECKPOINTS: &str = "STABLE_CHECKPOINTS";
+pub const STABLE_CHECKPOINTS_ID: usize = 130;
+
+pub const CHECKPOINT_DISCARDED_PROOFS: &str = "CHECKPOINT_DISCARDED_PROOFS";
+pub const CHECKPOINT_DISCARDED_PROOFS_ID: usize = 131;
+
+pub const LOG_REPLAY_INSTALLED_PROOFS: &str = "LOG_REPLAY_INSTALLED_PROOFS";
+pub const LOG_REPLAY_INSTALLED_PROOFS_ID: usize = 132;
+
+/// 140-149: Buffered future messages
+pub const CONSENSUS_BUFFERED_MESSAGES: &str = "CONSENSUS_BUFFERED_MESSAGES";
+pub const CONSENSUS_BUFFERED_MESSAGES_ID: usize = 140;
+
+pub const CONSENSUS_BUFFERED_BYTES: &str = "CONSENSUS_BUFFERED_BYTES";
+pub const CONSENSUS_BUFFERED_BYTES_ID: usize = 141;
+
+pub const SYNC_BUFFERED_MESSAGES: &str = "SYNC_BUFFERED_MESSAGES";
+pub const SYNC_BUFFERED_MESSAGES_ID: usize = 142;
+
+pub const SYNC_BUFFERED_BYTES: &str = "SYNC_BUFFERED_BYTES";
+pub const SYNC_BUFFERED_BYTES_ID: usize = 143;
+
+pub const BUFFER_DROPPED_MESSAGES: &str = "BUFFER_DROPPED_MESSAGES";
+pub const BUFFER_DROPPED_MESSAGES_ID: usize = 144;
+
+pub const BUFFER_DROPPED_BYTES: &str = "BUFFER_DROPPED_BYTES";
+pub const BUFFER_DROPPED_BYTES_ID: usize = 145;
+
+/// 150-159: Synchronizer
 pub const SYNC_WATCH_REQUESTS: &str = "SYNC_WATCH_REQUESTS";
 pub const SYNC_WATCH_REQUESTS_ID: usize = 150;
 
@@ -91,6 +120,88 @@ pub const SYNC_FORWARDED_REQUESTS_ID: usize = 154;
 pub const SYNC_FORWARDED_COUNT: &str = "SYNC_FORWARDED_COUNT";
 pub const SYNC_FORWARDED_COUNT_ID: usize = 155;
 
+pub const SYNC_LEADER_LIVENESS_TIMEOUTS: &str = "SYNC_LEADER_LIVENESS_TIMEOUTS";
+pub const SYNC_LEADER_LIVENESS_TIMEOUTS_ID: usize = 156;
+
+pub const SYNC_LEADER_THROUGHPUT: &str = "SYNC_LEADER_THROUGHPUT";
+pub const SYNC_LEADER_THROUGHPUT_ID: usize = 157;
+
+pub const SYNC_LEADER_PROPOSE_LATENCY: &str = "SYNC_LEADER_PROPOSE_LATENCY";
+pub const SYNC_LEADER_PROPOSE_LATENCY_ID: usize = 158;
+
+pub const SYNC_SLOW_LEADER_VIEW_CHANGES: &str = "SYNC_SLOW_LEADER_VIEW_CHANGES";
+pub const SYNC_SLOW_LEADER_VIEW_CHANGES_ID: usize = 159;
+
+/// 160-169: Peer misbehavior
+pub const RATE_LIMITED_MESSAGES: &str = "RATE_LIMITED_MESSAGES";
+pub const RATE_LIMITED_MESSAGES_ID: usize = 160;
+
+pub const MISBEHAVIOR_REPORTS: &str = "MISBEHAVIOR_REPORTS";
+pub const MISBEHAVIOR_REPORTS_ID: usize = 161;
+
+/// 170-179: Request verification
+pub const REQUEST_VERIFICATION_TIME: &str = "REQUEST_VERIFICATION_TIME";
+pub const REQUEST_VERIFICATION_TIME_ID: usize = 170;
+
+pub const INVALID_REQUESTS: &str = "INVALID_REQUESTS";
+pub const INVALID_REQUESTS_ID: usize = 171;
+
+/// 180-189: Batch construction
+pub const PROPOSER_TARGET_BATCH_SIZE: &str = "PROPOSER_TARGET_BATCH_SIZE";
+pub const PROPOSER_TARGET_BATCH_SIZE_ID: usize = 180;
+
+pub const PROPOSER_BATCH_TIMEOUT: &str = "PROPOSER_BATCH_TIMEOUT";
+pub const PROPOSER_BATCH_TIMEOUT_ID: usize = 181;
+
+pub const PROPOSER_OVERSIZED_REQUESTS: &str = "PROPOSER_OVERSIZED_REQUESTS";
+pub const PROPOSER_OVERSIZED_REQUESTS_ID: usize = 182;
+
+pub const PROPOSER_STARVED_CLIENTS: &str = "PROPOSER_STARVED_CLIENTS";
+pub const PROPOSER_STARVED_CLIENTS_ID: usize = 183;
+
+pub const PROPOSER_MAX_CLIENT_STARVATION: &str = "PROPOSER_MAX_CLIENT_STARVATION";
+pub const PROPOSER_MAX_CLIENT_STARVATION_ID: usize = 184;
+
+pub const PROPOSER_URGENT_REQUESTS: &str = "PROPOSER_URGENT_REQUESTS";
+pub const PROPOSER_URGENT_REQUESTS_ID: usize = 185;
+
+pub const PROPOSER_HEARTBEATS: &str = "PROPOSER_HEARTBEATS";
+pub const PROPOSER_HEARTBEATS_ID: usize = 186;
+
+pub const PROPOSER_INVALID_REQUESTS: &str = "PROPOSER_INVALID_REQUESTS";
+pub const PROPOSER_INVALID_REQUESTS_ID: usize = 187;
+
+/// 190-199: Leader rotation and view changes
+pub const LEADER_HANDOFFS: &str = "LEADER_HANDOFFS";
+pub const LEADER_HANDOFFS_ID: usize = 190;
+
+pub const STALLED_LEADER_HANDOFFS: &str = "STALLED_LEADER_HANDOFFS";
+pub const STALLED_LEADER_HANDOFFS_ID: usize = 191;
+
+pub const INVALID_BATCH_VIEW_CHANGES: &str = "INVALID_BATCH_VIEW_CHANGES";
+pub const INVALID_BATCH_VIEW_CHANGES_ID: usize = 192;
+
+pub const SYNC_STOP_RETRANSMISSIONS: &str = "SYNC_STOP_RETRANSMISSIONS";
+pub const SYNC_STOP_RETRANSMISSIONS_ID: usize = 193;
+
+pub const SYNC_VIEW_CHANGE_ESCALATIONS: &str = "SYNC_VIEW_CHANGE_ESCALATIONS";
+pub const SYNC_VIEW_CHANGE_ESCALATIONS_ID: usize = 194;
+
+pub const SYNC_VIEW_CHANGE_TIMEOUT: &str = "SYNC_VIEW_CHANGE_TIMEOUT";
+pub const SYNC_VIEW_CHANGE_TIMEOUT_ID: usize = 195;
+
+pub const SYNC_FAILED_VIEW_CHANGES: &str = "SYNC_FAILED_VIEW_CHANGES";
+pub const SYNC_FAILED_VIEW_CHANGES_ID: usize = 196;
+
+pub const SYNC_FETCHED_REQUESTS: &str = "SYNC_FETCHED_REQUESTS";
+pub const SYNC_FETCHED_REQUESTS_ID: usize = 197;
+
+pub const SYNC_REJECTED_SYNCS: &str = "SYNC_REJECTED_SYNCS";
+pub const SYNC_REJECTED_SYNCS_ID: usize = 198;
+
+pub const SYNC_ABORTED_QUORUM_JOINS: &str = "SYNC_ABORTED_QUORUM_JOINS";
+pub const SYNC_ABORTED_QUORUM_JOINS_ID: usize = 199;
+
 pub fn metrics() -> Vec<MetricRegistry> {
     vec![
         (
@@ -218,6 +329,132 @@ pub fn metrics() -> Vec<MetricRegistry> {
             MetricKind::Duration,
         )
             .into(),
+        (
+            STABLE_CHECKPOINTS_ID,
+            STABLE_CHECKPOINTS.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            CHECKPOINT_DISCARDED_PROOFS_ID,
+            CHECKPOINT_DISCARDED_PROOFS.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            LOG_REPLAY_INSTALLED_PROOFS_ID,
+            LOG_REPLAY_INSTALLED_PROOFS.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            CONSENSUS_BUFFERED_MESSAGES_ID,
+            CONSENSUS_BUFFERED_MESSAGES.to_string(),
+            MetricKind::Count,
+        )
+            .into(),
+        (
+            CONSENSUS_BUFFERED_BYTES_ID,
+            CONSENSUS_BUFFERED_BYTES.to_string(),
+            MetricKind::Count,
+        )
+            .into(),
+        (
+            SYNC_BUFFERED_MESSAGES_ID,
+            SYNC_BUFFERED_MESSAGES.to_string(),
+            MetricKind::Count,
+        )
+            .into(),
+        (
+            SYNC_BUFFERED_BYTES_ID,
+            SYNC_BUFFERED_BYTES.to_string(),
+            MetricKind::Count,
+        )
+            .into(),
+        (
+            BUFFER_DROPPED_MESSAGES_ID,
+            BUFFER_DROPPED_MESSAGES.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            BUFFER_DROPPED_BYTES_ID,
+            BUFFER_DROPPED_BYTES.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            RATE_LIMITED_MESSAGES_ID,
+            RATE_LIMITED_MESSAGES.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            MISBEHAVIOR_REPORTS_ID,
+            MISBEHAVIOR_REPORTS.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            REQUEST_VERIFICATION_TIME_ID,
+            REQUEST_VERIFICATION_TIME.to_string(),
+            MetricKind::Duration,
+        )
+            .into(),
+        (
+            INVALID_REQUESTS_ID,
+            INVALID_REQUESTS.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            PROPOSER_TARGET_BATCH_SIZE_ID,
+            PROPOSER_TARGET_BATCH_SIZE.to_string(),
+            MetricKind::Count,
+        )
+            .into(),
+        (
+            PROPOSER_BATCH_TIMEOUT_ID,
+            PROPOSER_BATCH_TIMEOUT.to_string(),
+            MetricKind::Count,
+        )
+            .into(),
+        (
+            PROPOSER_OVERSIZED_REQUESTS_ID,
+            PROPOSER_OVERSIZED_REQUESTS.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            PROPOSER_STARVED_CLIENTS_ID,
+            PROPOSER_STARVED_CLIENTS.to_string(),
+            MetricKind::Count,
+        )
+            .into(),
+        (
+            PROPOSER_MAX_CLIENT_STARVATION_ID,
+            PROPOSER_MAX_CLIENT_STARVATION.to_string(),
+            MetricKind::Count,
+        )
+            .into(),
+        (
+            PROPOSER_URGENT_REQUESTS_ID,
+            PROPOSER_URGENT_REQUESTS.to_string(),
+            MetricKind::Count,
+        )
+            .into(),
+        (
+            PROPOSER_HEARTBEATS_ID,
+            PROPOSER_HEARTBEATS.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            PROPOSER_INVALID_REQUESTS_ID,
+            PROPOSER_INVALID_REQUESTS.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
         (
             SYNC_WATCH_REQUESTS_ID,
             SYNC_WATCH_REQUESTS.to_string(),
@@ -254,6 +491,90 @@ pub fn metrics() -> Vec<MetricRegistry> {
             MetricKind::Counter,
         )
             .into(),
+        (
+            SYNC_LEADER_LIVENESS_TIMEOUTS_ID,
+            SYNC_LEADER_LIVENESS_TIMEOUTS.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            SYNC_LEADER_THROUGHPUT_ID,
+            SYNC_LEADER_THROUGHPUT.to_string(),
+            MetricKind::Count,
+        )
+            .into(),
+        (
+            SYNC_LEADER_PROPOSE_LATENCY_ID,
+            SYNC_LEADER_PROPOSE_LATENCY.to_string(),
+            MetricKind::Duration,
+        )
+            .into(),
+        (
+            SYNC_SLOW_LEADER_VIEW_CHANGES_ID,
+            SYNC_SLOW_LEADER_VIEW_CHANGES.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            LEADER_HANDOFFS_ID,
+            LEADER_HANDOFFS.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            STALLED_LEADER_HANDOFFS_ID,
+            STALLED_LEADER_HANDOFFS.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            INVALID_BATCH_VIEW_CHANGES_ID,
+            INVALID_BATCH_VIEW_CHANGES.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            SYNC_STOP_RETRANSMISSIONS_ID,
+            SYNC_STOP_RETRANSMISSIONS.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            SYNC_VIEW_CHANGE_ESCALATIONS_ID,
+            SYNC_VIEW_CHANGE_ESCALATIONS.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            SYNC_VIEW_CHANGE_TIMEOUT_ID,
+            SYNC_VIEW_CHANGE_TIMEOUT.to_string(),
+            MetricKind::Duration,
+        )
+            .into(),
+        (
+            SYNC_FAILED_VIEW_CHANGES_ID,
+            SYNC_FAILED_VIEW_CHANGES.to_string(),
+            MetricKind::Count,
+        )
+            .into(),
+        (
+            SYNC_FETCHED_REQUESTS_ID,
+            SYNC_FETCHED_REQUESTS.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            SYNC_REJECTED_SYNCS_ID,
+            SYNC_REJECTED_SYNCS.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
+        (
+            SYNC_ABORTED_QUORUM_JOINS_ID,
+            SYNC_ABORTED_QUORUM_JOINS.to_string(),
+            MetricKind::Counter,
+        )
+            .into(),
     ]
 }
 
@@ -350,6 +671,23 @@ impl ConsensusMetrics {
             self.prepare_quorum_time.elapsed(),
         )
     }
+
+    /// The time it took to decide this instance, from the first pre prepare to the commit quorum
+    pub fn decision_latency(&self) -> Duration {
+        self.commit_quorum_time
+            .saturating_duration_since(self.first_pre_prepare_time)
+    }
+
+    /// The time the leader took to propose this instance, until its first pre prepare.
+    /// Instances are opened ahead of time, so the leader is only able to propose this one
+    /// once it has started and the previous proposal, received at `previous_proposal`, is out
+    pub fn propose_latency(&self, previous_proposal: Option<Instant>) -> Duration {
+        let ready = previous_proposal.map_or(self.consensus_start_time, |previous| {
+            previous.max(self.consensus_start_time)
+        });
+
+        self.first_pre_prepare_time.saturating_duration_since(ready)
+    }
 }
 
 lazy_static! {
@@ -3,36 +3,58 @@
 //! By default, it is hidden to the user, unless explicitly enabled
 //! with the feature flag `expose_impl`.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::fmt::Debug;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::anyhow;
 use either::Either;
 use lazy_static::lazy_static;
 use tracing::{debug, error, info, instrument, trace, warn};
 
-use crate::bft::config::PBFTConfig;
+use crate::bft::checkpoint::{
+    CheckpointCertificate, CheckpointError, CheckpointNotifier, CheckpointStatus,
+    CheckpointTracker,
+};
+use crate::bft::config::{AuthenticationMode, PBFTConfig};
+use crate::bft::consensus::validity::{InvalidBatchRecord, SharedValidityPredicate};
 use crate::bft::consensus::{
-    Consensus, ConsensusPollStatus, ConsensusStatus, ProposerConsensusGuard,
+    Consensus, ConsensusPollStatus, ConsensusStatus, ProposerConsensusGuard, SeqNoWindow,
 };
+use crate::bft::auth::MacAuthenticator;
+use crate::bft::evidence::{EvidenceCollector, MisbehaviorProof};
 use crate::bft::log::decided::DecisionLog;
 use crate::bft::log::decisions::{Proof, ProofMetadata};
+use crate::bft::log::replay::{LogReplay, ReplayStatus};
 use crate::bft::log::{initialize_decided_log, Log};
-use crate::bft::message::serialize::PBFTConsensus;
-use crate::bft::message::{ConsensusMessageKind, ObserveEventKind, PBFTMessage};
+use crate::bft::message::serialize::{verify, PBFTConsensus};
+use crate::bft::message::{ConsensusMessageKind, LogReplayMessage, ObserveEventKind, PBFTMessage};
+use crate::bft::metric::{
+    INVALID_BATCH_VIEW_CHANGES_ID, LEADER_HANDOFFS_ID, LOG_REPLAY_INSTALLED_PROOFS_ID,
+    STALLED_LEADER_HANDOFFS_ID, SYNC_LEADER_LIVENESS_TIMEOUTS_ID,
+    SYNC_SLOW_LEADER_VIEW_CHANGES_ID,
+};
+use crate::bft::monitor::LeaderMonitor;
+use crate::bft::observer::{MessageType, ObserverHandle};
+use crate::bft::peers::{Misbehavior, MisbehaviorScores, PeerMonitor};
+use crate::bft::proposer::lanes::SharedClassifier;
+use crate::bft::proposer::rejected::RejectedRequest;
 use crate::bft::proposer::Proposer;
 use crate::bft::sync::view::ViewInfo;
 use crate::bft::sync::{
     AbstractSynchronizer, SyncReconfigurationResult, Synchronizer, SynchronizerPollStatus,
     SynchronizerStatus,
 };
+use crate::bft::timers::ViewTimer;
+use atlas_common::crypto::hash::Digest;
 use atlas_common::error::*;
 use atlas_common::maybe_vec::MaybeVec;
 use atlas_common::node_id::NodeId;
 use atlas_common::ordering::{Orderable, SeqNo};
 use atlas_common::serialization_helper::SerMsg;
+use atlas_common::Err;
 use atlas_communication::message::StoredMessage;
 use atlas_core::messages::SessionBased;
 use atlas_core::ordering_protocol::loggable::{DecomposedProof, LoggableOrderProtocol, OrderProtocolLogHelper, PProof};
@@ -48,21 +70,35 @@ use atlas_core::reconfiguration_protocol::ReconfigurationProtocol;
 use atlas_core::request_pre_processing::{RequestPProcessorSync, RequestPreProcessing};
 use atlas_core::serialize::ReconfigurationProtocolMessage;
 use atlas_core::timeouts::timeout::{ModTimeout, TimeoutModHandle, TimeoutableMod};
+use atlas_metrics::metrics::metric_increment;
 
+pub mod auth;
+pub mod buffer;
+pub mod checkpoint;
 pub mod config;
 pub mod consensus;
+pub mod evidence;
+#[cfg(test)]
+mod fixtures;
+pub mod forensics;
 pub mod log;
 pub mod message;
 pub mod metric;
+pub mod monitor;
 pub mod observer;
+pub mod peers;
 pub mod proposer;
 pub mod sync;
+pub mod timers;
 
 // The types responsible for this protocol
 pub type PBFT<RQ> = PBFTConsensus<RQ>;
 // The message type for this consensus protocol
 pub type SysMsg<RQ> = <PBFTConsensus<RQ> as OrderingProtocolMessage<RQ>>::ProtocolMessage;
 
+/// How many invalid batches we keep a record of
+const MAX_INVALID_BATCH_RECORDS: usize = 128;
+
 lazy_static! {
     static ref MOD_NAME: Arc<str> = Arc::from("FEBFT");
 }
@@ -83,6 +119,7 @@ pub enum SyncPhaseRes<O> {
     RunSyncProtocol,
     SyncProtocolFinished(ConsensusStatus<O>, Option<FeDecision<O>>),
     JoinedQuorum(ConsensusStatus<O>, Option<FeDecision<O>>, NodeId),
+    QuorumJoinAborted(Vec<NodeId>),
     RunCSTProtocol,
 }
 
@@ -117,6 +154,34 @@ where
     // This is completely owned by the server thread and therefore does not
     // Require any synchronization
     message_log: Log<RQ>,
+    // The checkpoint messages we have received and our latest stable checkpoint
+    checkpoints: CheckpointTracker<RQ>,
+    // The log replay, used to catch up to the quorum without a full state transfer
+    log_replay: LogReplay<RQ>,
+    // The per-peer rate limits and misbehavior scores
+    peers: PeerMonitor,
+    // The last message we handed out through polling, which has already been accounted for
+    // in the rate limits, as it was queued when it was first received
+    last_polled: Option<ShareableMessage<PBFTMessage<RQ>>>,
+    // The evidence of equivocations we have collected
+    evidence: EvidenceCollector<RQ>,
+    // The latest proposals we have found to be invalid, which got their leaders voted out
+    invalid_batches: VecDeque<InvalidBatchRecord>,
+    // The observer we report events to, if any
+    observer: Option<ObserverHandle>,
+    // The MAC authenticator for prepares, when configured to use MACs
+    authenticator: Option<Arc<MacAuthenticator>>,
+    // The maximum size, in bytes, of the requests of the pre prepares we accept
+    max_pre_prepare_bytes: Option<usize>,
+    // How long we wait for a pre prepare from the leader before starting a view change,
+    // when the leader sends heartbeats
+    leader_timeout: Option<Duration>,
+    // The monitor of the performance of the leader, when slow leaders are to be replaced
+    leader_monitor: Option<LeaderMonitor>,
+    // How long we wait for the leader taking over in a planned hand off, when leadership rotates
+    handoff_timeout: Option<Duration>,
+    // The view whose leader took over in a hand off and has not yet sent us a pre prepare
+    pending_handoff: Option<SeqNo>,
     // The proposer of this replica
     proposer: Arc<Proposer<RQ, NT>>,
     // The networking layer for a Node in the network (either Client or Replica)
@@ -194,6 +259,31 @@ where
             return Ok(OPExecResult::MessageDropped);
         }
 
+        // Our own timers are identified by their view, client ones by the request
+        let (view_timers, timeout): (Vec<_>, Vec<_>) = timeout
+            .into_iter()
+            .partition(|timeout| ViewTimer::from_id(timeout.id()).is_some());
+
+        let view_timers = view_timers
+            .iter()
+            .filter_map(|timeout| ViewTimer::from_id(timeout.id()))
+            .collect::<Vec<_>>();
+
+        for timer in &view_timers {
+            if let ViewTimer::ViewChange(view) = timer {
+                self.synchronizer.view_change_timed_out(
+                    *view,
+                    &*self.node,
+                    &self.timeouts,
+                    &self.message_log,
+                );
+            }
+        }
+
+        if self.leader_timed_out(&view_timers) {
+            return Ok(OPExecResult::MessageProcessedNoUpdate);
+        }
+
         let status = self
             .synchronizer
             .client_requests_timed_out(self.node.id(), &timeout);
@@ -206,8 +296,6 @@ where
             }
 
             if !stopped.is_empty() {
-                let stopped = self.pre_processor.clone_pending_rqs(stopped)?;
-
                 self.switch_phase(ConsensusPhase::SyncPhase);
 
                 self.synchronizer.begin_view_change(
@@ -233,6 +321,13 @@ where
     type Config = PBFTConfig;
 
     fn handle_off_ctx_message(&mut self, message: ShareableMessage<PBFTMessage<RQ>>) {
+        if !self.admit(&message)
+            || !self.verify_authenticator(&message)
+            || !self.verify_pre_prepare_size(&message)
+        {
+            return;
+        }
+
         match message.message() {
             PBFTMessage::Consensus(consensus) => {
                 debug!(
@@ -240,6 +335,14 @@ where
                     self.node.id(),
                     consensus
                 );
+
+                if self.consensus.window().is_above(consensus.sequence_number()) {
+                    self.peers
+                        .report(message.header().from(), Misbehavior::OutOfWindow);
+                } else {
+                    self.collect_evidence(&message);
+                }
+
                 self.consensus.queue(message);
             }
             PBFTMessage::ViewChange(view_change) => {
@@ -253,6 +356,48 @@ where
 
                 self.synchronizer.signal();
             }
+            PBFTMessage::Checkpoint(_) => {
+                // Checkpoints do not depend on the current view or sequence number
+                if let Err(err) = self.process_checkpoint(message) {
+                    warn!(
+                        "{:?} // Failed to process off context checkpoint message {:?}",
+                        self.node.id(),
+                        err
+                    );
+                }
+            }
+            PBFTMessage::LogReplay(LogReplayMessage::RequestProofs(first, last)) => {
+                if let Err(err) = self.log_replay.handle_request(
+                    message.header(),
+                    *first,
+                    *last,
+                    &self.message_log,
+                    self.checkpoints.stable_checkpoint(),
+                    &self.synchronizer.view(),
+                    &*self.node,
+                ) {
+                    self.report_error(message.header().from(), &err);
+
+                    warn!(
+                        "{:?} // Failed to process off context log replay request {:?}",
+                        self.node.id(),
+                        err
+                    );
+                }
+            }
+            PBFTMessage::LogReplay(replay) => {
+                warn!(
+                    "{:?} // Ignoring off context log replay message {:?}",
+                    self.node.id(),
+                    replay
+                );
+            }
+            PBFTMessage::FetchRequests(_) => {
+                self.process_fetch_requests(&message);
+            }
+            PBFTMessage::SessionKey(_) => {
+                self.process_session_key(&message);
+            }
             _ => {
                 todo!()
             }
@@ -282,16 +427,38 @@ where
     fn poll(&mut self) -> Result<FePollResult<RQ>> {
         trace!("{:?} // Polling {:?}", self.node.id(), self.phase);
 
-        match self.phase {
+        self.broadcast_local_checkpoints();
+
+        let poll_result = match self.phase {
             ConsensusPhase::NormalPhase => self.poll_normal_phase(),
             ConsensusPhase::SyncPhase => self.poll_sync_phase(),
+        }?;
+
+        if let OPPollResult::Exec(message) = &poll_result {
+            self.last_polled = Some(message.clone());
         }
+
+        Ok(poll_result)
     }
 
     fn process_message(
         &mut self,
         message: ShareableMessage<PBFTMessage<RQ>>,
     ) -> Result<FeExecutionResult<RQ>> {
+        // Messages we have taken from our own queues were already rate limited when they were received
+        let polled = self
+            .last_polled
+            .take()
+            .is_some_and(|polled| Arc::ptr_eq(&polled, &message));
+
+        if !polled
+            && (!self.admit(&message)
+                || !self.verify_authenticator(&message)
+                || !self.verify_pre_prepare_size(&message))
+        {
+            return Ok(OPExecResult::MessageDropped);
+        }
+
         match self.phase {
             ConsensusPhase::NormalPhase => self.update_normal_phase(message),
             ConsensusPhase::SyncPhase => self.update_sync_phase(message),
@@ -299,6 +466,35 @@ where
     }
 
     fn install_seq_no(&mut self, seq_no: SeqNo) -> Result<()> {
+        // The state transfer protocol reports the state it installed through our notifier
+        self.broadcast_local_checkpoints();
+
+        if self.consensus.window().is_above(seq_no) {
+            // A state beyond our window can only have come from a state transfer,
+            // which must be anchored on a stable checkpoint that covers it
+            let stable = self.checkpoints.stable_seq();
+
+            let certificate = match self.checkpoints.stable_checkpoint() {
+                Some(certificate) if stable.next() >= seq_no => certificate,
+                _ => return Err!(CheckpointError::NotCertified(seq_no, stable)),
+            };
+
+            let certified = self
+                .checkpoints
+                .local_digest(stable)
+                .is_some_and(|digest| certificate.certifies(stable, digest));
+
+            if !certified {
+                return Err!(CheckpointError::StateNotCertified(stable));
+            }
+
+            self.message_log.garbage_collect(stable);
+
+            self.consensus.advance_low_watermark(stable);
+
+            self.evidence.discard_before(stable.next());
+        }
+
         self.consensus
             .install_sequence_number(seq_no, &self.synchronizer.view());
 
@@ -325,6 +521,38 @@ where
                 warn!("Attempted to install view that is the same or older than the current view that is in place? New: {:?} vs {:?}", view, current_view);
             }
             Either::Right(_) => {
+                // The election history the view carries is not verified, so we elect
+                // its leaders with the history we agreed on ourselves. Should we have caught
+                // up through a state transfer, we skipped the decisions it installed, so we
+                // use the history vouched for by the stable checkpoint it was anchored on
+                let certified = self
+                    .checkpoints
+                    .stable_checkpoint()
+                    .and_then(|stable| stable.agreed_election(current_view.params().f()))
+                    .filter(|(agreed_view, _)| *agreed_view > current_view.sequence_number())
+                    .map(|(_, agreed)| agreed.clone());
+
+                let reelected = match certified {
+                    Some(agreed) => ViewInfo::reelect_with(&view, agreed),
+                    None => current_view.reelect(&view),
+                };
+
+                let view = match reelected {
+                    Ok(reelected) => {
+                        if reelected.leader() != view.leader() {
+                            warn!("{:?} // The view {:?} we received elects {:?}, while our history elects {:?}",
+                                self.node.id(), view.sequence_number(), view.leader(), reelected.leader());
+                        }
+
+                        reelected
+                    }
+                    Err(err) => {
+                        error!("{:?} // Failed to elect the leaders of the received view {:?}: {:?}", self.node.id(), view, err);
+
+                        return;
+                    }
+                };
+
                 self.consensus.install_view(&view);
                 if self.synchronizer.received_view_from_state_transfer(view) {
                     info!("Installed the view and synchronizer now requires execution in order to make sure everything is correctly setup.");
@@ -352,11 +580,28 @@ where
             timeout_dur,
             proposer_config,
             watermark,
+            log_window,
+            message_buffer,
+            rate_limits,
+            authentication,
+            heartbeats,
+            leader_monitor,
+            leader_election,
+            leader_rotation,
+            view_change_backoff,
+            checkpoint_channel,
         } = config;
 
+        let buffer_limits = message_buffer.unwrap_or_default();
+
         let OrderingProtocolArgs(node_id, timeouts, pre_processor, batch_input, node, quorum) =
             args;
 
+        let authenticator = match authentication.unwrap_or_default() {
+            AuthenticationMode::Signatures => None,
+            AuthenticationMode::MacVectors => Some(Arc::new(MacAuthenticator::new(node_id))),
+        };
+
         debug!("Initializing the synchronizer");
 
         let sync = Synchronizer::initialize_with_quorum(
@@ -364,24 +609,40 @@ where
             SeqNo::ZERO,
             quorum.clone(),
             timeout_dur,
+            view_change_backoff,
+            buffer_limits.clone(),
+            leader_election.unwrap_or_default().into(),
         )?;
 
-        let consensus_guard = ProposerConsensusGuard::new(sync.view(), watermark);
+        let window = SeqNoWindow::new(SeqNo::ZERO, log_window);
+
+        let consensus_guard = ProposerConsensusGuard::new(sync.view(), watermark, window);
 
         debug!("Initializing the consensus protocol");
 
-        let consensus = Consensus::<RQ>::new_replica(
+        let mut consensus = Consensus::<RQ>::new_replica(
             node_id,
             &sync.view(),
             SeqNo::ZERO,
             watermark,
+            window,
+            buffer_limits,
             consensus_guard.clone(),
             timeouts.clone(),
+            authenticator.clone(),
         );
 
+        consensus.set_leader_rotation(leader_rotation.as_ref().map(|rotation| rotation.every));
+
         debug!("Initializing the decided log.");
 
-        let dec_log = initialize_decided_log::<RQ>(node_id);
+        let dec_log = initialize_decided_log::<RQ>(node_id, window);
+
+        // We hold the pre prepares of other leaders to the same limit as our own batches
+        let max_pre_prepare_bytes = proposer_config.max_batch_bytes.map(|limit| limit as usize);
+
+        // The leader only fills its batches up to the target size while it is saturated
+        let saturated_batch_size = proposer_config.target_batch_size as usize;
 
         let proposer = Proposer::<RQ, NT>::new(
             node.clone(),
@@ -390,6 +651,7 @@ where
             timeouts.clone(),
             consensus_guard.clone(),
             proposer_config,
+            heartbeats.as_ref().map(|heartbeats| heartbeats.idle_interval),
         );
 
         let replica = Self {
@@ -401,10 +663,35 @@ where
             consensus_guard,
             unordered_rq_guard: Arc::new(Default::default()),
             message_log: dec_log,
+            checkpoints: CheckpointTracker::new(
+                node_id,
+                checkpoint_channel.unwrap_or_default(),
+            ),
+            log_replay: LogReplay::new(node_id, timeout_dur),
+            peers: PeerMonitor::new(node_id, rate_limits.unwrap_or_default()),
+            last_polled: None,
+            evidence: EvidenceCollector::new(node_id),
+            invalid_batches: VecDeque::with_capacity(MAX_INVALID_BATCH_RECORDS),
+            observer: None,
+            authenticator,
+            max_pre_prepare_bytes,
+            leader_timeout: heartbeats.map(|heartbeats| heartbeats.leader_timeout),
+            leader_monitor: leader_monitor
+                .map(|config| LeaderMonitor::new(config, saturated_batch_size)),
+            handoff_timeout: leader_rotation.map(|rotation| rotation.handoff_timeout),
+            pending_handoff: None,
             proposer,
             node,
         };
 
+        replica.watch_leader();
+
+        if let Some(authenticator) = &replica.authenticator {
+            let quorum = replica.synchronizer.view().quorum_members().clone();
+
+            authenticator.announce_keys::<RQ, NT>(quorum, &*replica.node);
+        }
+
         let crr_view = replica.synchronizer.view();
 
         info!(
@@ -421,7 +708,12 @@ where
             crr_view.leader_set()
         );
 
-        info!("{:?} // Watermark: {}", replica.node.id(), watermark);
+        info!(
+            "{:?} // Watermark: {}, Log window: {:?}",
+            replica.node.id(),
+            watermark,
+            log_window
+        );
 
         println!(
             "{:?} // Leader count: {}, Leader set: {:?}, Quorum: {:?}",
@@ -438,6 +730,15 @@ where
     }
 
     fn poll_sync_phase(&mut self) -> Result<OPPollResult<ProofMetadata, (), PBFTMessage<RQ>, RQ>> {
+        if self.log_replay.is_replaying() {
+            if self.log_replay.timed_out() {
+                return Ok(OPPollResult::RunCst);
+            }
+
+            // We can only resume the view change once we have caught up with the quorum
+            return Ok(OPPollResult::ReceiveMsg);
+        }
+
         // retrieve a view change message to be processed
         let poll_result = self.synchronizer.poll();
 
@@ -550,6 +851,10 @@ where
                             error!("Polling the sync phase should never return anything other than a run sync protocol or run cst protocol message, Protocol Finished");
                             OPPollResult::RePoll
                         }
+                        SyncPhaseRes::QuorumJoinAborted(_) => {
+                            // We are back to the normal phase of the current view
+                            OPPollResult::RePoll
+                        }
                     });
                 } else {
                     // The synchronizer should never return anything other than a view
@@ -601,6 +906,10 @@ where
 
                         let new_quorum = self.synchronizer.view().quorum_members().clone();
 
+                        if let Some(authenticator) = &self.authenticator {
+                            authenticator.announce_keys::<RQ, NT>([node], &*self.node);
+                        }
+
                         let join_info = JoinInfo::new(node, new_quorum);
 
                         let decision_adv = self.handle_sync_result(status, to_execute)?;
@@ -615,12 +924,32 @@ where
                             )
                         }
                     }
+                    SyncPhaseRes::QuorumJoinAborted(candidates) => {
+                        warn!(
+                            "Aborted the round to join one of {:?} to the quorum, resuming the current view",
+                            candidates
+                        );
+
+                        OPExecResult::MessageProcessedNoUpdate
+                    }
                     SyncPhaseRes::RunCSTProtocol => OPExecResult::RunCst,
                 });
             }
             PBFTMessage::Consensus(_) => {
                 self.consensus.queue(message);
             }
+            PBFTMessage::Checkpoint(_) => {
+                return self.process_checkpoint(message);
+            }
+            PBFTMessage::LogReplay(_) => {
+                return self.process_log_replay(message);
+            }
+            PBFTMessage::FetchRequests(_) => {
+                self.process_fetch_requests(&message);
+            }
+            PBFTMessage::SessionKey(_) => {
+                self.process_session_key(&message);
+            }
             _ => {}
         }
 
@@ -635,6 +964,10 @@ where
 
         let decisions = self.merge_decisions(decisions, finalized_decisions)?;
 
+        self.hand_off_leadership();
+
+        self.monitor_leader();
+
         Ok(decisions)
     }
 
@@ -659,7 +992,7 @@ where
                 self.synchronizer.signal();
 
                 match status {
-                    SynchronizerStatus::Nil => (),
+                    SynchronizerStatus::Nil | SynchronizerStatus::QuorumJoinAborted(_) => (),
                     SynchronizerStatus::Running => self.switch_phase(ConsensusPhase::SyncPhase),
                     // should not happen...
                     _ => {
@@ -667,12 +1000,691 @@ where
                     }
                 }
             }
+            PBFTMessage::Checkpoint(_) => {
+                return self.process_checkpoint(message);
+            }
+            PBFTMessage::LogReplay(_) => {
+                return self.process_log_replay(message);
+            }
+            PBFTMessage::FetchRequests(_) => {
+                self.process_fetch_requests(&message);
+            }
+            PBFTMessage::SessionKey(_) => {
+                self.process_session_key(&message);
+            }
             _ => {}
         }
 
         Ok(OPExecResult::MessageProcessedNoUpdate)
     }
 
+    /// Process a checkpoint message, discarding the decided proofs
+    /// that are covered by it, should it make a checkpoint stable
+    fn process_checkpoint(
+        &mut self,
+        message: ShareableMessage<PBFTMessage<RQ>>,
+    ) -> Result<FeExecutionResult<RQ>> {
+        let sender = message.header().from();
+
+        let status = match self
+            .checkpoints
+            .process_message(message, &self.synchronizer.view(), self.consensus.window())
+        {
+            Ok(status) => status,
+            Err(err) => {
+                self.report_error(sender, &err);
+
+                return Err(err);
+            }
+        };
+
+        Ok(match status {
+            CheckpointStatus::Ignored => OPExecResult::MessageDropped,
+            CheckpointStatus::Pending => OPExecResult::MessageProcessedNoUpdate,
+            CheckpointStatus::Stable(certificate) => {
+                self.checkpoint_stable(certificate.sequence_number())
+            }
+        })
+    }
+
+    /// Discard everything that precedes the checkpoint at `seq`, which has become stable.
+    /// Should it lie beyond our window, we have fallen behind the quorum and must run the
+    /// state transfer protocol, whose state is then anchored on this checkpoint
+    fn checkpoint_stable(&mut self, seq: SeqNo) -> FeExecutionResult<RQ> {
+        self.message_log.garbage_collect(seq);
+
+        if self.consensus.window().is_above(seq) {
+            info!(
+                "{:?} // Checkpoint {:?} is stable beyond our window, running the state transfer protocol",
+                self.node.id(),
+                seq
+            );
+
+            return OPExecResult::RunCst;
+        }
+
+        self.consensus.advance_low_watermark(seq);
+
+        self.evidence.discard_before(seq.next());
+
+        OPExecResult::MessageProcessedNoUpdate
+    }
+
+    /// Process a message fetching the bodies of the requests advertised in STOP messages,
+    /// which are served and received in any phase
+    fn process_fetch_requests(&self, message: &ShareableMessage<PBFTMessage<RQ>>) {
+        self.synchronizer
+            .process_fetch_requests(message, &self.pre_processor, &*self.node);
+    }
+
+    /// Process a log replay message, either serving the proofs requested by
+    /// another replica or installing the proofs we were missing
+    fn process_log_replay(
+        &mut self,
+        message: ShareableMessage<PBFTMessage<RQ>>,
+    ) -> Result<FeExecutionResult<RQ>> {
+        if let LogReplayMessage::RequestProofs(first, last) = message.message().log_replay() {
+            if let Err(err) = self.log_replay.handle_request(
+                message.header(),
+                *first,
+                *last,
+                &self.message_log,
+                self.checkpoints.stable_checkpoint(),
+                &self.synchronizer.view(),
+                &*self.node,
+            ) {
+                self.report_error(message.header().from(), &err);
+
+                return Err(err);
+            }
+
+            return Ok(OPExecResult::MessageProcessedNoUpdate);
+        }
+
+        let view = self.synchronizer.view();
+
+        let sender = message.header().from();
+
+        if let LogReplayMessage::Unavailable(_, Some(stable)) = message.message().log_replay() {
+            match self.checkpoints.adopt_certificate(stable.clone(), &view) {
+                Ok(CheckpointStatus::Stable(certificate)) => {
+                    let result = self.checkpoint_stable(certificate.sequence_number());
+
+                    // Our peers have moved past the decisions we are missing
+                    if let OPExecResult::RunCst = result {
+                        self.log_replay.clear();
+
+                        return Ok(OPExecResult::RunCst);
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    self.report_error(sender, &err);
+
+                    return Err(err);
+                }
+            }
+        }
+
+        let status = match self.log_replay.handle_reply(message, &view) {
+            Ok(status) => status,
+            Err(err) => {
+                self.report_error(sender, &err);
+
+                return Err(err);
+            }
+        };
+
+        Ok(match status {
+            ReplayStatus::Nil => OPExecResult::MessageDropped,
+            ReplayStatus::Running => OPExecResult::MessageProcessedNoUpdate,
+            ReplayStatus::Done(proofs) => {
+                let mut decisions = MaybeVec::builder();
+
+                let mut installed = 0;
+
+                for proof in proofs {
+                    let seq = proof.sequence_number();
+                    let proposal = proof.proposal();
+
+                    // The decisions we have already installed must still be delivered, so we
+                    // stop here and let the view change catch us up on the remaining ones
+                    let decision = match self.consensus.catch_up_to_quorum(
+                        &view,
+                        proof,
+                        &mut self.message_log,
+                    ) {
+                        Ok(decision) => decision,
+                        Err(err) => {
+                            error!(
+                                "{:?} // Failed to install replayed proof {:?}: {:?}",
+                                self.node.id(),
+                                seq,
+                                err
+                            );
+
+                            break;
+                        }
+                    };
+
+                    if let Some((proof_view, leader)) = proposal {
+                        self.synchronizer.decided(seq, proof_view, leader);
+                    }
+
+                    decisions.push(decision);
+
+                    installed += 1;
+                }
+
+                metric_increment(LOG_REPLAY_INSTALLED_PROOFS_ID, Some(installed));
+
+                self.hand_off_leadership();
+
+                OPExecResult::ProgressedDecision(DecisionsAhead::Ignore, decisions.build())
+            }
+            ReplayStatus::RunCst => OPExecResult::RunCst,
+        })
+    }
+
+    /// Attempt to catch up to the quorum by replaying the proofs we are missing,
+    /// instead of running the state transfer protocol.
+    /// Returns whether the log replay was started
+    fn start_log_replay(&mut self) -> bool {
+        let target = match self.synchronizer.pending_catch_up_seq() {
+            Some(target) => target,
+            None => return false,
+        };
+
+        let first = self
+            .message_log
+            .decision_log()
+            .last_execution()
+            .map(|seq| seq.next())
+            .unwrap_or(SeqNo::ZERO);
+
+        // The last decision is included in the view change data, so we only have
+        // to replay the ones that precede it
+        let last = target.prev();
+
+        if last < first {
+            return false;
+        }
+
+        self.log_replay
+            .start(first, last, &self.synchronizer.view(), &*self.node)
+    }
+
+    /// Notify the protocol that a local checkpoint of the application state
+    /// has been taken at sequence number `seq`, with the state digest `digest`.
+    ///
+    /// This will broadcast the corresponding checkpoint message to the quorum, so
+    /// that the checkpoint may become stable.
+    pub fn local_checkpoint_taken(&mut self, seq: SeqNo, digest: Digest) {
+        self.checkpoints
+            .local_checkpoint(seq, digest, &self.synchronizer.view(), &*self.node);
+    }
+
+    /// The handle through which the execution layer reports the local checkpoints it
+    /// finishes, such as the listener of the state transfer protocol
+    pub fn checkpoint_notifier(&self) -> CheckpointNotifier {
+        self.checkpoints.notifier()
+    }
+
+    /// Broadcast the local checkpoints reported through the [`CheckpointNotifier`]
+    fn broadcast_local_checkpoints(&mut self) {
+        for (seq, digest) in self.checkpoints.take_local_checkpoints() {
+            self.local_checkpoint_taken(seq, digest);
+        }
+    }
+
+    /// Install the session key announced by a peer. Should the key have changed, the peer
+    /// has likely restarted and lost the key we had announced to it, so we announce it again
+    fn process_session_key(&mut self, message: &ShareableMessage<PBFTMessage<RQ>>) {
+        let (Some(authenticator), PBFTMessage::SessionKey(session_key)) =
+            (&self.authenticator, message.message())
+        else {
+            return;
+        };
+
+        let sender = message.header().from();
+
+        let epoch = self.synchronizer.view().sequence_number();
+
+        if authenticator.install_peer_key(sender, session_key, epoch) {
+            authenticator.announce_keys::<RQ, NT>([sender], &*self.node);
+        }
+    }
+
+    /// Verify the MAC vector of a prepare message, if it carries one.
+    /// Messages that carry no authenticator are signed, and their signatures have
+    /// already been verified
+    fn verify_authenticator(&mut self, message: &ShareableMessage<PBFTMessage<RQ>>) -> bool {
+        let PBFTMessage::Consensus(consensus) = message.message() else {
+            return true;
+        };
+
+        if consensus.authenticator().is_none() {
+            return true;
+        }
+
+        let sender = message.header().from();
+
+        let valid = self
+            .authenticator
+            .as_ref()
+            .is_some_and(|authenticator| authenticator.verify(sender, consensus));
+
+        if !valid {
+            warn!(
+                "{:?} // Dropping consensus message {:?} from {:?} with an invalid authenticator",
+                self.node.id(),
+                consensus,
+                sender
+            );
+
+            self.peers.report(sender, Misbehavior::Invalid);
+        }
+
+        valid
+    }
+
+    /// Check that the requests of a pre prepare fit within our byte limit.
+    /// The leader would have split them over several batches, so it is at fault otherwise
+    fn verify_pre_prepare_size(&mut self, message: &ShareableMessage<PBFTMessage<RQ>>) -> bool {
+        let PBFTMessage::Consensus(consensus) = message.message() else {
+            return true;
+        };
+
+        let ConsensusMessageKind::PrePrepare(requests) = consensus.kind() else {
+            return true;
+        };
+
+        let limit = self.max_pre_prepare_bytes;
+
+        let Err(err) = verify::verify_pre_prepare_size::<RQ, _>(requests, limit) else {
+            return true;
+        };
+
+        let sender = message.header().from();
+
+        warn!(
+            "{:?} // Dropping pre prepare {:?} from {:?}: {}",
+            self.node.id(),
+            consensus,
+            sender,
+            err
+        );
+
+        self.report_error(sender, &err);
+
+        false
+    }
+
+    /// Check whether the given message is within the rate limits of its sender
+    fn admit(&mut self, message: &ShareableMessage<PBFTMessage<RQ>>) -> bool {
+        self.peers.admit(
+            message,
+            self.consensus.window(),
+            self.consensus.view_sequence_number(),
+        )
+    }
+
+    /// Report the sender of a message whose processing failed, if the error is its fault
+    fn report_error(&self, sender: NodeId, err: &anyhow::Error) {
+        if let Some(misbehavior) = Misbehavior::of_error(err) {
+            self.peers.report(sender, misbehavior);
+        }
+    }
+
+    /// Check whether the given consensus message, which must be within our window,
+    /// conflicts with one we have already received from the same sender,
+    /// reporting the equivocation if so
+    fn collect_evidence(&mut self, message: &ShareableMessage<PBFTMessage<RQ>>) {
+        let view = self.consensus.view_sequence_number();
+
+        if let Some(proof) = self.evidence.observe(message, view) {
+            self.peers.report(proof.culprit(), Misbehavior::Equivocation);
+
+            self.notify_observer(ObserveEventKind::Equivocation(
+                proof.culprit(),
+                proof.sequence_number(),
+            ));
+        }
+    }
+
+    /// The proofs of equivocation we have collected, which show that the
+    /// respective replicas have signed conflicting consensus messages
+    pub fn misbehavior_proofs(&self) -> Vec<&MisbehaviorProof<RQ>> {
+        self.evidence.proofs().collect()
+    }
+
+    /// Register an observer, to which we will report events such as detected equivocations
+    pub fn register_observer(&mut self, observer: ObserverHandle) {
+        self.observer = Some(observer);
+    }
+
+    fn notify_observer(&self, event: ObserveEventKind) {
+        if let Some(observer) = &self.observer {
+            if observer.tx().send(MessageType::Event(event)).is_err() {
+                warn!("{:?} // Failed to notify the observer", self.node.id());
+            }
+        }
+    }
+
+    /// Install the classifier which assigns each request to a priority lane of the proposer
+    pub fn set_request_classifier(&self, classifier: SharedClassifier<RQ>) {
+        self.proposer.set_classifier(classifier);
+    }
+
+    /// Take the requests we refused to order since the last call, either because they do
+    /// not fit in a batch or because the validity predicate rejected them.
+    /// The application should answer their clients with an error, as they will never be decided
+    pub fn take_rejected_requests(&self) -> Vec<RejectedRequest> {
+        self.proposer.take_rejected_requests()
+    }
+
+    /// Install the application predicate the proposed batches must satisfy before we
+    /// prepare them. It must be deterministic, as all correct replicas must agree on it
+    pub fn set_validity_predicate(&mut self, predicate: SharedValidityPredicate<RQ>) {
+        self.proposer.set_validity_predicate(predicate.clone());
+        self.consensus.set_validity_predicate(predicate);
+    }
+
+    /// The latest proposals which were rejected by the validity predicate,
+    /// oldest first
+    pub fn invalid_batches(&self) -> &VecDeque<InvalidBatchRecord> {
+        &self.invalid_batches
+    }
+
+    /// Vote out the leader whose proposal was rejected by the validity predicate,
+    /// keeping a record of the signed proposal against it.
+    /// Our STOP carries the requests of the rejected batch, so the next leader orders
+    /// the ones which are valid on their own
+    fn reject_invalid_batches(&mut self) {
+        let proofs = self.consensus.take_invalid_batches();
+
+        if proofs.is_empty() {
+            return;
+        }
+
+        let view = self.synchronizer.view();
+
+        let mut current_leader_failed = false;
+
+        let mut requests = Vec::new();
+
+        for proof in proofs {
+            warn!(
+                "{:?} // Leader {:?} proposed an invalid batch for {:?} in view {:?}: {}",
+                self.node.id(),
+                proof.culprit(),
+                proof.sequence_number(),
+                proof.view(),
+                proof.reason()
+            );
+
+            self.peers.report(proof.culprit(), Misbehavior::Invalid);
+
+            if proof.view() == view.sequence_number() {
+                current_leader_failed = true;
+
+                requests.extend(proof.requests());
+            }
+
+            if self.invalid_batches.len() >= MAX_INVALID_BATCH_RECORDS {
+                self.invalid_batches.pop_front();
+            }
+
+            self.invalid_batches.push_back(proof.record());
+        }
+
+        if !current_leader_failed || self.phase != ConsensusPhase::NormalPhase {
+            return;
+        }
+
+        metric_increment(INVALID_BATCH_VIEW_CHANGES_ID, Some(1));
+
+        self.switch_phase(ConsensusPhase::SyncPhase);
+
+        self.synchronizer.begin_view_change(
+            Some(requests),
+            &*self.node,
+            &self.timeouts,
+            &self.message_log,
+        );
+    }
+
+    /// Start the leader liveness timer for the current view, if the leader sends heartbeats.
+    /// It is satisfied by the next pre prepare the leader sends us
+    fn watch_leader(&self) {
+        let Some(leader_timeout) = self.leader_timeout else {
+            return;
+        };
+
+        let view = self.synchronizer.view();
+
+        if view.leader_set().contains(&self.node.id()) {
+            return;
+        }
+
+        let _ = self.timeouts.request_timeout(
+            ViewTimer::LeaderLiveness(view.sequence_number()).id(),
+            None,
+            leader_timeout,
+            1,
+            false,
+        );
+    }
+
+    /// We have received a pre prepare (possibly a heartbeat) from the given node,
+    /// so if it is the leader, it is alive and we restart the liveness timer
+    fn leader_activity(&mut self, from: NodeId) {
+        if self.leader_timeout.is_none() && self.handoff_timeout.is_none() {
+            return;
+        }
+
+        let view = self.synchronizer.view();
+
+        if !view.leader_set().contains(&from) {
+            return;
+        }
+
+        if self.pending_handoff.take().is_some() {
+            debug!(
+                "{:?} // The leader of view {:?} has taken over",
+                self.node.id(),
+                view.sequence_number()
+            );
+
+            let _ = self
+                .timeouts
+                .ack_received(ViewTimer::Handoff(view.sequence_number()).id(), from);
+        }
+
+        let _ = self
+            .timeouts
+            .ack_received(ViewTimer::LeaderLiveness(view.sequence_number()).id(), from);
+
+        self.watch_leader();
+    }
+
+    /// Handle the expiry of leader liveness timers, starting a view change if the
+    /// leader of the current view has not sent us anything in time.
+    /// Returns whether a view change was started
+    fn leader_timed_out(&mut self, timers: &[ViewTimer]) -> bool {
+        let view = self.synchronizer.view();
+
+        let handoff_timed_out = self.pending_handoff == Some(view.sequence_number())
+            && timers.contains(&ViewTimer::Handoff(view.sequence_number()));
+
+        let leader_timed_out =
+            timers.contains(&ViewTimer::LeaderLiveness(view.sequence_number()));
+
+        // Timers of older views are stale, and we are already changing views in the sync phase
+        if !(handoff_timed_out || leader_timed_out) || self.phase != ConsensusPhase::NormalPhase {
+            return false;
+        }
+
+        if handoff_timed_out {
+            self.pending_handoff = None;
+
+            warn!(
+                "{:?} // The leader of view {:?} has not taken over within {:?}, falling back to a view change",
+                self.node.id(),
+                view.sequence_number(),
+                self.handoff_timeout
+            );
+
+            metric_increment(STALLED_LEADER_HANDOFFS_ID, Some(1));
+        } else {
+            warn!(
+                "{:?} // The leader of view {:?} has not sent a pre prepare in {:?}, starting a view change",
+                self.node.id(),
+                view.sequence_number(),
+                self.leader_timeout
+            );
+
+            metric_increment(SYNC_LEADER_LIVENESS_TIMEOUTS_ID, Some(1));
+        }
+
+        self.switch_phase(ConsensusPhase::SyncPhase);
+
+        // No requests timed out, but this is still a timeout (rather than a received STOP)
+        self.synchronizer.begin_view_change(
+            Some(Vec::new()),
+            &*self.node,
+            &self.timeouts,
+            &self.message_log,
+        );
+
+        true
+    }
+
+    /// Hand leadership over to the next view, should we have decided our way past
+    /// a rotation point. Every correct replica decides the same sequence numbers,
+    /// so they all install the same view, without exchanging any messages
+    fn hand_off_leadership(&mut self) {
+        let handoff_points = self.consensus.pending_handoff_points();
+
+        if handoff_points.is_empty() || self.phase != ConsensusPhase::NormalPhase {
+            return;
+        }
+
+        let current_view = self.synchronizer.view();
+
+        // We hand off one view at a time, each elected with the history agreed at the
+        // rotation point before it, so we elect the same leaders as the replicas which
+        // handed off at each rotation point as they decided it
+        let mut handoffs: u64 = 0;
+
+        for handoff_at in handoff_points {
+            if !self.synchronizer.hand_off(self.synchronizer.view().next_view(), handoff_at) {
+                break;
+            }
+
+            handoffs += 1;
+        }
+
+        if handoffs == 0 {
+            return;
+        }
+
+        let view = self.synchronizer.view();
+
+        info!(
+            "{:?} // Handing leadership of view {:?} over to {:?} in view {:?}, at sequence number {:?}",
+            self.node.id(),
+            current_view.sequence_number(),
+            view.leader(),
+            view.sequence_number(),
+            self.consensus.sequence_number()
+        );
+
+        metric_increment(LEADER_HANDOFFS_ID, Some(handoffs));
+
+        self.consensus.install_view(&view);
+
+        self.watch_handoff(&view);
+    }
+
+    /// Start the timer which falls back to a view change, should the leader
+    /// taking over in a hand off not send us a pre prepare in time
+    fn watch_handoff(&mut self, view: &ViewInfo) {
+        let Some(handoff_timeout) = self.handoff_timeout else {
+            return;
+        };
+
+        if view.leader_set().contains(&self.node.id()) {
+            return;
+        }
+
+        self.pending_handoff = Some(view.sequence_number());
+
+        let _ = self.timeouts.request_timeout(
+            ViewTimer::Handoff(view.sequence_number()).id(),
+            None,
+            handoff_timeout,
+            1,
+            false,
+        );
+    }
+
+    /// Evaluate the performance of the leader on the instances we have just decided,
+    /// voting to replace it if it falls below the expected performance
+    fn monitor_leader(&mut self) {
+        let samples = self.consensus.take_leader_samples();
+
+        let Some(monitor) = &mut self.leader_monitor else {
+            return;
+        };
+
+        let Some(underperformance) = monitor.decided(samples) else {
+            return;
+        };
+
+        let view = self.synchronizer.view();
+
+        // Only vote against the leader of the view we are in, and never against ourselves
+        if underperformance.view != view.sequence_number()
+            || view.leader_set().contains(&self.node.id())
+            || self.phase != ConsensusPhase::NormalPhase
+        {
+            return;
+        }
+
+        warn!(
+            "{:?} // The leader of view {:?} is underperforming ({:?}, expected {:?}), starting a view change",
+            self.node.id(),
+            view.sequence_number(),
+            underperformance.observed,
+            underperformance.expected
+        );
+
+        metric_increment(SYNC_SLOW_LEADER_VIEW_CHANGES_ID, Some(1));
+
+        self.switch_phase(ConsensusPhase::SyncPhase);
+
+        self.synchronizer.begin_view_change(
+            Some(Vec::new()),
+            &*self.node,
+            &self.timeouts,
+            &self.message_log,
+        );
+    }
+
+    /// The misbehavior scores of our peers.
+    /// The returned handle can be shared with monitoring code, so that operators
+    /// can alert on (or disconnect) peers that keep misbehaving
+    pub fn misbehavior_scores(&self) -> MisbehaviorScores {
+        self.peers.scores().clone()
+    }
+
+    /// The latest stable checkpoint, which can be used as the trust anchor
+    /// for states received through state transfer
+    pub fn stable_checkpoint(&self) -> Option<&CheckpointCertificate<RQ>> {
+        self.checkpoints.stable_checkpoint()
+    }
+
     /// Advance the consensus phase with a received message
     fn adv_consensus(
         &mut self,
@@ -680,21 +1692,66 @@ where
     ) -> Result<FeExecutionResult<RQ>> {
         let _seq = self.consensus.sequence_number();
 
+        let sender = message.header().from();
+
+        let is_pre_prepare = matches!(
+            message.message().consensus().kind(),
+            ConsensusMessageKind::PrePrepare(_)
+        );
+
+        let above_window = self
+            .consensus
+            .window()
+            .is_above(message.message().consensus().sequence_number());
+
+        if !above_window {
+            self.collect_evidence(&message);
+        }
+
         // debug!(
         //     "{:?} // Processing consensus message {:?} ",
         //     self.id(),
         //     message
         // );
 
-        let status = self.consensus.process_message(
+        let status = match self.consensus.process_message(
             message,
             &self.synchronizer,
             &self.timeouts,
             &self.node,
-        )?;
+        ) {
+            Ok(status) => status,
+            Err(err) => {
+                self.report_error(sender, &err);
+
+                return Err(err);
+            }
+        };
+
+        // Only a pre prepare which we have validated and accepted shows the leader is alive,
+        // or anyone could keep its liveness timer from expiring with bogus ones
+        if is_pre_prepare
+            && matches!(
+                status,
+                ConsensusStatus::Deciding(_) | ConsensusStatus::Decided(_)
+            )
+        {
+            self.leader_activity(sender);
+        }
+
+        self.reject_invalid_batches();
 
         Ok(match status {
-            ConsensusStatus::VotedTwice(_) | ConsensusStatus::MessageIgnored => {
+            ConsensusStatus::VotedTwice(node) => {
+                self.peers.report(node, Misbehavior::Duplicate);
+
+                OPExecResult::MessageDropped
+            }
+            ConsensusStatus::MessageIgnored => {
+                if above_window {
+                    self.peers.report(sender, Misbehavior::OutOfWindow);
+                }
+
                 OPExecResult::MessageDropped
             }
             ConsensusStatus::MessageQueued => OPExecResult::MessageQueued,
@@ -724,6 +1781,19 @@ where
             finalized_decisions.push(exec_info);
         }
 
+        if !finalized_decisions.is_empty() {
+            let decision_log = self.message_log.decision_log();
+
+            if let (Some(seq), Some((proof_view, leader))) = (decision_log.last_execution(), decision_log.last_proposal()) {
+                self.synchronizer.decided(seq, proof_view, leader);
+
+                self.synchronizer.committed_in_view(proof_view);
+            }
+        }
+
+        self.evidence
+            .discard_before(self.consensus.sequence_number());
+
         Ok(finalized_decisions)
     }
 
@@ -759,6 +1829,13 @@ where
 
                 SyncPhaseRes::JoinedQuorum(consensus_decision, decision, node)
             }
+            SynchronizerStatus::QuorumJoinAborted(candidates) => {
+                //No node was voted into the quorum, so we resume the current view
+                //The candidates were told so, and stop waiting to be added to it
+                self.switch_phase(ConsensusPhase::NormalPhase);
+
+                SyncPhaseRes::QuorumJoinAborted(candidates)
+            }
             SynchronizerStatus::RunCst => {
                 //This happens when a new view is being introduced and we are not up to date
                 //With the rest of the replicas. This might happen because the replica was faulty
@@ -766,10 +1843,16 @@ where
 
                 //After we update the state, we go back to the sync phase (this phase) so we can check if we are missing
                 //Anything or to finalize and go back to the normal phase
-                info!("Running CST protocol as requested by the synchronizer");
-
                 self.switch_phase(ConsensusPhase::SyncPhase);
 
+                if self.start_log_replay() {
+                    info!("Replaying the missing decisions from the quorum instead of running the CST protocol");
+
+                    return SyncPhaseRes::RunSyncProtocol;
+                }
+
+                info!("Running CST protocol as requested by the synchronizer");
+
                 SyncPhaseRes::RunCSTProtocol
             }
             // should not happen...
@@ -906,7 +1989,10 @@ where
                     //Other operations.
                     self.consensus_guard.lock_consensus();
                 }
-                (ConsensusPhase::SyncPhase, ConsensusPhase::NormalPhase) => {}
+                (ConsensusPhase::SyncPhase, ConsensusPhase::NormalPhase) => {
+                    // Give the leader of the new view its time to propose
+                    self.watch_leader();
+                }
                 (_, _) => {}
             }
 
@@ -962,9 +2048,21 @@ where
             PBFTMessage::ViewChange(_view_change) => {
                 Err(anyhow!("Failed to get type for view change message."))
             }
+            PBFTMessage::Checkpoint(_checkpoint) => {
+                Err(anyhow!("Failed to get type for checkpoint message."))
+            }
+            PBFTMessage::LogReplay(_replay) => {
+                Err(anyhow!("Failed to get type for log replay message."))
+            }
+            PBFTMessage::FetchRequests(_fetch) => {
+                Err(anyhow!("Failed to get type for fetch requests message."))
+            }
             PBFTMessage::ObserverMessage(_) => {
                 Err(anyhow!("Failed to get type for view change message."))
             }
+            PBFTMessage::SessionKey(_) => {
+                Err(anyhow!("Failed to get type for session key message."))
+            }
         }
     }
 
@@ -1073,6 +2171,14 @@ where
                 Ok(ReconfigurationAttemptResult::AlreadyPartOfQuorum)
             }
             SyncReconfigurationResult::InProgress => Ok(ReconfigurationAttemptResult::InProgress),
+            SyncReconfigurationResult::Aborted => {
+                warn!(
+                    "The last quorum view change to integrate node {:?} was aborted without a majority",
+                    joining_node
+                );
+
+                Ok(ReconfigurationAttemptResult::Failed)
+            }
             SyncReconfigurationResult::Completed => Ok(ReconfigurationAttemptResult::Successful(
                 self.synchronizer.view().quorum_members().clone(),
             )),
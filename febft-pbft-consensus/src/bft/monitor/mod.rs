@@ -0,0 +1,324 @@
+//! Monitoring of the performance of the leader, so that a slow leader gets replaced.
+//!
+//! A Byzantine leader can delay its proposals to just under the client request timeout,
+//! degrading the throughput of the whole system without ever being suspected (as shown by
+//! the Aardvark attack). To prevent this, every replica measures the throughput of the leader
+//! and the latency of its proposals (from the moment the leader was able to propose an instance,
+//! once it was opened and the previous proposal was out, until we receive its pre prepare) and
+//! compares them against the best performance of the recent leaders. A leader which falls below
+//! the configured fraction of that expectation is voted out through a view change.
+//!
+//! Performance is only evaluated while the system is saturated: a batch smaller than the target
+//! batch size (such as an empty heartbeat) means the leader did not have enough requests to
+//! propose, so its throughput reflects the demand rather than its speed and the evaluation
+//! window starts over.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+use atlas_common::ordering::SeqNo;
+use atlas_metrics::metrics::{metric_duration, metric_store_count};
+
+use crate::bft::config::LeaderMonitorConfig;
+use crate::bft::metric::{SYNC_LEADER_PROPOSE_LATENCY_ID, SYNC_LEADER_THROUGHPUT_ID};
+
+/// The performance of the leader on a single decided consensus instance
+#[derive(Debug, Clone)]
+pub struct LeaderSample {
+    pub view: SeqNo,
+    pub batch_size: usize,
+    /// The time between the leader being able to propose the instance and the reception of its pre prepare
+    pub propose_latency: Duration,
+    pub decided_at: Instant,
+}
+
+/// The performance of the leader over an evaluation window
+#[derive(Debug, Clone, Copy)]
+pub struct LeaderPerformance {
+    /// In requests per second
+    pub throughput: f64,
+    pub propose_latency: Duration,
+}
+
+/// A leader whose performance is below the expectation
+#[derive(Debug, Clone)]
+pub struct LeaderUnderperformance {
+    pub view: SeqNo,
+    pub observed: LeaderPerformance,
+    pub expected: LeaderPerformance,
+}
+
+pub struct LeaderMonitor {
+    config: LeaderMonitorConfig,
+    // The batch size from which a decision shows the leader had enough requests to propose
+    saturated_batch_size: usize,
+    // The samples of the window being evaluated, all from the same view
+    window: Vec<LeaderSample>,
+    // The performance of the most recent windows which were deemed acceptable, with their view
+    history: VecDeque<(SeqNo, LeaderPerformance)>,
+}
+
+impl LeaderMonitor {
+    pub fn new(config: LeaderMonitorConfig, saturated_batch_size: usize) -> Self {
+        Self {
+            window: Vec::with_capacity(config.window as usize),
+            history: VecDeque::with_capacity(config.history as usize),
+            saturated_batch_size: saturated_batch_size.max(1),
+            config,
+        }
+    }
+
+    /// The best performance among the leaders of the recent windows: the highest throughput
+    /// and the lowest proposal latency any of them achieved, on average over its windows
+    fn expectation(&self) -> Option<LeaderPerformance> {
+        let mut leaders: BTreeMap<SeqNo, Vec<&LeaderPerformance>> = BTreeMap::new();
+
+        for (view, performance) in &self.history {
+            leaders.entry(*view).or_default().push(performance);
+        }
+
+        leaders
+            .values()
+            .map(|windows| LeaderPerformance {
+                throughput: windows
+                    .iter()
+                    .map(|performance| performance.throughput)
+                    .sum::<f64>()
+                    / windows.len() as f64,
+                propose_latency: windows
+                    .iter()
+                    .map(|performance| performance.propose_latency)
+                    .sum::<Duration>()
+                    / windows.len() as u32,
+            })
+            .reduce(|best, leader| LeaderPerformance {
+                throughput: best.throughput.max(leader.throughput),
+                propose_latency: best.propose_latency.min(leader.propose_latency),
+            })
+    }
+
+    /// The performance over the window, unless all its instances were decided at once
+    fn window_performance(&self) -> Option<LeaderPerformance> {
+        let requests = self
+            .window
+            .iter()
+            .skip(1)
+            .map(|sample| sample.batch_size)
+            .sum::<usize>();
+
+        let elapsed = match (self.window.first(), self.window.last()) {
+            (Some(first), Some(last)) => {
+                last.decided_at.saturating_duration_since(first.decided_at)
+            }
+            _ => Duration::ZERO,
+        };
+
+        if elapsed.is_zero() {
+            return None;
+        }
+
+        let throughput = requests as f64 / elapsed.as_secs_f64();
+
+        let propose_latency = self
+            .window
+            .iter()
+            .map(|sample| sample.propose_latency)
+            .sum::<Duration>()
+            / self.window.len().max(1) as u32;
+
+        Some(LeaderPerformance {
+            throughput,
+            propose_latency,
+        })
+    }
+
+    /// Feed the samples of the instances decided since the last call.
+    /// Returns the underperformance of the leader, should the evaluation window it completes
+    /// fall below the expectation
+    pub fn decided(&mut self, samples: Vec<LeaderSample>) -> Option<LeaderUnderperformance> {
+        let mut underperformance = None;
+
+        for sample in samples {
+            let new_view = self
+                .window
+                .first()
+                .is_some_and(|first| first.view != sample.view);
+
+            let saturated = sample.batch_size >= self.saturated_batch_size;
+
+            if !saturated || new_view {
+                // Either the leader did not have enough requests to propose or it has changed,
+                // so the window no longer reflects its performance under load
+                self.window.clear();
+
+                if !saturated {
+                    continue;
+                }
+            }
+
+            let view = sample.view;
+
+            self.window.push(sample);
+
+            if self.window.len() >= (self.config.window as usize).max(2) {
+                underperformance = self.evaluate_window(view).or(underperformance);
+            }
+        }
+
+        underperformance
+    }
+
+    fn evaluate_window(&mut self, view: SeqNo) -> Option<LeaderUnderperformance> {
+        let observed = self.window_performance();
+
+        self.window.clear();
+
+        let observed = observed?;
+
+        metric_store_count(SYNC_LEADER_THROUGHPUT_ID, observed.throughput as usize);
+
+        metric_duration(SYNC_LEADER_PROPOSE_LATENCY_ID, observed.propose_latency);
+
+        let expected = self.expectation();
+
+        debug!(
+            "Leader of view {:?} performance: {:?}, expected {:?}",
+            view, observed, expected
+        );
+
+        if let Some(expected) = expected {
+            let threshold = self.config.threshold.clamp(f64::EPSILON, 1.0);
+
+            let slow_throughput = observed.throughput < expected.throughput * threshold;
+            let slow_proposals = !expected.propose_latency.is_zero()
+                && observed.propose_latency.as_secs_f64()
+                    > expected.propose_latency.as_secs_f64() / threshold;
+
+            if slow_throughput || slow_proposals {
+                // An underperforming window should not lower our expectations
+                return Some(LeaderUnderperformance {
+                    view,
+                    observed,
+                    expected,
+                });
+            }
+        }
+
+        if self.history.len() >= (self.config.history as usize).max(1) {
+            self.history.pop_front();
+        }
+
+        self.history.push_back((view, observed));
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod monitor_tests {
+    use crate::bft::metric::ConsensusMetrics;
+
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    /// Feeds windows of two decisions, each window starting where the last one ended
+    struct Feeder {
+        monitor: LeaderMonitor,
+        now: Instant,
+    }
+
+    impl Feeder {
+        fn new(threshold: f64) -> Self {
+            Self {
+                monitor: LeaderMonitor::new(LeaderMonitorConfig::new(threshold, 2, 8), 5),
+                now: Instant::now(),
+            }
+        }
+
+        /// A window in which the leader of `view` proposed `batch_size` requests
+        /// `elapsed` after the previous ones, taking `latency` to propose each
+        fn window(
+            &mut self,
+            view: u32,
+            batch_size: usize,
+            elapsed: Duration,
+            latency: Duration,
+        ) -> Option<LeaderUnderperformance> {
+            let sample = |decided_at| LeaderSample {
+                view: SeqNo::from(view),
+                batch_size,
+                propose_latency: latency,
+                decided_at,
+            };
+
+            let first = sample(self.now);
+
+            self.now += elapsed;
+
+            self.monitor.decided(vec![first, sample(self.now)])
+        }
+    }
+
+    #[test]
+    fn test_leader_is_only_judged_when_saturated() {
+        let mut feeder = Feeder::new(0.5);
+
+        // 100 requests per second
+        assert!(feeder.window(0, 10, ms(100), ms(1)).is_none());
+
+        // Batches below the target size show there was not enough demand to judge the leader
+        assert!(feeder.window(1, 4, ms(10_000), ms(1)).is_none());
+        assert!(feeder.window(1, 0, ms(10_000), ms(1)).is_none());
+
+        // Under load, 10 requests per second is too slow
+        let slow = feeder.window(1, 10, ms(1_000), ms(1)).unwrap();
+
+        assert_eq!(slow.view, SeqNo::from(1u32));
+    }
+
+    #[test]
+    fn test_throughput_is_compared_against_the_best_leader() {
+        let mut feeder = Feeder::new(0.5);
+
+        // 100 and 60 requests per second, averaging 80
+        assert!(feeder.window(0, 10, ms(100), ms(1)).is_none());
+        assert!(feeder.window(1, 12, ms(200), ms(1)).is_none());
+
+        // 45 requests per second is above half of the average, but not of the best leader
+        assert!(feeder.window(2, 9, ms(200), ms(1)).is_some());
+    }
+
+    #[test]
+    fn test_propose_latency_is_compared_against_the_best_leader() {
+        let mut feeder = Feeder::new(0.5);
+
+        assert!(feeder.window(0, 10, ms(100), ms(40)).is_none());
+        assert!(feeder.window(1, 10, ms(100), ms(10)).is_none());
+
+        // Within twice the average latency, but not within twice the best one
+        assert!(feeder.window(2, 10, ms(100), ms(30)).is_some());
+        assert!(feeder.window(2, 10, ms(100), ms(20)).is_none());
+    }
+
+    #[test]
+    fn test_propose_latency_starts_once_the_leader_can_propose() {
+        let mut metrics = ConsensusMetrics::new();
+
+        let start = metrics.consensus_start_time;
+
+        metrics.first_pre_prepare_time = start + ms(50);
+
+        assert_eq!(metrics.propose_latency(None), ms(50));
+
+        // The instance was opened ahead of time, while the previous one was being proposed
+        assert_eq!(metrics.propose_latency(Some(start + ms(30))), ms(20));
+
+        // The previous proposal was out before this instance started
+        assert_eq!(metrics.propose_latency(Some(start - ms(10))), ms(50));
+    }
+}
@@ -0,0 +1,694 @@
+//! Per-peer rate limiting and misbehavior scoring.
+//!
+//! Every replica can only push a limited amount of messages of each kind to us,
+//! enforced by a token bucket per peer and message kind. Messages that exceed the
+//! limit are dropped before they reach the consensus or the synchronizer.
+//!
+//! The first message a peer sends us of each kind for a given slot (the sequence number
+//! and view it refers to) is never rate limited, as long as that slot is within our window
+//! and view: correct replicas send exactly one such message, so dropping it could stall the
+//! protocol. Only the repeated messages of a slot, or those of slots we are not at, use up
+//! the tokens of a peer. Checkpoints, log replay, request fetching and session key messages
+//! are not tied to the slots we are at, so all of them use up tokens.
+//!
+//! Alongside the rate limits, we keep a misbehavior score for each peer which
+//! rises whenever it sends us messages that are rate limited, duplicated, invalid
+//! or outside of our sequence number window. These scores are exposed through
+//! [`MisbehaviorScores`], so that operators can alert on them or disconnect a peer.
+//! Scores decay over time, so a peer that stops misbehaving is eventually forgiven.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::{trace, warn};
+
+use atlas_common::node_id::NodeId;
+use atlas_common::ordering::{Orderable, SeqNo};
+use atlas_core::ordering_protocol::ShareableMessage;
+use atlas_metrics::metrics::metric_increment;
+
+use crate::bft::checkpoint::CheckpointError;
+use crate::bft::config::{RateLimitConfig, TokenBucketConfig};
+use crate::bft::consensus::SeqNoWindow;
+use crate::bft::log::deciding::DecidingLogError;
+use crate::bft::log::decisions::ProofError;
+use crate::bft::log::replay::LogReplayError;
+use crate::bft::message::serialize::verify::RequestVerificationError;
+use crate::bft::message::{ConsensusMessageKind, PBFTMessage, ViewChangeMessageKind};
+use crate::bft::metric::{MISBEHAVIOR_REPORTS_ID, RATE_LIMITED_MESSAGES_ID};
+
+/// How many slots of each kind we remember a peer sending us a message for
+const MAX_FIRST_TIME_SLOTS: usize = 1024;
+
+/// How many views past ours the view change messages which are never rate limited may refer to,
+/// as a view change may escalate past the view it was started for
+const FIRST_TIME_VIEWS_AHEAD: u32 = 2;
+
+/// How long it takes for the score of a peer to decrease by one point
+const SCORE_DECAY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The sequence number and the view a message refers to
+type Slot = (SeqNo, SeqNo);
+
+/// The kinds of messages that are rate limited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MessageKind {
+    PrePrepare,
+    Prepare,
+    Commit,
+    Stop,
+    StopData,
+    Sync,
+    SessionKey,
+    LogReplay,
+    Checkpoint,
+    FetchRequests,
+}
+
+impl MessageKind {
+    /// The kind of the given message, if it is subject to rate limiting
+    pub fn of<O>(message: &PBFTMessage<O>) -> Option<Self> {
+        match message {
+            PBFTMessage::Consensus(consensus) => Some(match consensus.kind() {
+                ConsensusMessageKind::PrePrepare(_) => MessageKind::PrePrepare,
+                ConsensusMessageKind::Prepare(_) => MessageKind::Prepare,
+                ConsensusMessageKind::Commit(_) => MessageKind::Commit,
+            }),
+            PBFTMessage::ViewChange(view_change) => Some(match view_change.kind() {
+                ViewChangeMessageKind::Stop(_)
+                | ViewChangeMessageKind::StopQuorumJoin(_)
+                | ViewChangeMessageKind::AbortQuorumJoin => MessageKind::Stop,
+                ViewChangeMessageKind::StopData(_) => MessageKind::StopData,
+                ViewChangeMessageKind::Sync(_) => MessageKind::Sync,
+            }),
+            PBFTMessage::SessionKey(_) => Some(MessageKind::SessionKey),
+            PBFTMessage::LogReplay(_) => Some(MessageKind::LogReplay),
+            PBFTMessage::Checkpoint(_) => Some(MessageKind::Checkpoint),
+            PBFTMessage::FetchRequests(_) => Some(MessageKind::FetchRequests),
+            _ => None,
+        }
+    }
+
+    /// The slot the given message refers to, which must be of this kind
+    fn slot<O>(&self, message: &PBFTMessage<O>) -> Slot {
+        match message {
+            PBFTMessage::Consensus(consensus) => (consensus.sequence_number(), consensus.view()),
+            _ => (SeqNo::ZERO, message.sequence_number()),
+        }
+    }
+
+    /// Whether the given slot of this kind is one we are currently at
+    fn is_current(&self, (seq, view): Slot, window: &SeqNoWindow, curr_view: SeqNo) -> bool {
+        match self {
+            MessageKind::PrePrepare | MessageKind::Prepare | MessageKind::Commit => {
+                view == curr_view && window.contains(seq)
+            }
+            MessageKind::Stop | MessageKind::StopData | MessageKind::Sync => {
+                view > curr_view && u32::from(view) - u32::from(curr_view) <= FIRST_TIME_VIEWS_AHEAD
+            }
+            // These refer to no slot, so they always use up tokens
+            MessageKind::SessionKey
+            | MessageKind::LogReplay
+            | MessageKind::Checkpoint
+            | MessageKind::FetchRequests => false,
+        }
+    }
+}
+
+/// The types of misbehavior that increase the score of a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misbehavior {
+    /// The peer exceeded its rate limit
+    RateLimited,
+    /// The peer sent us a message it had already sent (e.g. voted twice)
+    Duplicate,
+    /// The peer sent us a message that failed verification
+    Invalid,
+    /// The peer sent us a message outside of our sequence number window
+    OutOfWindow,
+    /// The peer signed conflicting messages for the same slot
+    Equivocation,
+}
+
+impl Misbehavior {
+    /// How much this misbehavior weighs on the score of a peer
+    fn weight(&self) -> u64 {
+        match self {
+            Misbehavior::RateLimited => 1,
+            Misbehavior::OutOfWindow => 2,
+            Misbehavior::Duplicate => 5,
+            Misbehavior::Invalid => 10,
+            Misbehavior::Equivocation => 20,
+        }
+    }
+
+    /// The misbehavior of the sender of a message whose processing failed with the given error,
+    /// or `None` if the error is not the fault of the sender
+    pub fn of_error(err: &anyhow::Error) -> Option<Self> {
+        if let Some(err) = err.downcast_ref::<DecidingLogError>() {
+            return match err {
+                DecidingLogError::DuplicateVoteFromNode(_) => Some(Misbehavior::Duplicate),
+                DecidingLogError::FailedToCalculateDigest(_)
+                | DecidingLogError::FailedToGetLeadersRequestSpace(_) => None,
+                _ => Some(Misbehavior::Invalid),
+            };
+        }
+
+        if let Some(err) = err.downcast_ref::<CheckpointError>() {
+            return match err {
+                CheckpointError::DuplicateVote(_, _) => Some(Misbehavior::Duplicate),
+                CheckpointError::NotCertified(_, _) => Some(Misbehavior::OutOfWindow),
+                CheckpointError::CertificateMismatch(_, _)
+                | CheckpointError::CertificateTooSmall(_, _) => Some(Misbehavior::Invalid),
+                CheckpointError::StateNotCertified(_) => None,
+            };
+        }
+
+        if let Some(err) = err.downcast_ref::<LogReplayError>() {
+            return Some(match err {
+                LogReplayError::DuplicateReply(_) => Misbehavior::Duplicate,
+                _ => Misbehavior::Invalid,
+            });
+        }
+
+        if err.downcast_ref::<ProofError>().is_some()
+            || err.downcast_ref::<RequestVerificationError>().is_some()
+        {
+            return Some(Misbehavior::Invalid);
+        }
+
+        None
+    }
+}
+
+/// A token bucket, refilled continuously at a fixed rate
+struct TokenBucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(config: &TokenBucketConfig) -> Self {
+        Self {
+            capacity: config.capacity as f64,
+            refill_per_sec: config.refill_per_sec as f64,
+            tokens: config.capacity as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn try_take(&mut self) -> bool {
+        let now = Instant::now();
+
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The misbehavior score of a peer, which decays by one point every [`SCORE_DECAY_INTERVAL`]
+struct Score {
+    value: u64,
+    // The instant up to which the decay has been applied
+    decayed_at: Instant,
+}
+
+impl Score {
+    fn new(now: Instant) -> Self {
+        Self {
+            value: 0,
+            decayed_at: now,
+        }
+    }
+
+    /// Apply the decay accumulated up to `now`
+    fn decay(&mut self, now: Instant) -> u64 {
+        let elapsed = now.saturating_duration_since(self.decayed_at);
+
+        let intervals = elapsed.as_secs() / SCORE_DECAY_INTERVAL.as_secs();
+
+        self.value = self.value.saturating_sub(intervals);
+
+        self.decayed_at = if self.value == 0 {
+            // A clean peer does not bank decay against its future misbehavior
+            now
+        } else {
+            self.decayed_at + SCORE_DECAY_INTERVAL * intervals as u32
+        };
+
+        self.value
+    }
+}
+
+/// A shareable handle to the misbehavior scores of our peers
+#[derive(Clone, Default)]
+pub struct MisbehaviorScores(Arc<Mutex<BTreeMap<NodeId, Score>>>);
+
+impl MisbehaviorScores {
+    /// The current score of the given peer
+    pub fn score(&self, node: &NodeId) -> u64 {
+        self.score_at(node, Instant::now())
+    }
+
+    /// A snapshot of the scores of all peers which have misbehaved recently
+    pub fn scores(&self) -> BTreeMap<NodeId, u64> {
+        let now = Instant::now();
+
+        let mut scores = self.0.lock().unwrap();
+
+        scores.retain(|_, score| score.decay(now) > 0);
+
+        scores
+            .iter()
+            .map(|(node, score)| (*node, score.value))
+            .collect()
+    }
+
+    fn score_at(&self, node: &NodeId, now: Instant) -> u64 {
+        self.0
+            .lock()
+            .unwrap()
+            .get_mut(node)
+            .map_or(0, |score| score.decay(now))
+    }
+
+    fn increase(&self, node: NodeId, amount: u64) -> u64 {
+        self.increase_at(node, amount, Instant::now())
+    }
+
+    fn increase_at(&self, node: NodeId, amount: u64, now: Instant) -> u64 {
+        let mut scores = self.0.lock().unwrap();
+
+        let score = scores.entry(node).or_insert_with(|| Score::new(now));
+
+        score.value = score.decay(now).saturating_add(amount);
+
+        score.value
+    }
+}
+
+/// Keeps track of the rate limits and misbehavior scores of our peers
+pub struct PeerMonitor {
+    node_id: NodeId,
+    config: RateLimitConfig,
+    buckets: BTreeMap<(NodeId, MessageKind), TokenBucket>,
+    // The slots each peer has sent us a message of each kind for
+    first_seen: BTreeMap<(NodeId, MessageKind), BTreeSet<Slot>>,
+    scores: MisbehaviorScores,
+}
+
+impl PeerMonitor {
+    pub fn new(node_id: NodeId, config: RateLimitConfig) -> Self {
+        Self {
+            node_id,
+            config,
+            buckets: Default::default(),
+            first_seen: Default::default(),
+            scores: Default::default(),
+        }
+    }
+
+    /// The misbehavior scores of our peers
+    pub fn scores(&self) -> &MisbehaviorScores {
+        &self.scores
+    }
+
+    fn bucket_config(&self, kind: MessageKind) -> &TokenBucketConfig {
+        match kind {
+            MessageKind::PrePrepare => &self.config.pre_prepare,
+            MessageKind::Prepare => &self.config.prepare,
+            MessageKind::Commit => &self.config.commit,
+            MessageKind::Stop => &self.config.stop,
+            MessageKind::StopData => &self.config.stop_data,
+            MessageKind::Sync => &self.config.sync,
+            MessageKind::SessionKey => &self.config.session_key,
+            MessageKind::LogReplay => &self.config.log_replay,
+            MessageKind::Checkpoint => &self.config.checkpoint,
+            MessageKind::FetchRequests => &self.config.fetch_requests,
+        }
+    }
+
+    /// Whether this is the first message of its kind the sender sent us for a slot
+    /// we are currently at, recording it if so
+    fn first_time(
+        &mut self,
+        sender: NodeId,
+        kind: MessageKind,
+        slot: Slot,
+        window: &SeqNoWindow,
+        view: SeqNo,
+    ) -> bool {
+        if !kind.is_current(slot, window, view) {
+            return false;
+        }
+
+        let seen = self.first_seen.entry((sender, kind)).or_default();
+
+        if seen.len() >= MAX_FIRST_TIME_SLOTS {
+            seen.retain(|slot| kind.is_current(*slot, window, view));
+        }
+
+        seen.len() < MAX_FIRST_TIME_SLOTS && seen.insert(slot)
+    }
+
+    /// Check whether the given message is within the rate limits of its sender, given the
+    /// window and the view we are at. Messages sent by ourselves are never limited, nor
+    /// are the first messages of each kind a peer sends for the slots we are at
+    pub fn admit<O>(
+        &mut self,
+        message: &ShareableMessage<PBFTMessage<O>>,
+        window: &SeqNoWindow,
+        view: SeqNo,
+    ) -> bool {
+        let sender = message.header().from();
+
+        if sender == self.node_id {
+            return true;
+        }
+
+        let Some(kind) = MessageKind::of(message.message()) else {
+            return true;
+        };
+
+        let first_time = self.first_time(sender, kind, kind.slot(message.message()), window, view);
+
+        let config = self.bucket_config(kind).clone();
+
+        let within_limit = self
+            .buckets
+            .entry((sender, kind))
+            .or_insert_with(|| TokenBucket::new(&config))
+            .try_take();
+
+        let admitted = within_limit || first_time;
+
+        if !admitted {
+            trace!(
+                "{:?} // Rate limiting {:?} message from {:?}",
+                self.node_id,
+                kind,
+                sender
+            );
+
+            metric_increment(RATE_LIMITED_MESSAGES_ID, Some(1));
+
+            self.report(sender, Misbehavior::RateLimited);
+        }
+
+        admitted
+    }
+
+    /// Report a misbehavior by the given peer, increasing its score
+    pub fn report(&self, node: NodeId, misbehavior: Misbehavior) {
+        if node == self.node_id {
+            return;
+        }
+
+        let score = self.scores.increase(node, misbehavior.weight());
+
+        metric_increment(MISBEHAVIOR_REPORTS_ID, Some(1));
+
+        if misbehavior != Misbehavior::RateLimited {
+            warn!(
+                "{:?} // Node {:?} misbehaved ({:?}), its score is now {}",
+                self.node_id, node, misbehavior, score
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod peer_tests {
+    use anyhow::anyhow;
+
+    use crate::bft::fixtures::{digest, shared};
+    use crate::bft::message::{
+        CheckpointMessage, ConsensusMessage, FetchRequestsMessage, LogReplayMessage,
+        SessionKeyMessage, ViewChangeMessage,
+    };
+    use crate::bft::sync::view::election::LeaderElection;
+
+    use super::*;
+
+    fn seq(seq: u32) -> SeqNo {
+        SeqNo::from(seq)
+    }
+
+    fn message(from: u32, message: PBFTMessage<u8>) -> ShareableMessage<PBFTMessage<u8>> {
+        shared(NodeId::from(from), digest(0), message)
+    }
+
+    fn prepare(from: u32, instance: u32, view: u32) -> ShareableMessage<PBFTMessage<u8>> {
+        message(
+            from,
+            PBFTMessage::Consensus(ConsensusMessage::new(
+                seq(instance),
+                seq(view),
+                ConsensusMessageKind::Prepare(digest(1)),
+            )),
+        )
+    }
+
+    fn stop(from: u32, view: u32) -> ShareableMessage<PBFTMessage<u8>> {
+        message(
+            from,
+            PBFTMessage::ViewChange(ViewChangeMessage::new(
+                seq(view),
+                ViewChangeMessageKind::Stop(Vec::new()),
+            )),
+        )
+    }
+
+    /// A monitor which admits no message beyond those which are never rate limited
+    fn exhausted_monitor() -> PeerMonitor {
+        let mut config = RateLimitConfig::default();
+
+        config.prepare = TokenBucketConfig::new(0, 0);
+        config.stop = TokenBucketConfig::new(0, 0);
+
+        PeerMonitor::new(NodeId::from(0u32), config)
+    }
+
+    #[test]
+    fn test_first_time_consensus_messages_are_not_rate_limited() {
+        let mut peers = exhausted_monitor();
+
+        let window = SeqNoWindow::new(SeqNo::ZERO, Some(10));
+
+        assert!(peers.admit(&prepare(1, 1, 0), &window, SeqNo::ZERO));
+        assert!(peers.admit(&prepare(1, 2, 0), &window, SeqNo::ZERO));
+        // Each peer has its own slots
+        assert!(peers.admit(&prepare(2, 1, 0), &window, SeqNo::ZERO));
+
+        // Repeating a slot uses up tokens
+        assert!(!peers.admit(&prepare(1, 1, 0), &window, SeqNo::ZERO));
+
+        // As do slots outside of our window and view
+        assert!(!peers.admit(&prepare(1, 11, 0), &window, SeqNo::ZERO));
+        assert!(!peers.admit(&prepare(1, 3, 1), &window, SeqNo::ZERO));
+
+        assert_eq!(peers.scores().score(&NodeId::from(1u32)), 3);
+        assert_eq!(peers.scores().score(&NodeId::from(2u32)), 0);
+    }
+
+    #[test]
+    fn test_first_time_view_change_messages_are_not_rate_limited() {
+        let mut peers = exhausted_monitor();
+
+        let window = SeqNoWindow::new(SeqNo::ZERO, None);
+
+        assert!(peers.admit(&stop(1, 1), &window, SeqNo::ZERO));
+        assert!(!peers.admit(&stop(1, 1), &window, SeqNo::ZERO));
+
+        // An escalated view change
+        assert!(peers.admit(&stop(1, 2), &window, SeqNo::ZERO));
+
+        // Views we are already at, or too far ahead of us
+        assert!(!peers.admit(&stop(1, 0), &window, SeqNo::ZERO));
+        assert!(!peers.admit(&stop(1, 5), &window, SeqNo::ZERO));
+    }
+
+    #[test]
+    fn test_session_keys_are_rate_limited() {
+        let mut config = RateLimitConfig::default();
+
+        config.session_key = TokenBucketConfig::new(2, 0);
+
+        let mut peers = PeerMonitor::new(NodeId::from(0u32), config);
+
+        let window = SeqNoWindow::new(SeqNo::ZERO, Some(10));
+
+        let session_key = |from| {
+            message(
+                from,
+                PBFTMessage::SessionKey(SessionKeyMessage::new(vec![1])),
+            )
+        };
+
+        assert!(peers.admit(&session_key(1), &window, SeqNo::ZERO));
+        assert!(peers.admit(&session_key(1), &window, SeqNo::ZERO));
+        assert!(!peers.admit(&session_key(1), &window, SeqNo::ZERO));
+
+        // Each peer has its own bucket
+        assert!(peers.admit(&session_key(2), &window, SeqNo::ZERO));
+
+        assert_eq!(peers.scores().score(&NodeId::from(1u32)), 1);
+    }
+
+    #[test]
+    fn test_log_replay_requests_are_rate_limited() {
+        let mut config = RateLimitConfig::default();
+
+        config.log_replay = TokenBucketConfig::new(1, 0);
+
+        let mut peers = PeerMonitor::new(NodeId::from(0u32), config);
+
+        let window = SeqNoWindow::new(SeqNo::ZERO, Some(10));
+
+        let request = message(
+            1,
+            PBFTMessage::LogReplay(LogReplayMessage::RequestProofs(seq(1), seq(5))),
+        );
+
+        assert!(peers.admit(&request, &window, SeqNo::ZERO));
+        assert!(!peers.admit(&request, &window, SeqNo::ZERO));
+
+        // Other kinds of messages have their own buckets
+        assert!(peers.admit(&prepare(1, 1, 0), &window, SeqNo::ZERO));
+    }
+
+    #[test]
+    fn test_checkpoints_and_fetches_are_rate_limited() {
+        let mut config = RateLimitConfig::default();
+
+        config.checkpoint = TokenBucketConfig::new(1, 0);
+        config.fetch_requests = TokenBucketConfig::new(1, 0);
+
+        let mut peers = PeerMonitor::new(NodeId::from(0u32), config);
+
+        let window = SeqNoWindow::new(SeqNo::ZERO, Some(10));
+
+        let checkpoint = message(
+            1,
+            PBFTMessage::Checkpoint(CheckpointMessage::new(
+                seq(5),
+                digest(2),
+                SeqNo::ZERO,
+                LeaderElection::default(),
+            )),
+        );
+
+        let fetch = message(
+            1,
+            PBFTMessage::FetchRequests(FetchRequestsMessage::Request(Vec::new())),
+        );
+
+        assert!(peers.admit(&checkpoint, &window, SeqNo::ZERO));
+        assert!(!peers.admit(&checkpoint, &window, SeqNo::ZERO));
+
+        assert!(peers.admit(&fetch, &window, SeqNo::ZERO));
+        assert!(!peers.admit(&fetch, &window, SeqNo::ZERO));
+    }
+
+    #[test]
+    fn test_scores_decay_over_time() {
+        let scores = MisbehaviorScores::default();
+
+        let node = NodeId::from(1u32);
+        let start = Instant::now();
+
+        assert_eq!(scores.increase_at(node, 3, start), 3);
+
+        // Partial intervals are not lost
+        assert_eq!(scores.score_at(&node, start + SCORE_DECAY_INTERVAL / 2), 3);
+        assert_eq!(scores.score_at(&node, start + SCORE_DECAY_INTERVAL), 2);
+        assert_eq!(
+            scores.score_at(&node, start + SCORE_DECAY_INTERVAL * 3 / 2),
+            2
+        );
+        assert_eq!(scores.score_at(&node, start + SCORE_DECAY_INTERVAL * 2), 1);
+
+        // Scores never go below zero, and time spent clean is not banked
+        let clean = start + SCORE_DECAY_INTERVAL * 10;
+
+        assert_eq!(scores.score_at(&node, clean), 0);
+        assert_eq!(scores.increase_at(node, 2, clean), 2);
+        assert_eq!(scores.score_at(&node, clean + SCORE_DECAY_INTERVAL), 1);
+    }
+
+    #[test]
+    fn test_our_own_messages_are_never_limited() {
+        let mut peers = exhausted_monitor();
+
+        let window = SeqNoWindow::new(SeqNo::ZERO, Some(10));
+
+        for _ in 0..3 {
+            assert!(peers.admit(&prepare(0, 1, 0), &window, SeqNo::ZERO));
+        }
+    }
+
+    #[test]
+    fn test_errors_map_to_their_misbehavior() {
+        let node = NodeId::from(1u32);
+
+        let misbehavior = |err: anyhow::Error| Misbehavior::of_error(&err);
+
+        assert_eq!(
+            misbehavior(CheckpointError::DuplicateVote(node, seq(1)).into()),
+            Some(Misbehavior::Duplicate)
+        );
+        assert_eq!(
+            misbehavior(CheckpointError::NotCertified(seq(20), seq(1)).into()),
+            Some(Misbehavior::OutOfWindow)
+        );
+        assert_eq!(
+            misbehavior(CheckpointError::CertificateTooSmall(seq(20), 2).into()),
+            Some(Misbehavior::Invalid)
+        );
+        assert_eq!(
+            misbehavior(DecidingLogError::DuplicateVoteFromNode(node).into()),
+            Some(Misbehavior::Duplicate)
+        );
+        assert_eq!(
+            misbehavior(DecidingLogError::LeaderNotInLeaderSet(node).into()),
+            Some(Misbehavior::Invalid)
+        );
+        assert_eq!(
+            misbehavior(LogReplayError::DuplicateReply(node).into()),
+            Some(Misbehavior::Duplicate)
+        );
+        assert_eq!(
+            misbehavior(
+                RequestVerificationError::PrePrepareTooLarge {
+                    bytes: 2048,
+                    limit: 1024
+                }
+                .into()
+            ),
+            Some(Misbehavior::Invalid)
+        );
+
+        // Our own failures are not the fault of the sender
+        assert_eq!(
+            misbehavior(DecidingLogError::FailedToCalculateDigest(seq(1)).into()),
+            None
+        );
+        assert_eq!(
+            misbehavior(CheckpointError::StateNotCertified(seq(20)).into()),
+            None
+        );
+        assert_eq!(misbehavior(anyhow!("Unrelated failure")), None);
+    }
+}
@@ -0,0 +1,322 @@
+//! The thresholds the proposer uses to decide when to cut a batch.
+//!
+//! By default, the target batch size and the batch timeout are fixed by the [`ProposerConfig`].
+//! In adaptive mode, they are tuned online with an AIMD controller, driven by the latency of
+//! our recent decisions and by how many requests are waiting to be proposed. The controller
+//! adjusts once per batch we propose, from the average latency of the decisions reached since
+//! the previous batch, so how often it adjusts does not depend on how many decisions complete:
+//!
+//! - While the decision latency is above the configured target, the target batch size and the
+//!   batch timeout are decreased multiplicatively, as the system is congested. They are only
+//!   decreased for intervals in which decisions were reached, so a stale latency cannot shrink
+//!   them further.
+//! - When requests are left over after cutting a batch (we are saturated), the target batch
+//!   size is increased additively, trading some latency for throughput.
+//! - When batches are cut by the timeout with fewer requests than the target (we are lightly
+//!   loaded), the batch timeout is decreased, as waiting for more requests only adds latency.
+//!   Conversely, when full batches are cut, the timeout is increased additively.
+//!
+//! [`ProposerConfig`]: crate::bft::config::ProposerConfig
+
+use std::time::Duration;
+
+use tracing::debug;
+
+use atlas_metrics::metrics::metric_store_count;
+
+use crate::bft::config::AdaptiveBatchingConfig;
+use crate::bft::metric::{PROPOSER_BATCH_TIMEOUT_ID, PROPOSER_TARGET_BATCH_SIZE_ID};
+
+/// The weight of each new sample in the moving average of the decision latency
+const LATENCY_EWMA_WEIGHT: f64 = 0.2;
+
+/// In how many steps the batch timeout grows from its minimum to its maximum
+const TIMEOUT_INCREASE_STEPS: f64 = 16.0;
+
+pub enum BatchPolicy {
+    Static {
+        target_batch_size: usize,
+        batch_timeout: u128,
+    },
+    Adaptive(AdaptiveBatching),
+}
+
+impl BatchPolicy {
+    pub fn new(
+        target_batch_size: usize,
+        max_batch_size: usize,
+        batch_timeout: u128,
+        adaptive: Option<AdaptiveBatchingConfig>,
+    ) -> Self {
+        match adaptive {
+            None => BatchPolicy::Static {
+                target_batch_size,
+                batch_timeout,
+            },
+            Some(config) => BatchPolicy::Adaptive(AdaptiveBatching::new(
+                config,
+                target_batch_size,
+                max_batch_size,
+                batch_timeout,
+            )),
+        }
+    }
+
+    /// The amount of requests at which we cut a batch
+    pub fn target_batch_size(&self) -> usize {
+        match self {
+            BatchPolicy::Static {
+                target_batch_size, ..
+            } => *target_batch_size,
+            BatchPolicy::Adaptive(adaptive) => adaptive.target as usize,
+        }
+    }
+
+    /// The time (in microseconds) after which we cut a batch, regardless of its size
+    pub fn batch_timeout(&self) -> u128 {
+        match self {
+            BatchPolicy::Static { batch_timeout, .. } => *batch_timeout,
+            BatchPolicy::Adaptive(adaptive) => adaptive.timeout as u128,
+        }
+    }
+
+    /// Feed the latencies of the decisions reached since the last call
+    pub fn decisions_reached(&mut self, latencies: Vec<Duration>) {
+        if let BatchPolicy::Adaptive(adaptive) = self {
+            latencies
+                .into_iter()
+                .for_each(|latency| adaptive.decision_reached(latency));
+        }
+    }
+
+    /// Feed the size of a batch we have just proposed, along with
+    /// the amount of requests that are still waiting to be proposed
+    pub fn batch_proposed(&mut self, batch_size: usize, backlog: usize) {
+        if let BatchPolicy::Adaptive(adaptive) = self {
+            adaptive.batch_proposed(batch_size, backlog);
+        }
+    }
+}
+
+pub struct AdaptiveBatching {
+    config: AdaptiveBatchingConfig,
+    max_batch_size: f64,
+    target: f64,
+    timeout: f64,
+    // The moving average of the decision latency, in microseconds
+    latency: Option<f64>,
+    // The sum (in microseconds) and the amount of decision latencies since the last batch
+    interval_latency: f64,
+    interval_decisions: usize,
+}
+
+impl AdaptiveBatching {
+    fn new(
+        config: AdaptiveBatchingConfig,
+        target_batch_size: usize,
+        max_batch_size: usize,
+        batch_timeout: u128,
+    ) -> Self {
+        let mut adaptive = Self {
+            max_batch_size: max_batch_size as f64,
+            target: target_batch_size as f64,
+            timeout: batch_timeout as f64,
+            latency: None,
+            interval_latency: 0.0,
+            interval_decisions: 0,
+            config,
+        };
+
+        adaptive.clamp();
+
+        adaptive
+    }
+
+    fn congested(&self) -> bool {
+        self.latency
+            .is_some_and(|latency| latency > self.config.latency_target as f64)
+    }
+
+    fn decision_reached(&mut self, latency: Duration) {
+        self.interval_latency += latency.as_micros() as f64;
+        self.interval_decisions += 1;
+    }
+
+    /// Fold the average latency of the decisions reached in the interval which just ended
+    /// into the moving average. Returns whether any decision was reached in it
+    fn end_interval(&mut self) -> bool {
+        if self.interval_decisions == 0 {
+            return false;
+        }
+
+        let sample = self.interval_latency / self.interval_decisions as f64;
+
+        self.latency = Some(match self.latency {
+            None => sample,
+            Some(average) => average + LATENCY_EWMA_WEIGHT * (sample - average),
+        });
+
+        self.interval_latency = 0.0;
+        self.interval_decisions = 0;
+
+        true
+    }
+
+    fn batch_proposed(&mut self, batch_size: usize, backlog: usize) {
+        let decided = self.end_interval();
+
+        if self.congested() {
+            if decided {
+                self.target *= self.config.multiplicative_decrease;
+                self.timeout *= self.config.multiplicative_decrease;
+
+                self.clamp();
+            }
+
+            return;
+        }
+
+        let full_batch = batch_size >= self.target as usize;
+
+        if backlog > 0 {
+            self.target += self.config.additive_increase as f64;
+        }
+
+        if !full_batch {
+            self.timeout *= self.config.multiplicative_decrease;
+        } else {
+            self.timeout += self
+                .config
+                .max_batch_timeout
+                .saturating_sub(self.config.min_batch_timeout) as f64
+                / TIMEOUT_INCREASE_STEPS;
+        }
+
+        self.clamp();
+    }
+
+    fn clamp(&mut self) {
+        let min_batch_size = (self.config.min_batch_size as f64).min(self.max_batch_size);
+
+        self.target = self
+            .target
+            .clamp(min_batch_size.max(1.0), self.max_batch_size.max(1.0));
+        self.timeout = self.timeout.clamp(
+            self.config.min_batch_timeout as f64,
+            self.config
+                .max_batch_timeout
+                .max(self.config.min_batch_timeout) as f64,
+        );
+
+        debug!(
+            "Adaptive batching: target batch size {}, batch timeout {}us, decision latency {:?}us",
+            self.target as usize, self.timeout as u128, self.latency
+        );
+
+        metric_store_count(PROPOSER_TARGET_BATCH_SIZE_ID, self.target as usize);
+        metric_store_count(PROPOSER_BATCH_TIMEOUT_ID, self.timeout as usize);
+    }
+}
+
+#[cfg(test)]
+mod batching_tests {
+    use super::*;
+
+    const LATENCY_TARGET: Duration = Duration::from_millis(10);
+
+    fn adaptive() -> AdaptiveBatching {
+        let config =
+            AdaptiveBatchingConfig::new(LATENCY_TARGET.as_micros() as u64, 1, 100, 10_000, 10, 0.5);
+
+        AdaptiveBatching::new(config, 100, 1000, 1000)
+    }
+
+    #[test]
+    fn test_adjusts_once_per_batch() {
+        let mut adaptive = adaptive();
+
+        for _ in 0..10 {
+            adaptive.decision_reached(LATENCY_TARGET * 2);
+        }
+
+        // However many decisions were reached, the batch only decreases once
+        adaptive.batch_proposed(100, 0);
+
+        assert_eq!(adaptive.target as usize, 50);
+        assert_eq!(adaptive.timeout as u128, 500);
+    }
+
+    #[test]
+    fn test_adjusts_from_aggregate_latency() {
+        let mut adaptive = adaptive();
+
+        // A single slow decision among fast ones does not make us congested
+        for _ in 0..3 {
+            adaptive.decision_reached(LATENCY_TARGET / 5);
+        }
+
+        adaptive.decision_reached(LATENCY_TARGET * 2);
+
+        adaptive.batch_proposed(100, 50);
+
+        assert!(!adaptive.congested());
+        assert_eq!(adaptive.target as usize, 110);
+    }
+
+    #[test]
+    fn test_holds_without_decisions() {
+        let mut adaptive = adaptive();
+
+        adaptive.decision_reached(LATENCY_TARGET * 2);
+        adaptive.batch_proposed(100, 50);
+
+        assert_eq!(adaptive.target as usize, 50);
+
+        // Still congested, but no decision tells us whether the last decrease was enough
+        adaptive.batch_proposed(50, 50);
+
+        assert_eq!(adaptive.target as usize, 50);
+        assert_eq!(adaptive.timeout as u128, 500);
+    }
+
+    #[test]
+    fn test_light_load_shortens_the_timeout() {
+        let mut adaptive = adaptive();
+
+        adaptive.decision_reached(LATENCY_TARGET / 2);
+
+        // The batch was cut by the timeout, with nothing else waiting
+        adaptive.batch_proposed(10, 0);
+
+        assert_eq!(adaptive.target as usize, 100);
+        assert_eq!(adaptive.timeout as u128, 500);
+
+        // Full batches with requests left over grow both the target and the timeout
+        adaptive.batch_proposed(100, 10);
+
+        assert_eq!(adaptive.target as usize, 110);
+        assert!(adaptive.timeout as u128 > 500);
+    }
+
+    #[test]
+    fn test_stays_within_bounds() {
+        let mut adaptive = adaptive();
+
+        for _ in 0..20 {
+            adaptive.decision_reached(LATENCY_TARGET * 2);
+            adaptive.batch_proposed(1, 0);
+        }
+
+        assert_eq!(adaptive.target as usize, 1);
+        assert_eq!(adaptive.timeout as u128, 100);
+
+        for _ in 0..200 {
+            adaptive.decision_reached(Duration::ZERO);
+            adaptive.batch_proposed(adaptive.target as usize, 1000);
+        }
+
+        assert!(!adaptive.congested());
+        assert_eq!(adaptive.target as usize, 1000);
+        assert_eq!(adaptive.timeout as u128, 10_000);
+    }
+}
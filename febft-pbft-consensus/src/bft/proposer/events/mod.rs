@@ -0,0 +1,162 @@
+//! The events which wake up the proposer thread.
+//!
+//! Instead of polling for requests and sleeping between attempts, the proposer blocks on a
+//! [`ProposerWaker`] until one of its wake up sources fires, or until the deadline of the batch
+//! it is accumulating passes. Each source maps to a single [`ProposerEvent`]:
+//!
+//! - [`ProposerEvent::NewRequests`]: the intake thread has received requests from the pre processor
+//! - [`ProposerEvent::BatchDeadline`]: the timeout of the current batch has passed
+//! - [`ProposerEvent::SeqAvailable`]: the consensus made a sequence number available to propose to
+//! - [`ProposerEvent::Resumed`]: the consensus was unlocked or the sequence number window advanced
+//! - [`ProposerEvent::ViewChanged`]: a new view was installed, so the leader may have changed
+//! - [`ProposerEvent::Cancelled`]: the proposer is shutting down
+
+use std::sync::{Condvar, Mutex};
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProposerEvent {
+    NewRequests,
+    BatchDeadline,
+    SeqAvailable,
+    Resumed,
+    ViewChanged,
+    Cancelled,
+}
+
+impl ProposerEvent {
+    fn mask(self) -> u8 {
+        1 << (self as u8)
+    }
+}
+
+/// The set of events which happened since the proposer last woke up
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ProposerEvents(u8);
+
+impl ProposerEvents {
+    fn insert(&mut self, event: ProposerEvent) {
+        self.0 |= event.mask();
+    }
+
+    pub fn contains(&self, event: ProposerEvent) -> bool {
+        self.0 & event.mask() != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl From<ProposerEvent> for ProposerEvents {
+    fn from(event: ProposerEvent) -> Self {
+        let mut events = Self::default();
+
+        events.insert(event);
+
+        events
+    }
+}
+
+#[derive(Default)]
+pub struct ProposerWaker {
+    events: Mutex<ProposerEvents>,
+    condvar: Condvar,
+}
+
+impl ProposerWaker {
+    /// Signal an event to the proposer, waking it up if it is waiting
+    pub fn wake(&self, event: ProposerEvent) {
+        self.events.lock().unwrap().insert(event);
+
+        self.condvar.notify_one();
+    }
+
+    /// Block until an event is signalled or until the given deadline passes,
+    /// returning (and clearing) the events which happened in the meantime
+    pub fn wait(&self, deadline: Option<Instant>) -> ProposerEvents {
+        let mut events = self.events.lock().unwrap();
+
+        loop {
+            if !events.is_empty() {
+                return std::mem::take(&mut *events);
+            }
+
+            match deadline {
+                None => {
+                    events = self.condvar.wait(events).unwrap();
+                }
+                Some(deadline) => {
+                    let now = Instant::now();
+
+                    if now >= deadline {
+                        return ProposerEvent::BatchDeadline.into();
+                    }
+
+                    events = self.condvar.wait_timeout(events, deadline - now).unwrap().0;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod waker_tests {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn test_events_signalled_before_waiting_are_not_lost() {
+        let waker = ProposerWaker::default();
+
+        waker.wake(ProposerEvent::SeqAvailable);
+        waker.wake(ProposerEvent::NewRequests);
+
+        let events = waker.wait(None);
+
+        assert!(events.contains(ProposerEvent::SeqAvailable));
+        assert!(events.contains(ProposerEvent::NewRequests));
+        assert!(!events.contains(ProposerEvent::Resumed));
+
+        // The events were cleared by the previous wait
+        assert_eq!(
+            waker.wait(Some(Instant::now())),
+            ProposerEvents::from(ProposerEvent::BatchDeadline)
+        );
+    }
+
+    #[test]
+    fn test_wait_returns_at_the_deadline() {
+        let waker = ProposerWaker::default();
+
+        let deadline = Instant::now() + Duration::from_millis(20);
+
+        assert_eq!(
+            waker.wait(Some(deadline)),
+            ProposerEvents::from(ProposerEvent::BatchDeadline)
+        );
+        assert!(Instant::now() >= deadline);
+    }
+
+    #[test]
+    fn test_wake_unblocks_a_waiting_proposer() {
+        let waker = Arc::new(ProposerWaker::default());
+
+        let waiting = {
+            let waker = waker.clone();
+
+            thread::spawn(move || waker.wait(None))
+        };
+
+        thread::sleep(Duration::from_millis(20));
+
+        waker.wake(ProposerEvent::Cancelled);
+
+        let events = waiting.join().unwrap();
+
+        assert!(events.contains(ProposerEvent::Cancelled));
+    }
+}
@@ -0,0 +1,391 @@
+//! Fair queueing of the requests waiting to be proposed.
+//!
+//! Requests are queued per client session, in arrival order, and batches are built by
+//! serving the sessions in a round robin. Each turn, a session contributes as many requests
+//! as the weight of its client, and a client never contributes more than its quota to a single
+//! batch, no matter how many sessions it has. A round that is cut short by the end of a batch
+//! resumes on the next batch, from the first session that was left without its turn.
+//!
+//! A client which has pending requests but gets none of them into a batch is starved for
+//! that batch. We keep track of how many consecutive batches each client has been starved
+//! for, so that the fairness of the quotas can be verified through the metrics
+//! (which are reported by the [`PriorityLanes`] holding the queue).
+//!
+//! [`PriorityLanes`]: crate::bft::proposer::lanes::PriorityLanes
+
+use std::collections::{BTreeMap, VecDeque};
+
+use tracing::debug;
+
+use atlas_common::node_id::NodeId;
+use atlas_common::ordering::SeqNo;
+use atlas_communication::message::StoredMessage;
+use atlas_core::messages::SessionBased;
+
+use crate::bft::config::FairnessConfig;
+use crate::bft::message::serialize::verify::request_size;
+
+/// A session of a client, the unit of the round robin
+type ClientSession = (NodeId, SeqNo);
+
+pub struct FairQueue<RQ> {
+    // The pending requests of each session, in arrival order
+    queues: BTreeMap<ClientSession, VecDeque<StoredMessage<RQ>>>,
+    // The order in which the sessions with pending requests will be served
+    rotation: VecDeque<ClientSession>,
+    // The amount of requests a client contributes in each turn
+    weights: BTreeMap<NodeId, usize>,
+    // The maximum amount of requests a client contributes to a single batch
+    quotas: BTreeMap<NodeId, usize>,
+    default_quota: Option<usize>,
+    // The amount of consecutive batches each client with pending requests has been left out of
+    starvation: BTreeMap<NodeId, usize>,
+    len: usize,
+    bytes: usize,
+}
+
+impl<RQ> FairQueue<RQ>
+where
+    RQ: SessionBased,
+{
+    pub fn new(config: Option<FairnessConfig>) -> Self {
+        let FairnessConfig {
+            default_quota,
+            clients,
+        } = config.unwrap_or_default();
+
+        let weights = clients
+            .iter()
+            .filter_map(|client| Some((NodeId::from(client.client), client.weight? as usize)))
+            .collect();
+
+        let quotas = clients
+            .iter()
+            .filter_map(|client| Some((NodeId::from(client.client), client.quota? as usize)))
+            .collect();
+
+        Self {
+            queues: Default::default(),
+            rotation: Default::default(),
+            weights,
+            quotas,
+            default_quota: default_quota.map(|quota| quota as usize),
+            starvation: Default::default(),
+            len: 0,
+            bytes: 0,
+        }
+    }
+
+    /// The amount of pending requests
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The size, in bytes, of the pending requests
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// The amount of clients which were left out of the last batch
+    pub fn starved_clients(&self) -> usize {
+        self.starvation.len()
+    }
+
+    /// The longest amount of consecutive batches a client has been left out of
+    pub fn max_starvation(&self) -> usize {
+        self.starvation.values().copied().max().unwrap_or(0)
+    }
+
+    fn weight_of(&self, client: &NodeId) -> usize {
+        self.weights.get(client).copied().unwrap_or(1).max(1)
+    }
+
+    fn quota_of(&self, client: &NodeId) -> usize {
+        self.quotas
+            .get(client)
+            .copied()
+            .or(self.default_quota)
+            .unwrap_or(usize::MAX)
+            .max(1)
+    }
+
+    /// Queue the given requests, behind the pending requests of the same session
+    pub fn push_all(&mut self, messages: Vec<StoredMessage<RQ>>) {
+        for message in messages {
+            let session = (message.header().from(), message.message().session_number());
+
+            self.len += 1;
+            self.bytes += request_size(&message);
+
+            let queue = self.queues.entry(session).or_insert_with(|| {
+                self.rotation.push_back(session);
+
+                VecDeque::new()
+            });
+
+            queue.push_back(message);
+        }
+    }
+
+    /// Take the next batch out of the pending requests, serving the sessions in a round robin.
+    /// The batch holds at most `max_batch_size` requests, with at most `max_batch_bytes` bytes
+    pub fn take_batch(
+        &mut self,
+        max_batch_size: usize,
+        max_batch_bytes: Option<usize>,
+    ) -> Vec<StoredMessage<RQ>> {
+        let mut batch = Vec::with_capacity(max_batch_size.min(self.len));
+        let mut batch_bytes = 0;
+
+        let mut taken: BTreeMap<NodeId, usize> = BTreeMap::new();
+
+        // The sessions which can still contribute to this batch, in the order of their turns
+        let mut active = std::mem::take(&mut self.rotation);
+        // The sessions which can no longer contribute to this batch, but still have pending requests
+        let mut parked = VecDeque::new();
+
+        while batch.len() < max_batch_size {
+            let Some(session) = active.pop_front() else {
+                break;
+            };
+
+            let (client, _) = session;
+
+            let weight = self.weight_of(&client);
+            let quota = self.quota_of(&client);
+
+            let Some(queue) = self.queues.get_mut(&session) else {
+                continue;
+            };
+
+            let client_taken = taken.entry(client).or_insert(0);
+
+            let mut served = 0;
+            let mut exhausted = false;
+
+            while served < weight && batch.len() < max_batch_size {
+                if *client_taken >= quota {
+                    exhausted = true;
+                    break;
+                }
+
+                let Some(request) = queue.front() else {
+                    break;
+                };
+
+                let size = request_size(request);
+
+                if max_batch_bytes.is_some_and(|limit| batch_bytes + size > limit) {
+                    exhausted = true;
+                    break;
+                }
+
+                batch.extend(queue.pop_front());
+
+                batch_bytes += size;
+                served += 1;
+                *client_taken += 1;
+            }
+
+            if queue.is_empty() {
+                self.queues.remove(&session);
+            } else if exhausted {
+                parked.push_back(session);
+            } else {
+                active.push_back(session);
+            }
+        }
+
+        // The sessions which did not get their turn go first on the next batch
+        active.append(&mut parked);
+
+        self.rotation = active;
+        self.len -= batch.len();
+        self.bytes -= batch_bytes;
+
+        self.track_starvation(&taken);
+
+        batch
+    }
+
+    fn track_starvation(&mut self, taken: &BTreeMap<NodeId, usize>) {
+        let pending = self
+            .queues
+            .keys()
+            .map(|(client, _)| *client)
+            .collect::<Vec<_>>();
+
+        let mut starvation = BTreeMap::new();
+
+        for client in pending {
+            if taken.get(&client).is_some_and(|taken| *taken > 0) {
+                continue;
+            }
+
+            let batches = self.starvation.get(&client).copied().unwrap_or(0) + 1;
+
+            starvation.insert(client, batches);
+        }
+
+        // Clients which were served (or have nothing pending) are no longer starved
+        self.starvation = starvation;
+
+        if !self.starvation.is_empty() {
+            debug!(
+                "{} clients were left out of the batch, the longest for {} consecutive batches",
+                self.starved_clients(),
+                self.max_starvation()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod fairness_tests {
+    use atlas_communication::lookup_table::MessageModule;
+
+    use crate::bft::config::ClientShareConfig;
+    use crate::bft::fixtures::{digest, header_with};
+
+    use super::*;
+
+    struct Request {
+        session: SeqNo,
+        id: u32,
+    }
+
+    impl SessionBased for Request {
+        fn session_number(&self) -> SeqNo {
+            self.session
+        }
+    }
+
+    fn request(client: u32, session: u32, id: u32, bytes: usize) -> StoredMessage<Request> {
+        let header = header_with(
+            MessageModule::Application,
+            NodeId::from(client),
+            digest(0),
+            bytes,
+        );
+
+        let request = Request {
+            session: SeqNo::from(session),
+            id,
+        };
+
+        StoredMessage::new(header, request)
+    }
+
+    /// Queue `count` requests of a single session for each of the given clients, in
+    /// arrival order. Each request is identified by `client * 100 + n`
+    fn queue(config: Option<FairnessConfig>, clients: &[u32], count: u32) -> FairQueue<Request> {
+        let mut queue = FairQueue::new(config);
+
+        for client in clients {
+            queue.push_all(
+                (0..count)
+                    .map(|n| request(*client, 0, client * 100 + n, 10))
+                    .collect(),
+            );
+        }
+
+        queue
+    }
+
+    fn ids(batch: &[StoredMessage<Request>]) -> Vec<u32> {
+        batch.iter().map(|request| request.message().id).collect()
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let mut queue = queue(None, &[0, 1, 2], 2);
+
+        assert_eq!(
+            ids(&queue.take_batch(10, None)),
+            vec![0, 100, 200, 1, 101, 201]
+        );
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_round_resumes_on_the_next_batch() {
+        let mut queue = queue(None, &[0, 1, 2], 2);
+
+        assert_eq!(ids(&queue.take_batch(2, None)), vec![0, 100]);
+        assert_eq!(ids(&queue.take_batch(2, None)), vec![200, 1]);
+        assert_eq!(ids(&queue.take_batch(2, None)), vec![101, 201]);
+
+        assert_eq!(queue.len(), 0);
+        assert_eq!(queue.bytes(), 0);
+    }
+
+    #[test]
+    fn test_weights() {
+        let config = FairnessConfig::new(None, vec![ClientShareConfig::new(0, Some(2), None)]);
+
+        let mut queue = queue(Some(config), &[0, 1], 4);
+
+        assert_eq!(ids(&queue.take_batch(6, None)), vec![0, 1, 100, 2, 3, 101]);
+    }
+
+    #[test]
+    fn test_quota_is_per_client() {
+        let config = FairnessConfig::new(Some(2), vec![ClientShareConfig::new(1, None, Some(3))]);
+
+        let mut queue = FairQueue::new(Some(config));
+
+        // Client 0 spreads its requests over two sessions
+        queue.push_all((0..3).map(|n| request(0, 0, n, 10)).collect());
+        queue.push_all((0..3).map(|n| request(0, 1, 10 + n, 10)).collect());
+        queue.push_all((0..4).map(|n| request(1, 0, 100 + n, 10)).collect());
+
+        assert_eq!(ids(&queue.take_batch(10, None)), vec![0, 10, 100, 101, 102]);
+
+        // The quotas are per batch
+        assert_eq!(ids(&queue.take_batch(10, None)), vec![1, 11, 103]);
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn test_byte_limit() {
+        let mut queue = FairQueue::new(None);
+
+        queue.push_all(vec![request(0, 0, 0, 60), request(1, 0, 100, 50)]);
+        queue.push_all(vec![request(2, 0, 200, 40)]);
+
+        // Client 1 does not fit after client 0, but client 2 still does
+        assert_eq!(ids(&queue.take_batch(10, Some(100))), vec![0, 200]);
+        assert_eq!(queue.bytes(), 50);
+
+        // Client 1 was left without its turn, so it goes first
+        assert_eq!(ids(&queue.take_batch(10, Some(100))), vec![100]);
+    }
+
+    #[test]
+    fn test_starvation() {
+        let config = FairnessConfig::new(Some(1), Vec::new());
+
+        let mut queue = queue(Some(config), &[0, 1, 2], 3);
+
+        assert_eq!(ids(&queue.take_batch(1, None)), vec![0]);
+        assert_eq!(queue.starved_clients(), 2);
+        assert_eq!(queue.max_starvation(), 1);
+
+        assert_eq!(ids(&queue.take_batch(1, None)), vec![100]);
+        assert_eq!(queue.max_starvation(), 2);
+
+        // No client is ever left out of more batches than there are other clients
+        for _ in 0..7 {
+            queue.take_batch(1, None);
+
+            assert!(queue.max_starvation() <= 2);
+        }
+
+        assert!(queue.is_empty());
+        assert_eq!(queue.starved_clients(), 0);
+    }
+}
@@ -0,0 +1,343 @@
+//! Priority lanes for the requests waiting to be proposed.
+//!
+//! Each request is assigned a lane by a [`RequestClassifier`], where higher lanes hold
+//! higher priority requests (such as latency critical control operations) and lane 0 holds
+//! the bulk traffic. Every lane is fair queued on its own (see [`FairQueue`]).
+//!
+//! Batches are filled from the highest lane down, but while lower lanes have pending requests,
+//! a share of each batch is reserved for them, so that they are never starved by a steady
+//! stream of high priority requests. While requests above lane 0 are waiting, the batch is
+//! cut after the (shorter) urgent batch timeout.
+
+use std::sync::Arc;
+
+use atlas_communication::message::StoredMessage;
+use atlas_core::messages::SessionBased;
+use atlas_metrics::metrics::metric_store_count;
+
+use crate::bft::config::{FairnessConfig, PriorityLanesConfig};
+use crate::bft::message::serialize::verify::request_size;
+use crate::bft::metric::{
+    PROPOSER_MAX_CLIENT_STARVATION_ID, PROPOSER_STARVED_CLIENTS_ID, PROPOSER_URGENT_REQUESTS_ID,
+};
+use crate::bft::proposer::fairness::FairQueue;
+
+/// Assigns a priority lane to each request.
+/// Lanes above the configured amount are placed in the highest lane
+pub trait RequestClassifier<RQ>: Send + Sync {
+    /// The lane of the given request, where higher lanes are drained first
+    fn classify(&self, request: &RQ) -> usize;
+}
+
+/// The default classifier, which places every request in lane 0
+pub struct SingleLane;
+
+impl<RQ> RequestClassifier<RQ> for SingleLane {
+    fn classify(&self, _request: &RQ) -> usize {
+        0
+    }
+}
+
+pub type SharedClassifier<RQ> = Arc<dyn RequestClassifier<RQ>>;
+
+pub struct PriorityLanes<RQ> {
+    // The lanes, from the lowest priority to the highest
+    lanes: Vec<FairQueue<RQ>>,
+    // The share of each batch reserved for the lower lanes
+    lower_lanes_share: f64,
+    // The batch timeout (in microseconds) while requests above lane 0 are waiting
+    urgent_batch_timeout: Option<u128>,
+}
+
+impl<RQ> PriorityLanes<RQ>
+where
+    RQ: SessionBased,
+{
+    pub fn new(config: Option<PriorityLanesConfig>, fairness: Option<FairnessConfig>) -> Self {
+        let (lanes, lower_lanes_share, urgent_batch_timeout) = match config {
+            None => (1, 0.0, None),
+            Some(PriorityLanesConfig {
+                lanes,
+                lower_lanes_share,
+                urgent_batch_timeout,
+            }) => (
+                lanes.max(1) as usize,
+                lower_lanes_share.clamp(0.0, 1.0),
+                Some(urgent_batch_timeout as u128),
+            ),
+        };
+
+        Self {
+            lanes: (0..lanes)
+                .map(|_| FairQueue::new(fairness.clone()))
+                .collect(),
+            lower_lanes_share,
+            urgent_batch_timeout,
+        }
+    }
+
+    /// Whether we have more than one lane, in which case requests have to be classified
+    pub fn is_prioritized(&self) -> bool {
+        self.lanes.len() > 1
+    }
+
+    /// The lane the classifier assigned to the given request
+    pub fn lane_of(&self, classifier: &dyn RequestClassifier<RQ>, request: &RQ) -> usize {
+        classifier.classify(request).min(self.lanes.len() - 1)
+    }
+
+    /// The amount of pending requests, over all lanes
+    pub fn len(&self) -> usize {
+        self.lanes.iter().map(FairQueue::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lanes.iter().all(FairQueue::is_empty)
+    }
+
+    /// The size, in bytes, of the pending requests, over all lanes
+    pub fn bytes(&self) -> usize {
+        self.lanes.iter().map(FairQueue::bytes).sum()
+    }
+
+    /// The amount of requests waiting above lane 0
+    pub fn urgent(&self) -> usize {
+        self.lanes.iter().skip(1).map(FairQueue::len).sum()
+    }
+
+    /// The time (in microseconds) after which we cut a batch, given the regular batch timeout
+    pub fn batch_timeout(&self, batch_timeout: u128) -> u128 {
+        match self.urgent_batch_timeout {
+            Some(urgent_batch_timeout) if self.urgent() > 0 => {
+                batch_timeout.min(urgent_batch_timeout)
+            }
+            _ => batch_timeout,
+        }
+    }
+
+    /// Queue the given requests, in the lanes assigned by the classifier
+    pub fn push_all(
+        &mut self,
+        classifier: &dyn RequestClassifier<RQ>,
+        messages: Vec<StoredMessage<RQ>>,
+    ) {
+        if !self.is_prioritized() {
+            self.lanes[0].push_all(messages);
+
+            return;
+        }
+
+        let mut classified: Vec<Vec<StoredMessage<RQ>>> =
+            self.lanes.iter().map(|_| Vec::new()).collect();
+
+        for message in messages {
+            let lane = self.lane_of(classifier, message.message());
+
+            classified[lane].push(message);
+        }
+
+        self.lanes
+            .iter_mut()
+            .zip(classified)
+            .for_each(|(lane, messages)| lane.push_all(messages));
+
+        metric_store_count(PROPOSER_URGENT_REQUESTS_ID, self.urgent());
+    }
+
+    /// Take the next batch out of the pending requests, draining the highest lanes first.
+    /// The batch holds at most `max_batch_size` requests, with at most `max_batch_bytes` bytes
+    pub fn take_batch(
+        &mut self,
+        max_batch_size: usize,
+        max_batch_bytes: Option<usize>,
+    ) -> Vec<StoredMessage<RQ>> {
+        let reserved = (max_batch_size as f64 * self.lower_lanes_share).floor() as usize;
+
+        let mut batch = Vec::with_capacity(max_batch_size.min(self.len()));
+        let mut batch_bytes = 0;
+
+        for lane in (0..self.lanes.len()).rev() {
+            let pending_below = self.lanes[..lane].iter().map(FairQueue::len).sum::<usize>();
+
+            let capacity = max_batch_size
+                .saturating_sub(batch.len())
+                .saturating_sub(reserved.min(pending_below));
+
+            let lane_batch = self.lanes[lane].take_batch(
+                capacity,
+                max_batch_bytes.map(|limit| limit.saturating_sub(batch_bytes)),
+            );
+
+            batch_bytes += lane_batch
+                .iter()
+                .map(|request| request_size(request))
+                .sum::<usize>();
+
+            batch.extend(lane_batch);
+        }
+
+        metric_store_count(
+            PROPOSER_STARVED_CLIENTS_ID,
+            self.lanes.iter().map(FairQueue::starved_clients).sum(),
+        );
+        metric_store_count(
+            PROPOSER_MAX_CLIENT_STARVATION_ID,
+            self.lanes
+                .iter()
+                .map(FairQueue::max_starvation)
+                .max()
+                .unwrap_or(0),
+        );
+
+        if self.is_prioritized() {
+            metric_store_count(PROPOSER_URGENT_REQUESTS_ID, self.urgent());
+        }
+
+        batch
+    }
+}
+
+#[cfg(test)]
+mod lanes_tests {
+    use atlas_common::node_id::NodeId;
+    use atlas_common::ordering::SeqNo;
+    use atlas_communication::lookup_table::MessageModule;
+
+    use crate::bft::fixtures::{digest, header_with};
+
+    use super::*;
+
+    struct Request {
+        lane: usize,
+        id: u32,
+    }
+
+    impl SessionBased for Request {
+        fn session_number(&self) -> SeqNo {
+            SeqNo::ZERO
+        }
+    }
+
+    /// Places each request in the lane it asks for
+    struct ByLane;
+
+    impl RequestClassifier<Request> for ByLane {
+        fn classify(&self, request: &Request) -> usize {
+            request.lane
+        }
+    }
+
+    fn request(client: u32, lane: usize, id: u32) -> StoredMessage<Request> {
+        let header = header_with(MessageModule::Application, NodeId::from(client), digest(0), 10);
+
+        StoredMessage::new(header, Request { lane, id })
+    }
+
+    /// `count` requests in the given lane, from a client of their own
+    fn requests(lane: usize, count: u32) -> Vec<StoredMessage<Request>> {
+        (0..count)
+            .map(|n| request(lane as u32, lane, lane as u32 * 100 + n))
+            .collect()
+    }
+
+    fn lanes(share: f64) -> PriorityLanes<Request> {
+        PriorityLanes::new(Some(PriorityLanesConfig::new(2, share, 100)), None)
+    }
+
+    fn lanes_of(batch: &[StoredMessage<Request>]) -> Vec<usize> {
+        batch.iter().map(|request| request.message().lane).collect()
+    }
+
+    #[test]
+    fn test_higher_lanes_go_first() {
+        let mut lanes = lanes(0.0);
+
+        lanes.push_all(&ByLane, requests(0, 2));
+        lanes.push_all(&ByLane, requests(1, 2));
+
+        let batch = lanes.take_batch(10, None);
+
+        assert_eq!(lanes_of(&batch), vec![1, 1, 0, 0]);
+        assert_eq!(batch[0].message().id, 100);
+        assert!(lanes.is_empty());
+    }
+
+    #[test]
+    fn test_lower_lanes_reservation() {
+        let mut lanes = lanes(0.25);
+
+        lanes.push_all(&ByLane, requests(0, 8));
+        lanes.push_all(&ByLane, requests(1, 16));
+
+        // A quarter of the batch is kept for the lower lane
+        assert_eq!(
+            lanes_of(&lanes.take_batch(8, None)),
+            [vec![1; 6], vec![0; 2]].concat()
+        );
+
+        assert_eq!(lanes.len(), 16);
+        assert_eq!(lanes.urgent(), 10);
+    }
+
+    #[test]
+    fn test_reservation_is_bounded_by_the_pending_requests() {
+        let mut lanes = lanes(0.25);
+
+        lanes.push_all(&ByLane, requests(0, 1));
+        lanes.push_all(&ByLane, requests(1, 16));
+
+        // Only as much as the lower lane has pending is kept for it
+        assert_eq!(
+            lanes_of(&lanes.take_batch(8, None)),
+            [vec![1; 7], vec![0]].concat()
+        );
+
+        // Without pending requests below, the higher lane may fill the whole batch
+        lanes.push_all(&ByLane, requests(1, 8));
+
+        assert_eq!(lanes_of(&lanes.take_batch(8, None)), vec![1; 8]);
+    }
+
+    #[test]
+    fn test_lanes_over_the_amount_go_to_the_highest() {
+        let mut lanes = lanes(0.0);
+
+        assert_eq!(lanes.lane_of(&ByLane, &Request { lane: 5, id: 0 }), 1);
+
+        lanes.push_all(&ByLane, vec![request(0, 5, 0)]);
+
+        assert_eq!(lanes.urgent(), 1);
+    }
+
+    #[test]
+    fn test_urgent_batch_timeout() {
+        let mut lanes = lanes(0.0);
+
+        lanes.push_all(&ByLane, requests(0, 1));
+
+        assert_eq!(lanes.batch_timeout(1000), 1000);
+
+        lanes.push_all(&ByLane, requests(1, 1));
+
+        assert_eq!(lanes.batch_timeout(1000), 100);
+        assert_eq!(lanes.batch_timeout(50), 50);
+
+        lanes.take_batch(1, None);
+
+        assert_eq!(lanes.urgent(), 0);
+        assert_eq!(lanes.batch_timeout(1000), 1000);
+    }
+
+    #[test]
+    fn test_single_lane() {
+        let mut lanes = PriorityLanes::new(None, None);
+
+        assert!(!lanes.is_prioritized());
+
+        lanes.push_all(&ByLane, requests(1, 2));
+
+        assert_eq!(lanes.urgent(), 0);
+        assert_eq!(lanes.batch_timeout(1000), 1000);
+        assert_eq!(lanes.take_batch(10, None).len(), 2);
+    }
+}
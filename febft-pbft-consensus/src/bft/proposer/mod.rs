@@ -3,7 +3,7 @@ use rayon::{ThreadPool, ThreadPoolBuilder};
 use std::collections::BTreeMap;
 use std::fmt::{Debug, Formatter};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, MutexGuard};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
 use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 use tracing::{debug, error, info, instrument, trace, warn};
@@ -16,30 +16,47 @@ use atlas_communication::message::StoredMessage;
 use atlas_core::messages::{create_rq_correlation_id, ClientRqInfo, SessionBased};
 use atlas_core::metric::{RQ_BATCH_TRACKING_ID, RQ_CLIENT_TRACKING_ID};
 use atlas_core::ordering_protocol::networking::OrderProtocolSendNode;
-use atlas_core::request_pre_processing::{BatchOutput, PreProcessorOutputMessage};
+use atlas_core::request_pre_processing::BatchOutput;
 use atlas_core::timeouts::timeout::TimeoutModHandle;
 use atlas_metrics::metrics::{
     metric_correlation_id_passed, metric_duration, metric_increment,
     metric_initialize_correlation_id, metric_store_count,
 };
 
-use crate::bft::config::ProposerConfig;
+use crate::bft::config::{
+    AdaptiveBatchingConfig, FairnessConfig, PriorityLanesConfig, ProposerConfig,
+};
+use crate::bft::consensus::validity::{self, AcceptAll, SharedValidityPredicate};
 use crate::bft::consensus::ProposerConsensusGuard;
-use crate::bft::message::{ConsensusMessage, ConsensusMessageKind, PBFTMessage};
+use crate::bft::message::serialize::verify::request_size;
+use crate::bft::message::{ConsensusMessage, ConsensusMessageKind, PBFTMessage, SharedRequest};
 use crate::bft::metric::{
-    CLIENT_POOL_BATCH_SIZE_ID, ENTERED_PRE_PROPOSER, PROPOSER_BATCHES_MADE_ID, PROPOSER_LATENCY_ID,
-    PROPOSER_PROPOSE_TIME_ID, PROPOSER_REQUESTS_COLLECTED_ID, PROPOSER_REQUEST_PROCESSING_TIME_ID,
-    PROPOSER_REQUEST_TIME_ITERATIONS_ID,
+    CLIENT_POOL_BATCH_SIZE_ID, ENTERED_PRE_PROPOSER, PROPOSER_BATCHES_MADE_ID,
+    PROPOSER_HEARTBEATS_ID, PROPOSER_INVALID_REQUESTS_ID, PROPOSER_LATENCY_ID,
+    PROPOSER_OVERSIZED_REQUESTS_ID, PROPOSER_PROPOSE_TIME_ID, PROPOSER_REQUESTS_COLLECTED_ID,
+    PROPOSER_REQUEST_PROCESSING_TIME_ID, PROPOSER_REQUEST_TIME_ITERATIONS_ID,
 };
+use crate::bft::proposer::batching::BatchPolicy;
+use crate::bft::proposer::events::ProposerEvent;
+use crate::bft::proposer::lanes::{PriorityLanes, SharedClassifier, SingleLane};
+use crate::bft::proposer::rejected::{RejectedRequest, RejectedRequests, RejectionReason};
 use crate::bft::sync::view::{is_request_in_hash_space, ViewInfo};
 use crate::bft::PBFT;
 
 use super::sync::{AbstractSynchronizer, Synchronizer};
 
+pub mod batching;
+pub mod events;
+pub mod fairness;
+pub mod lanes;
+pub mod rejected;
 //pub mod follower_proposer;
 
 pub type BatchType<R> = Vec<StoredMessage<R>>;
 
+/// How long the intake thread waits for requests before checking whether the proposer was cancelled
+const INTAKE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 ///Handles taking requests from the client pools and storing the requests in the log,
 ///as well as creating new batches and delivering them to the batch_channel
 ///Another thread will then take from this channel and propose the requests
@@ -56,6 +73,8 @@ where
     consensus_guard: Arc<ProposerConsensusGuard>,
     // Should we shut down?
     cancelled: AtomicBool,
+    // The requests received by the intake thread, waiting to be processed
+    inbox: Mutex<Vec<StoredMessage<RQ>>>,
 
     thread_pool: Option<ThreadPool>,
 
@@ -64,26 +83,55 @@ where
     //Time limit for generating a batch with target_global_batch_size size
     global_batch_time_limit: u128,
     max_batch_size: usize,
+    // The maximum size, in bytes, of the requests of a batch
+    max_batch_bytes: Option<usize>,
+    // Whether to tune the target size and the time limit online
+    adaptive_batching: Option<AdaptiveBatchingConfig>,
+    // How batches are shared between clients
+    fairness: Option<FairnessConfig>,
+    // How long we stay idle before proposing an empty batch, if at all
+    heartbeat_interval: Option<Duration>,
+    priority_lanes: Option<PriorityLanesConfig>,
+    // Assigns the priority lane of each request
+    classifier: RwLock<SharedClassifier<RQ>>,
+    // The application predicate our proposals must satisfy
+    validity: RwLock<SharedValidityPredicate<RQ>>,
+    // The requests we refused to order, until the application answers their clients
+    rejected: RejectedRequests,
 }
 
 struct ProposeBuilder<RQ>
 where
     RQ: SerMsg,
 {
-    currently_accumulated: Vec<StoredMessage<RQ>>,
+    // The requests accumulated while we wait for the next batch to propose
+    pending: PriorityLanes<RQ>,
     last_proposal: Instant,
+    // The thresholds at which we cut a batch
+    batching: BatchPolicy,
 }
 
 impl<RQ> ProposeBuilder<RQ>
 where
-    RQ: SerMsg,
+    RQ: SerMsg + SessionBased,
 {
-    pub fn new(target_size: usize) -> Self {
+    pub fn new(
+        batching: BatchPolicy,
+        fairness: Option<FairnessConfig>,
+        priority_lanes: Option<PriorityLanesConfig>,
+    ) -> Self {
         Self {
-            currently_accumulated: Vec::with_capacity(target_size),
+            pending: PriorityLanes::new(priority_lanes, fairness),
             last_proposal: Instant::now(),
+            batching,
         }
     }
+
+    /// The time (in microseconds) after which we cut a batch, which
+    /// is shorter while high priority requests are waiting
+    fn batch_timeout(&self) -> u128 {
+        self.pending.batch_timeout(self.batching.batch_timeout())
+    }
 }
 
 impl<RQ, NT> Proposer<RQ, NT>
@@ -97,12 +145,17 @@ where
         timeouts: TimeoutModHandle,
         consensus_guard: Arc<ProposerConsensusGuard>,
         proposer_config: ProposerConfig,
+        heartbeat_interval: Option<Duration>,
     ) -> Arc<Self> {
         let ProposerConfig {
             target_batch_size,
             max_batch_size,
             batch_timeout,
             processing_threads,
+            max_batch_bytes,
+            adaptive_batching,
+            fairness,
+            priority_lanes,
         } = proposer_config;
 
         let thread_pool = if processing_threads > 1 {
@@ -117,11 +170,15 @@ where
         };
 
         info!(
-            "Proposer configuration: target_batch_size: {}, max_batch_size: {}, batch_timeout: {}, processing_threads: {}",
+            "Proposer configuration: target_batch_size: {}, max_batch_size: {}, batch_timeout: {}, processing_threads: {}, max_batch_bytes: {:?}, adaptive_batching: {:?}, fairness: {:?}, priority_lanes: {:?}",
             target_batch_size,
             max_batch_size,
             batch_timeout,
-            processing_threads
+            processing_threads,
+            max_batch_bytes,
+            adaptive_batching,
+            fairness,
+            priority_lanes
         );
 
         Arc::new(Self {
@@ -130,10 +187,19 @@ where
             synchronizer: sync,
             timeouts,
             cancelled: AtomicBool::new(false),
+            inbox: Mutex::new(Vec::new()),
             consensus_guard,
             target_global_batch_size: target_batch_size as usize,
             global_batch_time_limit: batch_timeout as u128,
             max_batch_size: max_batch_size as usize,
+            max_batch_bytes: max_batch_bytes.map(|limit| limit as usize),
+            adaptive_batching,
+            fairness,
+            heartbeat_interval,
+            priority_lanes,
+            classifier: RwLock::new(Arc::new(SingleLane)),
+            validity: RwLock::new(Arc::new(AcceptAll)),
+            rejected: RejectedRequests::default(),
             thread_pool,
         })
     }
@@ -183,11 +249,67 @@ where
     fn process_received_messages(
         &self,
         view_info: ViewInfo,
-        messages: Vec<StoredMessage<RQ>>,
+        mut messages: Vec<StoredMessage<RQ>>,
         propose_builder: &mut ProposeBuilder<RQ>,
     ) where
         NT: OrderProtocolSendNode<RQ, PBFT<RQ>> + 'static,
     {
+        if let Some(limit) = self.max_batch_bytes {
+            // A request that does not fit in a batch by itself can never be ordered,
+            // so neither propose it nor watch it (which would lead to a view change),
+            // but have its client told so
+            messages.retain(|message| {
+                let size = request_size(message);
+
+                if size > limit {
+                    warn!(
+                        "{:?} // Rejecting request {:?} from {:?} with {} bytes, over the batch limit of {} bytes",
+                        self.node_ref.id(),
+                        message.message().session_number(),
+                        message.header().from(),
+                        size,
+                        limit
+                    );
+
+                    metric_increment(PROPOSER_OVERSIZED_REQUESTS_ID, Some(1));
+
+                    self.rejected.reject(
+                        ClientRqInfo::from(message),
+                        RejectionReason::TooLarge { bytes: size, limit },
+                    );
+
+                    return false;
+                }
+
+                true
+            });
+        }
+
+        let validity = self.validity();
+
+        // A request which is invalid on its own would only get its batch rejected,
+        // so neither propose it nor watch it, but have its client told so
+        messages.retain(|message| {
+            if let Err(reason) = validity.validate_request(message) {
+                warn!(
+                    "{:?} // Rejecting request {:?} from {:?}, rejected by the validity predicate: {}",
+                    self.node_ref.id(),
+                    message.message().session_number(),
+                    message.header().from(),
+                    reason
+                );
+
+                metric_increment(PROPOSER_INVALID_REQUESTS_ID, Some(1));
+
+                self.rejected
+                    .reject(ClientRqInfo::from(message), RejectionReason::Invalid(reason));
+
+                return false;
+            }
+
+            true
+        });
+
         let is_leader = view_info.leader_set().contains(&self.node_ref.id());
 
         let leader_set_size = view_info.leader_set().len();
@@ -199,7 +321,7 @@ where
             .clone();
 
         if is_leader {
-            let mut messages = if let Some(thread_pool) = self.thread_pool.as_ref() {
+            let messages = if let Some(thread_pool) = self.thread_pool.as_ref() {
                 thread_pool.install(|| {
                     messages
                         .into_par_iter()
@@ -225,7 +347,9 @@ where
                     .collect()
             };
 
-            propose_builder.currently_accumulated.append(&mut messages);
+            propose_builder
+                .pending
+                .push_all(self.classifier().as_ref(), messages);
         } else {
             let digest_vec = if let Some(thread_pool) = self.thread_pool.as_ref() {
                 thread_pool.install(|| {
@@ -257,74 +381,70 @@ where
         std::thread::Builder::new()
             .name("Proposer thread".to_string())
             .spawn(move || {
+                let intake = self.clone().start_intake();
 
                 //The currently accumulated requests, accumulated while we wait for the next batch to propose
-                let mut ordered_propose = ProposeBuilder::new(self.target_global_batch_size);
+                let mut ordered_propose = ProposeBuilder::new(
+                    BatchPolicy::new(
+                        self.target_global_batch_size,
+                        self.max_batch_size,
+                        self.global_batch_time_limit,
+                        self.adaptive_batching.clone(),
+                    ),
+                    self.fairness.clone(),
+                    self.priority_lanes.clone(),
+                );
 
                 loop {
-                    if self.cancelled.load(Ordering::Relaxed) {
-                        warn!("Cancelling proposer thread");
-                        break;
-                    }
+                    let info = self.synchronizer.view();
 
-                    //We only want to produce batches to the channel if we are the leader, as
-                    //Only the leader will propose thing
+                    let is_leader = info.leader_set().contains(&self.node_ref.id());
 
-                    // Are we able to currently propose a request?
-                    while !self.consensus_guard.can_propose() {
-                        // Wait until we are able to propose a new block
-                        // Use a while to guard against spurious wake ups
-                        warn!("{:?} // Stopping proposer as we are currently not able to propose anything.", self.node_ref.id());
+                    let deadline = self.next_deadline(is_leader, &ordered_propose);
 
-                        self.consensus_guard.block_until_ready();
+                    let events = self.consensus_guard.waker().wait(deadline);
 
-                        info!("{:?} // Resuming proposer as we are now able to propose again.", self.node_ref.id());
-                    }
-
-                    //We do this as we don't want to get stuck waiting for requests that might never arrive
-                    //Or even just waiting for any type of request. We want to minimize the amount of time the
-                    //Consensus is waiting for new requests
+                    trace!(
+                        "{:?} // Proposer woken up by {:?}",
+                        self.node_ref.id(),
+                        events
+                    );
 
-                    //We don't need to do this for non leader replicas, as that would cause unnecessary strain as the
-                    //Thread is in an infinite loop
-                    // Receive the requests from the clients and process them
-                    let mut collected_requests: Option<PreProcessorOutputMessage<RQ>> = None;
+                    if events.contains(ProposerEvent::Cancelled)
+                        || self.cancelled.load(Ordering::Relaxed)
+                    {
+                        warn!("Cancelling proposer thread");
+                        break;
+                    }
 
+                    // The view might have changed while we were waiting
                     let info = self.synchronizer.view();
 
                     let is_leader = info.leader_set().contains(&self.node_ref.id());
 
+                    let collected_requests = std::mem::take(&mut *self.inbox.lock().unwrap());
 
-                    if is_leader {
-                        let time_until_next_propose = Duration::from_micros(self.get_time_until_next_propose(&ordered_propose) as u64);
-
-                        while let Ok(mut message_batch) = self.batch_reception.recv_timeout(time_until_next_propose) {
-                            if self.handle_received_message(&mut ordered_propose, &mut collected_requests, is_leader, message_batch) { break; }
-                        }
-                    } else {
-                        while let Ok(mut message_batch) = self.batch_reception.recv() {
-                            if self.handle_received_message(&mut ordered_propose, &mut collected_requests, is_leader, message_batch) { break; }
-                        }
-                    }
-                    
-                    let discovered_requests = if let Some(messages) = collected_requests {
-                        metric_increment(PROPOSER_REQUESTS_COLLECTED_ID, Some(messages.len() as u64));
+                    if !collected_requests.is_empty() {
+                        metric_increment(
+                            PROPOSER_REQUESTS_COLLECTED_ID,
+                            Some(collected_requests.len() as u64),
+                        );
 
                         let start_time = Instant::now();
 
-                        let counter = messages.len();
-
-                        self.process_received_messages(info.clone(), messages.into(), &mut ordered_propose);
+                        self.process_received_messages(
+                            info.clone(),
+                            collected_requests,
+                            &mut ordered_propose,
+                        );
 
-                        if counter > 0 {
-                            metric_duration(PROPOSER_REQUEST_PROCESSING_TIME_ID, start_time.elapsed());
-                            metric_increment(PROPOSER_REQUEST_TIME_ITERATIONS_ID, Some(1));
-                        }
+                        metric_duration(PROPOSER_REQUEST_PROCESSING_TIME_ID, start_time.elapsed());
+                        metric_increment(PROPOSER_REQUEST_TIME_ITERATIONS_ID, Some(1));
+                    }
 
-                        true
-                    } else {
-                        false
-                    };
+                    ordered_propose
+                        .batching
+                        .decisions_reached(self.consensus_guard.take_decision_latencies());
 
                     let start = Instant::now();
 
@@ -333,53 +453,88 @@ where
                     if ordered {
                         metric_duration(PROPOSER_PROPOSE_TIME_ID, start.elapsed());
                     }
+                }
 
-                    if !discovered_requests {
-                        self.sleep_for_appropriate_amount_of_time(&ordered_propose);
-                    }
+                // Make sure the intake thread has stopped, waking it up if it was the one
+                // to stop us
+                self.cancel();
+
+                if intake.join().is_err() {
+                    error!(
+                        "{:?} // The proposer intake thread panicked",
+                        self.node_ref.id()
+                    );
                 }
-            }).expect("Failed to launch proposer thread.")
+            })
+            .expect("Failed to launch proposer thread.")
     }
 
-    fn handle_received_message(
-        &self,
-        mut ordered_propose: &mut ProposeBuilder<RQ>,
-        mut collected_requests: &mut Option<PreProcessorOutputMessage<RQ>>,
-        is_leader: bool,
-        mut message_batch: PreProcessorOutputMessage<RQ>,
-    ) -> bool {
-        metric_store_count(CLIENT_POOL_BATCH_SIZE_ID, message_batch.len());
+    /// Start the thread which receives the requests from the pre processor and
+    /// hands them over to the proposer thread, waking it up.
+    /// It checks for cancellation every [`INTAKE_POLL_INTERVAL`], so shutting down is bounded
+    fn start_intake(self: Arc<Self>) -> JoinHandle<()>
+    where
+        RQ: 'static,
+        NT: 'static,
+    {
+        std::thread::Builder::new()
+            .name("Proposer intake thread".to_string())
+            .spawn(move || {
+                while !self.cancelled.load(Ordering::Relaxed) {
+                    match self.batch_reception.recv_timeout(INTAKE_POLL_INTERVAL) {
+                        Ok(message_batch) => {
+                            metric_store_count(CLIENT_POOL_BATCH_SIZE_ID, message_batch.len());
 
-        if let Some(ref mut requests) = &mut collected_requests {
-            requests.append(&mut message_batch);
-        } else {
-            *collected_requests = Some(message_batch);
-        }
+                            let mut message_batch: Vec<StoredMessage<RQ>> = message_batch.into();
+
+                            self.inbox.lock().unwrap().append(&mut message_batch);
 
-        let collected_request_count = collected_requests
-            .as_ref()
-            .map(|requests| requests.len())
-            .unwrap_or(0);
+                            self.consensus_guard
+                                .waker()
+                                .wake(ProposerEvent::NewRequests);
+                        }
+                        Err(TryRecvError::ChannelDc) => {
+                            warn!(
+                                "{:?} // The pre processor channel was disconnected, stopping the proposer",
+                                self.node_ref.id()
+                            );
 
-        let micros_since_last_batch = ordered_propose.last_proposal.elapsed().as_micros();
+                            self.cancel();
+                        }
+                        Err(_) => {}
+                    }
+                }
+            })
+            .expect("Failed to launch proposer intake thread.")
+    }
 
-        if is_leader
-            && (collected_request_count >= self.target_global_batch_size
-                || micros_since_last_batch >= self.global_batch_time_limit)
+    /// The instant at which the proposer should wake up on its own, if it
+    /// does not want to wait for an event: the deadline of the current batch
+    fn next_deadline(&self, is_leader: bool, propose: &ProposeBuilder<RQ>) -> Option<Instant> {
+        if !is_leader
+            || !self.consensus_guard.can_propose()
+            || !self.consensus_guard.has_available_seq_no()
         {
-            return true;
+            // We will be woken up by the event which allows us to propose
+            return None;
         }
 
-        false
-    }
+        if propose.pending.is_empty() {
+            // While idle, we only wake up to propose a heartbeat
+            return self
+                .heartbeat_interval
+                .map(|interval| propose.last_proposal + interval);
+        }
 
-    fn get_time_until_next_propose(&self, propose: &ProposeBuilder<RQ>) -> u128 {
-        let time_until_next_proposal = std::cmp::min(
-            self.global_batch_time_limit / 2,
-            self.global_batch_time_limit - propose.last_proposal.elapsed().as_micros(),
-        );
+        let bytes_limit_reached = self
+            .max_batch_bytes
+            .is_some_and(|limit| propose.pending.bytes() >= limit);
+
+        if propose.pending.len() >= propose.batching.target_batch_size() || bytes_limit_reached {
+            return Some(Instant::now());
+        }
 
-        time_until_next_proposal
+        Some(propose.last_proposal + Duration::from_micros(propose.batch_timeout() as u64))
     }
 
     /// attempt to propose the ordered requests that we have collected
@@ -391,12 +546,30 @@ where
     {
         //Now let's deal with ordered requests
         if is_leader {
-            let current_batch_size = propose.currently_accumulated.len();
+            let current_batch_size = propose.pending.len();
+
+            // With no requests to propose, we only propose an empty batch as a heartbeat,
+            // once we have been idle for the heartbeat interval
+            let heartbeat = propose.pending.is_empty();
+
+            let bytes_limit_reached = self
+                .max_batch_bytes
+                .is_some_and(|limit| propose.pending.bytes() >= limit);
+
+            if heartbeat {
+                let heartbeat_due = self
+                    .heartbeat_interval
+                    .is_some_and(|interval| propose.last_proposal.elapsed() >= interval);
 
-            if current_batch_size < self.target_global_batch_size {
+                if !heartbeat_due {
+                    return false;
+                }
+            } else if current_batch_size < propose.batching.target_batch_size()
+                && !bytes_limit_reached
+            {
                 let micros_since_last_batch = propose.last_proposal.elapsed().as_micros();
 
-                if micros_since_last_batch <= self.global_batch_time_limit {
+                if micros_since_last_batch <= propose.batch_timeout() {
                     //Batch isn't large enough and time hasn't passed, don't even attempt to propose
                     return false;
                 }
@@ -408,25 +581,50 @@ where
                 if let Some((seq, view)) = self.consensus_guard.next_seq_no() {
                     propose.last_proposal = Instant::now();
 
-                    let next_batch = if propose.currently_accumulated.len() > self.max_batch_size {
-                        //This now contains target_global_size requests. We want this to be our next batch
-                        //Currently accumulated contains the remaining messages to be sent in the next batch
-                        let mut next_batch = propose
-                            .currently_accumulated
-                            .split_off(propose.currently_accumulated.len() - self.max_batch_size);
+                    // Whatever does not fit in this batch (by count, by size or by the client
+                    // quotas) remains pending, to be sent in the next batches
+                    let mut current_batch: Vec<SharedRequest<RQ>> = propose
+                        .pending
+                        .take_batch(self.max_batch_size, self.max_batch_bytes)
+                        .into_iter()
+                        .map(Arc::new)
+                        .collect();
+
+                    // A correct leader never proposes a batch the validity predicate rejects,
+                    // as the other replicas would vote it out
+                    let (invalid, deferred) =
+                        validity::filter_batch(self.validity().as_ref(), seq, &mut current_batch);
+
+                    if !invalid.is_empty() {
+                        warn!(
+                            "{:?} // Discarding {} requests rejected by the validity predicate for {:?}",
+                            self.node_ref.id(),
+                            invalid.len(),
+                            seq
+                        );
+
+                        metric_increment(PROPOSER_INVALID_REQUESTS_ID, Some(invalid.len() as u64));
+                    }
 
-                        //So we swap that memory with the other vector memory and we have it!
-                        std::mem::swap(&mut next_batch, &mut propose.currently_accumulated);
+                    // The requests which only conflict with the rest of the batch wait for the next one
+                    propose.pending.push_all(
+                        self.classifier().as_ref(),
+                        deferred.into_iter().map(Arc::unwrap_or_clone).collect(),
+                    );
 
-                        Some(next_batch)
-                    } else {
-                        None
-                    };
+                    if heartbeat {
+                        debug!(
+                            "{:?} // Proposing a heartbeat to {:?}, as we have been idle",
+                            self.node_ref.id(),
+                            seq
+                        );
 
-                    let current_batch = std::mem::replace(
-                        &mut propose.currently_accumulated,
-                        next_batch.unwrap_or_else(Vec::new),
-                    );
+                        metric_increment(PROPOSER_HEARTBEATS_ID, Some(1));
+                    } else {
+                        propose
+                            .batching
+                            .batch_proposed(current_batch.len(), propose.pending.len());
+                    }
 
                     self.propose(seq, &view, current_batch);
 
@@ -447,7 +645,7 @@ where
         &self,
         seq: SeqNo,
         view: &ViewInfo,
-        mut currently_accumulated: Vec<StoredMessage<RQ>>,
+        mut currently_accumulated: Vec<SharedRequest<RQ>>,
     ) where
         NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
     {
@@ -465,7 +663,7 @@ where
                 if self.check_if_has_been_proposed(msg, &mut view_change_msg) {
                     // if it has been proposed, then we do not want to retain it
 
-                    let info1 = ClientRqInfo::from(msg);
+                    let info1 = ClientRqInfo::from(&**msg);
                     trace!(
                         "{:?} // Request {:?} has already been proposed, not retaining it",
                         info1,
@@ -515,8 +713,35 @@ where
         metric_increment(PROPOSER_BATCHES_MADE_ID, Some(1));
     }
 
+    /// Install the classifier which assigns the priority lane of each request
+    pub fn set_classifier(&self, classifier: SharedClassifier<RQ>) {
+        *self.classifier.write().unwrap() = classifier;
+    }
+
+    fn classifier(&self) -> SharedClassifier<RQ> {
+        self.classifier.read().unwrap().clone()
+    }
+
+    /// Install the application predicate the batches we propose must satisfy
+    pub fn set_validity_predicate(&self, validity: SharedValidityPredicate<RQ>) {
+        *self.validity.write().unwrap() = validity;
+    }
+
+    fn validity(&self) -> SharedValidityPredicate<RQ> {
+        self.validity.read().unwrap().clone()
+    }
+
+    /// Take the requests we refused to order since the last call
+    pub fn take_rejected_requests(&self) -> Vec<RejectedRequest> {
+        self.rejected.take()
+    }
+
+    /// Stop the proposer. The proposer thread exits as soon as it is woken up, and
+    /// joins the intake thread, which exits within [`INTAKE_POLL_INTERVAL`]
     pub fn cancel(&self) {
         self.cancelled.store(true, Ordering::Relaxed);
+
+        self.consensus_guard.waker().wake(ProposerEvent::Cancelled);
     }
 
     /// Check if the given request has already appeared in a view change message
@@ -572,19 +797,6 @@ where
 
         false
     }
-
-    /// Sleep for a given small amount of time, relative to how long we still
-    /// have until the next planned batch response
-    fn sleep_for_appropriate_amount_of_time(&self, last_proposed: &ProposeBuilder<RQ>) {
-        let time_until_next_proposal = std::cmp::min(
-            self.global_batch_time_limit / 2,
-            self.global_batch_time_limit - last_proposed.last_proposal.elapsed().as_micros(),
-        );
-
-        let sleep_duration = Duration::from_micros((time_until_next_proposal / 2) as u64);
-
-        std::thread::sleep(sleep_duration);
-    }
 }
 
 impl<RQ> Debug for ProposeBuilder<RQ>
@@ -0,0 +1,108 @@
+//! The requests the proposer refuses to order.
+//!
+//! A request which is over the batch byte limit by itself, or which the validity predicate
+//! rejects on its own, would never make it into a decision. Rather than leaving its client
+//! waiting for a reply that never comes, we keep a record of it, which the application takes
+//! through [`PBFTOrderProtocol::take_rejected_requests`] to answer the client with an error.
+//!
+//! Only the latest rejections are kept, should the application not take them.
+//!
+//! [`PBFTOrderProtocol::take_rejected_requests`]: crate::bft::PBFTOrderProtocol::take_rejected_requests
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use atlas_core::messages::ClientRqInfo;
+
+/// How many rejected requests are kept until the application takes them
+const MAX_REJECTED_REQUESTS: usize = 1024;
+
+/// Why a request was refused
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RejectionReason {
+    /// The request does not fit in a batch by itself
+    TooLarge { bytes: usize, limit: usize },
+    /// The validity predicate rejected the request
+    Invalid(String),
+}
+
+/// A request we refused to order, which its client should be told about
+#[derive(Clone, Debug)]
+pub struct RejectedRequest {
+    pub request: ClientRqInfo,
+    pub reason: RejectionReason,
+}
+
+/// The latest requests we refused to order, oldest first
+#[derive(Default)]
+pub struct RejectedRequests {
+    requests: Mutex<VecDeque<RejectedRequest>>,
+}
+
+impl RejectedRequests {
+    /// Record a refused request, forgetting the oldest one if we are at capacity
+    pub fn reject(&self, request: ClientRqInfo, reason: RejectionReason) {
+        let mut requests = self.requests.lock().unwrap();
+
+        if requests.len() >= MAX_REJECTED_REQUESTS {
+            requests.pop_front();
+        }
+
+        requests.push_back(RejectedRequest { request, reason });
+    }
+
+    /// Take the requests refused since the last call
+    pub fn take(&self) -> Vec<RejectedRequest> {
+        self.requests.lock().unwrap().drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod rejected_tests {
+    use atlas_common::node_id::NodeId;
+    use atlas_common::ordering::SeqNo;
+
+    use crate::bft::fixtures::digest;
+
+    use super::*;
+
+    fn request(id: u32) -> ClientRqInfo {
+        ClientRqInfo::new(digest(id as u8), NodeId::from(id), SeqNo::ZERO, SeqNo::ZERO)
+    }
+
+    #[test]
+    fn test_take_drains_in_order() {
+        let rejected = RejectedRequests::default();
+
+        let too_large = RejectionReason::TooLarge {
+            bytes: 2048,
+            limit: 1024,
+        };
+
+        rejected.reject(request(1), too_large.clone());
+        rejected.reject(request(2), RejectionReason::Invalid("no".to_string()));
+
+        let taken = rejected.take();
+
+        assert_eq!(taken.len(), 2);
+        assert_eq!(taken[0].request.sender(), NodeId::from(1u32));
+        assert_eq!(taken[0].reason, too_large);
+        assert_eq!(taken[1].request.sender(), NodeId::from(2u32));
+
+        assert!(rejected.take().is_empty());
+    }
+
+    #[test]
+    fn test_keeps_the_latest() {
+        let rejected = RejectedRequests::default();
+
+        for id in 0..(MAX_REJECTED_REQUESTS as u32 + 10) {
+            rejected.reject(request(id), RejectionReason::Invalid(String::new()));
+        }
+
+        let taken = rejected.take();
+
+        assert_eq!(taken.len(), MAX_REJECTED_REQUESTS);
+        assert_eq!(taken[0].request.sender(), NodeId::from(10u32));
+    }
+}
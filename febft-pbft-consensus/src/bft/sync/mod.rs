@@ -36,7 +36,10 @@ use atlas_core::ordering_protocol::{unwrap_shareable_message, ShareableMessage};
 use atlas_core::request_pre_processing::{RequestPProcessorSync, RequestPreProcessing};
 
 use atlas_core::timeouts::timeout::{ModTimeout, TimeoutModHandle};
+use atlas_metrics::metrics::metric_increment;
 
+use crate::bft::buffer::BufferUsage;
+use crate::bft::config::{MessageBufferConfig, ViewChangeBackoffConfig};
 use crate::bft::consensus::{Consensus, ConsensusStatus};
 use crate::bft::log::decisions::{CollectData, Proof, ViewDecisionPair};
 use crate::bft::log::Log;
@@ -44,13 +47,20 @@ use crate::bft::message::{
     ConsensusMessage, ConsensusMessageKind, FwdConsensusMessage, PBFTMessage, ViewChangeMessage,
     ViewChangeMessageKind,
 };
+use crate::bft::metric::{
+    SYNC_ABORTED_QUORUM_JOINS_ID, SYNC_BUFFERED_BYTES_ID, SYNC_BUFFERED_MESSAGES_ID,
+    SYNC_REJECTED_SYNCS_ID, SYNC_VIEW_CHANGE_ESCALATIONS_ID,
+};
+use crate::bft::sync::view::election::{ElectionHistory, LeaderElection};
 use crate::bft::sync::view::ViewInfo;
 use crate::bft::{FeDecision, PBFT};
 
+use self::verify::VerifiedSync;
 use self::{follower_sync::FollowerSynchronizer, replica_sync::ReplicaSynchronizer};
 
 pub mod follower_sync;
 pub mod replica_sync;
+pub mod verify;
 pub mod view;
 
 /// Attempt to extract a msg from the tbo queue
@@ -191,6 +201,8 @@ pub struct TboQueue<O> {
     next_view: Option<ViewInfo>,
     // Stores the previous view, for useful information when changing views
     previous_view: Option<ViewInfo>,
+    // The decisions which have not been fed to the leader election policy yet
+    history: ElectionHistory,
     // probe messages from this queue instead of
     // fetching them from the network
     get_queue: bool,
@@ -200,18 +212,27 @@ pub struct TboQueue<O> {
     stop_data: VecDeque<VecDeque<ShareableMessage<PBFTMessage<O>>>>,
     // stores all SYNC messages for the next view
     sync: VecDeque<VecDeque<ShareableMessage<PBFTMessage<O>>>>,
+    // how much we are buffering for views beyond the next one.
+    // Messages for the next view are not accounted, as they are ready to be processed
+    usage: BufferUsage,
 }
 
 impl<O> TboQueue<O> {
-    pub(crate) fn new(view: ViewInfo) -> Self {
+    pub(crate) fn new(view: ViewInfo, buffer_limits: MessageBufferConfig) -> Self {
         Self {
             view,
             next_view: None,
             previous_view: None,
+            history: ElectionHistory::default(),
             get_queue: false,
             stop: VecDeque::new(),
             stop_data: VecDeque::new(),
             sync: VecDeque::new(),
+            usage: BufferUsage::new(
+                buffer_limits,
+                SYNC_BUFFERED_MESSAGES_ID,
+                SYNC_BUFFERED_BYTES_ID,
+            ),
         }
     }
 
@@ -223,6 +244,11 @@ impl<O> TboQueue<O> {
         self.next_view.as_ref()
     }
 
+    /// Forget the next view we were working on, as it is not going to be installed
+    pub fn discard_next_view(&mut self) {
+        self.next_view = None;
+    }
+
     /// Advance to the next view we are working on
     pub fn advance(&mut self) -> bool {
         if let Some(next_view) = self.next_view.take() {
@@ -275,17 +301,42 @@ impl<O> TboQueue<O> {
         tbo_advance_message_queue(&mut self.stop);
         tbo_advance_message_queue(&mut self.stop_data);
         tbo_advance_message_queue(&mut self.sync);
+
+        // The messages for the new front view are now ready to be processed
+        let ready = [&self.stop, &self.stop_data, &self.sync]
+            .into_iter()
+            .filter_map(|queue| queue.front())
+            .flatten();
+
+        self.usage.release(ready);
     }
 
     /// Queues a view change message for later processing, or drops it
     /// immediately if it pertains to an older view change instance.
     pub fn queue(&mut self, m: ShareableMessage<PBFTMessage<O>>) {
+        let base = self.view.sequence_number().next();
+
+        match m.sequence_number().index(base) {
+            // The message pertains to an older view change instance, so it would be dropped anyway
+            Either::Left(_) => return,
+            Either::Right(0) => {}
+            Either::Right(index) => {
+                let mut queues = [&mut self.stop, &mut self.stop_data, &mut self.sync];
+
+                if !self.usage.make_room(&mut queues, index, &m) {
+                    return;
+                }
+            }
+        }
+
         match m.message().view_change().kind() {
             ViewChangeMessageKind::Stop(_) | ViewChangeMessageKind::StopQuorumJoin(_) => {
                 self.queue_stop(m)
             }
             ViewChangeMessageKind::StopData(_) => self.queue_stop_data(m),
             ViewChangeMessageKind::Sync(_) => self.queue_sync(m),
+            // Only relevant to a candidate waiting on the join round, which processes it right away
+            ViewChangeMessageKind::AbortQuorumJoin => {}
         }
     }
 
@@ -343,6 +394,28 @@ impl<O> TboQueue<O> {
         &self.view
     }
 
+    /// Record a decision, to be fed to the leader election policy once it is agreed on
+    pub fn decided(&mut self, seq: SeqNo, view: SeqNo, leader: NodeId) {
+        self.history.decided(seq, view, leader);
+    }
+
+    /// Every correct replica has decided the instances before `seq`, so feed them to the
+    /// leader election policy of the current view (and of the next one, should we already
+    /// be changing to it)
+    pub fn agree_history(&mut self, seq: SeqNo) {
+        let decisions = self.history.agree(seq);
+
+        if decisions.is_empty() {
+            return;
+        }
+
+        if let Some(next_view) = &mut self.next_view {
+            next_view.agree(decisions.iter().copied());
+        }
+
+        self.view.agree(decisions);
+    }
+
     pub fn previous_view(&self) -> &Option<ViewInfo> {
         &self.previous_view
     }
@@ -380,6 +453,103 @@ pub(super) enum ProtoPhase {
     SyncingState,
 }
 
+/// What we do when the timer of the view change we are running expires
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) enum ViewChangeTimeoutAction {
+    /// We have not gathered a quorum of STOP messages, and some of them may have
+    /// been lost, so we send ours again
+    RetransmitStop,
+    /// The leader of the view we are changing to did not complete the view change,
+    /// so we give up on it and change to the view after it
+    Escalate,
+    /// We are waiting on something the new leader is not responsible for, such as a
+    /// state transfer or our own admission into the quorum, so we keep waiting
+    Rearm,
+    /// There is no view change running which the timer could be watching
+    Disarm,
+}
+
+impl ProtoPhase {
+    fn view_change_timeout_action(&self, entering_quorum: bool) -> ViewChangeTimeoutAction {
+        match self {
+            ProtoPhase::Stopping(_) | ProtoPhase::Stopping2(_) => {
+                ViewChangeTimeoutAction::RetransmitStop
+            }
+            ProtoPhase::StoppingData(_) | ProtoPhase::Syncing if entering_quorum => {
+                ViewChangeTimeoutAction::Rearm
+            }
+            ProtoPhase::StoppingData(_) | ProtoPhase::Syncing => ViewChangeTimeoutAction::Escalate,
+            ProtoPhase::SyncingState => ViewChangeTimeoutAction::Rearm,
+            ProtoPhase::Init | ProtoPhase::ViewStopping(_) | ProtoPhase::ViewStopping2(_) => {
+                ViewChangeTimeoutAction::Disarm
+            }
+        }
+    }
+}
+
+/// The state of a round of STOP-QUORUM-JOIN votes on which node to add to the quorum
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum JoinRound {
+    /// The candidate received a quorum of votes (with the amount of votes it received)
+    Elected(NodeId, usize),
+    /// No candidate can receive a quorum of votes, even if every member of the quorum
+    /// which has yet to vote votes for it
+    Aborted,
+    /// No candidate has a quorum of votes yet, but there are votes left to receive
+    Pending,
+}
+
+/// Record the vote of `voter` for `candidate` in a join round. Only the members of the quorum
+/// vote, and each of them only once, whichever candidate the vote is for.
+/// Returns how many members of the quorum have voted, or `None` if the vote does not count
+fn cast_join_vote(
+    view: &ViewInfo,
+    votes: &mut BTreeMap<NodeId, BTreeSet<NodeId>>,
+    voter: NodeId,
+    candidate: NodeId,
+) -> Option<usize> {
+    if !view.quorum_members().contains(&voter)
+        || votes.values().any(|voters| voters.contains(&voter))
+    {
+        return None;
+    }
+
+    votes.entry(candidate).or_default().insert(voter);
+
+    Some(votes.values().map(BTreeSet::len).sum())
+}
+
+/// Tally the votes of a join round, after `received` distinct members of the quorum voted.
+/// Should more than one candidate reach a quorum, the one with the most votes is elected,
+/// with ties going to the lowest node id.
+/// The round is aborted as soon as no candidate can reach a quorum, so that members
+/// which never vote cannot keep it pending
+fn join_round(
+    view: &ViewInfo,
+    votes: &BTreeMap<NodeId, BTreeSet<NodeId>>,
+    received: usize,
+) -> JoinRound {
+    let quorum = view.params().quorum();
+
+    let elected = votes
+        .iter()
+        .map(|(candidate, voters)| (*candidate, voters.len()))
+        .filter(|(_, votes)| *votes >= quorum)
+        .min_by(|(candidate, votes), (candidate_2, votes_2)| {
+            votes_2.cmp(votes).then(candidate.cmp(candidate_2))
+        });
+
+    let max_votes = votes.values().map(BTreeSet::len).max().unwrap_or(0);
+
+    let remaining = view.params().n().saturating_sub(received);
+
+    match elected {
+        Some((candidate, votes)) => JoinRound::Elected(candidate, votes),
+        None if max_votes + remaining < quorum => JoinRound::Aborted,
+        None => JoinRound::Pending,
+    }
+}
+
 // TODO: finish statuses returned from `process_message`
 #[derive(Debug)]
 pub enum SynchronizerStatus<O> {
@@ -392,6 +562,9 @@ pub enum SynchronizerStatus<O> {
     /// The view change protocol just finished running and we
     /// have successfully joined the quorum.
     NewViewJoinedQuorum(ConsensusStatus<O>, Option<FeDecision<O>>, NodeId),
+    /// No node received a quorum of votes to join the quorum, so the
+    /// join round was aborted and we remain in the current view.
+    QuorumJoinAborted(Vec<NodeId>),
     /// Before we finish the view change protocol, we need
     /// to run the CST protocol.
     RunCst,
@@ -435,6 +608,8 @@ pub enum SyncReconfigurationResult {
     InProgress,
     // We have successfully completed the reconfiguration
     Completed,
+    // The last attempt to add this node was aborted, as no node received a quorum of votes
+    Aborted,
 }
 
 ///A trait describing some of the necessary methods for the synchronizer
@@ -464,13 +639,18 @@ pub struct Synchronizer<RQ: SerMsg> {
     phase: Cell<ProtoPhase>,
     //Tbo queue, keeps track of the current view and keeps messages arriving in order
     tbo: Mutex<TboQueue<RQ>>,
-    //Stores currently received requests from other nodes
-    stopped: RefCell<IntMap<u64, Vec<StoredMessage<RQ>>>>,
+    //Stores the digests of the currently received requests from other nodes
+    stopped: RefCell<IntMap<u64, Vec<ClientRqInfo>>>,
     //Stores currently received requests from other nodes
     currently_adding_node: Cell<Option<NodeId>>,
     //Stores which nodes are currently being added to the quorum, along with the number of votes
     //For each of the nodeIDs
     currently_adding: RefCell<BTreeMap<NodeId, BTreeSet<NodeId>>>,
+    //The candidates of join rounds which were aborted in this view, which have not yet had
+    //the abort reported back to their reconfiguration attempt
+    aborted_joins: RefCell<BTreeSet<NodeId>>,
+    //The members of the quorum which told us they aborted the join round we are a candidate in
+    join_aborts: RefCell<BTreeSet<NodeId>>,
     //TODO: This does not require a Mutex I believe since it's only accessed when
     // Processing messages (which is always done in the replica thread)
     collects: Mutex<CollectsType<RQ>>,
@@ -512,7 +692,7 @@ where
 
         if let Some(previous_view) = view.previous_view() {
             if current_view.sequence_number() != previous_view.sequence_number()
-                && !self.tbo.lock().unwrap().install_view(previous_view)
+                && !self.install_view(previous_view)
             {
                 // If we don't install a new view, then we don't want to forget our current state now do we?
 
@@ -536,7 +716,7 @@ where
                 // Without first processing this sync message
                 self.phase.replace(ProtoPhase::Syncing);
             } else {
-                self.tbo.lock().unwrap().install_view(view);
+                self.install_view(view);
                 return false;
             }
 
@@ -545,7 +725,7 @@ where
             return true;
         } else {
             // This is the first view, so we can just install it
-            if !self.tbo.lock().unwrap().install_view(view) {
+            if !self.install_view(view) {
                 // If we don't install a new view, then we don't want to forget our current state now do we?
                 debug!("Replacing our phase with Init");
                 self.phase.replace(ProtoPhase::Init);
@@ -564,33 +744,50 @@ impl<RQ> Synchronizer<RQ>
 where
     RQ: SerMsg + SessionBased + 'static,
 {
-    pub fn new_follower(node_id: NodeId, view: ViewInfo) -> Arc<Self> {
+    pub fn new_follower(
+        node_id: NodeId,
+        view: ViewInfo,
+        buffer_limits: MessageBufferConfig,
+    ) -> Arc<Self> {
         Arc::new(Self {
             node_id,
             phase: Cell::new(ProtoPhase::Init),
             stopped: RefCell::new(Default::default()),
             currently_adding_node: Cell::new(None),
             currently_adding: RefCell::new(Default::default()),
+            aborted_joins: RefCell::new(Default::default()),
+            join_aborts: RefCell::new(Default::default()),
             collects: Mutex::new(Default::default()),
-            tbo: Mutex::new(TboQueue::new(view)),
+            tbo: Mutex::new(TboQueue::new(view, buffer_limits)),
             finalize_state: RefCell::new(None),
             entering_quorum: Cell::new(false),
             accessory: SynchronizerAccessory::Follower(FollowerSynchronizer::new()),
         })
     }
 
-    pub fn new_replica(node_id: NodeId, view: ViewInfo, timeout_dur: Duration) -> Arc<Self> {
+    pub fn new_replica(
+        node_id: NodeId,
+        view: ViewInfo,
+        timeout_dur: Duration,
+        view_change_backoff: Option<ViewChangeBackoffConfig>,
+        buffer_limits: MessageBufferConfig,
+    ) -> Arc<Self> {
         Arc::new(Self {
             node_id,
             phase: Cell::new(ProtoPhase::Init),
             stopped: RefCell::new(Default::default()),
             currently_adding_node: Cell::new(None),
             currently_adding: RefCell::new(Default::default()),
+            aborted_joins: RefCell::new(Default::default()),
+            join_aborts: RefCell::new(Default::default()),
             collects: Mutex::new(Default::default()),
-            tbo: Mutex::new(TboQueue::new(view)),
+            tbo: Mutex::new(TboQueue::new(view, buffer_limits)),
             finalize_state: RefCell::new(None),
             entering_quorum: Cell::new(false),
-            accessory: SynchronizerAccessory::Replica(ReplicaSynchronizer::new(timeout_dur)),
+            accessory: SynchronizerAccessory::Replica(ReplicaSynchronizer::new(
+                timeout_dur,
+                view_change_backoff,
+            )),
         })
     }
 
@@ -600,29 +797,57 @@ where
         seq_no: SeqNo,
         quorum_members: Vec<NodeId>,
         timeout_dur: Duration,
+        view_change_backoff: Option<ViewChangeBackoffConfig>,
+        buffer_limits: MessageBufferConfig,
+        leader_election: LeaderElection,
     ) -> Result<Arc<Self>> {
         let n = quorum_members.len();
 
         let _f = (n - 1) / 3;
 
-        let view_info = ViewInfo::from_quorum(seq_no, quorum_members)?;
+        let view_info =
+            ViewInfo::from_quorum_with_election(seq_no, quorum_members, leader_election)?;
 
         info!("Initializing synchronizer with view {:?}", view_info);
 
         Ok(Arc::new(Self {
             node_id,
             phase: Cell::new(ProtoPhase::Init),
-            tbo: Mutex::new(TboQueue::new(view_info)),
+            tbo: Mutex::new(TboQueue::new(view_info, buffer_limits)),
             stopped: RefCell::new(Default::default()),
             currently_adding_node: Cell::new(None),
             currently_adding: RefCell::new(Default::default()),
+            aborted_joins: RefCell::new(Default::default()),
+            join_aborts: RefCell::new(Default::default()),
             collects: Mutex::new(Default::default()),
             finalize_state: RefCell::new(None),
             entering_quorum: Cell::new(false),
-            accessory: SynchronizerAccessory::Replica(ReplicaSynchronizer::new(timeout_dur)),
+            accessory: SynchronizerAccessory::Replica(ReplicaSynchronizer::new(
+                timeout_dur,
+                view_change_backoff,
+            )),
         }))
     }
 
+    /// Record the view and the leader of the decision of instance `seq`, so that the leaders
+    /// of the views installed after every correct replica has decided it are elected with it
+    pub fn decided(&self, seq: SeqNo, view: SeqNo, leader: NodeId) {
+        self.tbo.lock().unwrap().decided(seq, view, leader);
+    }
+
+    /// We have committed a decision in the given view. Should it be the view we are in,
+    /// the view is making progress, which resets the view change backoff. Decisions we
+    /// catch up to (in a `SYNC` message or through log replay) say nothing about our view
+    pub fn committed_in_view(&self, view: SeqNo) {
+        if view != self.tbo.lock().unwrap().view().sequence_number() {
+            return;
+        }
+
+        if let SynchronizerAccessory::Replica(rep) = &self.accessory {
+            rep.view_progressed();
+        }
+    }
+
     /// The next view that is going to be processed
     fn next_view(&self) -> Option<ViewInfo> {
         self.tbo.lock().unwrap().next_view().cloned()
@@ -640,7 +865,49 @@ where
 
     /// Advance the view the next one in the queue
     fn advance_view(&self) -> bool {
-        self.tbo.lock().unwrap().advance()
+        let advanced = self.tbo.lock().unwrap().advance();
+
+        if advanced {
+            self.view_installed();
+        }
+
+        advanced
+    }
+
+    /// Install the given view into the queue
+    fn install_view(&self, view: ViewInfo) -> bool {
+        let installed = self.tbo.lock().unwrap().install_view(view);
+
+        if installed {
+            self.view_installed();
+        }
+
+        installed
+    }
+
+    /// The join rounds aborted in the previous view no longer concern the candidates,
+    /// which have to attempt to join the new view from scratch
+    fn view_installed(&self) {
+        self.aborted_joins.borrow_mut().clear();
+    }
+
+    /// Install a view which takes over from the current one at a pre agreed sequence number,
+    /// `handoff_at`, without running the view change protocol. Hand offs only happen while no
+    /// view change is running, as a view change would supersede them.
+    /// Returns whether the view was installed
+    pub fn hand_off(&self, view: ViewInfo, handoff_at: SeqNo) -> bool {
+        if !matches!(self.phase.get(), ProtoPhase::Init) {
+            return false;
+        }
+
+        if !self.install_view(view) {
+            return false;
+        }
+
+        // Every correct replica decides the instances before the hand off
+        self.tbo.lock().unwrap().agree_history(handoff_at);
+
+        true
     }
 
     /// Signal this `TboQueue` that it may be able to extract new
@@ -667,6 +934,21 @@ where
         self.tbo.lock().unwrap().can_process_sync()
     }
 
+    /// The sequence number of the latest decision of the quorum, which we must
+    /// catch up to before we can resume the view change protocol.
+    /// Only available when we are waiting on the state of the quorum
+    pub fn pending_catch_up_seq(&self) -> Option<SeqNo> {
+        match self.phase.get() {
+            ProtoPhase::SyncingState => self
+                .finalize_state
+                .borrow()
+                .as_ref()
+                .and_then(|state| state.last_proof.as_ref())
+                .map(|proof| proof.sequence_number()),
+            _ => None,
+        }
+    }
+
     /// Check if we can process new view change messages.
     /// If there are pending messages that are now processable (but weren't when we received them)
     /// We return them. If there are no pending messages then we will wait for new messages from other replicas
@@ -720,8 +1002,8 @@ where
     }
 
     /// Advances the state of the view change state machine.
-    //
-    // TODO: retransmit STOP msgs
+    /// Lost STOP messages are retransmitted when the view change times out
+    /// (see [`Synchronizer::view_change_timed_out`])
     pub fn process_message<NT, RP>(
         &self,
         s_message: ShareableMessage<PBFTMessage<RQ>>,
@@ -787,6 +1069,11 @@ where
                             node.id()
                         );
 
+                        SynchronizerStatus::Nil
+                    }
+                    ViewChangeMessageKind::AbortQuorumJoin => {
+                        debug!("{:?} // Received abort quorum join message while not waiting on a join round. Ignoring", node.id());
+
                         SynchronizerStatus::Nil
                     }
                 };
@@ -827,6 +1114,11 @@ where
 
                         return stop_status!(i, &current_view);
                     }
+                    ViewChangeMessageKind::AbortQuorumJoin => {
+                        debug!("{:?} // Received abort quorum join message while in stopping state. Ignoring", node.id());
+
+                        return stop_status!(i, &current_view);
+                    }
                     ViewChangeMessageKind::Stop(_) => i + 1,
                     ViewChangeMessageKind::StopData(_) => {
                         return match &self.accessory {
@@ -871,6 +1163,11 @@ where
 
                 // FIXME: Check if we have already seen the messages in the stop quorum
 
+                // The STOP only carries the digests, so ask its sender for the requests we lack
+                if let SynchronizerAccessory::Replica(rep) = &self.accessory {
+                    rep.fetch_missing_requests(header.from(), &stopped, rq_pre_processor, &**node);
+                }
+
                 self.stopped
                     .borrow_mut()
                     .insert(header.from().into(), stopped);
@@ -913,7 +1210,6 @@ where
                             previous_view,
                             consensus,
                             log,
-                            rq_pre_processor,
                             timeouts,
                             &**node,
                         ),
@@ -944,7 +1240,7 @@ where
                 let current_view = self.view();
                 let next_seq = current_view.sequence_number().next();
 
-                let (received, node_id) = match message.kind() {
+                let node_id = match message.kind() {
                     ViewChangeMessageKind::Stop(_) | ViewChangeMessageKind::StopQuorumJoin(_)
                         if msg_seq != next_seq =>
                     {
@@ -967,7 +1263,12 @@ where
 
                         return SynchronizerStatus::Running;
                     }
-                    ViewChangeMessageKind::StopQuorumJoin(node) => (received + 1, *node),
+                    ViewChangeMessageKind::StopQuorumJoin(node) => *node,
+                    ViewChangeMessageKind::AbortQuorumJoin => {
+                        debug!("{:?} // Received abort quorum join message while in view stopping state. Ignoring", node.id());
+
+                        return stop_status!(received, &current_view);
+                    }
                     ViewChangeMessageKind::StopData(_) => {
                         return match &self.accessory {
                             SynchronizerAccessory::Follower(_) => {
@@ -1003,88 +1304,87 @@ where
                     }
                 };
 
-                {
-                    let mut write_guard = self.currently_adding.borrow_mut();
-
-                    let received_votes = write_guard.entry(node_id).or_default();
+                let vote = cast_join_vote(
+                    &current_view,
+                    &mut self.currently_adding.borrow_mut(),
+                    header.from(),
+                    node_id,
+                );
 
-                    if received_votes.insert(header.from()) {
+                let received = match vote {
+                    Some(received) => {
                         debug!(
                             "{:?} // Received stop quorum join message from {:?} with node {:?} ",
                             node.id(),
                             header.from(),
                             node_id
                         );
-                    } else {
-                        debug!("{:?} // Received duplicate stop quorum join message from {:?} with node {:?} ", node.id(), header.from(), node_id);
+
+                        received
                     }
-                }
+                    None => {
+                        warn!("{:?} // Received stop quorum join message from {:?} with node {:?}, which is either not a member of the quorum or has already voted. Ignoring", node.id(), header.from(), node_id);
+
+                        return stop_status!(received, &current_view);
+                    }
+                };
 
                 // We don't need to actually receive the reconfiguration confirmation to add a node to the quorum, if the quorum is already reached
                 //TODO: Is this the correct procedure?
                 self.phase.replace(ProtoPhase::ViewStopping(received));
 
-                if received >= current_view.params().quorum() {
-                    let mut votes: Vec<_> = self
-                        .currently_adding
-                        .borrow()
-                        .iter()
-                        .map(|(node, voters)| (*node, voters.len()))
-                        .collect();
-
-                    votes.sort_by(|(_node, votes), (_node_2, votes_2)| votes_2.cmp(votes));
-
-                    if let Some(vote_count) = votes.first() {
-                        if vote_count.1 >= current_view.params().quorum() {
-                            self.currently_adding_node.replace(Some(node_id));
+                let round = join_round(&current_view, &self.currently_adding.borrow(), received);
 
-                            let node_to_add = vote_count.0;
+                match round {
+                    JoinRound::Elected(node_to_add, votes) => {
+                        self.currently_adding_node.replace(Some(node_to_add));
 
-                            let next_view = current_view.next_view_with_new_node(node_to_add);
+                        let next_view = current_view.next_view_with_new_node(node_to_add);
 
-                            let previous_view = current_view.clone();
+                        let previous_view = current_view.clone();
 
-                            //We have received the necessary amount of stopping requests
-                            //To now that we should move to the next view
+                        //We have received the necessary amount of stopping requests
+                        //To now that we should move to the next view
 
-                            let next_leader = next_view.leader();
+                        let next_leader = next_view.leader();
 
-                            warn!("{:?} // Stopping quorum reached with {} votes for node {:?} moving to next view {:?}. ", node.id(), vote_count.1, node_to_add, next_view);
+                        warn!("{:?} // Stopping quorum reached with {} votes for node {:?} moving to next view {:?}. ", node.id(), votes, node_to_add, next_view);
 
-                            self.install_next_view(next_view);
+                        self.install_next_view(next_view);
 
-                            match &self.accessory {
-                                SynchronizerAccessory::Replica(rep) => rep.handle_stopping_quorum(
-                                    self,
-                                    previous_view,
-                                    consensus,
-                                    log,
-                                    rq_pre_processor,
-                                    timeouts,
-                                    &**node,
-                                ),
-                                SynchronizerAccessory::Follower(_) => {}
-                            }
-
-                            if next_leader == node.id() {
-                                warn!("{:?} // I am the new leader, moving to the stopping data phase.", node.id());
+                        match &self.accessory {
+                            SynchronizerAccessory::Replica(rep) => rep.handle_stopping_quorum(
+                                self,
+                                previous_view,
+                                consensus,
+                                log,
+                                timeouts,
+                                &**node,
+                            ),
+                            SynchronizerAccessory::Follower(_) => {}
+                        }
 
-                                //Move to the stopping data phase as we are the new leader
-                                self.phase.replace(ProtoPhase::StoppingData(0));
-                            } else {
-                                self.phase.replace(ProtoPhase::Syncing);
-                            }
-                        } else if received >= current_view.params().n() {
-                            error!("We have received view stopping messages from all nodes in the network and yet we don't have quorum {} votes for any node. {:?}",
-                                   current_view.params().quorum(), votes);
+                        if next_leader == node.id() {
+                            warn!(
+                                "{:?} // I am the new leader, moving to the stopping data phase.",
+                                node.id()
+                            );
 
-                            todo!("")
+                            //Move to the stopping data phase as we are the new leader
+                            self.phase.replace(ProtoPhase::StoppingData(0));
                         } else {
-                            warn!("{:?} // Stopping quorum reached, but not enough votes to add node {:?}. ", node.id(), vote_count.0);
+                            self.phase.replace(ProtoPhase::Syncing);
                         }
                     }
-                } else {
-                    self.phase.replace(ProtoPhase::ViewStopping2(received));
+                    JoinRound::Aborted => {
+                        return self.abort_quorum_join(&current_view, &**node);
+                    }
+                    JoinRound::Pending if received >= current_view.params().quorum() => {
+                        warn!("{:?} // Stopping quorum reached, but not enough votes to add any node yet. ", node.id());
+                    }
+                    JoinRound::Pending => {
+                        self.phase.replace(ProtoPhase::ViewStopping2(received));
+                    }
                 }
 
                 SynchronizerStatus::Running
@@ -1123,6 +1423,14 @@ where
 
                                 return SynchronizerStatus::Running;
                             }
+                            ViewChangeMessageKind::AbortQuorumJoin => {
+                                return self.process_join_abort(
+                                    s_message.header(),
+                                    msg_seq,
+                                    &**node,
+                                    timeouts,
+                                );
+                            }
                             ViewChangeMessageKind::StopData(_) if msg_seq != seq => {
                                 warn!("{:?} // Received stop data message for view {:?} but we are in view {:?}",
                                       node.id(), msg_seq, seq);
@@ -1228,27 +1536,45 @@ where
                             let sound = sound(&next_view, &normalized_collects);
 
                             if !sound.test() {
-                                //FIXME: BFT-SMaRt doesn't do anything if `sound`
-                                // evaluates to false; do we keep the same behavior,
-                                // and wait for a new time out? but then, no other
-                                // consensus messages have been processed... this
-                                // may be a point of contention on the lib!
-
+                                // The replicas would reject our SYNC message, so we let the
+                                // view change timer move everyone on to the next view
                                 error!(
                                     "{:?} // The view change is not sound. Cancelling.",
                                     node.id()
                                 );
-                                /*
-                                collects_guard.clear();
 
                                 return SynchronizerStatus::Running;
-                                */
                             }
 
-                            let p = quiet_unwrap!(
-                                rq_pre_processor.collect_pending_rqs(),
-                                SynchronizerStatus::Running
-                            );
+                            // A value bound in the previous view must be proposed again
+                            let p = if let Some(value) = sound.value() {
+                                let authentic = |stored: &StoredMessage<PBFTMessage<RQ>>| {
+                                    validate_signature(&**node, stored)
+                                };
+
+                                let bound = verify::bound_requests(
+                                    value,
+                                    curr_cid,
+                                    normalized_collects.iter().flatten().copied(),
+                                    &authentic,
+                                );
+
+                                match bound {
+                                    Some(requests) => requests,
+                                    None => {
+                                        error!("{:?} // None of the collects carries the pre prepares of the bound value {:?}. Cancelling.", node.id(), value);
+
+                                        return SynchronizerStatus::Running;
+                                    }
+                                }
+                            } else {
+                                let pending = quiet_unwrap!(
+                                    rq_pre_processor.collect_pending_rqs(),
+                                    SynchronizerStatus::Running
+                                );
+
+                                pending.into_iter().map(Arc::new).collect()
+                            };
                             let node_sign = node.network_info_provider().get_key_pair().clone();
 
                             //We create the pre-prepare here as we are the new leader,
@@ -1256,7 +1582,10 @@ where
                             let (header, message) = {
                                 info!("{:?} // Forged pre-prepare: {}", node.id(), p.len());
 
-                                let forged_pre_prepare = consensus.forge_propose(p, &next_view);
+                                // The replicas require the proposal for the instance the collects
+                                // resume from, whether or not we are caught up to it
+                                let forged_pre_prepare =
+                                    consensus.forge_propose(curr_cid, p, &next_view);
 
                                 let (message, digest) =
                                     node.serialize_digest_message(forged_pre_prepare).unwrap();
@@ -1352,6 +1681,14 @@ where
 
                         return SynchronizerStatus::Running;
                     }
+                    ViewChangeMessageKind::AbortQuorumJoin => {
+                        return self.process_join_abort(
+                            s_message.header(),
+                            msg_seq,
+                            &**node,
+                            timeouts,
+                        );
+                    }
                     ViewChangeMessageKind::StopData(_) => {
                         return match &self.accessory {
                             SynchronizerAccessory::Follower(_) => {
@@ -1406,34 +1743,33 @@ where
                 };
 
                 // leader has already performed this computation in the
-                // STOP-DATA phase of Mod-SMaRt
-                let signed: Vec<_> = signed_collects::<RQ, _>(&**node, collects);
-
-                let proof = highest_proof::<RQ, _, _>(&next_view, &**node, signed.iter());
-
-                let curr_cid = proof
-                    .map(|p| p.sequence_number())
-                    .map(|seq| seq.next())
-                    .unwrap_or(SeqNo::ZERO);
+                // STOP-DATA phase of Mod-SMaRt, repeat it to make sure it
+                // proposed what the collects require
+                let verified = verify::verify_sync(&next_view, &proposed, &collects, &**node);
 
-                let normalized_collects: Vec<_> =
-                    { normalized_collects(curr_cid, collect_data(signed.iter())).collect() };
+                let VerifiedSync {
+                    curr_cid,
+                    sound,
+                    proof,
+                    normalized_collects,
+                } = match verified {
+                    Ok(verified) => verified,
+                    Err(err) => {
+                        warn!(
+                            "{:?} // Rejecting the sync message of the leader {:?} of view {:?}: {}",
+                            node.id(),
+                            next_view.leader(),
+                            seq,
+                            err
+                        );
 
-                let sound = sound(&next_view, &normalized_collects);
+                        metric_increment(SYNC_REJECTED_SYNCS_ID, Some(1));
 
-                if !sound.test() {
-                    error!(
-                        "{:?} // The view change is not sound. Cancelling.",
-                        node.id()
-                    );
+                        self.escalate_view_change(&**node, timeouts, log);
 
-                    //FIXME: BFT-SMaRt doesn't do anything if `sound`
-                    // evaluates to false; do we keep the same behavior,
-                    // and wait for a new time out? but then, no other
-                    // consensus messages have been processed... this
-                    // may be a point of contention on the lib!
-                    //return SynchronizerStatus::Running;
-                }
+                        return SynchronizerStatus::Running;
+                    }
+                };
 
                 let state = FinalizeState {
                     curr_cid,
@@ -1518,6 +1854,16 @@ where
             return SyncReconfigurationResult::AlreadyPartOfQuorum;
         }
 
+        if self.aborted_joins.borrow_mut().remove(&joining_node) {
+            info!(
+                "{:?} // The last attempt to add node {:?} to the quorum was aborted",
+                node.id(),
+                joining_node
+            );
+
+            return SyncReconfigurationResult::Aborted;
+        }
+
         match self.phase.get() {
             ProtoPhase::Init => {
                 // This means this is ready to change views
@@ -1555,7 +1901,7 @@ where
     pub fn attempt_join_quorum<NT>(
         &self,
         node: &NT,
-        _timeouts: &TimeoutModHandle,
+        timeouts: &TimeoutModHandle,
     ) -> ReconfigurationAttemptResult
     where
         NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
@@ -1569,6 +1915,13 @@ where
             info!("{:?} // Attempted to join quorum, but we are already a part of it, can we process sync messages {:?}", node.id(), self.can_process_sync());
 
             return ReconfigurationAttemptResult::AlreadyPartOfQuorum;
+        } else if self.aborted_joins.borrow_mut().remove(&node.id()) {
+            info!(
+                "{:?} // Our last attempt to join the quorum was aborted",
+                node.id()
+            );
+
+            return ReconfigurationAttemptResult::Failed;
         } else if let Some(next_view) = self.next_view() {
             if next_view.quorum_members().contains(&node.id()) {
                 //We are already a part of the quorum, so we don't need to do anything
@@ -1578,18 +1931,22 @@ where
             }
         }
 
-        //TODO: Timeout waiting for the sync/stopping data. This is because
-        // We actually might try to enter while the protocol is running a different view change,
-        // so the view change to integrate us into the quorum might be delayed
-
         // Simulate that we were accepted into the quorum
         let view = current_view.next_view_with_new_node(node.id());
 
         self.entering_quorum.replace(true);
         self.currently_adding_node.replace(Some(self.node_id));
+        self.join_aborts.borrow_mut().clear();
 
         self.install_next_view(view.clone());
 
+        // We might try to enter while the quorum is running a different view change,
+        // so the view change which integrates us might be delayed. The timer keeps
+        // us waiting for the STOP-DATA or SYNC messages rather than giving up
+        if let SynchronizerAccessory::Replica(rep) = &self.accessory {
+            rep.arm_view_change_timer(view.sequence_number(), self.node_id, timeouts);
+        }
+
         if view.leader() == node.id() {
             // If we are the leader of the next view, then we should move to the stopping data phase and wait
             // For the rest of the nodes to send us the information
@@ -1666,7 +2023,7 @@ where
     /// originated in the other replicas.
     pub fn begin_view_change<NT>(
         &self,
-        timed_out: Option<Vec<StoredMessage<RQ>>>,
+        timed_out: Option<Vec<ClientRqInfo>>,
         node: &NT,
         timeouts: &TimeoutModHandle,
         _log: &Log<RQ>,
@@ -1821,6 +2178,10 @@ where
             // sent by the leader in the SYNC message
 
             if let Some(last_proof) = last_proof {
+                if let Some((proof_view, leader)) = last_proof.proposal() {
+                    self.decided(last_proof.sequence_number(), proof_view, leader);
+                }
+
                 let quorum_result = consensus
                     .catch_up_to_quorum(&view, last_proof, log)
                     .expect("Failed to catch up to quorum");
@@ -1836,6 +2197,11 @@ where
             None
         };
 
+        // Every correct replica has decided the instances before the one the new view
+        // starts at (or catches up to them with state transfer), so the leaders of the
+        // views after this one can be elected with them
+        self.tbo.lock().unwrap().agree_history(curr_cid);
+
         // finalize view change by broadcasting a PREPARE msg
         let consensus_result = consensus
             .finalize_view_change((header, message), &view, self, timeouts, log, node)
@@ -1844,6 +2210,10 @@ where
         // Update proto phase
         self.phase.replace(ProtoPhase::Init);
 
+        if let SynchronizerAccessory::Replica(rep) = &self.accessory {
+            rep.view_change_finished(self.node_id, timeouts);
+        }
+
         if self.currently_adding_node.get().is_some() {
             let node = self.currently_adding_node.replace(None);
 
@@ -1899,6 +2269,243 @@ where
         }
     }
 
+    /// The timer of the view change to the given view has expired.
+    /// Depending on how far the view change got, we either send our STOP message again
+    /// or, when the new leader did not complete it, move on to the view after it.
+    /// Returns whether we moved on to a new view change
+    pub fn view_change_timed_out<NT>(
+        &self,
+        view: SeqNo,
+        node: &NT,
+        timeouts: &TimeoutModHandle,
+        log: &Log<RQ>,
+    ) -> bool
+    where
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+    {
+        let SynchronizerAccessory::Replica(rep) = &self.accessory else {
+            return false;
+        };
+
+        if !rep.is_view_change_timer(view) {
+            return false;
+        }
+
+        // The replicas we asked for the requests we are missing did not send them in time
+        rep.retry_fetches(node);
+
+        let action = self
+            .phase
+            .get()
+            .view_change_timeout_action(self.entering_quorum.get());
+
+        debug!(
+            "{:?} // The view change to view {:?} timed out in phase {:?}, {:?}",
+            node.id(),
+            view,
+            self.phase.get(),
+            action
+        );
+
+        match action {
+            ViewChangeTimeoutAction::RetransmitStop => {
+                if !rep.retransmit_stop(self, timeouts, node) {
+                    rep.disarm_view_change_timer(self.node_id, timeouts);
+                }
+
+                false
+            }
+            ViewChangeTimeoutAction::Escalate => {
+                self.escalate_view_change(node, timeouts, log);
+
+                true
+            }
+            ViewChangeTimeoutAction::Rearm => {
+                rep.arm_view_change_timer(view, self.node_id, timeouts);
+
+                false
+            }
+            ViewChangeTimeoutAction::Disarm => {
+                rep.disarm_view_change_timer(self.node_id, timeouts);
+
+                false
+            }
+        }
+    }
+
+    /// The leader of the view we were changing to did not complete the view change in time,
+    /// so we consider that view as failed and start the view change to the one after it
+    fn escalate_view_change<NT>(&self, node: &NT, timeouts: &TimeoutModHandle, log: &Log<RQ>)
+    where
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+    {
+        let Some(failed_view) = self.next_view() else {
+            return;
+        };
+
+        warn!(
+            "{:?} // The leader {:?} of view {:?} did not complete the view change, moving on to view {:?}",
+            node.id(),
+            failed_view.leader(),
+            failed_view.sequence_number(),
+            failed_view.sequence_number().next()
+        );
+
+        metric_increment(SYNC_VIEW_CHANGE_ESCALATIONS_ID, Some(1));
+
+        // Give the next leader longer to complete the view change than the one which failed.
+        // The requests we stopped for it were not ordered, so we stop them again
+        let stopped = match &self.accessory {
+            SynchronizerAccessory::Replica(rep) => {
+                rep.view_change_failed();
+
+                rep.escalated_requests(failed_view.sequence_number())
+            }
+            SynchronizerAccessory::Follower(_) => Vec::new(),
+        };
+
+        // Our STOP messages refer to the view after the one we are in,
+        // so we move into the failed view before stopping it
+        self.advance_view();
+
+        self.phase.replace(ProtoPhase::Init);
+
+        self.begin_view_change(Some(stopped), node, timeouts, log);
+
+        // The STOP messages of the replicas which escalated before us are now processable
+        self.signal();
+    }
+
+    /// Every member of the quorum has voted on which node to add to it, and none of the
+    /// candidates received a quorum of votes. We cannot settle on one of them ourselves, as
+    /// other replicas may have received different votes, so we abort the join round and
+    /// remain in the current view. The candidates are told right away, and may then attempt
+    /// to join again
+    fn abort_quorum_join<NT>(&self, current_view: &ViewInfo, node: &NT) -> SynchronizerStatus<RQ>
+    where
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+    {
+        let my_id = node.id();
+
+        let votes = std::mem::take(&mut *self.currently_adding.borrow_mut());
+
+        warn!(
+            "{:?} // Received stop quorum join messages from all nodes without {} votes for any node, aborting the join round and remaining in view {:?}. {:?}",
+            my_id,
+            current_view.params().quorum(),
+            current_view.sequence_number(),
+            votes
+        );
+
+        metric_increment(SYNC_ABORTED_QUORUM_JOINS_ID, Some(1));
+
+        let candidates: Vec<_> = votes.into_keys().collect();
+
+        self.aborted_joins
+            .borrow_mut()
+            .extend(candidates.iter().copied());
+
+        self.currently_adding_node.replace(None);
+
+        self.phase.replace(ProtoPhase::Init);
+
+        if let SynchronizerAccessory::Replica(rep) = &self.accessory {
+            rep.handle_quorum_join_aborted(self, node, &candidates);
+        }
+
+        SynchronizerStatus::QuorumJoinAborted(candidates)
+    }
+
+    /// A member of the quorum told us it aborted the join round we are a candidate in.
+    /// Once `f + 1` of them did, at least one correct replica aborted it, so the view which
+    /// would have added us is not going to be installed and we stop waiting for it
+    fn process_join_abort<NT>(
+        &self,
+        header: &Header,
+        view_seq: SeqNo,
+        node: &NT,
+        timeouts: &TimeoutModHandle,
+    ) -> SynchronizerStatus<RQ>
+    where
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+    {
+        let current_view = self.view();
+
+        let waiting_on = self.next_view().map(|view| view.sequence_number());
+
+        if !self.entering_quorum.get()
+            || waiting_on != Some(view_seq)
+            || !current_view.quorum_members().contains(&header.from())
+        {
+            debug!(
+                "{:?} // Received abort quorum join message from {:?} for view {:?} which does not match our join attempt. Ignoring",
+                node.id(),
+                header.from(),
+                view_seq
+            );
+
+            return SynchronizerStatus::Running;
+        }
+
+        let aborts = {
+            let mut join_aborts = self.join_aborts.borrow_mut();
+
+            join_aborts.insert(header.from());
+
+            join_aborts.len()
+        };
+
+        if aborts <= current_view.params().f() {
+            return SynchronizerStatus::Running;
+        }
+
+        warn!(
+            "{:?} // {} members of the quorum aborted the round to join us to the quorum, remaining in view {:?}",
+            node.id(),
+            aborts,
+            current_view.sequence_number()
+        );
+
+        self.join_aborts.borrow_mut().clear();
+        self.entering_quorum.replace(false);
+        self.currently_adding_node.replace(None);
+        self.tbo.lock().unwrap().discard_next_view();
+
+        if let SynchronizerAccessory::Replica(rep) = &self.accessory {
+            rep.disarm_view_change_timer(self.node_id, timeouts);
+        }
+
+        self.aborted_joins.borrow_mut().insert(self.node_id);
+
+        self.phase.replace(ProtoPhase::Init);
+
+        SynchronizerStatus::QuorumJoinAborted(vec![self.node_id])
+    }
+
+    /// Process a message fetching the bodies of the requests advertised in STOP messages.
+    /// These are not ordered by view, so they are handled as soon as they arrive
+    pub fn process_fetch_requests<NT, RP>(
+        &self,
+        message: &ShareableMessage<PBFTMessage<RQ>>,
+        pre_processor: &RP,
+        node: &NT,
+    ) where
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+        RP: RequestPreProcessing<RQ> + RequestPProcessorSync<RQ>,
+    {
+        match &self.accessory {
+            // Followers do not take part in view changes
+            SynchronizerAccessory::Follower(_) => {}
+            SynchronizerAccessory::Replica(rep) => rep.handle_fetch_requests(
+                &self.view(),
+                message.header(),
+                message.message().fetch_requests(),
+                pre_processor,
+                node,
+            ),
+        }
+    }
+
     /// Client requests have timed out. We must now send a stop message containing all of the
     /// Requests that have timed out
     pub fn client_requests_timed_out(
@@ -1938,7 +2545,9 @@ where
     where
         NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
     {
-        highest_proof::<RQ, _, _>(view, node, guard.values())
+        let authentic = |stored: &StoredMessage<PBFTMessage<RQ>>| validate_signature(node, stored);
+
+        highest_proof(view, &authentic, collect_data(guard.values()))
     }
 }
 
@@ -2183,26 +2792,16 @@ fn normalized_collects<'a, O: 'a>(
     })
 }
 
-fn signed_collects<RQ, NT>(
-    node: &NT,
-    collects: Vec<StoredMessage<PBFTMessage<RQ>>>,
-) -> Vec<StoredMessage<PBFTMessage<RQ>>>
-where
-    RQ: SerMsg,
-    NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
-{
-    collects
-        .into_iter()
-        .filter(|stored| validate_signature::<RQ, _, _>(node, stored))
-        .collect()
-}
-
-fn validate_signature<'a, RQ, M, NT>(node: &'a NT, stored: &'a StoredMessage<M>) -> bool
+/// Check that a message forwarded to us by another replica was signed by the node in its header.
+///
+/// Only the header is kept along with the deserialized message, so the signature is verified
+/// over the header alone (which carries the digest of the payload). The message is then bound
+/// to the header by serializing it again and comparing its digest with the signed one
+pub(crate) fn validate_signature<RQ, NT>(node: &NT, stored: &StoredMessage<PBFTMessage<RQ>>) -> bool
 where
     RQ: SerMsg,
     NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
 {
-    //TODO: Fix this as I believe it will always be false
     let wm = match WireMessage::from_header(*stored.header(), MessageModule::Protocol) {
         Ok(wm) => wm,
         _ => {
@@ -2230,20 +2829,42 @@ where
         }
     };
 
-    wm.is_valid(Some(key.public_key()), false).is_ok()
+    // There is no payload to check, as we only have the header
+    if wm.is_valid(Some(key.public_key()), false).is_err() {
+        return false;
+    }
+
+    match node.serialize_digest_message(stored.message().clone()) {
+        Ok((_, digest)) => digest == *stored.header().digest(),
+        Err(err) => {
+            error!(
+                "{:?} // Failed to serialize message from {:?}: {:?}",
+                node.id(),
+                stored.header().from(),
+                err
+            );
+
+            false
+        }
+    }
 }
 
-fn highest_proof<'a, RQ, I, NT>(view: &ViewInfo, node: &NT, collects: I) -> Option<&'a Proof<RQ>>
+/// The highest proof among the collects whose votes are all correctly signed, as
+/// determined by `authentic`
+fn highest_proof<'a, RQ>(
+    view: &ViewInfo,
+    authentic: &dyn Fn(&StoredMessage<PBFTMessage<RQ>>) -> bool,
+    collects: impl Iterator<Item = &'a CollectData<RQ>>,
+) -> Option<&'a Proof<RQ>>
 where
-    RQ: SerMsg,
-    I: Iterator<Item = &'a StoredMessage<PBFTMessage<RQ>>>,
-    NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+    RQ: 'a,
 {
-    collect_data(collects)
+    collects
         // fetch proofs
         .filter_map(|collect| collect.last_proof())
-        // check if COMMIT msgs are signed, and all have the same digest
-        //
+        // check if COMMIT msgs are signed, and all have the same digest.
+        // PREPARE msgs may have been authenticated with MACs, which we cannot verify,
+        // but a correct replica only commits once it has seen a quorum of them
         .filter(move |proof| {
             let digest = proof.batch_digest();
 
@@ -2258,34 +2879,16 @@ where
                         //If he does not have the digest, then it is not valid
                         .unwrap_or(false)
                 })
-                .filter(move |&stored| validate_signature::<RQ, _, _>(node, stored))
-                .count()
-                >= view.params().quorum();
-
-            let prepares_valid = proof
-                .prepares()
-                .iter()
-                .filter(|stored| {
-                    stored
-                        .message()
-                        .consensus()
-                        .has_proposed_digest(&digest)
-                        //If he does not have the digest, then it is not valid
-                        .unwrap_or(false)
-                })
-                .filter(move |&stored| validate_signature::<RQ, _, _>(node, stored))
+                .filter(|stored| authentic(stored))
                 .count()
                 >= view.params().quorum();
 
             debug!(
-                "{:?} // Proof {:?} is valid? commits valid: {:?} &&  prepares_valid: {:?}",
-                node.id(),
-                proof,
-                commits_valid,
-                prepares_valid
+                "Proof {:?} is valid? commits valid: {:?}",
+                proof, commits_valid
             );
 
-            commits_valid && prepares_valid
+            commits_valid
         })
         .max_by_key(|proof| proof.sequence_number())
 }
@@ -2310,3 +2913,189 @@ impl<O> Debug for SynchronizerPollStatus<O> {
         }
     }
 }
+
+#[cfg(test)]
+mod view_change_tests {
+    use super::*;
+
+    #[test]
+    fn test_view_change_timeout_actions() {
+        use ViewChangeTimeoutAction::*;
+
+        let expected = [
+            (ProtoPhase::Init, Disarm),
+            (ProtoPhase::Stopping(1), RetransmitStop),
+            (ProtoPhase::Stopping2(2), RetransmitStop),
+            (ProtoPhase::ViewStopping(1), Disarm),
+            (ProtoPhase::ViewStopping2(1), Disarm),
+            (ProtoPhase::StoppingData(2), Escalate),
+            (ProtoPhase::Syncing, Escalate),
+            (ProtoPhase::SyncingState, Rearm),
+        ];
+
+        for (phase, action) in expected {
+            assert_eq!(
+                phase.view_change_timeout_action(false),
+                action,
+                "{:?}",
+                phase
+            );
+        }
+
+        // A replica joining the quorum cannot vote out the leader which is admitting it
+        assert_eq!(ProtoPhase::Syncing.view_change_timeout_action(true), Rearm);
+        assert_eq!(
+            ProtoPhase::StoppingData(0).view_change_timeout_action(true),
+            Rearm
+        );
+        assert_eq!(
+            ProtoPhase::Stopping2(0).view_change_timeout_action(true),
+            RetransmitStop
+        );
+    }
+
+    #[test]
+    fn test_escalation_skips_the_failed_view() {
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+
+        let mut tbo = TboQueue::<u8>::new(view.clone(), MessageBufferConfig::default());
+
+        // The view change to view 1 is underway
+        let failed_view = view.next_view();
+        tbo.install_next_view(failed_view.clone());
+
+        assert_eq!(tbo.view().sequence_number().next(), SeqNo::from(1));
+
+        // Escalating moves us into the failed view, so our new STOP is for the one after it
+        assert!(tbo.advance());
+
+        assert!(tbo.next_view().is_none());
+        assert_eq!(tbo.view().sequence_number(), SeqNo::from(1));
+
+        let target = tbo.view().next_view();
+
+        assert_eq!(target.sequence_number(), SeqNo::from(2));
+        assert_ne!(target.leader(), failed_view.leader());
+        assert_eq!(target.quorum_members(), failed_view.quorum_members());
+    }
+
+    #[test]
+    fn test_escalation_without_view_change() {
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+
+        let mut tbo = TboQueue::<u8>::new(view, MessageBufferConfig::default());
+
+        // There is no view to give up on
+        assert!(!tbo.advance());
+        assert_eq!(tbo.view().sequence_number(), SeqNo::ZERO);
+    }
+
+    #[test]
+    fn test_join_round_without_majority() {
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+
+        let (first, second) = (NodeId::from(4u32), NodeId::from(5u32));
+
+        let voters =
+            |ids: &[u32]| -> BTreeSet<NodeId> { ids.iter().map(|id| NodeId::from(*id)).collect() };
+
+        let mut votes = BTreeMap::new();
+        votes.insert(first, voters(&[0, 1]));
+        votes.insert(second, voters(&[2]));
+
+        assert_eq!(join_round(&view, &votes, 3), JoinRound::Pending);
+
+        // The last member voted for the other candidate, so neither can get a quorum
+        votes.insert(second, voters(&[2, 3]));
+
+        assert_eq!(join_round(&view, &votes, 4), JoinRound::Aborted);
+
+        votes.insert(first, voters(&[0, 1, 3]));
+
+        assert_eq!(join_round(&view, &votes, 4), JoinRound::Elected(first, 3));
+    }
+
+    #[test]
+    fn test_join_round_aborts_despite_a_silent_member() {
+        let view = ViewInfo::new(SeqNo::ZERO, 7, 2).unwrap();
+
+        let (first, second) = (NodeId::from(7u32), NodeId::from(8u32));
+
+        let voters =
+            |ids: &[u32]| -> BTreeSet<NodeId> { ids.iter().map(|id| NodeId::from(*id)).collect() };
+
+        let mut votes = BTreeMap::new();
+        votes.insert(first, voters(&[0, 1, 2]));
+        votes.insert(second, voters(&[3, 4]));
+
+        // The first candidate may still be elected by the members left to vote
+        assert_eq!(join_round(&view, &votes, 5), JoinRound::Pending);
+
+        // The last member never votes, which can no longer give anyone a quorum
+        votes.insert(second, voters(&[3, 4, 5]));
+
+        assert_eq!(join_round(&view, &votes, 6), JoinRound::Aborted);
+    }
+
+    #[test]
+    fn test_join_votes_from_distinct_members() {
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+
+        let (first, second) = (NodeId::from(4u32), NodeId::from(5u32));
+
+        let mut votes = BTreeMap::new();
+
+        assert_eq!(
+            cast_join_vote(&view, &mut votes, NodeId::from(0u32), first),
+            Some(1)
+        );
+        assert_eq!(
+            cast_join_vote(&view, &mut votes, NodeId::from(1u32), first),
+            Some(2)
+        );
+
+        // A member may neither vote twice nor vote for another candidate as well
+        assert_eq!(
+            cast_join_vote(&view, &mut votes, NodeId::from(1u32), first),
+            None
+        );
+        assert_eq!(
+            cast_join_vote(&view, &mut votes, NodeId::from(1u32), second),
+            None
+        );
+
+        // Nodes outside the quorum have no say on who joins it
+        assert_eq!(cast_join_vote(&view, &mut votes, second, second), None);
+
+        assert_eq!(
+            cast_join_vote(&view, &mut votes, NodeId::from(2u32), second),
+            Some(3)
+        );
+        assert_eq!(join_round(&view, &votes, 3), JoinRound::Pending);
+
+        assert_eq!(
+            cast_join_vote(&view, &mut votes, NodeId::from(3u32), second),
+            Some(4)
+        );
+        assert_eq!(join_round(&view, &votes, 4), JoinRound::Aborted);
+    }
+
+    #[test]
+    fn test_join_round_tie_break() {
+        let view = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+
+        let (first, second) = (NodeId::from(4u32), NodeId::from(5u32));
+
+        let quorum: BTreeSet<_> = view.quorum_members().iter().copied().take(3).collect();
+
+        let mut votes = BTreeMap::new();
+        votes.insert(second, quorum.clone());
+        votes.insert(first, quorum);
+
+        assert_eq!(join_round(&view, &votes, 6), JoinRound::Elected(first, 3));
+
+        votes.insert(second, view.quorum_members().iter().copied().collect());
+
+        assert_eq!(join_round(&view, &votes, 7), JoinRound::Elected(second, 4));
+    }
+}
@@ -3,35 +3,42 @@
 //! This code allows a replica to change its view, where a new
 //! leader is elected.
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::marker::PhantomData;
 use std::time::{Duration, Instant};
 
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use atlas_common::collections;
 use atlas_common::node_id::NodeId;
-use atlas_common::ordering::Orderable;
+use atlas_common::ordering::{Orderable, SeqNo};
 use atlas_common::serialization_helper::SerMsg;
 use atlas_communication::message::{Header, StoredMessage};
 use atlas_core::messages::{ClientRqInfo, ForwardedRequestsMessage, SessionBased};
 use atlas_core::ordering_protocol::networking::OrderProtocolSendNode;
 use atlas_core::request_pre_processing::network::RequestPreProcessingHandle;
-use atlas_core::request_pre_processing::RequestPreProcessing;
+use atlas_core::request_pre_processing::{RequestPProcessorSync, RequestPreProcessing};
 use atlas_core::timeouts::timeout::{ModTimeout, TimeoutModHandle};
 use atlas_core::timeouts::{TimeOutable, TimeoutID};
-use atlas_metrics::metrics::{metric_duration, metric_increment};
+use atlas_metrics::metrics::{metric_duration, metric_increment, metric_store_count};
 
+use crate::bft::config::ViewChangeBackoffConfig;
 use crate::bft::consensus::Consensus;
 use crate::bft::log::decisions::CollectData;
 use crate::bft::log::Log;
 use crate::bft::message::{
-    ConsensusMessage, ConsensusMessageKind, PBFTMessage, ViewChangeMessage, ViewChangeMessageKind,
+    ConsensusMessage, ConsensusMessageKind, FetchRequestsMessage, PBFTMessage, ViewChangeMessage,
+    ViewChangeMessageKind,
 };
 use crate::bft::metric::{
-    SYNC_BATCH_RECEIVED_ID, SYNC_STOPPED_COUNT_ID, SYNC_STOPPED_REQUESTS_ID, SYNC_WATCH_REQUESTS_ID,
+    SYNC_BATCH_RECEIVED_ID, SYNC_FAILED_VIEW_CHANGES_ID, SYNC_FETCHED_REQUESTS_ID,
+    SYNC_STOPPED_COUNT_ID, SYNC_STOPPED_REQUESTS_ID, SYNC_STOP_RETRANSMISSIONS_ID,
+    SYNC_VIEW_CHANGE_TIMEOUT_ID, SYNC_WATCH_REQUESTS_ID,
 };
 use crate::bft::sync::view::ViewInfo;
+use crate::bft::timers::ViewTimer;
 use crate::bft::PBFT;
 
 use super::{AbstractSynchronizer, Synchronizer, SynchronizerStatus};
@@ -41,19 +48,508 @@ use super::{AbstractSynchronizer, Synchronizer, SynchronizerStatus};
 // - TboQueue for sync phase messages
 // This synchronizer will only move forward on replica messages
 
-pub struct ReplicaSynchronizer<RQ: SerMsg> {
+/// The timeout used for client requests and view changes, which backs off
+/// with the view changes that fail in a row
+struct ViewChangeBackoff {
     timeout_dur: Cell<Duration>,
+    // How the timeouts grow with consecutive failed view changes, if they do
+    config: Option<ViewChangeBackoffConfig>,
+    // The view changes which failed since a view last made progress
+    failed_view_changes: Cell<u32>,
+    // Whether we have installed a view through a view change, which has not yet decided anything
+    awaiting_progress: Cell<bool>,
+}
+
+impl ViewChangeBackoff {
+    pub(super) fn new(timeout_dur: Duration, config: Option<ViewChangeBackoffConfig>) -> Self {
+        let timeout_dur = config
+            .as_ref()
+            .map_or(timeout_dur, |config| config.timeout(0));
+
+        Self {
+            timeout_dur: Cell::new(timeout_dur),
+            config,
+            failed_view_changes: Cell::new(0),
+            awaiting_progress: Cell::new(false),
+        }
+    }
+
+    pub(super) fn timeout_dur(&self) -> Duration {
+        self.timeout_dur.get()
+    }
+
+    /// A view change has failed, either because its new leader did not complete it or because
+    /// the view it installed was changed before deciding anything, so we back off
+    pub(super) fn view_change_failed(&self) {
+        self.failed_view_changes
+            .set(self.failed_view_changes.get().saturating_add(1));
+
+        self.update_timeout();
+    }
+
+    /// A view change installed a view, which must now decide something
+    pub(super) fn view_change_finished(&self) {
+        self.awaiting_progress.set(true);
+    }
+
+    /// We are leaving the view we are in. Should a view change have installed
+    /// it and it decided nothing, that view change failed as well
+    pub(super) fn leaving_view(&self) {
+        if self.awaiting_progress.replace(false) {
+            self.view_change_failed();
+        }
+    }
+
+    /// The view we are in has committed a decision, so it is making progress
+    pub(super) fn view_progressed(&self) {
+        self.awaiting_progress.set(false);
+
+        if self.failed_view_changes.replace(0) > 0 {
+            self.update_timeout();
+        }
+    }
+
+    fn update_timeout(&self) {
+        let Some(config) = &self.config else {
+            return;
+        };
+
+        let failed = self.failed_view_changes.get();
+
+        self.timeout_dur.set(config.timeout(failed));
+
+        debug!(
+            "Backing off the view change timeout to {:?} after {} failed view changes",
+            self.timeout_dur.get(),
+            failed
+        );
+
+        metric_store_count(SYNC_FAILED_VIEW_CHANGES_ID, failed as usize);
+    }
+}
+
+/// The requests advertised in STOP messages whose bodies we are fetching, along with the
+/// replicas which advertised them. We ask one of them at a time, and move on to the next
+/// should the view change time out before it replies
+#[derive(Default)]
+struct FetchTracker {
+    // The replicas which advertised each request, the one we last asked for it first
+    advertisers: HashMap<ClientRqInfo, VecDeque<NodeId>>,
+}
+
+impl FetchTracker {
+    /// `from` advertised the given requests. It is recorded as an advertiser of the ones we
+    /// are already fetching, and the others are returned, as we may have to fetch them
+    fn advertised(&mut self, from: NodeId, advertised: &[ClientRqInfo]) -> Vec<ClientRqInfo> {
+        advertised
+            .iter()
+            .filter(|rq| match self.advertisers.get_mut(*rq) {
+                Some(advertisers) => {
+                    if !advertisers.contains(&from) {
+                        advertisers.push_back(from);
+                    }
+
+                    false
+                }
+                None => true,
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// We are asking `from` for the given requests
+    fn fetching(&mut self, from: NodeId, requests: &[ClientRqInfo]) {
+        for rq in requests {
+            if let Entry::Vacant(entry) = self.advertisers.entry(rq.clone()) {
+                entry.insert(VecDeque::from([from]));
+            }
+        }
+    }
+
+    /// We have received the given request. Returns whether we were fetching it
+    fn fetched(&mut self, rq: &ClientRqInfo) -> bool {
+        self.advertisers.remove(rq).is_some()
+    }
+
+    /// Move on to the next advertiser of each of the requests we are still fetching.
+    /// Returns the requests we must ask each replica for
+    fn retry(&mut self) -> HashMap<NodeId, Vec<ClientRqInfo>> {
+        let mut retries: HashMap<NodeId, Vec<ClientRqInfo>> = HashMap::new();
+
+        for (rq, advertisers) in self.advertisers.iter_mut() {
+            advertisers.rotate_left(1);
+
+            if let Some(next) = advertisers.front() {
+                retries.entry(*next).or_default().push(rq.clone());
+            }
+        }
+
+        retries
+    }
+
+    fn clear(&mut self) {
+        self.advertisers.clear();
+    }
+}
+
+/// The requested requests which we advertised in our own STOP message.
+/// We only serve those, or we would hand our pending requests to anyone who asks
+fn advertised_requests(
+    requested: &[ClientRqInfo],
+    advertised: &[ClientRqInfo],
+) -> Vec<ClientRqInfo> {
+    requested
+        .iter()
+        .filter(|rq| advertised.contains(*rq))
+        .cloned()
+        .collect()
+}
+
+/// The STOP message we sent in the view change we are running
+struct SentStop {
+    // The view we asked to change to
+    view: SeqNo,
+    requests: Vec<ClientRqInfo>,
+}
+
+impl SentStop {
+    fn new(view: SeqNo, requests: Vec<ClientRqInfo>) -> Self {
+        Self { view, requests }
+    }
+
+    /// The requests to send again in our STOP message for `view`,
+    /// if this is the STOP message we sent for it
+    fn retransmission(&self, view: SeqNo) -> Option<&[ClientRqInfo]> {
+        (self.view == view).then_some(&self.requests[..])
+    }
+
+    /// The requests to carry over to the STOP message for the view after `failed_view`,
+    /// as the view change to it failed before any of them could be ordered
+    fn escalation(&self, failed_view: SeqNo) -> Vec<ClientRqInfo> {
+        if self.view == failed_view {
+            self.requests.clone()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+pub struct ReplicaSynchronizer<RQ: SerMsg> {
+    // The timeout used for client requests and view changes
+    backoff: ViewChangeBackoff,
+    // The view we are changing to, while the timer of that view change is armed
+    view_change_timer: Cell<Option<SeqNo>>,
+    // The STOP message we sent in the view change we are running, so that we can send it again
+    sent_stop: RefCell<Option<SentStop>>,
+    // The requests advertised in STOP messages whose bodies we have asked for
+    fetching: RefCell<FetchTracker>,
     _phantom: PhantomData<fn() -> RQ>,
 }
 
 impl<RQ: SerMsg + SessionBased + 'static> ReplicaSynchronizer<RQ> {
-    pub fn new(timeout_dur: Duration) -> Self {
+    pub fn new(timeout_dur: Duration, backoff: Option<ViewChangeBackoffConfig>) -> Self {
         Self {
-            timeout_dur: Cell::new(timeout_dur),
+            backoff: ViewChangeBackoff::new(timeout_dur, backoff),
+            view_change_timer: Cell::new(None),
+            sent_stop: RefCell::new(None),
+            fetching: RefCell::new(Default::default()),
             _phantom: Default::default(),
         }
     }
 
+    /// The timeout currently used for client requests and view changes
+    pub fn timeout_dur(&self) -> Duration {
+        self.backoff.timeout_dur()
+    }
+
+    /// A view change has failed, as its new leader did not complete it
+    pub(super) fn view_change_failed(&self) {
+        self.backoff.view_change_failed();
+    }
+
+    /// A consensus instance was committed in the view we are in, so it is making progress
+    pub(super) fn view_progressed(&self) {
+        self.backoff.view_progressed();
+    }
+
+    /// The requests of the STOP message we sent for the view change to `failed_view`,
+    /// which we must advertise again in the view change to the view after it
+    pub(super) fn escalated_requests(&self, failed_view: SeqNo) -> Vec<ClientRqInfo> {
+        self.sent_stop
+            .borrow()
+            .as_ref()
+            .map(|sent_stop| sent_stop.escalation(failed_view))
+            .unwrap_or_default()
+    }
+
+    /// Start the timer of the view change to the given view, replacing the one we had
+    pub(super) fn arm_view_change_timer(
+        &self,
+        view: SeqNo,
+        my_id: NodeId,
+        timeouts: &TimeoutModHandle,
+    ) {
+        self.disarm_view_change_timer(my_id, timeouts);
+
+        let _ = timeouts.request_timeout(
+            ViewTimer::ViewChange(view).id(),
+            None,
+            self.timeout_dur(),
+            1,
+            false,
+        );
+
+        self.view_change_timer.set(Some(view));
+    }
+
+    /// Stop the timer of the view change we were running, if it is armed
+    pub(super) fn disarm_view_change_timer(&self, my_id: NodeId, timeouts: &TimeoutModHandle) {
+        if let Some(view) = self.view_change_timer.take() {
+            let _ = timeouts.ack_received(ViewTimer::ViewChange(view).id(), my_id);
+        }
+    }
+
+    /// Whether the timer of the view change to the given view is armed
+    pub(super) fn is_view_change_timer(&self, view: SeqNo) -> bool {
+        self.view_change_timer.get() == Some(view)
+    }
+
+    /// Send our STOP message for the view change we are running again, as either it or
+    /// the STOP messages of the other replicas may have been lost, and restart its timer.
+    /// Returns whether we had sent a STOP message for the view we are changing to
+    pub(super) fn retransmit_stop<NT>(
+        &self,
+        base_sync: &Synchronizer<RQ>,
+        timeouts: &TimeoutModHandle,
+        node: &NT,
+    ) -> bool
+    where
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+    {
+        let current_view = base_sync.view();
+        let next_seq = current_view.sequence_number().next();
+
+        let requests = match &*self.sent_stop.borrow() {
+            Some(sent_stop) => match sent_stop.retransmission(next_seq) {
+                Some(requests) => requests.to_vec(),
+                None => return false,
+            },
+            None => return false,
+        };
+
+        warn!(
+            "{:?} // No quorum of STOP messages for view {:?} yet, retransmitting ours",
+            node.id(),
+            next_seq
+        );
+
+        metric_increment(SYNC_STOP_RETRANSMISSIONS_ID, Some(1));
+
+        let message = PBFTMessage::ViewChange(ViewChangeMessage::new(
+            next_seq,
+            ViewChangeMessageKind::Stop(requests),
+        ));
+
+        let targets = current_view.quorum_members().clone();
+
+        let _ = node.broadcast_signed(message, targets.into_iter());
+
+        self.arm_view_change_timer(next_seq, node.id(), timeouts);
+
+        true
+    }
+
+    /// The view change we were running is over
+    pub(super) fn view_change_finished(&self, my_id: NodeId, timeouts: &TimeoutModHandle) {
+        self.disarm_view_change_timer(my_id, timeouts);
+
+        self.sent_stop.borrow_mut().take();
+
+        self.fetching.borrow_mut().clear();
+
+        self.backoff.view_change_finished();
+    }
+
+    /// Ask the sender of a STOP message for the bodies of the requests it advertised,
+    /// which are neither pending in our pre processor nor already being fetched.
+    /// The requests we are already fetching can now be fetched from it as well
+    pub(super) fn fetch_missing_requests<NT, RP>(
+        &self,
+        from: NodeId,
+        advertised: &[ClientRqInfo],
+        pre_processor: &RP,
+        node: &NT,
+    ) where
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+        RP: RequestPProcessorSync<RQ>,
+    {
+        if from == node.id() {
+            return;
+        }
+
+        let mut fetching = self.fetching.borrow_mut();
+
+        let candidates = fetching.advertised(from, advertised);
+
+        if candidates.is_empty() {
+            return;
+        }
+
+        let known = match pre_processor.clone_pending_rqs(candidates.clone()) {
+            Ok(known) => known.iter().map(ClientRqInfo::from).collect::<HashSet<_>>(),
+            Err(err) => {
+                warn!(
+                    "{:?} // Failed to check which stopped requests we have: {:?}",
+                    node.id(),
+                    err
+                );
+
+                return;
+            }
+        };
+
+        let missing = candidates
+            .into_iter()
+            .filter(|rq| !known.contains(rq))
+            .collect::<Vec<_>>();
+
+        if missing.is_empty() {
+            return;
+        }
+
+        debug!(
+            "{:?} // Fetching {} stopped requests from {:?}",
+            node.id(),
+            missing.len(),
+            from
+        );
+
+        fetching.fetching(from, &missing);
+
+        let message = PBFTMessage::FetchRequests(FetchRequestsMessage::Request(missing));
+
+        let _ = node.send_signed(message, from, true);
+    }
+
+    /// Ask the next replica which advertised each of the requests we are still fetching
+    /// for them, as the one we asked has not replied in time
+    pub(super) fn retry_fetches<NT>(&self, node: &NT)
+    where
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+    {
+        let retries = self.fetching.borrow_mut().retry();
+
+        for (advertiser, requests) in retries {
+            debug!(
+                "{:?} // Fetching {} stopped requests from {:?} instead",
+                node.id(),
+                requests.len(),
+                advertiser
+            );
+
+            let message = PBFTMessage::FetchRequests(FetchRequestsMessage::Request(requests));
+
+            let _ = node.send_signed(message, advertiser, true);
+        }
+    }
+
+    /// Handle a request for the bodies of the requests we advertised in our STOP message,
+    /// or the reply to one of our own requests
+    pub(super) fn handle_fetch_requests<NT, RP>(
+        &self,
+        view: &ViewInfo,
+        header: &Header,
+        message: &FetchRequestsMessage<RQ>,
+        pre_processor: &RP,
+        node: &NT,
+    ) where
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+        RP: RequestPreProcessing<RQ> + RequestPProcessorSync<RQ>,
+    {
+        match message {
+            FetchRequestsMessage::Request(requested) => {
+                if !view.quorum_members().contains(&header.from()) {
+                    warn!(
+                        "{:?} // Ignoring the request for stopped requests from {:?}, which is not a quorum member",
+                        node.id(),
+                        header.from()
+                    );
+
+                    return;
+                }
+
+                let servable = match &*self.sent_stop.borrow() {
+                    Some(sent_stop) => advertised_requests(requested, &sent_stop.requests),
+                    None => Vec::new(),
+                };
+
+                if servable.is_empty() {
+                    return;
+                }
+
+                let requests = match pre_processor.clone_pending_rqs(servable) {
+                    Ok(requests) => requests,
+                    Err(err) => {
+                        warn!(
+                            "{:?} // Failed to collect the requests asked for by {:?}: {:?}",
+                            node.id(),
+                            header.from(),
+                            err
+                        );
+
+                        return;
+                    }
+                };
+
+                debug!(
+                    "{:?} // Sending {} of the {} requests asked for by {:?}",
+                    node.id(),
+                    requests.len(),
+                    requested.len(),
+                    header.from()
+                );
+
+                let reply = PBFTMessage::FetchRequests(FetchRequestsMessage::Reply(requests));
+
+                let _ = node.send_signed(reply, header.from(), true);
+            }
+            FetchRequestsMessage::Reply(requests) => {
+                // Only accept the requests we have asked for
+                let fetched = {
+                    let mut fetching = self.fetching.borrow_mut();
+
+                    requests
+                        .iter()
+                        .filter(|rq| fetching.fetched(&ClientRqInfo::from(*rq)))
+                        .cloned()
+                        .collect::<Vec<_>>()
+                };
+
+                if fetched.is_empty() {
+                    return;
+                }
+
+                debug!(
+                    "{:?} // Fetched {} stopped requests from {:?}",
+                    node.id(),
+                    fetched.len(),
+                    header.from()
+                );
+
+                metric_increment(SYNC_FETCHED_REQUESTS_ID, Some(fetched.len() as u64));
+
+                // Make them pending, so they can be proposed in the new view
+                if let Err(err) = pre_processor.process_stopped_requests(fetched) {
+                    warn!(
+                        "{:?} // Failed to register the fetched requests: {:?}",
+                        node.id(),
+                        err
+                    );
+                }
+            }
+        }
+    }
+
     /// Handle having received a quorum of Stopping messages
     /// This means we are ready to move to the next view
     /// From this point we will move to the State transfer protocol
@@ -61,27 +557,25 @@ impl<RQ: SerMsg + SessionBased + 'static> ReplicaSynchronizer<RQ> {
     ///
     /// Therefore, we start by clearing our stopped requests and treating them as
     /// newly proposed requests (by resetting their timer)
-    pub(super) fn handle_stopping_quorum<NT, RP>(
+    pub(super) fn handle_stopping_quorum<NT>(
         &self,
         base_sync: &Synchronizer<RQ>,
         previous_view: ViewInfo,
         consensus: &Consensus<RQ>,
         log: &Log<RQ>,
-        pre_processor: &RP,
         timeouts: &TimeoutModHandle,
         node: &NT,
     ) where
         NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
-        RP: RequestPreProcessing<RQ>,
     {
         // NOTE:
         // - install new view (i.e. update view seq no) (Done in the synchronizer)
-        // - add requests from STOP into client requests
-        //   in the log, to be ordered
+        // - the requests from STOP were added to the client requests
+        //   as their bodies were fetched, to be ordered
         // - reset the timers of the requests in the STOP
         //   messages with TimeoutPhase::Init(_)
         // - send STOP-DATA message
-        self.take_stopped_requests_and_register_them(base_sync, pre_processor, timeouts);
+        self.take_stopped_requests_and_register_them(base_sync, timeouts);
         self.watch_all_requests(timeouts);
 
         let view_info = base_sync
@@ -95,7 +589,9 @@ impl<RQ: SerMsg + SessionBased + 'static> ReplicaSynchronizer<RQ> {
 
         let incomplete_proof = consensus.collect_incomplete_proof(previous_view.params().f());
 
-        let collect = CollectData::new(incomplete_proof, last_proof);
+        let pre_prepares = consensus.collect_in_exec_pre_prepares();
+
+        let collect = CollectData::new(incomplete_proof, pre_prepares, last_proof);
 
         debug!(
             "{:?} // Sending STOP-DATA message collect data {:?}",
@@ -109,6 +605,10 @@ impl<RQ: SerMsg + SessionBased + 'static> ReplicaSynchronizer<RQ> {
         ));
 
         let _ = node.send_signed(message, current_leader, true);
+
+        // The STOP-DATA and SYNC phases get a fresh timer, which expires should
+        // the new leader fail to complete the view change
+        self.arm_view_change_timer(current_view_seq, node.id(), timeouts);
     }
 
     /// Start a new view change
@@ -119,21 +619,21 @@ impl<RQ: SerMsg + SessionBased + 'static> ReplicaSynchronizer<RQ> {
         base_sync: &Synchronizer<RQ>,
         timeouts: &TimeoutModHandle,
         node: &NT,
-        timed_out: Option<Vec<StoredMessage<RQ>>>,
+        timed_out: Option<Vec<ClientRqInfo>>,
     ) where
         NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
     {
         // stop all timers
         self.unwatch_all_requests(timeouts);
 
+        self.backoff.leaving_view();
+
         // broadcast STOP message with pending requests collected
         // from peer nodes' STOP messages
         let requests = self.stopped_requests(base_sync, timed_out);
 
         let current_view = base_sync.view();
 
-        //TODO: Timeout this request and keep sending it until we have achieved a new regency
-
         info!(
             "{:?} // Beginning a view change from view {:?} to next view with stopped rqs {:?}",
             node.id(),
@@ -141,14 +641,26 @@ impl<RQ: SerMsg + SessionBased + 'static> ReplicaSynchronizer<RQ> {
             requests.len()
         );
 
+        let next_seq = current_view.sequence_number().next();
+
+        self.sent_stop
+            .borrow_mut()
+            .replace(SentStop::new(next_seq, requests.clone()));
+
         let message = PBFTMessage::ViewChange(ViewChangeMessage::new(
-            current_view.sequence_number().next(),
+            next_seq,
             ViewChangeMessageKind::Stop(requests),
         ));
 
         let targets = current_view.quorum_members().clone();
 
         let _ = node.broadcast_signed(message, targets.into_iter());
+
+        // Until we have achieved the new view, we keep sending our STOP message
+        // whenever this timer expires without a quorum of STOP messages
+        self.arm_view_change_timer(next_seq, node.id(), timeouts);
+
+        metric_duration(SYNC_VIEW_CHANGE_TIMEOUT_ID, self.timeout_dur());
     }
 
     pub(super) fn handle_begin_quorum_view_change<NT>(
@@ -177,6 +689,28 @@ impl<RQ: SerMsg + SessionBased + 'static> ReplicaSynchronizer<RQ> {
         let _ = node.broadcast_signed(message, current_view.quorum_members().clone().into_iter());
     }
 
+    /// Tell the candidates of a join round we aborted that none of them was elected,
+    /// so they stop waiting for the view which would have added them to the quorum
+    pub(super) fn handle_quorum_join_aborted<NT>(
+        &self,
+        base_sync: &Synchronizer<RQ>,
+        node: &NT,
+        candidates: &[NodeId],
+    ) where
+        NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+    {
+        let current_view = base_sync.view();
+
+        let message = ViewChangeMessage::new(
+            current_view.sequence_number().next(),
+            ViewChangeMessageKind::AbortQuorumJoin,
+        );
+
+        let message = PBFTMessage::ViewChange(message);
+
+        let _ = node.broadcast_signed(message, candidates.iter().copied());
+    }
+
     /// Watch a vector of requests received
     pub fn watch_received_requests(
         &self,
@@ -187,7 +721,7 @@ impl<RQ: SerMsg + SessionBased + 'static> ReplicaSynchronizer<RQ> {
 
         let _ = timeouts.request_timeouts(
             transform_client_rq_to_timeouts(requests),
-            self.timeout_dur.get(),
+            self.timeout_dur(),
             1,
             true,
         );
@@ -247,32 +781,22 @@ impl<RQ: SerMsg + SessionBased + 'static> ReplicaSynchronizer<RQ> {
         digests
     }
 
-    /// Register all of the requests that are missing from the view change
-    fn take_stopped_requests_and_register_them<RP>(
+    /// Watch all of the requests that are missing from the view change.
+    /// Their bodies were registered with the pre processor when we fetched them
+    fn take_stopped_requests_and_register_them(
         &self,
         base_sync: &Synchronizer<RQ>,
-        pre_processor: &RP,
         timeouts: &TimeoutModHandle,
-    ) where
-        RP: RequestPreProcessing<RQ>,
-    {
-        // TODO: maybe optimize this `stopped_requests` call, to avoid
-        // a heap allocation of a `Vec`?
-
+    ) {
         let start_time = Instant::now();
 
-        let requests = self.drain_stopped_request(base_sync);
-
-        let rq_info = requests.iter().map(ClientRqInfo::from).collect::<Vec<_>>();
-
-        let count = requests.len();
+        let rq_info = self.drain_stopped_request(base_sync);
 
-        // Register the requests with the pre-processor
-        pre_processor.process_stopped_requests(requests).unwrap();
+        let count = rq_info.len();
 
         let _ = timeouts.request_timeouts(
             transform_client_rq_to_timeouts(rq_info),
-            self.timeout_dur.get(),
+            self.timeout_dur(),
             1,
             true,
         );
@@ -359,7 +883,7 @@ impl<RQ: SerMsg + SessionBased + 'static> ReplicaSynchronizer<RQ> {
         }
 
         if !stopped.is_empty() || !base_sync.stopped.borrow().is_empty() {
-            let known_stops = self.stopped_request_digests(base_sync, None);
+            let known_stops = self.stopped_requests(base_sync, None);
 
             for stopped_rq in known_stops {
                 if stopped.contains(&stopped_rq) {
@@ -405,80 +929,42 @@ impl<RQ: SerMsg + SessionBased + 'static> ReplicaSynchronizer<RQ> {
     /// Obtain the requests that we know have timed out so we can send out a stop message
     /// to other nodes
     ///
-    /// Clones all the nodes in the `stopped` list
+    /// Since STOP messages only carry the digests of the requests, every STOP we have
+    /// received thus far for the new view is included in our own, plus the requests that
+    /// timed out on us
     fn stopped_requests(
         &self,
         base_sync: &Synchronizer<RQ>,
-        requests: Option<Vec<StoredMessage<RQ>>>,
-    ) -> Vec<StoredMessage<RQ>> {
-        // Use a hashmap so we are sure we don't send any repeat requests in our stop messages
-        let mut all_reqs = collections::hash_map();
-
-        // Include the requests that we have timed out
-        if let Some(requests) = requests {
-            for r in requests {
-                all_reqs.insert(r.header().unique_digest(), r);
-            }
-        }
-
-        // TODO: optimize this; we are including every STOP we have
-        // received thus far for the new view in our own STOP, plus
-        // the requests that timed out on us
-        for (_, stopped) in base_sync.stopped.borrow().iter() {
-            for r in stopped {
-                all_reqs
-                    .entry(r.header().unique_digest())
-                    .or_insert_with(|| r.clone());
-            }
-        }
-
-        all_reqs.drain().map(|(_, stop)| stop).collect()
-    }
-
-    fn stopped_request_digests(
-        &self,
-        base_sync: &Synchronizer<RQ>,
-        requests: Option<Vec<StoredMessage<RQ>>>,
+        requests: Option<Vec<ClientRqInfo>>,
     ) -> Vec<ClientRqInfo> {
-        // Use a hashmap so we are sure we don't send any repeat requests in our stop messages
+        // Use a hashset so we are sure we don't send any repeat requests in our stop messages
         let mut all_reqs = collections::hash_set();
 
         // Include the requests that we have timed out
         if let Some(requests) = requests {
-            for r in requests {
-                all_reqs.insert(ClientRqInfo::from(&r));
-            }
+            all_reqs.extend(requests);
         }
 
-        // TODO: optimize this; we are including every STOP we have
-        // received thus far for the new view in our own STOP, plus
-        // the requests that timed out on us
         for (_, stopped) in base_sync.stopped.borrow().iter() {
-            for r in stopped {
-                all_reqs.insert(ClientRqInfo::from(r));
-            }
+            all_reqs.extend(stopped.iter().cloned());
         }
 
         all_reqs.drain().collect()
     }
 
     /// Drain our current received stopped messages
-    fn drain_stopped_request(&self, base_sync: &Synchronizer<RQ>) -> Vec<StoredMessage<RQ>> {
-        // Use a hashmap so we are sure we don't send any repeat requests in our stop messages
-        let mut all_reqs = collections::hash_map();
+    fn drain_stopped_request(&self, base_sync: &Synchronizer<RQ>) -> Vec<ClientRqInfo> {
+        // Use a hashset so we are sure we don't register any repeat requests
+        let mut all_reqs = collections::hash_set();
 
         // we did not time out, but rather are just
         // clearing the buffer of STOP messages received
         // for the current view change
         for (_, stopped) in base_sync.stopped.borrow_mut().drain() {
-            for r in stopped {
-                all_reqs
-                    .entry(r.header().unique_digest())
-                    .or_insert_with(|| r);
-            }
+            all_reqs.extend(stopped);
         }
 
-        all_reqs.drain().map(|(_, stop)| stop).collect()
+        all_reqs.drain().collect()
     }
 }
 
@@ -526,3 +1012,198 @@ fn transform_client_rq_to_timeouts_ack(
         })
         .collect()
 }
+
+#[cfg(test)]
+mod backoff_tests {
+    use super::*;
+
+    fn backoff() -> ViewChangeBackoff {
+        ViewChangeBackoff::new(
+            Duration::from_secs(5),
+            Some(ViewChangeBackoffConfig::new(
+                Duration::from_secs(1),
+                Duration::from_secs(10),
+                2.0,
+            )),
+        )
+    }
+
+    #[test]
+    fn backoff_replaces_the_base_timeout() {
+        assert_eq!(backoff().timeout_dur(), Duration::from_secs(1));
+
+        let fixed = ViewChangeBackoff::new(Duration::from_secs(5), None);
+
+        fixed.view_change_failed();
+
+        assert_eq!(fixed.timeout_dur(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn failed_view_changes_back_off() {
+        let backoff = backoff();
+
+        backoff.view_change_failed();
+        backoff.view_change_failed();
+
+        assert_eq!(backoff.timeout_dur(), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn views_which_decide_nothing_count_as_failures() {
+        let backoff = backoff();
+
+        backoff.view_change_finished();
+        backoff.leaving_view();
+
+        assert_eq!(backoff.timeout_dur(), Duration::from_secs(2));
+
+        // Leaving a view which was not installed by a view change is not a failure
+        backoff.leaving_view();
+
+        assert_eq!(backoff.timeout_dur(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn progress_resets_the_backoff() {
+        let backoff = backoff();
+
+        backoff.view_change_failed();
+        backoff.view_change_finished();
+        backoff.view_progressed();
+
+        assert_eq!(backoff.timeout_dur(), Duration::from_secs(1));
+
+        // The view made progress, so leaving it is not a failure
+        backoff.leaving_view();
+
+        assert_eq!(backoff.timeout_dur(), Duration::from_secs(1));
+    }
+}
+
+#[cfg(test)]
+mod fetch_tests {
+    use crate::bft::fixtures::digest;
+
+    use super::*;
+
+    fn rq(byte: u8) -> ClientRqInfo {
+        ClientRqInfo::new(
+            digest(byte),
+            NodeId::from(1000u32),
+            SeqNo::from(byte as u32),
+            SeqNo::ZERO,
+        )
+    }
+
+    fn node(id: u32) -> NodeId {
+        NodeId::from(id)
+    }
+
+    #[test]
+    fn requests_are_fetched_once() {
+        let mut tracker = FetchTracker::default();
+
+        assert_eq!(
+            tracker.advertised(node(1), &[rq(1), rq(2)]),
+            vec![rq(1), rq(2)]
+        );
+
+        tracker.fetching(node(1), &[rq(1), rq(2)]);
+
+        // Another replica advertising them does not make us ask again
+        assert_eq!(tracker.advertised(node(2), &[rq(1), rq(3)]), vec![rq(3)]);
+
+        assert!(tracker.fetched(&rq(1)));
+        assert!(!tracker.fetched(&rq(1)));
+        // We never asked for it
+        assert!(!tracker.fetched(&rq(3)));
+    }
+
+    #[test]
+    fn retries_go_through_the_advertisers_in_turn() {
+        let mut tracker = FetchTracker::default();
+
+        tracker.advertised(node(1), &[rq(1)]);
+        tracker.fetching(node(1), &[rq(1)]);
+        tracker.advertised(node(2), &[rq(1)]);
+        tracker.advertised(node(3), &[rq(1)]);
+        // Advertising it twice does not give it two turns
+        tracker.advertised(node(2), &[rq(1)]);
+
+        for expected in [2, 3, 1, 2] {
+            let retries = tracker.retry();
+
+            assert_eq!(retries.len(), 1);
+            assert_eq!(retries.get(&node(expected)), Some(&vec![rq(1)]));
+        }
+
+        assert!(tracker.fetched(&rq(1)));
+        assert!(tracker.retry().is_empty());
+    }
+
+    #[test]
+    fn retries_are_grouped_by_advertiser() {
+        let mut tracker = FetchTracker::default();
+
+        tracker.fetching(node(1), &[rq(1), rq(2)]);
+        tracker.advertised(node(2), &[rq(1), rq(2)]);
+        tracker.fetching(node(3), &[rq(3)]);
+
+        let retries = tracker.retry();
+
+        let from_second = retries.get(&node(2)).unwrap();
+
+        assert_eq!(from_second.len(), 2);
+        assert!(from_second.contains(&rq(1)) && from_second.contains(&rq(2)));
+        assert_eq!(retries.get(&node(3)), Some(&vec![rq(3)]));
+    }
+
+    #[test]
+    fn only_advertised_requests_are_served() {
+        assert_eq!(
+            advertised_requests(&[rq(1), rq(2), rq(3)], &[rq(2), rq(3), rq(4)]),
+            vec![rq(2), rq(3)]
+        );
+        assert!(advertised_requests(&[rq(1)], &[]).is_empty());
+    }
+}
+
+#[cfg(test)]
+mod stop_tests {
+    use crate::bft::fixtures::digest;
+
+    use super::*;
+
+    fn rq(byte: u8) -> ClientRqInfo {
+        ClientRqInfo::new(
+            digest(byte),
+            NodeId::from(1000u32),
+            SeqNo::from(byte as u32),
+            SeqNo::ZERO,
+        )
+    }
+
+    #[test]
+    fn stop_is_retransmitted_for_its_own_view() {
+        let sent_stop = SentStop::new(SeqNo::from(2u32), vec![rq(1), rq(2)]);
+
+        assert_eq!(
+            sent_stop.retransmission(SeqNo::from(2u32)),
+            Some(&[rq(1), rq(2)][..])
+        );
+
+        // We have moved on to another view change since we sent it
+        assert_eq!(sent_stop.retransmission(SeqNo::from(3u32)), None);
+    }
+
+    #[test]
+    fn escalation_keeps_the_stopped_requests() {
+        let sent_stop = SentStop::new(SeqNo::from(2u32), vec![rq(1), rq(2)]);
+
+        assert_eq!(sent_stop.escalation(SeqNo::from(2u32)), vec![rq(1), rq(2)]);
+
+        // A STOP message of an older view change does not refer to the failed view
+        assert!(sent_stop.escalation(SeqNo::from(3u32)).is_empty());
+    }
+}
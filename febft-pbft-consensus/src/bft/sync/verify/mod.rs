@@ -0,0 +1,607 @@
+//! Verification of the SYNC message the new leader sends at the end of a view change.
+//!
+//! A SYNC carries the signed STOP-DATA messages (the collects) the leader based its choice on,
+//! along with the pre prepare it proposes for the new view. Instead of trusting the leader,
+//! every replica repeats its computation over those collects: the highest proof among them
+//! sets the consensus instance the new view resumes at, and the `sound` predicate (through
+//! `certified_value`) tells whether a value prepared in a previous view is bound to it.
+//!
+//! A value bound in a previous view is the digest of that view's pre prepares, which no pre
+//! prepare of the new view can reproduce. Every STOP-DATA message therefore carries the signed
+//! pre prepares of the instance in execution: those hashing to the bound value tell which
+//! requests it orders, and the leader must propose exactly those requests again. When no value
+//! is bound, the leader proposes a fresh batch of its own.
+
+use thiserror::Error;
+
+use atlas_common::collections;
+use atlas_common::crypto::hash::{Context, Digest};
+use atlas_common::node_id::NodeId;
+use atlas_common::ordering::{Orderable, SeqNo};
+use atlas_common::serialization_helper::SerMsg;
+use atlas_communication::message::StoredMessage;
+use atlas_core::ordering_protocol::networking::OrderProtocolSendNode;
+
+use crate::bft::log::decisions::{CollectData, Proof, StoredConsensusMessage};
+use crate::bft::message::{
+    ConsensusMessage, ConsensusMessageKind, FwdConsensusMessage, PBFTMessage, SharedRequest,
+    ViewChangeMessageKind,
+};
+use crate::bft::sync::view::ViewInfo;
+use crate::bft::PBFT;
+
+use super::{highest_proof, normalized_collects, sound, validate_signature, Sound};
+
+/// The computation the leader performed over the collects of a SYNC message, repeated by us
+pub struct VerifiedSync<'a, O> {
+    pub(super) curr_cid: SeqNo,
+    pub(super) sound: Sound,
+    pub(super) proof: Option<&'a Proof<O>>,
+    pub(super) normalized_collects: Vec<Option<&'a CollectData<O>>>,
+}
+
+/// Fully verify a SYNC message from the leader of `view`. The proposal and every collect
+/// must be signed by their senders, and the proposal must be the one the collects require
+pub fn verify_sync<'a, RQ, NT>(
+    view: &ViewInfo,
+    proposed: &FwdConsensusMessage<RQ>,
+    collects: &'a [StoredMessage<PBFTMessage<RQ>>],
+    node: &NT,
+) -> Result<VerifiedSync<'a, RQ>, SyncVerificationError>
+where
+    RQ: SerMsg,
+    NT: OrderProtocolSendNode<RQ, PBFT<RQ>>,
+{
+    let authentic = |stored: &StoredMessage<PBFTMessage<RQ>>| validate_signature(node, stored);
+
+    let proposer = proposed.header().from();
+
+    let proposal = StoredMessage::new(
+        *proposed.header(),
+        PBFTMessage::Consensus(proposed.consensus().clone()),
+    );
+
+    if !authentic(&proposal) {
+        return Err(SyncVerificationError::ForgedProposal(proposer));
+    }
+
+    if let Some(forged) = collects.iter().find(|stored| !authentic(stored)) {
+        return Err(SyncVerificationError::ForgedCollect(forged.header().from()));
+    }
+
+    verify_leader_collects(
+        view,
+        proposer,
+        proposed.consensus(),
+        collects
+            .iter()
+            .map(|stored| (stored.header().from(), stored.message())),
+        &authentic,
+    )
+}
+
+/// Repeat the computation the leader of `view` performed over the collects, given along with
+/// their senders, and check that `proposal` is the pre prepare it requires.
+/// `authentic` tells whether the votes in the proofs of the collects are signed by their senders
+pub fn verify_leader_collects<'a, O>(
+    view: &ViewInfo,
+    proposer: NodeId,
+    proposal: &ConsensusMessage<O>,
+    collects: impl Iterator<Item = (NodeId, &'a PBFTMessage<O>)>,
+    authentic: &dyn Fn(&StoredMessage<PBFTMessage<O>>) -> bool,
+) -> Result<VerifiedSync<'a, O>, SyncVerificationError>
+where
+    O: 'a,
+{
+    let view_seq = view.sequence_number();
+
+    if proposer != view.leader() {
+        return Err(SyncVerificationError::WrongProposer(
+            proposer,
+            view.leader(),
+        ));
+    }
+
+    if !matches!(proposal.kind(), ConsensusMessageKind::PrePrepare(_)) {
+        return Err(SyncVerificationError::NotPrePrepare);
+    }
+
+    if proposal.view() != view_seq {
+        return Err(SyncVerificationError::WrongView(proposal.view(), view_seq));
+    }
+
+    let mut senders = collections::hash_set();
+    let mut stop_data = Vec::new();
+
+    for (sender, message) in collects {
+        if !view.quorum_members().contains(&sender) {
+            return Err(SyncVerificationError::NotAMember(sender, view_seq));
+        }
+
+        if !senders.insert(sender) {
+            return Err(SyncVerificationError::DuplicateCollect(sender));
+        }
+
+        let collect = match message {
+            PBFTMessage::ViewChange(view_change) if view_change.sequence_number() == view_seq => {
+                match view_change.kind() {
+                    ViewChangeMessageKind::StopData(collect) => collect,
+                    _ => return Err(SyncVerificationError::UnexpectedCollect(sender, view_seq)),
+                }
+            }
+            _ => return Err(SyncVerificationError::UnexpectedCollect(sender, view_seq)),
+        };
+
+        stop_data.push(collect);
+    }
+
+    if stop_data.len() < view.params().quorum() {
+        return Err(SyncVerificationError::NotEnoughCollects(
+            stop_data.len(),
+            view.params().quorum(),
+        ));
+    }
+
+    let proof = highest_proof(view, authentic, stop_data.iter().copied());
+
+    let curr_cid = proof
+        .map(|p| p.sequence_number().next())
+        .unwrap_or(SeqNo::ZERO);
+
+    let normalized_collects: Vec<_> =
+        normalized_collects(curr_cid, stop_data.into_iter()).collect();
+
+    let sound = sound(view, &normalized_collects);
+
+    if !sound.test() {
+        return Err(SyncVerificationError::Unsound);
+    }
+
+    // The new view resumes right after the highest decided instance, which is also the one
+    // a value can be bound to. Without a decided instance, it starts from the first one
+    if proposal.sequence_number() != curr_cid {
+        return Err(SyncVerificationError::WrongInstance(
+            proposal.sequence_number(),
+            curr_cid,
+        ));
+    }
+
+    // With no value bound, the proposal is the fresh batch the leader signed for this view,
+    // which was checked above. Otherwise, it must order the requests of the bound value
+    if let Some(value) = sound.value() {
+        let bound = bound_requests(
+            value,
+            curr_cid,
+            normalized_collects.iter().flatten().copied(),
+            authentic,
+        )
+        .ok_or(SyncVerificationError::UnjustifiedValue(*value))?;
+
+        let proposed = match proposal.kind() {
+            ConsensusMessageKind::PrePrepare(requests) => requests,
+            _ => unreachable!(),
+        };
+
+        if !same_requests(proposed, &bound) {
+            return Err(SyncVerificationError::ValueMismatch(*value));
+        }
+    }
+
+    Ok(VerifiedSync {
+        curr_cid,
+        sound,
+        proof,
+        normalized_collects,
+    })
+}
+
+/// The requests of the value bound to the instance `seq`, taken from the first collect whose
+/// pre prepares for that instance are authentic and hash to the value
+pub fn bound_requests<'a, O>(
+    value: &Digest,
+    seq: SeqNo,
+    collects: impl Iterator<Item = &'a CollectData<O>>,
+    authentic: &dyn Fn(&StoredMessage<PBFTMessage<O>>) -> bool,
+) -> Option<Vec<SharedRequest<O>>>
+where
+    O: 'a,
+{
+    collects
+        .map(CollectData::pre_prepares)
+        .filter(|pre_prepares| !pre_prepares.is_empty())
+        .filter(|pre_prepares| batch_digest(pre_prepares) == *value)
+        .find_map(|pre_prepares| {
+            let mut requests = Vec::new();
+
+            for pre_prepare in pre_prepares {
+                if !authentic(pre_prepare) {
+                    return None;
+                }
+
+                match pre_prepare.message() {
+                    PBFTMessage::Consensus(consensus) if consensus.sequence_number() == seq => {
+                        match consensus.kind() {
+                            ConsensusMessageKind::PrePrepare(batch) => {
+                                requests.extend(batch.iter().cloned())
+                            }
+                            _ => return None,
+                        }
+                    }
+                    _ => return None,
+                }
+            }
+
+            Some(requests)
+        })
+}
+
+/// The digest of a batch, as prepared by the replicas, given its pre prepares
+/// in the order of the leader set
+pub(crate) fn batch_digest<O>(pre_prepares: &[StoredConsensusMessage<O>]) -> Digest {
+    let mut ctx = Context::new();
+
+    for pre_prepare in pre_prepares {
+        ctx.update(pre_prepare.header().digest().as_ref());
+    }
+
+    ctx.finish()
+}
+
+fn same_requests<O>(proposed: &[SharedRequest<O>], bound: &[SharedRequest<O>]) -> bool {
+    proposed.len() == bound.len()
+        && proposed
+            .iter()
+            .zip(bound)
+            .all(|(proposed, bound)| proposed.header().digest() == bound.header().digest())
+}
+
+/// Why the SYNC message of a new leader was rejected
+#[derive(Error, Debug)]
+pub enum SyncVerificationError {
+    #[error("The proposal is not signed by {0:?}")]
+    ForgedProposal(NodeId),
+    #[error("The proposal was sent by {0:?} instead of the leader {1:?}")]
+    WrongProposer(NodeId, NodeId),
+    #[error("The proposal is not a pre prepare")]
+    NotPrePrepare,
+    #[error("The proposal is for view {0:?} instead of view {1:?}")]
+    WrongView(SeqNo, SeqNo),
+    #[error("The proposal is for instance {0:?} but the collects require instance {1:?}")]
+    WrongInstance(SeqNo, SeqNo),
+    #[error("The proposal does not order the requests of the bound value {0:?}")]
+    ValueMismatch(Digest),
+    #[error("None of the collects carries the pre prepares of the bound value {0:?}")]
+    UnjustifiedValue(Digest),
+    #[error("The collects neither bind a value nor leave the instance free")]
+    Unsound,
+    #[error("The collect of {0:?} is not signed by it")]
+    ForgedCollect(NodeId),
+    #[error("The collect of {0:?} is not a STOP-DATA message for view {1:?}")]
+    UnexpectedCollect(NodeId, SeqNo),
+    #[error("{0:?} sent a collect but is not a member of view {1:?}")]
+    NotAMember(NodeId, SeqNo),
+    #[error("There is more than one collect from {0:?}")]
+    DuplicateCollect(NodeId),
+    #[error("Only {0} collects were included, but {1} are required")]
+    NotEnoughCollects(usize, usize),
+}
+
+#[cfg(test)]
+mod verify_tests {
+    use crate::bft::fixtures::{digest, shared};
+    use crate::bft::log::decisions::{IncompleteProof, PrepareSet, ViewDecisionPair};
+    use crate::bft::message::ViewChangeMessage;
+
+    use super::*;
+
+    type Message = PBFTMessage<u8>;
+
+    fn request(byte: u8) -> SharedRequest<u8> {
+        shared(NodeId::from(1000u32), digest(byte), byte)
+    }
+
+    fn new_view() -> ViewInfo {
+        ViewInfo::new(SeqNo::ZERO.next(), 4, 1).unwrap()
+    }
+
+    fn collect(
+        view: SeqNo,
+        write_set: Vec<ViewDecisionPair>,
+        quorum_prepares: Option<ViewDecisionPair>,
+        pre_prepares: Vec<StoredConsensusMessage<u8>>,
+    ) -> Message {
+        let incomplete_proof =
+            IncompleteProof::new(SeqNo::ZERO, PrepareSet(write_set), quorum_prepares);
+
+        PBFTMessage::ViewChange(ViewChangeMessage::new(
+            view,
+            ViewChangeMessageKind::StopData(CollectData::new(incomplete_proof, pre_prepares, None)),
+        ))
+    }
+
+    fn proposal(seq: SeqNo, view: SeqNo) -> ConsensusMessage<u8> {
+        proposal_with(seq, view, Vec::new())
+    }
+
+    fn proposal_with(
+        seq: SeqNo,
+        view: SeqNo,
+        requests: Vec<SharedRequest<u8>>,
+    ) -> ConsensusMessage<u8> {
+        ConsensusMessage::new(seq, view, ConsensusMessageKind::PrePrepare(requests))
+    }
+
+    fn verify<'a>(
+        view: &ViewInfo,
+        proposer: NodeId,
+        proposal: &ConsensusMessage<u8>,
+        collects: &'a [(NodeId, Message)],
+    ) -> Result<VerifiedSync<'a, u8>, SyncVerificationError> {
+        verify_leader_collects(
+            view,
+            proposer,
+            proposal,
+            collects.iter().map(|(sender, message)| (*sender, message)),
+            &|_| true,
+        )
+    }
+
+    fn quorum_collects(view: &ViewInfo, make: impl Fn() -> Message) -> Vec<(NodeId, Message)> {
+        view.quorum_members()
+            .iter()
+            .take(view.params().quorum())
+            .map(|member| (*member, make()))
+            .collect()
+    }
+
+    fn unbound_collects(view: &ViewInfo) -> Vec<(NodeId, Message)> {
+        quorum_collects(view, || {
+            collect(view.sequence_number(), Vec::new(), None, Vec::new())
+        })
+    }
+
+    /// Collects binding the value proposed by a pre prepare of the old view,
+    /// which carry that pre prepare if `justified`
+    fn bound_collects(
+        view: &ViewInfo,
+        requests: Vec<SharedRequest<u8>>,
+        justified: bool,
+    ) -> (Digest, Vec<(NodeId, Message)>) {
+        let old_leader = view.leader();
+
+        let pre_prepare: StoredConsensusMessage<u8> = shared(
+            old_leader,
+            digest(9),
+            PBFTMessage::Consensus(proposal_with(SeqNo::ZERO, SeqNo::ZERO, requests)),
+        );
+
+        let value = batch_digest(&[pre_prepare.clone()]);
+
+        let prepared = ViewDecisionPair(SeqNo::ZERO, value);
+
+        let collects = quorum_collects(view, || {
+            let pre_prepares = if justified {
+                vec![pre_prepare.clone()]
+            } else {
+                Vec::new()
+            };
+
+            collect(
+                view.sequence_number(),
+                vec![prepared.clone()],
+                Some(prepared.clone()),
+                pre_prepares,
+            )
+        });
+
+        (value, collects)
+    }
+
+    #[test]
+    fn test_accepts_the_required_proposal() {
+        let view = new_view();
+
+        let collects = unbound_collects(&view);
+
+        let proposal = proposal(SeqNo::ZERO, view.sequence_number());
+
+        let verified = verify(&view, view.leader(), &proposal, &collects).unwrap();
+
+        assert_eq!(verified.curr_cid, SeqNo::ZERO);
+        assert!(verified.proof.is_none());
+        assert!(verified.sound.test());
+        assert_eq!(verified.normalized_collects.len(), collects.len());
+    }
+
+    #[test]
+    fn test_rejects_invalid_proposals() {
+        let view = new_view();
+
+        let collects = unbound_collects(&view);
+
+        let follower = *view
+            .quorum_members()
+            .iter()
+            .find(|member| **member != view.leader())
+            .unwrap();
+
+        let valid = proposal(SeqNo::ZERO, view.sequence_number());
+
+        assert!(matches!(
+            verify(&view, follower, &valid, &collects),
+            Err(SyncVerificationError::WrongProposer(proposer, _)) if proposer == follower
+        ));
+
+        let old_view = proposal(SeqNo::ZERO, SeqNo::ZERO);
+
+        assert!(matches!(
+            verify(&view, view.leader(), &old_view, &collects),
+            Err(SyncVerificationError::WrongView(..))
+        ));
+
+        let commit = ConsensusMessage::new(
+            SeqNo::ZERO,
+            view.sequence_number(),
+            ConsensusMessageKind::Commit(digest(0)),
+        );
+
+        assert!(matches!(
+            verify(&view, view.leader(), &commit, &collects),
+            Err(SyncVerificationError::NotPrePrepare)
+        ));
+    }
+
+    #[test]
+    fn test_rejects_invalid_collects() {
+        let view = new_view();
+
+        let proposal = proposal(SeqNo::ZERO, view.sequence_number());
+
+        let mut too_few = unbound_collects(&view);
+        too_few.pop();
+
+        assert!(matches!(
+            verify(&view, view.leader(), &proposal, &too_few),
+            Err(SyncVerificationError::NotEnoughCollects(2, 3))
+        ));
+
+        let mut duplicated = unbound_collects(&view);
+        duplicated.push(duplicated[0].clone());
+
+        assert!(matches!(
+            verify(&view, view.leader(), &proposal, &duplicated),
+            Err(SyncVerificationError::DuplicateCollect(_))
+        ));
+
+        let mut outsider = unbound_collects(&view);
+        outsider.push((
+            NodeId::from(10u32),
+            collect(view.sequence_number(), Vec::new(), None, Vec::new()),
+        ));
+
+        assert!(matches!(
+            verify(&view, view.leader(), &proposal, &outsider),
+            Err(SyncVerificationError::NotAMember(..))
+        ));
+
+        let mut stale = unbound_collects(&view);
+        stale[0].1 = collect(SeqNo::ZERO, Vec::new(), None, Vec::new());
+
+        assert!(matches!(
+            verify(&view, view.leader(), &proposal, &stale),
+            Err(SyncVerificationError::UnexpectedCollect(..))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_unsound_collects() {
+        let view = new_view();
+
+        // A quorum prepared a value in view 1, yet no write set certifies it
+        let prepared = ViewDecisionPair(SeqNo::ZERO.next(), digest(5));
+
+        let collects = quorum_collects(&view, || {
+            collect(
+                view.sequence_number(),
+                Vec::new(),
+                Some(prepared.clone()),
+                Vec::new(),
+            )
+        });
+
+        let proposal = proposal(SeqNo::ZERO, view.sequence_number());
+
+        assert!(matches!(
+            verify(&view, view.leader(), &proposal, &collects),
+            Err(SyncVerificationError::Unsound)
+        ));
+    }
+
+    #[test]
+    fn test_bound_value_pins_the_instance() {
+        let view = new_view();
+
+        let requests = vec![request(1), request(2)];
+
+        let (value, collects) = bound_collects(&view, requests.clone(), true);
+
+        let skipping = proposal_with(SeqNo::ZERO.next(), view.sequence_number(), requests.clone());
+
+        assert!(matches!(
+            verify(&view, view.leader(), &skipping, &collects),
+            Err(SyncVerificationError::WrongInstance(..))
+        ));
+
+        let resuming = proposal_with(SeqNo::ZERO, view.sequence_number(), requests);
+
+        let verified = verify(&view, view.leader(), &resuming, &collects).unwrap();
+
+        assert_eq!(verified.sound.value(), Some(&value));
+    }
+
+    #[test]
+    fn test_collects_without_a_proof_pin_the_first_instance() {
+        let view = new_view();
+
+        let collects = unbound_collects(&view);
+
+        let skipping = proposal(SeqNo::ZERO.next(), view.sequence_number());
+
+        assert!(matches!(
+            verify(&view, view.leader(), &skipping, &collects),
+            Err(SyncVerificationError::WrongInstance(proposed, expected))
+                if proposed == SeqNo::ZERO.next() && expected == SeqNo::ZERO
+        ));
+    }
+
+    #[test]
+    fn test_rejects_a_swapped_batch() {
+        let view = new_view();
+
+        let requests = vec![request(1), request(2)];
+
+        let (value, collects) = bound_collects(&view, requests, true);
+
+        // The leader keeps the instance but replaces the requests of the bound value
+        let swapped = proposal_with(SeqNo::ZERO, view.sequence_number(), vec![request(3)]);
+
+        assert!(matches!(
+            verify(&view, view.leader(), &swapped, &collects),
+            Err(SyncVerificationError::ValueMismatch(mismatched)) if mismatched == value
+        ));
+
+        let reordered = proposal_with(
+            SeqNo::ZERO,
+            view.sequence_number(),
+            vec![request(2), request(1)],
+        );
+
+        assert!(matches!(
+            verify(&view, view.leader(), &reordered, &collects),
+            Err(SyncVerificationError::ValueMismatch(_))
+        ));
+
+        let empty = proposal(SeqNo::ZERO, view.sequence_number());
+
+        assert!(matches!(
+            verify(&view, view.leader(), &empty, &collects),
+            Err(SyncVerificationError::ValueMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_an_unjustified_value() {
+        let view = new_view();
+
+        let requests = vec![request(1)];
+
+        let (value, collects) = bound_collects(&view, requests.clone(), false);
+
+        let proposal = proposal_with(SeqNo::ZERO, view.sequence_number(), requests);
+
+        assert!(matches!(
+            verify(&view, view.leader(), &proposal, &collects),
+            Err(SyncVerificationError::UnjustifiedValue(unjustified)) if unjustified == value
+        ));
+    }
+}
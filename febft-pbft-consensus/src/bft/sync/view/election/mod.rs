@@ -0,0 +1,260 @@
+//! The policies which elect the leader of each view.
+//!
+//! Every replica computes the leader of a view on its own, so a policy must be a deterministic
+//! function of the view's sequence number, its quorum members and the decided history it was fed
+//! (the view and leader of the decided [`Proof`]s, in order). Correct replicas that fed the same
+//! decided history to the same policy elect the same leader.
+//!
+//! The policy travels with the [`ViewInfo`], so that the views derived from it (the next view,
+//! the previous one, the view of a node joining the quorum) elect their leaders in the same way.
+//!
+//! Replicas learn of decisions at different times, so the policy is only fed the decisions
+//! up to a sequence number every correct replica has decided: the one a view is installed at,
+//! be it the one fixed in the `SYNC` message of a view change or the rotation point of a hand
+//! off. The [`ElectionHistory`] holds the decisions back until then.
+//!
+//! [`Proof`]: crate::bft::log::decisions::Proof
+//! [`ViewInfo`]: crate::bft::sync::view::ViewInfo
+
+use std::collections::{BTreeMap, VecDeque};
+
+use atlas_common::node_id::NodeId;
+use atlas_common::ordering::SeqNo;
+#[cfg(feature = "serialize_serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::bft::config::LeaderElectionMode;
+
+/// Elects the leader of each view
+pub trait LeaderElectionPolicy {
+    /// The leader of the given view, out of its (non empty) quorum members
+    fn elect(&self, view: SeqNo, quorum_members: &[NodeId]) -> NodeId;
+
+    /// A consensus instance proposed by `leader` was decided in the given view.
+    /// Must be fed the decided history in order. Feeding the same decision
+    /// twice in a row has no effect
+    fn decided(&mut self, view: SeqNo, leader: NodeId, quorum_members: &[NodeId]);
+}
+
+/// The leader of view `v` is the member `v mod n` of the quorum
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoundRobin;
+
+impl LeaderElectionPolicy for RoundRobin {
+    fn elect(&self, view: SeqNo, quorum_members: &[NodeId]) -> NodeId {
+        quorum_members[usize::from(view) % quorum_members.len()]
+    }
+
+    fn decided(&mut self, _view: SeqNo, _leader: NodeId, _quorum_members: &[NodeId]) {}
+}
+
+/// Round robin, skipping the members which recently failed as leaders.
+///
+/// A view in which nothing was decided (which shows up as a gap between the views of
+/// consecutive decisions) is a failure of its leader. We remember the leaders of the last
+/// `memory` failed views and pass over them when electing, unless every member failed.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reputation {
+    memory: usize,
+    // The view of the last decision we were fed
+    last_view: Option<SeqNo>,
+    // The leaders of the most recently failed views, the oldest first
+    failed: VecDeque<NodeId>,
+}
+
+impl Reputation {
+    pub fn new(memory: usize) -> Self {
+        Self {
+            memory,
+            last_view: None,
+            failed: VecDeque::with_capacity(memory),
+        }
+    }
+
+    /// Whether the given member is among the leaders which recently failed
+    pub fn has_failed(&self, member: &NodeId) -> bool {
+        self.failed.contains(member)
+    }
+}
+
+impl LeaderElectionPolicy for Reputation {
+    fn elect(&self, view: SeqNo, quorum_members: &[NodeId]) -> NodeId {
+        let n = quorum_members.len();
+        let first = usize::from(view) % n;
+
+        (0..n)
+            .map(|offset| quorum_members[(first + offset) % n])
+            .find(|candidate| !self.has_failed(candidate))
+            .unwrap_or(quorum_members[first])
+    }
+
+    fn decided(&mut self, view: SeqNo, _leader: NodeId, quorum_members: &[NodeId]) {
+        if self.last_view.is_some_and(|last_view| last_view >= view) {
+            return;
+        }
+
+        let Some(last_view) = self.last_view.replace(view) else {
+            return;
+        };
+
+        // The views in between decided nothing. Their leaders were elected with the
+        // history we had before this decision, so we elect them all before recording any
+        let failed = (last_view.into_u32() + 1..view.into_u32())
+            .map(|skipped| self.elect(SeqNo::from(skipped), quorum_members))
+            .collect::<Vec<_>>();
+
+        for leader in failed {
+            if self.failed.len() >= self.memory {
+                self.failed.pop_front();
+            }
+
+            if self.memory > 0 {
+                self.failed.push_back(leader);
+            }
+        }
+    }
+}
+
+/// The leader which last decided keeps leading, and view changes hand leadership
+/// over to the members which follow it in the quorum, in order.
+///
+/// Leadership is anchored to the replica which is actually deciding rather than to
+/// the view numbers, so it does not move when the quorum changes.
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StableLeader {
+    // The view of the last decision, and the leader which proposed it
+    anchor: Option<(SeqNo, NodeId)>,
+}
+
+impl LeaderElectionPolicy for StableLeader {
+    fn elect(&self, view: SeqNo, quorum_members: &[NodeId]) -> NodeId {
+        let anchored = self.anchor.and_then(|(anchor_view, leader)| {
+            let position = quorum_members.iter().position(|member| *member == leader)?;
+
+            let handovers = view.into_u32().checked_sub(anchor_view.into_u32())? as usize;
+
+            Some(quorum_members[(position + handovers) % quorum_members.len()])
+        });
+
+        anchored.unwrap_or_else(|| RoundRobin.elect(view, quorum_members))
+    }
+
+    fn decided(&mut self, view: SeqNo, leader: NodeId, _quorum_members: &[NodeId]) {
+        if self
+            .anchor
+            .is_some_and(|(anchor_view, _)| anchor_view > view)
+        {
+            return;
+        }
+
+        self.anchor = Some((view, leader));
+    }
+}
+
+/// The leader election policy of a view, along with the decided history it was fed
+#[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LeaderElection {
+    RoundRobin(RoundRobin),
+    Reputation(Reputation),
+    StableLeader(StableLeader),
+}
+
+impl Default for LeaderElection {
+    fn default() -> Self {
+        Self::RoundRobin(RoundRobin)
+    }
+}
+
+impl From<LeaderElectionMode> for LeaderElection {
+    fn from(mode: LeaderElectionMode) -> Self {
+        match mode {
+            LeaderElectionMode::RoundRobin => Self::RoundRobin(RoundRobin),
+            LeaderElectionMode::Reputation { memory } => {
+                Self::Reputation(Reputation::new(memory as usize))
+            }
+            LeaderElectionMode::StableLeader => Self::StableLeader(StableLeader::default()),
+        }
+    }
+}
+
+impl LeaderElectionPolicy for LeaderElection {
+    fn elect(&self, view: SeqNo, quorum_members: &[NodeId]) -> NodeId {
+        match self {
+            LeaderElection::RoundRobin(policy) => policy.elect(view, quorum_members),
+            LeaderElection::Reputation(policy) => policy.elect(view, quorum_members),
+            LeaderElection::StableLeader(policy) => policy.elect(view, quorum_members),
+        }
+    }
+
+    fn decided(&mut self, view: SeqNo, leader: NodeId, quorum_members: &[NodeId]) {
+        match self {
+            LeaderElection::RoundRobin(policy) => policy.decided(view, leader, quorum_members),
+            LeaderElection::Reputation(policy) => policy.decided(view, leader, quorum_members),
+            LeaderElection::StableLeader(policy) => policy.decided(view, leader, quorum_members),
+        }
+    }
+}
+
+/// The decisions which have not been fed to the election policy yet,
+/// as no correct replica is known to have decided them all
+pub struct ElectionHistory {
+    // The view and leader of the pending decisions, by their sequence number.
+    // A decision with the same view and leader as the one before it is not kept,
+    // as feeding it would have no effect
+    pending: BTreeMap<SeqNo, (SeqNo, NodeId)>,
+    // Every decision before this one has been agreed on
+    agreed_before: SeqNo,
+}
+
+impl Default for ElectionHistory {
+    fn default() -> Self {
+        Self {
+            pending: BTreeMap::new(),
+            agreed_before: SeqNo::ZERO,
+        }
+    }
+}
+
+impl ElectionHistory {
+    /// The instance `seq`, proposed by `leader`, was decided in the given view
+    pub fn decided(&mut self, seq: SeqNo, view: SeqNo, leader: NodeId) {
+        if seq < self.agreed_before {
+            return;
+        }
+
+        let repeated = self
+            .pending
+            .range(..seq)
+            .next_back()
+            .is_some_and(|(_, previous)| *previous == (view, leader));
+
+        if !repeated {
+            self.pending.insert(seq, (view, leader));
+        }
+    }
+
+    /// Every correct replica has decided the instances before `seq`. Take the view and
+    /// leader of the pending decisions among them, in order, to feed the election policy
+    pub fn agree(&mut self, seq: SeqNo) -> Vec<(SeqNo, NodeId)> {
+        if seq <= self.agreed_before {
+            return Vec::new();
+        }
+
+        self.agreed_before = seq;
+
+        let remaining = self.pending.split_off(&seq);
+
+        std::mem::replace(&mut self.pending, remaining)
+            .into_values()
+            .collect()
+    }
+
+    /// The amount of decisions we are holding back
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
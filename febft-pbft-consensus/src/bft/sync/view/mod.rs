@@ -19,6 +19,10 @@ use serde::{Deserialize, Serialize};
 use std::ops::{Add, Div};
 use thiserror::Error;
 
+use crate::bft::sync::view::election::{LeaderElection, LeaderElectionPolicy};
+
+pub mod election;
+
 /// This struct contains information related with an
 /// active `febft` view.
 #[cfg_attr(feature = "serialize_serde", derive(Serialize, Deserialize))]
@@ -34,6 +38,11 @@ pub struct ViewInfo {
     leader_hash_space_division: BTreeMap<NodeId, (Vec<u8>, Vec<u8>)>,
     // The parameters of the view
     params: SystemParams,
+    // The policy which elected the leaders of this view, fed the history agreed before it
+    election: LeaderElection,
+    // The policy which elects the leaders of the views after this one, which is also
+    // fed the history agreed on since this view was installed
+    agreed: LeaderElection,
 }
 
 impl Orderable for ViewInfo {
@@ -77,49 +86,67 @@ impl ViewInfo {
 
         let quorum_members: Vec<NodeId> = NodeId::targets_u32(0..n as u32).collect();
 
-        let destined_leader = quorum_members[(usize::from(seq)) % n];
-
-        let mut leader_set = vec![destined_leader];
-
-        for i in 1..LEADER_COUNT {
-            leader_set.push(quorum_members[(usize::from(seq) + i) % n]);
-        }
-
-        let division = calculate_hash_space_division(&leader_set);
-
-        Ok(ViewInfo {
+        Ok(Self::elected(
             seq,
-            quorum_members,
-            leader_set,
-            leader_hash_space_division: division,
             params,
-        })
+            quorum_members,
+            LeaderElection::default(),
+        ))
     }
 
     /// Creates a new instance of `ViewInfo`, from a given list of quorum members
     pub fn from_quorum(seq: SeqNo, quorum_members: Vec<NodeId>) -> Result<Self> {
+        Self::from_quorum_with_election(seq, quorum_members, LeaderElection::default())
+    }
+
+    /// Creates a new instance of `ViewInfo`, from a given list of quorum members,
+    /// whose leaders are elected by the given policy
+    pub fn from_quorum_with_election(
+        seq: SeqNo,
+        quorum_members: Vec<NodeId>,
+        election: LeaderElection,
+    ) -> Result<Self> {
         let n = quorum_members.len();
         let f = (n - 1) / 3;
 
         let params = SystemParams::new(n, f)?;
 
-        let destined_leader = quorum_members[(usize::from(seq)) % n];
+        Ok(Self::elected(seq, params, quorum_members, election))
+    }
+
+    /// Build a view whose leader set is elected by the given policy
+    fn elected(
+        seq: SeqNo,
+        params: SystemParams,
+        quorum_members: Vec<NodeId>,
+        election: LeaderElection,
+    ) -> Self {
+        let n = quorum_members.len();
+
+        let destined_leader = election.elect(seq, &quorum_members);
+
+        let position = quorum_members
+            .iter()
+            .position(|member| *member == destined_leader)
+            .unwrap_or_default();
 
         let mut leader_set = vec![destined_leader];
 
         for i in 1..LEADER_COUNT {
-            leader_set.push(quorum_members[(usize::from(seq) + i) % n]);
+            leader_set.push(quorum_members[(position + i) % n]);
         }
 
         let division = calculate_hash_space_division(&leader_set);
 
-        Ok(ViewInfo {
+        ViewInfo {
             seq,
             quorum_members,
             leader_set,
             leader_hash_space_division: division,
             params,
-        })
+            agreed: election.clone(),
+            election,
+        }
     }
 
     /// Initialize a view with a given leader set
@@ -146,6 +173,8 @@ impl ViewInfo {
             leader_set,
             leader_hash_space_division: division,
             params,
+            election: LeaderElection::default(),
+            agreed: LeaderElection::default(),
         })
     }
 
@@ -157,7 +186,7 @@ impl ViewInfo {
     /// Returns a new view with the sequence number after
     /// the current view's number.
     pub fn next_view(&self) -> ViewInfo {
-        Self::new(self.seq.next(), self.params.n(), self.params.f()).unwrap()
+        self.peek(self.seq.next())
     }
 
     pub fn next_view_with_new_node(&self, joined_node: NodeId) -> ViewInfo {
@@ -165,7 +194,8 @@ impl ViewInfo {
 
         quorum_members.push(joined_node);
 
-        Self::from_quorum(self.seq.next(), quorum_members).unwrap()
+        Self::from_quorum_with_election(self.seq.next(), quorum_members, self.agreed.clone())
+            .unwrap()
     }
 
     pub fn previous_view(&self) -> Option<ViewInfo> {
@@ -173,17 +203,64 @@ impl ViewInfo {
             return None;
         }
 
-        Some(Self::new(self.seq.prev(), self.params.n(), self.params.f()).unwrap())
+        Some(self.peek(self.seq.prev()))
     }
 
     /// Returns a new view with the specified sequence number.
+    /// The views up to this one are elected with the history this view was elected with,
+    /// so their leaders never change, and the views after it with the history agreed since
     pub fn peek(&self, seq: SeqNo) -> ViewInfo {
-        Self::new(seq, self.params.n(), self.params.f()).unwrap()
+        let election = if seq > self.seq {
+            &self.agreed
+        } else {
+            &self.election
+        };
+
+        Self::elected(
+            seq,
+            self.params.clone(),
+            self.quorum_members.clone(),
+            election.clone(),
+        )
+    }
+
+    /// Elect the leaders of a view we received from another replica with the history we
+    /// agreed on, instead of trusting the one it carries, which we cannot verify
+    pub fn reelect(&self, view: &ViewInfo) -> Result<ViewInfo> {
+        Self::reelect_with(view, self.agreed.clone())
+    }
+
+    /// Elect the leaders of a view we received from another replica with the given
+    /// agreed history, such as the one vouched for by a stable checkpoint
+    pub fn reelect_with(view: &ViewInfo, agreed: LeaderElection) -> Result<ViewInfo> {
+        Self::from_quorum_with_election(
+            view.sequence_number(),
+            view.quorum_members().clone(),
+            agreed,
+        )
     }
 
     /// Returns the primary of the current view.
     pub fn leader(&self) -> NodeId {
-        self.quorum_members[usize::from(self.seq) % self.params.n()]
+        self.leader_set[0]
+    }
+
+    /// The policy which elected the leaders of this view
+    pub fn election(&self) -> &LeaderElection {
+        &self.election
+    }
+
+    /// The policy which elects the leaders of the views after this one
+    pub fn agreed_election(&self) -> &LeaderElection {
+        &self.agreed
+    }
+
+    /// Feed the view and leader of the decisions every correct replica agreed on, in order.
+    /// The leaders of this view are already elected, so this only affects the views after it
+    pub fn agree(&mut self, decisions: impl IntoIterator<Item = (SeqNo, NodeId)>) {
+        for (view, leader) in decisions {
+            self.agreed.decided(view, leader, &self.quorum_members);
+        }
     }
 
     /// The set of leaders for this view.
@@ -313,6 +390,214 @@ mod view_tests {
             );
         }
     }
+
+    #[test]
+    fn test_round_robin_election() {
+        use super::*;
+
+        let view_info = ViewInfo::new(SeqNo::ZERO, 4, 1).unwrap();
+
+        let mut view = view_info.clone();
+
+        for seq in 0..8u32 {
+            assert_eq!(view.sequence_number(), SeqNo::from(seq));
+            assert_eq!(view.leader(), NodeId::from(seq % 4));
+
+            view = view.next_view();
+        }
+    }
+
+    #[test]
+    fn test_reputation_election_skips_failed_leaders() {
+        use super::election::{LeaderElection, Reputation};
+        use super::*;
+
+        let quorum: Vec<NodeId> = NodeId::targets_u32(0..4).collect();
+
+        let mut view_info = ViewInfo::from_quorum_with_election(
+            SeqNo::ZERO,
+            quorum,
+            LeaderElection::Reputation(Reputation::new(2)),
+        )
+        .unwrap();
+
+        // Nothing was decided in view 1, so its leader failed
+        view_info.agree([(SeqNo::ZERO, NodeId::from(0u32))]);
+        view_info.agree([(SeqNo::from(2u32), NodeId::from(2u32))]);
+
+        let mut replica = view_info.clone();
+
+        replica.agree([(SeqNo::from(2u32), NodeId::from(2u32))]);
+
+        assert_eq!(
+            view_info.peek(SeqNo::from(4u32)).leader(),
+            NodeId::from(0u32)
+        );
+        assert_eq!(
+            view_info.peek(SeqNo::from(5u32)).leader(),
+            NodeId::from(2u32)
+        );
+        // Feeding the same decision again does not change the outcome
+        assert_eq!(
+            replica.peek(SeqNo::from(5u32)).leader(),
+            view_info.peek(SeqNo::from(5u32)).leader()
+        );
+
+        // Once it has been forgotten, the failed leader gets its turn back
+        view_info.agree([(SeqNo::from(4u32), NodeId::from(0u32))]);
+        view_info.agree([(SeqNo::from(7u32), NodeId::from(3u32))]);
+
+        assert_eq!(
+            view_info.peek(SeqNo::from(9u32)).leader(),
+            NodeId::from(1u32)
+        );
+    }
+
+    #[test]
+    fn test_stable_leader_election() {
+        use super::election::{LeaderElection, StableLeader};
+        use super::*;
+
+        let quorum: Vec<NodeId> = NodeId::targets_u32(0..4).collect();
+
+        let mut view_info = ViewInfo::from_quorum_with_election(
+            SeqNo::ZERO,
+            quorum,
+            LeaderElection::StableLeader(StableLeader::default()),
+        )
+        .unwrap();
+
+        assert_eq!(view_info.leader(), NodeId::from(0u32));
+
+        view_info.agree([(SeqNo::ZERO, NodeId::from(2u32))]);
+
+        // The leader of the view the decision was agreed in does not change
+        assert_eq!(view_info.peek(SeqNo::ZERO).leader(), NodeId::from(0u32));
+        assert_eq!(view_info.next_view().leader(), NodeId::from(3u32));
+        assert_eq!(
+            view_info
+                .next_view_with_new_node(NodeId::from(4u32))
+                .leader(),
+            NodeId::from(3u32)
+        );
+    }
+
+    #[test]
+    fn test_agreed_history_only_affects_later_views() {
+        use super::election::{LeaderElection, StableLeader};
+        use super::*;
+
+        let quorum: Vec<NodeId> = NodeId::targets_u32(0..4).collect();
+
+        let mut view_info = ViewInfo::from_quorum_with_election(
+            SeqNo::from(2u32),
+            quorum,
+            LeaderElection::StableLeader(StableLeader::default()),
+        )
+        .unwrap();
+
+        view_info.agree([(SeqNo::from(2u32), NodeId::from(0u32))]);
+
+        assert_eq!(view_info.leader(), NodeId::from(2u32));
+        assert_eq!(
+            view_info.previous_view().unwrap().leader(),
+            NodeId::from(1u32)
+        );
+        assert_eq!(
+            view_info.peek(SeqNo::from(2u32)).leader(),
+            NodeId::from(2u32)
+        );
+        assert_eq!(view_info.next_view().leader(), NodeId::from(1u32));
+    }
+
+    #[test]
+    fn test_received_views_are_reelected() {
+        use super::election::{LeaderElection, StableLeader};
+        use super::*;
+
+        let quorum: Vec<NodeId> = NodeId::targets_u32(0..4).collect();
+
+        let ours = ViewInfo::from_quorum_with_election(
+            SeqNo::ZERO,
+            quorum.clone(),
+            LeaderElection::StableLeader(StableLeader::default()),
+        )
+        .unwrap();
+
+        // A peer which claims a history in which replica 3 decided
+        let mut theirs = ours.clone();
+        theirs.agree([(SeqNo::ZERO, NodeId::from(3u32))]);
+
+        let received = theirs.next_view();
+
+        assert_eq!(received.leader(), NodeId::from(0u32));
+
+        let reelected = ours.reelect(&received).unwrap();
+
+        assert_eq!(reelected.sequence_number(), received.sequence_number());
+        assert_eq!(reelected.leader(), NodeId::from(1u32));
+    }
+
+    #[test]
+    fn test_received_views_are_reelected_with_a_certified_history() {
+        use super::election::{LeaderElection, StableLeader};
+        use super::*;
+
+        let quorum: Vec<NodeId> = NodeId::targets_u32(0..4).collect();
+
+        // The quorum agreed that replica 3 decided, which we skipped through a state transfer
+        let mut quorum_view = ViewInfo::from_quorum_with_election(
+            SeqNo::ZERO,
+            quorum,
+            LeaderElection::StableLeader(StableLeader::default()),
+        )
+        .unwrap();
+
+        quorum_view.agree([(SeqNo::ZERO, NodeId::from(3u32))]);
+
+        let received = quorum_view.next_view();
+
+        let reelected =
+            ViewInfo::reelect_with(&received, quorum_view.agreed_election().clone()).unwrap();
+
+        assert_eq!(reelected.leader(), received.leader());
+        assert_eq!(reelected.leader(), NodeId::from(0u32));
+    }
+
+    #[test]
+    fn test_election_history_holds_decisions_back() {
+        use super::election::ElectionHistory;
+        use super::*;
+
+        let mut history = ElectionHistory::default();
+
+        history.decided(SeqNo::ZERO, SeqNo::ZERO, NodeId::from(0u32));
+        history.decided(SeqNo::from(1u32), SeqNo::ZERO, NodeId::from(0u32));
+        history.decided(SeqNo::from(2u32), SeqNo::from(1u32), NodeId::from(1u32));
+        history.decided(SeqNo::from(3u32), SeqNo::from(2u32), NodeId::from(2u32));
+
+        // Repeated decisions are not kept
+        assert_eq!(history.pending(), 3);
+
+        assert_eq!(
+            history.agree(SeqNo::from(3u32)),
+            vec![
+                (SeqNo::ZERO, NodeId::from(0u32)),
+                (SeqNo::from(1u32), NodeId::from(1u32))
+            ]
+        );
+        assert_eq!(history.pending(), 1);
+
+        // Decisions before the agreed point are not fed twice
+        history.decided(SeqNo::from(2u32), SeqNo::from(1u32), NodeId::from(1u32));
+        assert!(history.agree(SeqNo::from(2u32)).is_empty());
+
+        assert_eq!(
+            history.agree(SeqNo::from(4u32)),
+            vec![(SeqNo::from(2u32), NodeId::from(2u32))]
+        );
+        assert_eq!(history.pending(), 0);
+    }
 }
 
 impl Debug for ViewInfo {
@@ -0,0 +1,113 @@
+//! The timers which are identified by a view.
+//!
+//! The timeouts layer only identifies a timer by a sequence number (or by a client
+//! request), so the timers of each view are packed into disjoint sequence numbers,
+//! one for each kind of timer. Acknowledging one of them never disarms the others.
+
+use atlas_common::ordering::SeqNo;
+use atlas_core::timeouts::TimeoutID;
+
+/// How many kinds of view timers there are
+const VIEW_TIMER_KINDS: u32 = 3;
+
+/// A timer of a given view
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewTimer {
+    /// The leader of the view must send us a pre prepare (possibly a heartbeat) in time
+    LeaderLiveness(SeqNo),
+    /// The leader taking over the view in a hand off must send us a pre prepare in time
+    Handoff(SeqNo),
+    /// The view change to the view must complete in time
+    ViewChange(SeqNo),
+}
+
+impl ViewTimer {
+    /// The view this timer belongs to
+    pub fn view(&self) -> SeqNo {
+        match self {
+            ViewTimer::LeaderLiveness(view)
+            | ViewTimer::Handoff(view)
+            | ViewTimer::ViewChange(view) => *view,
+        }
+    }
+
+    fn kind(&self) -> u32 {
+        match self {
+            ViewTimer::LeaderLiveness(_) => 0,
+            ViewTimer::Handoff(_) => 1,
+            ViewTimer::ViewChange(_) => 2,
+        }
+    }
+
+    /// The identifier of this timer in the timeouts layer
+    pub fn id(&self) -> TimeoutID {
+        TimeoutID::SeqNoBased(SeqNo::from(
+            self.view().into_u32() * VIEW_TIMER_KINDS + self.kind(),
+        ))
+    }
+
+    /// The view timer with the given identifier, if it is one
+    pub fn from_id(id: &TimeoutID) -> Option<Self> {
+        let TimeoutID::SeqNoBased(seq) = id else {
+            return None;
+        };
+
+        let view = SeqNo::from(seq.into_u32() / VIEW_TIMER_KINDS);
+
+        match seq.into_u32() % VIEW_TIMER_KINDS {
+            0 => Some(ViewTimer::LeaderLiveness(view)),
+            1 => Some(ViewTimer::Handoff(view)),
+            _ => Some(ViewTimer::ViewChange(view)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod timer_tests {
+    use atlas_common::node_id::NodeId;
+
+    use super::*;
+
+    fn timers(view: u32) -> [ViewTimer; 3] {
+        let view = SeqNo::from(view);
+
+        [
+            ViewTimer::LeaderLiveness(view),
+            ViewTimer::Handoff(view),
+            ViewTimer::ViewChange(view),
+        ]
+    }
+
+    #[test]
+    fn timers_round_trip_through_their_ids() {
+        for view in [0, 1, 2, 7, 1000] {
+            for timer in timers(view) {
+                assert_eq!(ViewTimer::from_id(&timer.id()), Some(timer));
+            }
+        }
+    }
+
+    #[test]
+    fn timers_do_not_collide() {
+        // Every identifier decodes to a single timer, so distinct timers never share one
+        let decoded = (0..4)
+            .flat_map(timers)
+            .map(|timer| ViewTimer::from_id(&timer.id()).unwrap())
+            .collect::<Vec<_>>();
+
+        for (i, timer) in decoded.iter().enumerate() {
+            assert!(!decoded[i + 1..].contains(timer));
+        }
+    }
+
+    #[test]
+    fn client_timers_are_not_view_timers() {
+        let id = TimeoutID::SessionBased {
+            session: SeqNo::ZERO,
+            seq_no: SeqNo::from(3u32),
+            from: NodeId::from(1u32),
+        };
+
+        assert!(ViewTimer::from_id(&id).is_none());
+    }
+}
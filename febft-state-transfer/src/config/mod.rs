@@ -1,5 +1,10 @@
 use std::time::Duration;
 
+use crate::CheckpointListener;
+
 pub struct StateTransferConfig {
     pub timeout_duration: Duration,
+    /// Told about every checkpoint we finish or install, such as the checkpoint
+    /// notifier of the ordering protocol
+    pub checkpoint_listener: Option<CheckpointListener>,
 }
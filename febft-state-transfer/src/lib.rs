@@ -129,6 +129,10 @@ struct ReceivedStateCid {
     count: usize,
 }
 
+/// Called with the sequence number and the digest of every local checkpoint we finish,
+/// so the ordering protocol can agree with the other replicas that it is stable
+pub type CheckpointListener = Box<dyn Fn(SeqNo, Digest) + Send + Sync>;
+
 // NOTE: in this module, we may use cid interchangeably with
 // consensus sequence number
 /// The collaborative state transfer algorithm.
@@ -156,6 +160,9 @@ where
 
     /// Persistent logging for the state transfer protocol.
     persistent_log: PL,
+
+    /// Who we tell about the local checkpoints we finish
+    checkpoint_listener: Option<CheckpointListener>,
 }
 
 /// Status returned from processing a state transfer message.
@@ -368,6 +375,11 @@ where
         match status {
             CstStatus::Running => (),
             CstStatus::State(state) => {
+                // The ordering protocol only installs a state it can anchor on a stable checkpoint
+                if let Some(listener) = &self.checkpoint_listener {
+                    listener(state.checkpoint.sequence_number(), *state.checkpoint.digest());
+                }
+
                 let start = Instant::now();
 
                 self.install_channel
@@ -477,15 +489,18 @@ where
         NT: StateTransferSendNode<Self::Serialization>,
         PL: MonolithicStateLog<S>,
     {
-        let StateTransferConfig { timeout_duration } = config;
-
-        Ok(Self::new(
-            node,
+        let StateTransferConfig {
             timeout_duration,
-            timeouts,
-            log,
-            executor_handle,
-        ))
+            checkpoint_listener,
+        } = config;
+
+        let mut state_transfer = Self::new(node, timeout_duration, timeouts, log, executor_handle);
+
+        if let Some(listener) = checkpoint_listener {
+            state_transfer.set_checkpoint_listener(listener);
+        }
+
+        Ok(state_transfer)
     }
 }
 
@@ -518,9 +533,15 @@ where
             curr_seq: SeqNo::ZERO,
             persistent_log,
             install_channel,
+            checkpoint_listener: None,
         }
     }
 
+    /// Install the listener which is told about every local checkpoint we finish
+    pub fn set_checkpoint_listener(&mut self, listener: CheckpointListener) {
+        self.checkpoint_listener = Some(listener);
+    }
+
     /// Checks if the CST layer is waiting for a local checkpoint to
     /// complete.
     ///
@@ -939,6 +960,10 @@ where
 
                 self.current_checkpoint_state = checkpoint_state;
 
+                if let Some(listener) = &self.checkpoint_listener {
+                    listener(checkpoint.sequence_number(), *checkpoint.digest());
+                }
+
                 self.persistent_log
                     .write_checkpoint(OperationMode::NonBlockingSync(None), checkpoint)?;
 